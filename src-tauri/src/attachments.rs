@@ -0,0 +1,486 @@
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::AppHandle;
+use zip::CompressionMethod;
+
+use crate::error::{during, RosePadError};
+use crate::paths;
+use crate::rpad::{self, RpadArchive, RpadEntry};
+
+/// URI scheme the editor uses to reference attachments stored inside an archive.
+pub const ASSET_SCHEME: &str = "rpad-asset";
+
+const ASSETS_DIR: &str = "assets/";
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentDto {
+    pub name: String,
+    pub size: i64,
+    pub mime: String,
+    pub uri: String,
+}
+
+pub(crate) fn mime_for(name: &str) -> &'static str {
+    let ext = Path::new(name)
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_ascii_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        "txt" | "log" | "md" => "text/plain; charset=utf-8",
+        "json" => "application/json",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+// Already-compressed formats gain nothing from deflate
+fn compression_for(name: &str) -> CompressionMethod {
+    match mime_for(name) {
+        "image/png" | "image/jpeg" | "image/gif" | "image/webp" | "application/pdf"
+        | "audio/mpeg" | "video/mp4" | "video/webm" => CompressionMethod::Stored,
+        _ => CompressionMethod::Deflated,
+    }
+}
+
+fn sanitize_ext(ext: &str) -> String {
+    ext.trim_start_matches('.')
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .take(16)
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+/// Content-addressed entry name: `assets/<blake3>.<ext>`.
+fn asset_name(data: &[u8], ext: &str) -> String {
+    let hash = blake3::hash(data).to_hex();
+    let ext = sanitize_ext(ext);
+    if ext.is_empty() {
+        format!("{ASSETS_DIR}{hash}")
+    } else {
+        format!("{ASSETS_DIR}{hash}.{ext}")
+    }
+}
+
+// Accept both "assets/<file>" and the bare "<file>" from the UI
+fn entry_name(name: &str) -> Result<String, RosePadError> {
+    let file = name.strip_prefix(ASSETS_DIR).unwrap_or(name);
+    if file.is_empty() || file.contains('/') || file.contains('\\') || file.starts_with('.') {
+        return Err(RosePadError::invalid_name(name));
+    }
+    Ok(format!("{ASSETS_DIR}{file}"))
+}
+
+/// Build the URI the webview loads an attachment from. Documents store the
+/// relative `assets/<file>` instead (see `to_stored`), so the URI is built from
+/// wherever the document is when it is opened.
+/// The document path is fully percent-encoded so the last `/` separates it from the asset.
+pub fn asset_uri(doc_path: &str, name: &str) -> String {
+    let doc = utf8_percent_encode(doc_path, NON_ALPHANUMERIC);
+    let file = name.strip_prefix(ASSETS_DIR).unwrap_or(name);
+    // WebView2 only routes custom schemes through http://<scheme>.localhost
+    if cfg!(windows) {
+        format!("http://{ASSET_SCHEME}.localhost/{doc}/{file}")
+    } else {
+        format!("{ASSET_SCHEME}://localhost/{doc}/{file}")
+    }
+}
+
+fn to_dto(doc_path: &str, entry: &RpadEntry) -> AttachmentDto {
+    AttachmentDto {
+        name: entry.name.clone(),
+        size: entry.data.len() as i64,
+        mime: mime_for(&entry.name).into(),
+        uri: asset_uri(doc_path, &entry.name),
+    }
+}
+
+/// Wrap raw bytes in a content-addressed archive entry.
+pub(crate) fn new_entry(data: Vec<u8>, ext: &str) -> RpadEntry {
+    let name = asset_name(&data, ext);
+    RpadEntry {
+        method: compression_for(&name),
+        name,
+        data,
+    }
+}
+
+/// Entry name referenced by an `<img src>`, if it points at an embedded asset:
+/// the stored `assets/<file>` or a URI from `asset_uri` for any document path.
+pub(crate) fn entry_for_src(src: &str) -> Option<String> {
+    if src.starts_with(ASSETS_DIR) {
+        return entry_name(src).ok();
+    }
+    let rest = src
+        .strip_prefix(&format!("{ASSET_SCHEME}://localhost"))
+        .or_else(|| src.strip_prefix(&format!("http://{ASSET_SCHEME}.localhost")))?;
+    let (_, file) = rest.rsplit_once('/')?;
+    let file = percent_decode_str(file).decode_utf8().ok()?;
+    entry_name(&file).ok()
+}
+
+/// The HTML an .rpad stores: asset URIs from the editor become `assets/<file>`,
+/// which stays valid when the document is renamed, moved or copied elsewhere.
+pub(crate) fn to_stored(html: &str) -> String {
+    map_srcs(html, entry_for_src)
+}
+
+/// The HTML the editor gets for the document at `doc_path`: stored asset
+/// references, and URIs built for wherever the document used to be, point at
+/// `doc_path`.
+pub(crate) fn for_editor(doc_path: &str, html: &str) -> String {
+    map_srcs(html, |src| {
+        entry_for_src(src).map(|name| asset_uri(doc_path, &name))
+    })
+}
+
+// Replace the `src` of every tag for which `map` returns a new value. Text
+// never contains a raw `<` in serialized HTML, so every `<` starts a tag.
+fn map_srcs(html: &str, map: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let tag = &rest[..tag_len(rest)];
+        match map_tag_src(tag, &map) {
+            Some(mapped) => out.push_str(&mapped),
+            None => out.push_str(tag),
+        }
+        rest = &rest[tag.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// Length of the tag `s` starts with, up to and including its `>`; quoted
+// attribute values may contain `>`
+fn tag_len(s: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in s.char_indices().skip(1) {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return i + 1,
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+fn map_tag_src(tag: &str, map: &impl Fn(&str) -> Option<String>) -> Option<String> {
+    let bytes = tag.as_bytes();
+    let is_space = |i: usize| bytes.get(i).is_some_and(|b| b.is_ascii_whitespace());
+    // Skip the tag name
+    let mut i = 1;
+    while i < bytes.len() && !is_space(i) && !matches!(bytes[i], b'>' | b'/') {
+        i += 1;
+    }
+    loop {
+        while i < bytes.len() && (is_space(i) || bytes[i] == b'/') {
+            i += 1;
+        }
+        if i >= bytes.len() || bytes[i] == b'>' {
+            return None;
+        }
+        let name_start = i;
+        while i < bytes.len() && !is_space(i) && !matches!(bytes[i], b'=' | b'>' | b'/') {
+            i += 1;
+        }
+        let name = &tag[name_start..i];
+        if bytes.get(i) != Some(&b'=') {
+            continue;
+        }
+        i += 1;
+        let (start, end, next) = match bytes.get(i) {
+            Some(&q @ (b'"' | b'\'')) => {
+                let end = tag[i + 1..]
+                    .find(q as char)
+                    .map_or(tag.len(), |e| i + 1 + e);
+                (i + 1, end, end + 1)
+            }
+            _ => {
+                let end = tag[i..]
+                    .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                    .map_or(tag.len(), |e| i + e);
+                (i, end, end)
+            }
+        };
+        if name.eq_ignore_ascii_case("src") {
+            let mapped = map(&tag[start..end])?;
+            return Some(format!("{}{mapped}{}", &tag[..start], &tag[end..]));
+        }
+        i = next;
+    }
+}
+
+fn add_attachment(path: &str, data: Vec<u8>, ext: &str) -> Result<AttachmentDto, RosePadError> {
+    let p = Path::new(path);
+    let mut archive = RpadArchive::open(p)?;
+    let entry = new_entry(data, ext);
+    // Same content already stored → reuse the existing entry
+    if let Some(existing) = archive.entries.iter().find(|e| e.name == entry.name) {
+        return Ok(to_dto(path, existing));
+    }
+    let dto = to_dto(path, &entry);
+    archive.entries.push(entry);
+    archive.save(p)?;
+    Ok(dto)
+}
+
+/// Copy a file from disk into the archive's `assets/` folder.
+#[tauri::command]
+pub async fn add_rpad_attachment(
+    app: AppHandle,
+    path: String,
+    src: String,
+) -> Result<AttachmentDto, RosePadError> {
+    during("add_rpad_attachment", async move {
+        paths::document(&app, &path)?;
+        let srcp = paths::document(&app, &src)?;
+        if !srcp.is_file() {
+            return Err(RosePadError::not_a_file(&srcp));
+        }
+        let data = fs::read(&srcp).map_err(|e| RosePadError::io(e, &srcp))?;
+        let ext = srcp
+            .extension()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+            .to_string();
+        add_attachment(&path, data, &ext)
+    })
+    .await
+}
+
+/// Store raw bytes (e.g. a pasted screenshot) as an attachment.
+#[tauri::command]
+pub async fn add_rpad_attachment_bytes(
+    app: AppHandle,
+    path: String,
+    data: Vec<u8>,
+    ext: String,
+) -> Result<AttachmentDto, RosePadError> {
+    during("add_rpad_attachment_bytes", async move {
+        paths::document(&app, &path)?;
+        add_attachment(&path, data, &ext)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn list_rpad_attachments(
+    app: AppHandle,
+    path: String,
+) -> Result<Vec<AttachmentDto>, RosePadError> {
+    during("list_rpad_attachments", async move {
+        paths::document(&app, &path)?;
+        let archive = RpadArchive::open(Path::new(&path))?;
+        Ok(archive
+            .entries
+            .iter()
+            .filter(|e| e.name.starts_with(ASSETS_DIR))
+            .map(|e| to_dto(&path, e))
+            .collect())
+    })
+    .await
+}
+
+/// Write an attachment out to `dest` and return the path written.
+#[tauri::command]
+pub async fn extract_rpad_attachment(
+    app: AppHandle,
+    path: String,
+    name: String,
+    dest: String,
+) -> Result<String, RosePadError> {
+    during("extract_rpad_attachment", async move {
+        paths::document(&app, &path)?;
+        paths::document(&app, &dest)?;
+        let archive = RpadArchive::open(Path::new(&path))?;
+        let entry_name = entry_name(&name)?;
+        let entry = archive
+            .entries
+            .iter()
+            .find(|e| e.name == entry_name)
+            .ok_or_else(|| RosePadError::not_found(Path::new(&entry_name)))?;
+        let destp = PathBuf::from(&dest);
+        fs::write(&destp, &entry.data).map_err(|e| RosePadError::io(e, &destp))?;
+        Ok(destp.to_string_lossy().to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn remove_rpad_attachment(
+    app: AppHandle,
+    path: String,
+    name: String,
+) -> Result<(), RosePadError> {
+    during("remove_rpad_attachment", async move {
+        paths::document(&app, &path)?;
+        let p = Path::new(&path);
+        let mut archive = RpadArchive::open(p)?;
+        let entry_name = entry_name(&name)?;
+        let before = archive.entries.len();
+        archive.entries.retain(|e| e.name != entry_name);
+        if archive.entries.len() == before {
+            return Err(RosePadError::not_found(Path::new(&entry_name)));
+        }
+        archive.save(p)
+    })
+    .await
+}
+
+fn read_asset(app: &AppHandle, uri_path: &str) -> Result<(String, Vec<u8>), RosePadError> {
+    let trimmed = uri_path.trim_start_matches('/');
+    let (doc, file) = trimmed
+        .rsplit_once('/')
+        .ok_or_else(|| "malformed asset uri".to_string())?;
+    let doc = percent_decode_str(doc)
+        .decode_utf8()
+        .map_err(|_| "malformed asset uri".to_string())?;
+    let file = percent_decode_str(file)
+        .decode_utf8()
+        .map_err(|_| "malformed asset uri".to_string())?;
+    let entry_name = entry_name(&file)?;
+    if !Path::new(doc.as_ref())
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("rpad"))
+    {
+        return Err("asset uri does not point into an .rpad document".into());
+    }
+    paths::document(app, &doc)?;
+    let data = rpad::read_entry(Path::new(doc.as_ref()), &entry_name)?
+        .ok_or_else(|| RosePadError::not_found(Path::new(&entry_name)))?;
+    Ok((mime_for(&entry_name).to_string(), data))
+}
+
+/// Handler for `rpad-asset://localhost/<encoded document path>/<asset>`.
+pub fn serve_asset(app: &AppHandle, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let built = match read_asset(app, request.uri().path()) {
+        Ok((mime, data)) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, mime)
+            // Names are content hashes, so an entry never changes
+            .header(header::CACHE_CONTROL, "max-age=31536000, immutable")
+            .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .body(data),
+        Err(e) => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(e.to_string().into_bytes()),
+    };
+    built.unwrap_or_else(|_| {
+        let mut res = Response::new(Vec::new());
+        *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        res
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_names_stay_in_assets() {
+        assert_eq!(entry_name("assets/ab12.png").unwrap(), "assets/ab12.png");
+        assert_eq!(entry_name("ab12.png").unwrap(), "assets/ab12.png");
+        assert_eq!(
+            entry_name("photo name.jpg").unwrap(),
+            "assets/photo name.jpg"
+        );
+        for bad in [
+            "",
+            "assets/",
+            "../data.json",
+            "assets/../data.json",
+            "assets/sub/x.png",
+            "sub\\x.png",
+            ".hidden",
+            "assets/.hidden",
+            "/etc/passwd",
+        ] {
+            assert!(entry_name(bad).is_err(), "{bad:?}");
+        }
+        // Only the asset folder, never the manifest or the document body
+        assert_eq!(entry_name("manifest.json").unwrap(), "assets/manifest.json");
+    }
+
+    #[test]
+    fn uris_round_trip() {
+        for doc in [
+            "/home/me/Notes/Trip.rpad",
+            "/home/me/My Notes/ünï/a/b.rpad",
+            "C:\\Users\\me\\Docs\\x.rpad",
+            "/odd/na#me?.rpad",
+        ] {
+            let uri = asset_uri(doc, "assets/ab12.png");
+            assert!(uri.ends_with("/ab12.png"), "{uri}");
+            assert_eq!(entry_for_src(&uri).as_deref(), Some("assets/ab12.png"));
+            let path = uri
+                .strip_prefix(&format!("{ASSET_SCHEME}://localhost"))
+                .or_else(|| uri.strip_prefix(&format!("http://{ASSET_SCHEME}.localhost")))
+                .unwrap();
+            let (encoded, file) = path.trim_start_matches('/').rsplit_once('/').unwrap();
+            assert_eq!(percent_decode_str(encoded).decode_utf8().unwrap(), doc);
+            assert_eq!(file, "ab12.png");
+        }
+        assert_eq!(
+            entry_for_src("assets/ab12.png").as_deref(),
+            Some("assets/ab12.png")
+        );
+        assert_eq!(entry_for_src("https://example.com/assets/a.png"), None);
+        assert_eq!(entry_for_src("assets/../data.json"), None);
+        assert_eq!(entry_for_src("data:image/png;base64,AAAA"), None);
+    }
+
+    #[test]
+    fn documents_store_relative_references() {
+        let old = "/old/place/Doc.rpad";
+        let new = "/new/place/Renamed.rpad";
+        let editor = format!(
+            r#"<p>a src="assets/x.png" b</p><img class="w" src="{}" alt="one > two"><img src='{}'><img src="https://example.com/p.png"><p>after</p>"#,
+            asset_uri(old, "assets/one.png"),
+            asset_uri(old, "assets/two.gif"),
+        );
+        let stored = to_stored(&editor);
+        assert_eq!(
+            stored,
+            r#"<p>a src="assets/x.png" b</p><img class="w" src="assets/one.png" alt="one > two"><img src='assets/two.gif'><img src="https://example.com/p.png"><p>after</p>"#
+        );
+        assert!(!stored.contains("old"));
+        // Opened after a move, the images point into the document where it is now
+        let reopened = for_editor(new, &stored);
+        assert!(reopened.contains(&asset_uri(new, "assets/one.png")));
+        assert!(reopened.contains(&asset_uri(new, "assets/two.gif")));
+        assert!(reopened.starts_with(r#"<p>a src="assets/x.png" b</p>"#));
+        assert_eq!(to_stored(&reopened), stored);
+        // Documents saved with absolute URIs are repaired on load
+        assert_eq!(for_editor(new, &editor), reopened);
+        // Tags without a src, and unquoted values
+        assert_eq!(to_stored("<br/><hr><p>x</p>"), "<br/><hr><p>x</p>");
+        assert_eq!(
+            for_editor(new, "<img alt=x src=assets/a.png>"),
+            format!("<img alt=x src={}>", asset_uri(new, "assets/a.png"))
+        );
+    }
+}
@@ -0,0 +1,130 @@
+// Open, Save and folder dialogs run here rather than in the webview, so the
+// paths the user picks can be granted (see `paths::grant` and
+// `paths::grant_root`) without the webview being able to grant arbitrary
+// paths itself.
+
+use serde::Deserialize;
+use std::path::PathBuf;
+use tauri::AppHandle;
+use tauri_plugin_dialog::{DialogExt, FileDialogBuilder, FilePath};
+
+use crate::error::{during, RosePadError};
+use crate::paths;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DialogFilter {
+    pub name: String,
+    pub extensions: Vec<String>,
+}
+
+fn builder(
+    app: &AppHandle,
+    title: Option<String>,
+    filters: Option<Vec<DialogFilter>>,
+) -> FileDialogBuilder<tauri::Wry> {
+    let mut dialog = app.dialog().file();
+    if let Some(title) = title {
+        dialog = dialog.set_title(title);
+    }
+    for filter in filters.unwrap_or_default() {
+        let extensions: Vec<&str> = filter.extensions.iter().map(String::as_str).collect();
+        dialog = dialog.add_filter(filter.name, &extensions);
+    }
+    dialog
+}
+
+fn into_path(picked: FilePath) -> Result<PathBuf, RosePadError> {
+    picked
+        .into_path()
+        .map_err(|e| RosePadError::failed(format!("The dialog returned no usable path: {e}")))
+}
+
+fn into_string(picked: FilePath) -> Result<String, RosePadError> {
+    Ok(into_path(picked)?.to_string_lossy().to_string())
+}
+
+/// Ask for an existing file to open or import; `None` when cancelled.
+#[tauri::command]
+pub async fn open_file_dialog(
+    app: AppHandle,
+    title: Option<String>,
+    filters: Option<Vec<DialogFilter>>,
+) -> Result<Option<String>, RosePadError> {
+    during("open_file_dialog", async move {
+        let Some(picked) = builder(&app, title, filters).blocking_pick_file() else {
+            return Ok(None);
+        };
+        let path = into_string(picked)?;
+        paths::grant(path.as_ref())?;
+        Ok(Some(path))
+    })
+    .await
+}
+
+/// Ask where to save or export; the file need not exist yet. `None` when cancelled.
+#[tauri::command]
+pub async fn save_file_dialog(
+    app: AppHandle,
+    title: Option<String>,
+    default_path: Option<String>,
+    filters: Option<Vec<DialogFilter>>,
+) -> Result<Option<String>, RosePadError> {
+    during("save_file_dialog", async move {
+        let mut dialog = builder(&app, title, filters);
+        if let Some(default_path) = default_path {
+            let default_path = std::path::Path::new(&default_path);
+            if let Some(dir) = default_path.parent().filter(|d| d.is_dir()) {
+                dialog = dialog.set_directory(dir);
+            }
+            if let Some(name) = default_path.file_name() {
+                dialog = dialog.set_file_name(name.to_string_lossy());
+            }
+        }
+        let Some(picked) = dialog.blocking_save_file() else {
+            return Ok(None);
+        };
+        let path = into_string(picked)?;
+        paths::grant_save_target(path.as_ref())?;
+        Ok(Some(path))
+    })
+    .await
+}
+
+// Folders older builds created for the workspace inside the one picked
+const WORKSPACE_FOLDERS: &[&str] = &["RosePad Workspace", "RosePadWorkspace"];
+
+// The picked folder, or the workspace folder an older build made inside it
+fn workspace_folder(picked: PathBuf) -> PathBuf {
+    let is_named = picked.file_name().is_some_and(|n| {
+        WORKSPACE_FOLDERS
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(&n.to_string_lossy()))
+    });
+    if is_named {
+        return picked;
+    }
+    WORKSPACE_FOLDERS
+        .iter()
+        .map(|c| picked.join(c))
+        .find(|p| p.is_dir())
+        .unwrap_or(picked)
+}
+
+/// Ask for a folder to use as a workspace; `None` when cancelled. The folder
+/// returned may then be passed to `add_workspace`.
+#[tauri::command]
+pub async fn open_folder_dialog(
+    app: AppHandle,
+    title: Option<String>,
+) -> Result<Option<String>, RosePadError> {
+    during("open_folder_dialog", async move {
+        let Some(picked) = builder(&app, title, None).blocking_pick_folder() else {
+            return Ok(None);
+        };
+        let folder = workspace_folder(into_path(picked)?);
+        paths::grant_root(&folder)?;
+        Ok(Some(folder.to_string_lossy().to_string()))
+    })
+    .await
+}
@@ -0,0 +1,541 @@
+// Diffs and three-way merges between versions of a document. Plain text is
+// compared line by line; .rpad HTML block by block (see `html::split_blocks`),
+// so a changed paragraph shows up as one changed unit instead of a rewrite of
+// the whole single-line document.
+
+use serde::Serialize;
+use similar::{capture_diff_slices, Algorithm, ChangeTag, DiffOp, DiffTag, TextDiff};
+use std::{ops::Range, path::Path};
+use tauri::AppHandle;
+
+use crate::attachments;
+use crate::error::{during, RosePadError};
+use crate::history;
+use crate::html;
+use crate::paths;
+use crate::rpad::RpadArchive;
+use crate::textfile;
+
+/// Unchanged lines kept around each change.
+pub(crate) const CONTEXT_LINES: usize = 3;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DiffMode {
+    Text,
+    Blocks,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum LineKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// One line, or one block in `Blocks` mode.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffLineDto {
+    pub kind: LineKind,
+    pub text: String,
+    /// 1-based line numbers; `None` on the side the line does not exist in.
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffHunkDto {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<DiffLineDto>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentDiffDto {
+    pub mode: DiffMode,
+    pub hunks: Vec<DiffHunkDto>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum MergeChunkDto {
+    /// Unchanged, changed on one side only, or changed the same way on both.
+    Resolved { lines: Vec<String> },
+    Conflict {
+        base: Vec<String>,
+        ours: Vec<String>,
+        theirs: Vec<String>,
+    },
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeResultDto {
+    pub mode: DiffMode,
+    /// The merged document with conflicts marked: git-style markers for text,
+    /// `data-merge-conflict` wrappers for HTML.
+    pub merged: String,
+    pub conflicts: usize,
+    pub chunks: Vec<MergeChunkDto>,
+}
+
+pub(crate) fn is_rpad(path: &Path) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("rpad"))
+}
+
+/// The comparable text of a document: the HTML of an .rpad, the file otherwise.
+pub(crate) fn read_document(path: &Path) -> Result<String, RosePadError> {
+    if is_rpad(path) {
+        Ok(RpadArchive::open(path)?.document.html)
+    } else {
+        Ok(textfile::read_text(path)?.content)
+    }
+}
+
+// Pick how to compare two documents. An .rpad compared with a text file is
+// reduced to its plain text first.
+fn prepare(docs: Vec<(String, bool)>) -> (DiffMode, Vec<String>) {
+    if docs.iter().all(|(_, rpad)| *rpad) {
+        return (DiffMode::Blocks, docs.into_iter().map(|(d, _)| d).collect());
+    }
+    let texts = docs
+        .into_iter()
+        .map(|(d, rpad)| if rpad { html::plain_text(&d) } else { d })
+        .collect();
+    (DiffMode::Text, texts)
+}
+
+fn units(content: &str, mode: DiffMode) -> Vec<&str> {
+    match mode {
+        DiffMode::Text => content.split_inclusive('\n').collect(),
+        DiffMode::Blocks => html::split_blocks(content),
+    }
+}
+
+fn display(unit: &str) -> String {
+    unit.trim_end_matches(['\r', '\n']).to_string()
+}
+
+/// Hunks turning `old` into `new`; empty when they are the same.
+pub(crate) fn diff_units(old: &[&str], new: &[&str], context: usize) -> Vec<DiffHunkDto> {
+    let diff = TextDiff::configure().diff_slices(old, new);
+    diff.grouped_ops(context)
+        .into_iter()
+        .map(|group| {
+            let lines: Vec<DiffLineDto> = group
+                .iter()
+                .flat_map(|op| diff.iter_changes(op))
+                .map(|c| DiffLineDto {
+                    kind: match c.tag() {
+                        ChangeTag::Equal => LineKind::Equal,
+                        ChangeTag::Insert => LineKind::Insert,
+                        ChangeTag::Delete => LineKind::Delete,
+                    },
+                    text: display(c.value()),
+                    old_line: c.old_index().map(|i| i + 1),
+                    new_line: c.new_index().map(|i| i + 1),
+                })
+                .collect();
+            let (first, last) = (&group[0], &group[group.len() - 1]);
+            let old_range = first.old_range().start..last.old_range().end;
+            let new_range = first.new_range().start..last.new_range().end;
+            DiffHunkDto {
+                old_start: old_range.start + 1,
+                old_len: old_range.len(),
+                new_start: new_range.start + 1,
+                new_len: new_range.len(),
+                lines,
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn diff_content(old: &str, new: &str, mode: DiffMode) -> Vec<DiffHunkDto> {
+    diff_units(&units(old, mode), &units(new, mode), CONTEXT_LINES)
+}
+
+// A changed range of the base and what one side replaced it with
+struct Edit {
+    base: Range<usize>,
+    side: Range<usize>,
+}
+
+fn edits(base: &[&str], side: &[&str]) -> Vec<Edit> {
+    capture_diff_slices(Algorithm::Myers, base, side)
+        .iter()
+        .filter(|op| op.tag() != DiffTag::Equal)
+        .map(|op: &DiffOp| Edit {
+            base: op.old_range(),
+            side: op.new_range(),
+        })
+        .collect()
+}
+
+pub(crate) enum Chunk<'a> {
+    Resolved(Vec<&'a str>),
+    Conflict {
+        base: Vec<&'a str>,
+        ours: Vec<&'a str>,
+        theirs: Vec<&'a str>,
+    },
+}
+
+// What `side` has in place of `base[range]`, given its edits that fall in the range
+fn side_range(range: &Range<usize>, edits: &[&Edit]) -> Option<Range<usize>> {
+    let (first, last) = (edits.first()?, edits.last()?);
+    Some(
+        first.side.start - (first.base.start - range.start)
+            ..last.side.end + (range.end - last.base.end),
+    )
+}
+
+/// diff3-style merge. Changes on one side are taken; overlapping (or touching)
+/// changes on both sides become a conflict unless they are identical.
+pub(crate) fn merge3<'a>(base: &[&'a str], ours: &[&'a str], theirs: &[&'a str]) -> Vec<Chunk<'a>> {
+    let (a, b) = (edits(base, ours), edits(base, theirs));
+    let (mut i, mut j, mut pos) = (0, 0, 0);
+    let mut stable: Vec<&'a str> = Vec::new();
+    let mut out = Vec::new();
+
+    loop {
+        let start = match (a.get(i), b.get(j)) {
+            (Some(x), Some(y)) => x.base.start.min(y.base.start),
+            (Some(x), None) => x.base.start,
+            (None, Some(y)) => y.base.start,
+            (None, None) => break,
+        };
+        stable.extend_from_slice(&base[pos..start]);
+        // Grow the region until no edit on either side touches it
+        let mut end = start;
+        let (mut ea, mut eb) = (Vec::new(), Vec::new());
+        loop {
+            if let Some(x) = a.get(i).filter(|x| x.base.start <= end) {
+                end = end.max(x.base.end);
+                ea.push(x);
+                i += 1;
+            } else if let Some(y) = b.get(j).filter(|y| y.base.start <= end) {
+                end = end.max(y.base.end);
+                eb.push(y);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+        let region = start..end;
+        let ours_part = side_range(&region, &ea).map(|r| &ours[r]);
+        let theirs_part = side_range(&region, &eb).map(|r| &theirs[r]);
+        match (ours_part, theirs_part) {
+            (Some(o), Some(t)) if o != t => {
+                if !stable.is_empty() {
+                    out.push(Chunk::Resolved(std::mem::take(&mut stable)));
+                }
+                out.push(Chunk::Conflict {
+                    base: base[region].to_vec(),
+                    ours: o.to_vec(),
+                    theirs: t.to_vec(),
+                });
+            }
+            (Some(o), _) => stable.extend_from_slice(o),
+            (None, Some(t)) => stable.extend_from_slice(t),
+            (None, None) => {}
+        }
+        pos = end;
+    }
+    stable.extend_from_slice(&base[pos..]);
+    if !stable.is_empty() {
+        out.push(Chunk::Resolved(stable));
+    }
+    out
+}
+
+// Without a common ancestor, use what both sides share as the base: content
+// only one side has is kept, and places where both differ conflict
+fn shared_base<'a>(ours: &[&'a str], theirs: &[&'a str]) -> Vec<&'a str> {
+    capture_diff_slices(Algorithm::Myers, ours, theirs)
+        .iter()
+        .filter(|op| op.tag() == DiffTag::Equal)
+        .flat_map(|op| ours[op.old_range()].iter().copied())
+        .collect()
+}
+
+fn render_merge(chunks: &[Chunk], mode: DiffMode) -> String {
+    let mut out = String::new();
+    let push_side = |out: &mut String, units: &[&str]| {
+        for u in units {
+            out.push_str(u);
+            if mode == DiffMode::Text && !u.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+    };
+    for chunk in chunks {
+        match chunk {
+            Chunk::Resolved(units) => out.extend(units.iter().copied()),
+            Chunk::Conflict { ours, theirs, .. } if mode == DiffMode::Text => {
+                if !out.is_empty() && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str("<<<<<<< ours\n");
+                push_side(&mut out, ours);
+                out.push_str("=======\n");
+                push_side(&mut out, theirs);
+                out.push_str(">>>>>>> theirs\n");
+            }
+            // The editor's merge_conflict node; it needs at least one block,
+            // so a side that deleted the region shows as an empty paragraph
+            Chunk::Conflict { ours, theirs, .. } => {
+                for (side, units) in [("ours", ours), ("theirs", theirs)] {
+                    out.push_str(&format!("<div data-merge-conflict=\"{side}\">"));
+                    if units.is_empty() {
+                        out.push_str("<p></p>");
+                    }
+                    push_side(&mut out, units);
+                    out.push_str("</div>");
+                }
+            }
+        }
+    }
+    out
+}
+
+pub(crate) fn merge_content(
+    base: Option<&str>,
+    ours: &str,
+    theirs: &str,
+    mode: DiffMode,
+) -> MergeResultDto {
+    let (o, t) = (units(ours, mode), units(theirs, mode));
+    let b = match base {
+        Some(base) => units(base, mode),
+        None => shared_base(&o, &t),
+    };
+    let chunks = merge3(&b, &o, &t);
+    let lines = |v: &[&str]| v.iter().map(|u| display(u)).collect();
+    MergeResultDto {
+        mode,
+        merged: render_merge(&chunks, mode),
+        conflicts: chunks
+            .iter()
+            .filter(|c| matches!(c, Chunk::Conflict { .. }))
+            .count(),
+        chunks: chunks
+            .iter()
+            .map(|c| match c {
+                Chunk::Resolved(units) => MergeChunkDto::Resolved {
+                    lines: lines(units),
+                },
+                Chunk::Conflict { base, ours, theirs } => MergeChunkDto::Conflict {
+                    base: lines(base),
+                    ours: lines(ours),
+                    theirs: lines(theirs),
+                },
+            })
+            .collect(),
+    }
+}
+
+fn load(path: &str) -> Result<(String, bool), RosePadError> {
+    let p = Path::new(path);
+    Ok((read_document(p)?, is_rpad(p)))
+}
+
+/// Compare two documents, e.g. a file and its "name (1)" copy.
+#[tauri::command]
+pub async fn diff_documents(
+    app: AppHandle,
+    old_path: String,
+    new_path: String,
+) -> Result<DocumentDiffDto, RosePadError> {
+    during("diff_documents", async move {
+        paths::document(&app, &old_path)?;
+        paths::document(&app, &new_path)?;
+        let (mode, docs) = prepare(vec![load(&old_path)?, load(&new_path)?]);
+        Ok(DocumentDiffDto {
+            mode,
+            hunks: diff_content(&docs[0], &docs[1], mode),
+        })
+    })
+    .await
+}
+
+/// Three-way merge of two documents. The common ancestor is a file
+/// (`base_path`) or a history snapshot (`base_snapshot`); without either, what
+/// both documents share is used as the base.
+#[tauri::command]
+pub async fn merge_documents(
+    app: AppHandle,
+    ours_path: String,
+    theirs_path: String,
+    base_path: Option<String>,
+    base_snapshot: Option<i64>,
+) -> Result<MergeResultDto, RosePadError> {
+    during("merge_documents", async move {
+        for p in [Some(&ours_path), Some(&theirs_path), base_path.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            paths::document(&app, p)?;
+        }
+        let mut docs = vec![load(&ours_path)?, load(&theirs_path)?];
+        if let Some(p) = base_path {
+            docs.push(load(&p)?);
+        } else if let Some(id) = base_snapshot {
+            let (snapshot, content) = history::snapshot_content(&app, id)?;
+            docs.push((content, is_rpad(Path::new(&snapshot.path))));
+        }
+        let (mode, docs) = prepare(docs);
+        let mut result = merge_content(docs.get(2).map(String::as_str), &docs[0], &docs[1], mode);
+        // The merge is opened in the editor in place of `ours`
+        if mode == DiffMode::Blocks {
+            result.merged = attachments::for_editor(&ours_path, &result.merged);
+        }
+        Ok(result)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(s: &str) -> Vec<&str> {
+        s.split(' ').filter(|l| !l.is_empty()).collect()
+    }
+
+    // Resolved chunks as their lines, conflicts as "ours|theirs" against "base"
+    fn describe(chunks: &[Chunk]) -> Vec<String> {
+        chunks
+            .iter()
+            .map(|c| match c {
+                Chunk::Resolved(units) => units.join(" "),
+                Chunk::Conflict { base, ours, theirs } => {
+                    format!(
+                        "<{}|{} from {}>",
+                        ours.join(" "),
+                        theirs.join(" "),
+                        base.join(" ")
+                    )
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge3_cases() {
+        let cases = [
+            ("nothing changed", "a b c", "a b c", "a b c", vec!["a b c"]),
+            ("only ours", "a b c", "a B c", "a b c", vec!["a B c"]),
+            ("only theirs", "a b c", "a b c", "a b C", vec!["a b C"]),
+            (
+                "clean",
+                "a b c d e",
+                "A b c d e",
+                "a b c d E",
+                vec!["A b c d E"],
+            ),
+            ("same change", "a b c", "a X c", "a X c", vec!["a X c"]),
+            ("same deletion", "a b c", "a c", "a c", vec!["a c"]),
+            (
+                "insert and edit",
+                "a b c",
+                "a b n c",
+                "A b c",
+                vec!["A b n c"],
+            ),
+            (
+                "conflicting edits",
+                "a b c",
+                "a X c",
+                "a Y c",
+                vec!["a", "<X|Y from b>", "c"],
+            ),
+            (
+                "edit against deletion",
+                "a b c",
+                "a c",
+                "a Y c",
+                vec!["a", "<|Y from b>", "c"],
+            ),
+            (
+                "touching edits",
+                "a b c d",
+                "a B c d",
+                "a b C d",
+                vec!["a", "<B c|b C from b c>", "d"],
+            ),
+            (
+                "conflict at the start",
+                "a b",
+                "X b",
+                "Y b",
+                vec!["<X|Y from a>", "b"],
+            ),
+            (
+                "conflicting appends",
+                "a",
+                "a x",
+                "a y",
+                vec!["a", "<x|y from >"],
+            ),
+        ];
+        for (name, base, ours, theirs, expected) in cases {
+            let chunks = merge3(&lines(base), &lines(ours), &lines(theirs));
+            assert_eq!(describe(&chunks), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn text_conflicts_get_markers() {
+        let r = merge_content(Some("a\nb\nc\n"), "a\nX\nc\n", "a\nY\nc\n", DiffMode::Text);
+        assert_eq!(r.conflicts, 1);
+        assert_eq!(
+            r.merged,
+            "a\n<<<<<<< ours\nX\n=======\nY\n>>>>>>> theirs\nc\n"
+        );
+        let r = merge_content(Some("a\nb\nc"), "A\nb\nc", "a\nb\nC", DiffMode::Text);
+        assert_eq!(r.conflicts, 0);
+        assert_eq!(r.merged, "A\nb\nC");
+    }
+
+    #[test]
+    fn block_conflicts_use_the_editor_node() {
+        let base = "<p>a</p><p>b</p><p>c</p>";
+        let r = merge_content(
+            Some(base),
+            "<p>a</p><p>X</p><p>c</p>",
+            "<p>a</p><p>Y</p><p>c</p>",
+            DiffMode::Blocks,
+        );
+        assert_eq!(r.conflicts, 1);
+        assert_eq!(
+            r.merged,
+            "<p>a</p><div data-merge-conflict=\"ours\"><p>X</p></div>\
+             <div data-merge-conflict=\"theirs\"><p>Y</p></div><p>c</p>"
+        );
+        // A deleted side still has a block to show
+        let r = merge_content(
+            Some(base),
+            "<p>a</p><p>c</p>",
+            "<p>a</p><p>Y</p><p>c</p>",
+            DiffMode::Blocks,
+        );
+        assert!(r
+            .merged
+            .contains("<div data-merge-conflict=\"ours\"><p></p></div>"));
+    }
+
+    #[test]
+    fn without_a_base_shared_content_is_kept() {
+        let r = merge_content(None, "a\nb\nc\n", "a\nb\nc\nd\n", DiffMode::Text);
+        assert_eq!(r.conflicts, 0);
+        assert_eq!(r.merged, "a\nb\nc\nd\n");
+    }
+}
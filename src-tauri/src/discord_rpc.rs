@@ -5,6 +5,8 @@ use std::env;
 use std::sync::Mutex;
 use std::thread;
 
+use crate::error::RosePadError;
+
 lazy_static! {
     static ref DRPC_CLIENT: Mutex<Option<DiscordIpcClient>> =
         Mutex::new(Some(DiscordIpcClient::new("1309620291470626846")));
@@ -21,38 +23,40 @@ fn is_discord_rpc_disabled() -> bool {
     value == "1"
 }
 
-#[tauri::command]
-pub fn connect_rpc() -> Result<(), String> {
+fn check_enabled(operation: &'static str) -> Result<(), RosePadError> {
     if is_discord_rpc_disabled() {
-        return Err("Discord RPC is disabled".to_string());
+        return Err(RosePadError::rpc_disabled().during(operation));
     }
+    Ok(())
+}
 
-    let mut drpc = DRPC_CLIENT.lock().map_err(|e| e.to_string())?;
-    if let Some(ref mut client) = *drpc {
-        match client.connect() {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!("Failed to connect to Discord IPC: {}", e)),
+// Run `f` against the IPC client, tagging its errors with `operation`
+fn with_client(
+    operation: &'static str,
+    f: impl FnOnce(&mut DiscordIpcClient) -> discord_ipc_rp::Result<()>,
+) -> Result<(), RosePadError> {
+    check_enabled(operation)?;
+    let mut drpc = DRPC_CLIENT
+        .lock()
+        .map_err(|e| RosePadError::failed(e.to_string()).during(operation))?;
+    match *drpc {
+        Some(ref mut client) => {
+            f(client).map_err(|e| RosePadError::rpc_unavailable(e).during(operation))
         }
-    } else {
-        Err("Discord IPC client not initialized".to_string())
+        None => Err(
+            RosePadError::rpc_unavailable("the IPC client is not initialized").during(operation),
+        ),
     }
 }
 
 #[tauri::command]
-pub fn clear_activity() -> Result<(), String> {
-    if is_discord_rpc_disabled() {
-        return Err("Discord RPC is disabled".to_string());
-    }
+pub fn connect_rpc() -> Result<(), RosePadError> {
+    with_client("connect_rpc", |client| client.connect())
+}
 
-    let mut drpc = DRPC_CLIENT.lock().map_err(|e| e.to_string())?;
-    if let Some(ref mut client) = *drpc {
-        match client.clear_activity() {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e.to_string()),
-        }
-    } else {
-        Err("Discord IPC client not initialized".to_string())
-    }
+#[tauri::command]
+pub fn clear_activity() -> Result<(), RosePadError> {
+    with_client("clear_activity", |client| client.clear_activity())
 }
 
 #[tauri::command]
@@ -62,10 +66,8 @@ pub fn update_activity(
     large_image: String,
     large_image_text: String,
     start: i64,
-) -> Result<(), String> {
-    if is_discord_rpc_disabled() {
-        return Err("Discord RPC is disabled".to_string());
-    }
+) -> Result<(), RosePadError> {
+    check_enabled("update_activity")?;
 
     thread::spawn(move || {
         let drpc = DRPC_CLIENT.lock().map_err(|e| e.to_string());
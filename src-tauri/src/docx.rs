@@ -0,0 +1,1330 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Cursor, Read, Write},
+    path::{Path, PathBuf},
+};
+use tauri::AppHandle;
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::attachments;
+use crate::durable;
+use crate::error::{during, RosePadError};
+use crate::html::{self, css_color, css_font_size_pt, escape_attr, escape_text, Element, Node};
+use crate::journal;
+use crate::paths;
+use crate::rpad::RpadArchive;
+use crate::workspace::unique_dest;
+
+const W_NS: &str = "http://schemas.openxmlformats.org/wordprocessingml/2006/main";
+const R_NS: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+const REL_NS: &str = "http://schemas.openxmlformats.org/package/2006/relationships";
+const REL_BASE: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+const WP_NS: &str = "http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing";
+const A_NS: &str = "http://schemas.openxmlformats.org/drawingml/2006/main";
+const PIC_NS: &str = "http://schemas.openxmlformats.org/drawingml/2006/picture";
+
+// Text width of the page set in `html_to_docx` (A4 less two 1" margins), in EMU
+const MAX_IMAGE_EMU: u64 = 9026 * 635;
+const EMU_PER_PX: u64 = 9525;
+// Image formats Word displays without a fallback
+const DOCX_IMAGE_EXTS: &[&str] = &["png", "jpg", "jpeg", "gif", "bmp"];
+
+// Sizes in half-points for headings h1..h6, matching the editor's relative scale
+const HEADING_SIZES: [u32; 6] = [40, 32, 28, 26, 24, 22];
+
+// Word's highlight palette; anything else is written as run shading
+const HIGHLIGHT_COLORS: &[(&str, &str)] = &[
+    ("yellow", "FFFF00"),
+    ("green", "00FF00"),
+    ("cyan", "00FFFF"),
+    ("magenta", "FF00FF"),
+    ("blue", "0000FF"),
+    ("red", "FF0000"),
+    ("darkBlue", "000080"),
+    ("darkCyan", "008080"),
+    ("darkGreen", "008000"),
+    ("darkMagenta", "800080"),
+    ("darkRed", "800000"),
+    ("darkYellow", "808000"),
+    ("darkGray", "808080"),
+    ("lightGray", "C0C0C0"),
+    ("black", "000000"),
+    ("white", "FFFFFF"),
+];
+
+const MONO_FONTS: &[&str] = &[
+    "courier new",
+    "courier",
+    "consolas",
+    "menlo",
+    "monaco",
+    "lucida console",
+    "source code pro",
+    "cascadia code",
+    "cascadia mono",
+];
+
+#[derive(Clone, Default, PartialEq, Debug)]
+struct RunStyle {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strike: bool,
+    code: bool,
+    color: Option<String>,
+    highlight: Option<String>,
+    size_half_pt: Option<u32>,
+}
+
+fn hex_color(value: &str) -> Option<String> {
+    css_color(value).map(|(r, g, b)| format!("{r:02X}{g:02X}{b:02X}"))
+}
+
+fn word_align(align: &str) -> Option<&'static str> {
+    match align.trim().to_ascii_lowercase().as_str() {
+        "center" => Some("center"),
+        "right" | "end" => Some("right"),
+        "justify" => Some("both"),
+        _ => None,
+    }
+}
+
+// ---------- .rpad HTML → DOCX ----------
+
+/// Maps an `<img src>` to the attachment's file name and bytes.
+pub type ImageLookup<'a> = dyn FnMut(&str) -> Option<(String, Vec<u8>)> + 'a;
+
+struct DocxWriter<'a> {
+    body: String,
+    footnotes: Vec<String>,
+    // (ordered, start, level) per list instance; numId = index + 1
+    nums: Vec<(bool, u64, usize)>,
+    // (src, file name under word/media, bytes) per embedded image; rId = index + 4
+    media: Vec<(String, String, Vec<u8>)>,
+    pictures: usize,
+    image: &'a mut ImageLookup<'a>,
+}
+
+impl DocxWriter<'_> {
+    fn blocks(&mut self, nodes: &[Node], level: usize) {
+        let mut pending: Vec<Node> = Vec::new();
+        for node in nodes {
+            match node {
+                Node::Element(el) if is_block(&el.tag) => {
+                    self.flush_inline(&mut pending);
+                    self.block(el, level);
+                }
+                Node::Text(t) if t.trim().is_empty() && pending.is_empty() => {}
+                other => pending.push(other.clone()),
+            }
+        }
+        self.flush_inline(&mut pending);
+    }
+
+    fn flush_inline(&mut self, pending: &mut Vec<Node>) {
+        if pending.is_empty() {
+            return;
+        }
+        let runs = self.runs(pending, &RunStyle::default());
+        pending.clear();
+        self.paragraph(None, None, None, 0, &runs);
+    }
+
+    fn block(&mut self, el: &Element, level: usize) {
+        match el.tag.as_str() {
+            "p" | "li" => {
+                let runs = self.runs(&el.children, &RunStyle::default());
+                let align = el.style("text-align");
+                self.paragraph(None, align.as_deref(), None, level, &runs);
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let n = &el.tag[1..];
+                let runs = self.runs(&el.children, &RunStyle::default());
+                let align = el.style("text-align");
+                let style = format!("Heading{n}");
+                self.paragraph(Some(&style), align.as_deref(), None, level, &runs);
+            }
+            "ul" | "ol" => self.list(el, level),
+            "pre" => {
+                let code = el.text();
+                let code = code.strip_suffix('\n').unwrap_or(&code);
+                for line in code.split('\n') {
+                    let run = text_run(line, &RunStyle::default());
+                    self.paragraph(Some("Code"), None, None, level, &run);
+                }
+            }
+            _ => self.blocks(&el.children, level),
+        }
+    }
+
+    fn list(&mut self, el: &Element, level: usize) {
+        let ordered = el.tag == "ol";
+        let start = el
+            .attr("start")
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(1);
+        // Each list gets its own instance so numbering restarts like it does in the editor
+        let ilvl = level.min(8);
+        self.nums.push((ordered, start, ilvl));
+        let num_id = self.nums.len();
+
+        for child in &el.children {
+            let li = match child {
+                Node::Element(li) if li.tag == "li" => li,
+                _ => continue,
+            };
+            let mut numbered = false;
+            for part in &li.children {
+                match part {
+                    Node::Element(p)
+                        if !numbered && matches!(p.tag.as_str(), "p" | "h1" | "h2" | "h3") =>
+                    {
+                        let runs = self.runs(&p.children, &RunStyle::default());
+                        let align = p.style("text-align");
+                        self.paragraph(None, align.as_deref(), Some((num_id, ilvl)), 0, &runs);
+                        numbered = true;
+                    }
+                    Node::Element(nested) if nested.tag == "ul" || nested.tag == "ol" => {
+                        self.list(nested, level + 1);
+                    }
+                    Node::Element(other) => self.block(other, level + 1),
+                    Node::Text(t) if t.trim().is_empty() => {}
+                    Node::Text(t) => {
+                        let runs = text_run(t, &RunStyle::default());
+                        if numbered {
+                            self.paragraph(None, None, None, level + 1, &runs);
+                        } else {
+                            self.paragraph(None, None, Some((num_id, ilvl)), 0, &runs);
+                            numbered = true;
+                        }
+                    }
+                }
+            }
+            if !numbered {
+                self.paragraph(None, None, Some((num_id, ilvl)), 0, "");
+            }
+        }
+    }
+
+    fn paragraph(
+        &mut self,
+        style: Option<&str>,
+        align: Option<&str>,
+        num: Option<(usize, usize)>,
+        indent_level: usize,
+        runs: &str,
+    ) {
+        let mut ppr = String::new();
+        if let Some(s) = style {
+            ppr.push_str(&format!("<w:pStyle w:val=\"{s}\"/>"));
+        }
+        if let Some((id, ilvl)) = num {
+            ppr.push_str(&format!(
+                "<w:numPr><w:ilvl w:val=\"{ilvl}\"/><w:numId w:val=\"{id}\"/></w:numPr>"
+            ));
+        } else if indent_level > 0 {
+            ppr.push_str(&format!("<w:ind w:left=\"{}\"/>", 720 * indent_level));
+        }
+        if let Some(jc) = align.and_then(word_align) {
+            ppr.push_str(&format!("<w:jc w:val=\"{jc}\"/>"));
+        }
+        self.body.push_str("<w:p>");
+        if !ppr.is_empty() {
+            self.body.push_str(&format!("<w:pPr>{ppr}</w:pPr>"));
+        }
+        self.body.push_str(runs);
+        self.body.push_str("</w:p>");
+    }
+
+    fn runs(&mut self, nodes: &[Node], style: &RunStyle) -> String {
+        let mut out = String::new();
+        for node in nodes {
+            match node {
+                Node::Text(t) => out.push_str(&text_run(t, style)),
+                Node::Element(el) => {
+                    let mut s = style.clone();
+                    match el.tag.as_str() {
+                        "strong" | "b" => s.bold = true,
+                        "em" | "i" => s.italic = true,
+                        "u" => s.underline = true,
+                        "s" | "del" | "strike" => s.strike = true,
+                        "code" => s.code = true,
+                        "br" => {
+                            out.push_str("<w:r><w:br/></w:r>");
+                            continue;
+                        }
+                        "footnote" => {
+                            let body = self.runs(&el.children, &RunStyle::default());
+                            self.footnotes.push(body);
+                            out.push_str(&format!(
+                                "<w:r><w:rPr><w:rStyle w:val=\"FootnoteReference\"/></w:rPr><w:footnoteReference w:id=\"{}\"/></w:r>",
+                                self.footnotes.len()
+                            ));
+                            continue;
+                        }
+                        "img" => {
+                            let alt = el.attr("alt").unwrap_or("");
+                            match el.attr("src").and_then(|src| self.picture(src, alt)) {
+                                Some(run) => out.push_str(&run),
+                                // Formats Word cannot show keep their alt text, so nothing silently vanishes
+                                None if !alt.is_empty() => {
+                                    out.push_str(&text_run(&format!("[{alt}]"), style))
+                                }
+                                None => {}
+                            }
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    if let Some(c) = el.style("color").and_then(|v| hex_color(&v)) {
+                        s.color = Some(c);
+                    }
+                    if let Some(c) = el.style("background-color").and_then(|v| hex_color(&v)) {
+                        s.highlight = Some(c);
+                    }
+                    if let Some(pt) = el.style("font-size").and_then(|v| css_font_size_pt(&v)) {
+                        s.size_half_pt = Some((pt * 2.0).round() as u32);
+                    }
+                    out.push_str(&self.runs(&el.children, &s));
+                }
+            }
+        }
+        out
+    }
+
+    // An inline picture run for `src`, adding the image to the package once
+    fn picture(&mut self, src: &str, alt: &str) -> Option<String> {
+        let index = match self.media.iter().position(|(s, _, _)| s == src) {
+            Some(i) => i,
+            None => {
+                let (name, data) = (self.image)(src)?;
+                let ext = Path::new(&name)
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(str::to_ascii_lowercase)
+                    .filter(|e| DOCX_IMAGE_EXTS.contains(&e.as_str()))?;
+                let part = format!("image{}.{ext}", self.media.len() + 1);
+                self.media.push((src.to_string(), part, data));
+                self.media.len() - 1
+            }
+        };
+        let (_, part, data) = &self.media[index];
+        let (w, h) = image_size(data).unwrap_or((480, 320));
+        let mut cx = w as u64 * EMU_PER_PX;
+        let mut cy = h as u64 * EMU_PER_PX;
+        if cx > MAX_IMAGE_EMU {
+            cy = cy * MAX_IMAGE_EMU / cx;
+            cx = MAX_IMAGE_EMU;
+        }
+        // Every drawing needs its own id, even when it shows the same image
+        self.pictures += 1;
+        let id = self.pictures;
+        let alt = escape_attr(alt);
+        Some(format!(
+            "<w:r><w:drawing><wp:inline distT=\"0\" distB=\"0\" distL=\"0\" distR=\"0\"><wp:extent cx=\"{cx}\" cy=\"{cy}\"/>\
+<wp:docPr id=\"{id}\" name=\"Picture {id}\" descr=\"{alt}\"/><a:graphic xmlns:a=\"{A_NS}\"><a:graphicData uri=\"{PIC_NS}\">\
+<pic:pic xmlns:pic=\"{PIC_NS}\"><pic:nvPicPr><pic:cNvPr id=\"{id}\" name=\"{part}\"/><pic:cNvPicPr/></pic:nvPicPr>\
+<pic:blipFill><a:blip r:embed=\"rId{}\"/><a:stretch><a:fillRect/></a:stretch></pic:blipFill>\
+<pic:spPr><a:xfrm><a:off x=\"0\" y=\"0\"/><a:ext cx=\"{cx}\" cy=\"{cy}\"/></a:xfrm><a:prstGeom prst=\"rect\"><a:avLst/></a:prstGeom></pic:spPr>\
+</pic:pic></a:graphicData></a:graphic></wp:inline></w:drawing></w:r>",
+            index + 4
+        ))
+    }
+}
+
+/// Pixel size of a PNG, GIF, BMP or JPEG image, read from its header.
+fn image_size(data: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |i: usize| Some(u16::from_be_bytes(data.get(i..i + 2)?.try_into().ok()?) as u32);
+    let le16 = |i: usize| Some(u16::from_le_bytes(data.get(i..i + 2)?.try_into().ok()?) as u32);
+    let be32 = |i: usize| Some(u32::from_be_bytes(data.get(i..i + 4)?.try_into().ok()?));
+    let le32 = |i: usize| Some(i32::from_le_bytes(data.get(i..i + 4)?.try_into().ok()?));
+    let size = if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        (be32(16)?, be32(20)?)
+    } else if data.starts_with(b"GIF8") {
+        (le16(6)?, le16(8)?)
+    } else if data.starts_with(b"BM") {
+        (le32(18)?.unsigned_abs(), le32(22)?.unsigned_abs())
+    } else if data.starts_with(&[0xFF, 0xD8]) {
+        // Walk the segments up to the first start-of-frame marker
+        let mut i = 2;
+        loop {
+            if *data.get(i)? != 0xFF {
+                return None;
+            }
+            let marker = *data.get(i + 1)?;
+            let len = be16(i + 2)? as usize;
+            if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+                break (be16(i + 7)?, be16(i + 5)?);
+            }
+            i += 2 + len;
+        }
+    } else {
+        return None;
+    };
+    (size.0 > 0 && size.1 > 0).then_some(size)
+}
+
+fn is_block(tag: &str) -> bool {
+    matches!(
+        tag,
+        "p" | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "ul"
+            | "ol"
+            | "li"
+            | "pre"
+            | "div"
+            | "blockquote"
+            | "table"
+    )
+}
+
+fn run_props(style: &RunStyle) -> String {
+    // Element order follows CT_RPr; Word rejects out-of-order properties
+    let mut rpr = String::new();
+    if style.code {
+        rpr.push_str(
+            "<w:rFonts w:ascii=\"Courier New\" w:hAnsi=\"Courier New\" w:cs=\"Courier New\"/>",
+        );
+    }
+    if style.bold {
+        rpr.push_str("<w:b/>");
+    }
+    if style.italic {
+        rpr.push_str("<w:i/>");
+    }
+    if style.strike {
+        rpr.push_str("<w:strike/>");
+    }
+    if let Some(c) = &style.color {
+        rpr.push_str(&format!("<w:color w:val=\"{c}\"/>"));
+    }
+    if let Some(sz) = style.size_half_pt {
+        rpr.push_str(&format!("<w:sz w:val=\"{sz}\"/><w:szCs w:val=\"{sz}\"/>"));
+    }
+    if let Some(hl) = &style.highlight {
+        match HIGHLIGHT_COLORS.iter().find(|(_, hex)| hex == hl) {
+            Some((name, _)) => rpr.push_str(&format!("<w:highlight w:val=\"{name}\"/>")),
+            None => {
+                if style.underline {
+                    rpr.push_str("<w:u w:val=\"single\"/>");
+                }
+                rpr.push_str(&format!(
+                    "<w:shd w:val=\"clear\" w:color=\"auto\" w:fill=\"{hl}\"/>"
+                ));
+                return format!("<w:rPr>{rpr}</w:rPr>");
+            }
+        }
+    }
+    if style.underline {
+        rpr.push_str("<w:u w:val=\"single\"/>");
+    }
+    if rpr.is_empty() {
+        String::new()
+    } else {
+        format!("<w:rPr>{rpr}</w:rPr>")
+    }
+}
+
+fn text_run(text: &str, style: &RunStyle) -> String {
+    if text.is_empty() {
+        return String::new();
+    }
+    let rpr = run_props(style);
+    let mut out = String::new();
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push_str(&format!("<w:r>{rpr}<w:br/></w:r>"));
+        }
+        if !line.is_empty() {
+            out.push_str(&format!(
+                "<w:r>{rpr}<w:t xml:space=\"preserve\">{}</w:t></w:r>",
+                escape_text(line)
+            ));
+        }
+    }
+    out
+}
+
+fn numbering_xml(nums: &[(bool, u64, usize)]) -> String {
+    let mut out = format!("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><w:numbering xmlns:w=\"{W_NS}\">");
+    let bullets = ["\u{2022}", "\u{25E6}", "\u{25AA}"];
+    for (abs_id, ordered) in [(0, false), (1, true)] {
+        out.push_str(&format!(
+            "<w:abstractNum w:abstractNumId=\"{abs_id}\"><w:multiLevelType w:val=\"hybridMultilevel\"/>"
+        ));
+        for lvl in 0..9 {
+            let (fmt, text) = if ordered {
+                ("decimal".to_string(), format!("%{}.", lvl + 1))
+            } else {
+                ("bullet".to_string(), bullets[lvl % 3].to_string())
+            };
+            out.push_str(&format!(
+                "<w:lvl w:ilvl=\"{lvl}\"><w:start w:val=\"1\"/><w:numFmt w:val=\"{fmt}\"/><w:lvlText w:val=\"{text}\"/><w:lvlJc w:val=\"left\"/><w:pPr><w:ind w:left=\"{}\" w:hanging=\"360\"/></w:pPr></w:lvl>",
+                720 * (lvl + 1)
+            ));
+        }
+        out.push_str("</w:abstractNum>");
+    }
+    for (i, (ordered, start, ilvl)) in nums.iter().enumerate() {
+        out.push_str(&format!(
+            "<w:num w:numId=\"{}\"><w:abstractNumId w:val=\"{}\"/>",
+            i + 1,
+            u8::from(*ordered)
+        ));
+        if *ordered {
+            out.push_str(&format!(
+                "<w:lvlOverride w:ilvl=\"{ilvl}\"><w:startOverride w:val=\"{start}\"/></w:lvlOverride>"
+            ));
+        }
+        out.push_str("</w:num>");
+    }
+    out.push_str("</w:numbering>");
+    out
+}
+
+fn styles_xml() -> String {
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><w:styles xmlns:w=\"{W_NS}\">\
+<w:docDefaults><w:rPrDefault><w:rPr><w:rFonts w:ascii=\"Calibri\" w:hAnsi=\"Calibri\" w:cs=\"Calibri\"/><w:sz w:val=\"24\"/><w:szCs w:val=\"24\"/></w:rPr></w:rPrDefault>\
+<w:pPrDefault><w:pPr><w:spacing w:after=\"120\"/></w:pPr></w:pPrDefault></w:docDefaults>\
+<w:style w:type=\"paragraph\" w:default=\"1\" w:styleId=\"Normal\"><w:name w:val=\"Normal\"/></w:style>"
+    );
+    for (i, sz) in HEADING_SIZES.iter().enumerate() {
+        let n = i + 1;
+        out.push_str(&format!(
+            "<w:style w:type=\"paragraph\" w:styleId=\"Heading{n}\"><w:name w:val=\"heading {n}\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>\
+<w:pPr><w:keepNext/><w:spacing w:before=\"240\" w:after=\"120\"/><w:outlineLvl w:val=\"{i}\"/></w:pPr><w:rPr><w:b/><w:sz w:val=\"{sz}\"/><w:szCs w:val=\"{sz}\"/></w:rPr></w:style>"
+        ));
+    }
+    out.push_str(
+        "<w:style w:type=\"paragraph\" w:styleId=\"Code\"><w:name w:val=\"Code\"/><w:basedOn w:val=\"Normal\"/><w:pPr><w:spacing w:after=\"0\"/></w:pPr>\
+<w:rPr><w:rFonts w:ascii=\"Courier New\" w:hAnsi=\"Courier New\" w:cs=\"Courier New\"/><w:sz w:val=\"20\"/></w:rPr></w:style>\
+<w:style w:type=\"paragraph\" w:styleId=\"FootnoteText\"><w:name w:val=\"footnote text\"/><w:basedOn w:val=\"Normal\"/><w:pPr><w:spacing w:after=\"0\"/></w:pPr><w:rPr><w:sz w:val=\"20\"/></w:rPr></w:style>\
+<w:style w:type=\"character\" w:styleId=\"FootnoteReference\"><w:name w:val=\"footnote reference\"/><w:rPr><w:vertAlign w:val=\"superscript\"/></w:rPr></w:style>\
+</w:styles>",
+    );
+    out
+}
+
+fn footnotes_xml(notes: &[String]) -> String {
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><w:footnotes xmlns:w=\"{W_NS}\">\
+<w:footnote w:type=\"separator\" w:id=\"-1\"><w:p><w:r><w:separator/></w:r></w:p></w:footnote>\
+<w:footnote w:type=\"continuationSeparator\" w:id=\"0\"><w:p><w:r><w:continuationSeparator/></w:r></w:p></w:footnote>"
+    );
+    for (i, runs) in notes.iter().enumerate() {
+        out.push_str(&format!(
+            "<w:footnote w:id=\"{}\"><w:p><w:pPr><w:pStyle w:val=\"FootnoteText\"/></w:pPr>\
+<w:r><w:rPr><w:rStyle w:val=\"FootnoteReference\"/></w:rPr><w:footnoteRef/></w:r><w:r><w:t xml:space=\"preserve\"> </w:t></w:r>{runs}</w:p></w:footnote>",
+            i + 1
+        ));
+    }
+    out.push_str("</w:footnotes>");
+    out
+}
+
+/// Render editor HTML into a complete .docx package. `image` maps an
+/// `<img src>` to the attachment's file name and bytes; images it does not
+/// resolve, or in formats Word cannot show, are written as their alt text.
+pub fn html_to_docx(
+    input: &str,
+    title: &str,
+    image: &mut ImageLookup<'_>,
+) -> Result<Vec<u8>, String> {
+    let mut w = DocxWriter {
+        body: String::new(),
+        footnotes: Vec::new(),
+        nums: Vec::new(),
+        media: Vec::new(),
+        pictures: 0,
+        image,
+    };
+    w.blocks(&html::parse(input), 0);
+    if w.body.is_empty() {
+        w.body.push_str("<w:p/>");
+    }
+
+    let document = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><w:document xmlns:w=\"{W_NS}\" xmlns:r=\"{R_NS}\" xmlns:wp=\"{WP_NS}\"><w:body>{}\
+<w:sectPr><w:pgSz w:w=\"11906\" w:h=\"16838\"/><w:pgMar w:top=\"1440\" w:right=\"1440\" w:bottom=\"1440\" w:left=\"1440\" w:header=\"708\" w:footer=\"708\" w:gutter=\"0\"/></w:sectPr></w:body></w:document>",
+        w.body
+    );
+    let mut image_types = String::new();
+    let mut image_rels = String::new();
+    for (i, (_, part, _)) in w.media.iter().enumerate() {
+        let ext = part.rsplit('.').next().unwrap_or("");
+        let default = format!("Extension=\"{ext}\"");
+        if !image_types.contains(&default) {
+            image_types.push_str(&format!(
+                "<Default {default} ContentType=\"{}\"/>",
+                attachments::mime_for(part)
+            ));
+        }
+        image_rels.push_str(&format!(
+            "<Relationship Id=\"rId{}\" Type=\"{REL_BASE}/image\" Target=\"media/{part}\"/>",
+            i + 4
+        ));
+    }
+    let mut parts: Vec<(String, Vec<u8>)> = vec![
+        (
+            "[Content_Types].xml",
+            format!("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\
+<Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/><Default Extension=\"xml\" ContentType=\"application/xml\"/>{image_types}\
+<Override PartName=\"/word/document.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml\"/>\
+<Override PartName=\"/word/styles.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml\"/>\
+<Override PartName=\"/word/numbering.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml\"/>\
+<Override PartName=\"/word/footnotes.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.footnotes+xml\"/>\
+<Override PartName=\"/docProps/core.xml\" ContentType=\"application/vnd.openxmlformats-package.core-properties+xml\"/></Types>"),
+        ),
+        (
+            "_rels/.rels",
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><Relationships xmlns=\"{REL_NS}\">\
+<Relationship Id=\"rId1\" Type=\"{REL_BASE}/officeDocument\" Target=\"word/document.xml\"/>\
+<Relationship Id=\"rId2\" Type=\"http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties\" Target=\"docProps/core.xml\"/></Relationships>"
+            ),
+        ),
+        (
+            "docProps/core.xml",
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><cp:coreProperties xmlns:cp=\"http://schemas.openxmlformats.org/package/2006/metadata/core-properties\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\"><dc:title>{}</dc:title></cp:coreProperties>",
+                escape_text(title)
+            ),
+        ),
+        (
+            "word/_rels/document.xml.rels",
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?><Relationships xmlns=\"{REL_NS}\">\
+<Relationship Id=\"rId1\" Type=\"{REL_BASE}/styles\" Target=\"styles.xml\"/>\
+<Relationship Id=\"rId2\" Type=\"{REL_BASE}/numbering\" Target=\"numbering.xml\"/>\
+<Relationship Id=\"rId3\" Type=\"{REL_BASE}/footnotes\" Target=\"footnotes.xml\"/>{image_rels}</Relationships>"
+            ),
+        ),
+        ("word/document.xml", document),
+        ("word/styles.xml", styles_xml()),
+        ("word/numbering.xml", numbering_xml(&w.nums)),
+        ("word/footnotes.xml", footnotes_xml(&w.footnotes)),
+    ]
+    .into_iter()
+    .map(|(name, body)| (name.to_string(), body.into_bytes()))
+    .collect();
+    for (_, part, data) in w.media {
+        parts.push((format!("word/media/{part}"), data));
+    }
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, body) in parts {
+        zip.start_file(name, options).map_err(|e| e.to_string())?;
+        zip.write_all(&body).map_err(|e| e.to_string())?;
+    }
+    Ok(zip.finish().map_err(|e| e.to_string())?.into_inner())
+}
+
+// ---------- DOCX → .rpad HTML ----------
+
+struct ListLevel {
+    num_id: String,
+    ordered: bool,
+}
+
+struct DocxReader {
+    // styleId → heading level (1-6), or 0 for code paragraphs
+    para_styles: HashMap<String, u8>,
+    // numId → per-level (ordered, start)
+    numbering: HashMap<String, Vec<(bool, u64)>>,
+    footnotes: HashMap<String, String>,
+    out: String,
+    lists: Vec<ListLevel>,
+    code: Option<String>,
+}
+
+fn w_attr<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes()
+        .find(|a| a.name() == name)
+        .map(|a| a.value())
+}
+
+fn child<'a, 'i>(node: roxmltree::Node<'a, 'i>, name: &str) -> Option<roxmltree::Node<'a, 'i>> {
+    node.children()
+        .find(|c| c.is_element() && c.tag_name().name() == name)
+}
+
+fn on_off(node: Option<roxmltree::Node>) -> bool {
+    match node {
+        Some(n) => !matches!(w_attr(n, "val"), Some("0") | Some("false") | Some("none")),
+        None => false,
+    }
+}
+
+fn parse_styles(xml: &str) -> HashMap<String, u8> {
+    let mut map = HashMap::new();
+    let doc = match roxmltree::Document::parse(xml) {
+        Ok(d) => d,
+        Err(_) => return map,
+    };
+    for style in doc.descendants().filter(|n| n.tag_name().name() == "style") {
+        let id = match w_attr(style, "styleId") {
+            Some(id) => id.to_string(),
+            None => continue,
+        };
+        let name = child(style, "name")
+            .and_then(|n| w_attr(n, "val"))
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let outline = child(style, "pPr")
+            .and_then(|p| child(p, "outlineLvl"))
+            .and_then(|o| w_attr(o, "val"))
+            .and_then(|v| v.parse::<u8>().ok());
+        let level = if let Some(n) = name.strip_prefix("heading ") {
+            n.trim().parse::<u8>().ok()
+        } else if name == "title" {
+            Some(1)
+        } else if name.contains("code") || name.contains("source") {
+            Some(0)
+        } else {
+            outline.filter(|o| *o < 6).map(|o| o + 1)
+        };
+        if let Some(l) = level {
+            map.insert(id, l.min(6));
+        }
+    }
+    map
+}
+
+fn parse_numbering(xml: &str) -> HashMap<String, Vec<(bool, u64)>> {
+    let mut out = HashMap::new();
+    let doc = match roxmltree::Document::parse(xml) {
+        Ok(d) => d,
+        Err(_) => return out,
+    };
+    let mut abstracts: HashMap<String, Vec<(bool, u64)>> = HashMap::new();
+    for abs in doc
+        .descendants()
+        .filter(|n| n.tag_name().name() == "abstractNum")
+    {
+        let id = w_attr(abs, "abstractNumId").unwrap_or("").to_string();
+        let mut levels = vec![(false, 1); 9];
+        for lvl in abs.children().filter(|n| n.tag_name().name() == "lvl") {
+            let i = w_attr(lvl, "ilvl")
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(0);
+            if i >= levels.len() {
+                continue;
+            }
+            let fmt = child(lvl, "numFmt").and_then(|n| w_attr(n, "val"));
+            let start = child(lvl, "start")
+                .and_then(|n| w_attr(n, "val"))
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1);
+            levels[i] = (!matches!(fmt, Some("bullet") | Some("none") | None), start);
+        }
+        abstracts.insert(id, levels);
+    }
+    for num in doc.descendants().filter(|n| n.tag_name().name() == "num") {
+        let id = w_attr(num, "numId").unwrap_or("").to_string();
+        let abs = child(num, "abstractNumId")
+            .and_then(|n| w_attr(n, "val"))
+            .unwrap_or("");
+        let mut levels = abstracts
+            .get(abs)
+            .cloned()
+            .unwrap_or_else(|| vec![(false, 1); 9]);
+        for ov in num
+            .children()
+            .filter(|n| n.tag_name().name() == "lvlOverride")
+        {
+            let i = w_attr(ov, "ilvl")
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(0);
+            let start = child(ov, "startOverride")
+                .and_then(|n| w_attr(n, "val"))
+                .and_then(|v| v.parse().ok());
+            if let (Some(l), Some(s)) = (levels.get_mut(i), start) {
+                l.1 = s;
+            }
+        }
+        out.insert(id, levels);
+    }
+    out
+}
+
+fn run_style(rpr: Option<roxmltree::Node>) -> RunStyle {
+    let mut s = RunStyle::default();
+    let rpr = match rpr {
+        Some(r) => r,
+        None => return s,
+    };
+    s.bold = on_off(child(rpr, "b"));
+    s.italic = on_off(child(rpr, "i"));
+    s.underline = on_off(child(rpr, "u"));
+    s.strike = on_off(child(rpr, "strike")) || on_off(child(rpr, "dstrike"));
+    s.color = child(rpr, "color")
+        .and_then(|c| w_attr(c, "val"))
+        .filter(|v| *v != "auto" && v.len() == 6)
+        .map(|v| v.to_ascii_uppercase());
+    s.highlight = child(rpr, "highlight")
+        .and_then(|h| w_attr(h, "val"))
+        .and_then(|name| HIGHLIGHT_COLORS.iter().find(|(n, _)| *n == name))
+        .map(|(_, hex)| hex.to_string())
+        .or_else(|| {
+            child(rpr, "shd")
+                .and_then(|s| w_attr(s, "fill"))
+                .filter(|v| *v != "auto" && v.len() == 6)
+                .map(|v| v.to_ascii_uppercase())
+        });
+    s.size_half_pt = child(rpr, "sz")
+        .and_then(|n| w_attr(n, "val"))
+        .and_then(|v| v.parse().ok());
+    s.code = child(rpr, "rFonts")
+        .and_then(|f| w_attr(f, "ascii").or_else(|| w_attr(f, "hAnsi")))
+        .is_some_and(|font| MONO_FONTS.contains(&font.to_ascii_lowercase().as_str()));
+    s
+}
+
+fn styled_html(text: &str, s: &RunStyle) -> String {
+    let mut html = escape_text(text);
+    if s.code {
+        html = format!("<code>{html}</code>");
+    }
+    if s.strike {
+        html = format!("<s>{html}</s>");
+    }
+    if s.underline {
+        html = format!("<u>{html}</u>");
+    }
+    if s.italic {
+        html = format!("<em>{html}</em>");
+    }
+    if s.bold {
+        html = format!("<strong>{html}</strong>");
+    }
+    if let Some(sz) = s.size_half_pt {
+        let pt = sz as f32 / 2.0;
+        html = format!("<span style=\"font-size: {pt}pt\">{html}</span>");
+    }
+    if let Some(hl) = &s.highlight {
+        html = format!("<span style=\"background-color: #{hl}\">{html}</span>");
+    }
+    if let Some(c) = &s.color {
+        html = format!("<span style=\"color: #{c}\">{html}</span>");
+    }
+    html
+}
+
+enum Segment {
+    Text(String, RunStyle),
+    Raw(String),
+}
+
+impl DocxReader {
+    fn inline(&self, node: roxmltree::Node, segs: &mut Vec<Segment>) {
+        for c in node.children().filter(|c| c.is_element()) {
+            match c.tag_name().name() {
+                "r" => self.run(c, segs),
+                "hyperlink" | "smartTag" | "ins" | "sdt" | "sdtContent" | "fldSimple"
+                | "customXml" | "moveTo" => self.inline(c, segs),
+                _ => {}
+            }
+        }
+    }
+
+    fn run(&self, r: roxmltree::Node, segs: &mut Vec<Segment>) {
+        let style = run_style(child(r, "rPr"));
+        for c in r.children().filter(|c| c.is_element()) {
+            let text = match c.tag_name().name() {
+                "t" => c.text().unwrap_or("").to_string(),
+                "tab" => "\t".into(),
+                "br" | "cr" => " ".into(),
+                "noBreakHyphen" => "-".into(),
+                "footnoteReference" => {
+                    if let Some(body) = w_attr(c, "id").and_then(|id| self.footnotes.get(id)) {
+                        segs.push(Segment::Raw(format!("<footnote>{body}</footnote>")));
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            // Merge with the previous run when formatting matches to keep markup small
+            if let Some(Segment::Text(prev, prev_style)) = segs.last_mut() {
+                if *prev_style == style {
+                    prev.push_str(&text);
+                    continue;
+                }
+            }
+            segs.push(Segment::Text(text, style.clone()));
+        }
+    }
+
+    fn inline_html(&self, node: roxmltree::Node) -> String {
+        let mut segs = Vec::new();
+        self.inline(node, &mut segs);
+        segs.iter()
+            .map(|s| match s {
+                Segment::Text(t, st) => styled_html(t, st),
+                Segment::Raw(h) => h.clone(),
+            })
+            .collect()
+    }
+
+    fn close_lists_to(&mut self, depth: usize) {
+        while self.lists.len() > depth {
+            let l = self.lists.pop().unwrap_or(ListLevel {
+                num_id: String::new(),
+                ordered: false,
+            });
+            self.out.push_str(if l.ordered {
+                "</li></ol>"
+            } else {
+                "</li></ul>"
+            });
+        }
+    }
+
+    fn flush_code(&mut self) {
+        if let Some(code) = self.code.take() {
+            self.out
+                .push_str(&format!("<pre><code>{}</code></pre>", escape_text(&code)));
+        }
+    }
+
+    fn paragraph(&mut self, p: roxmltree::Node) {
+        let ppr = child(p, "pPr");
+        let style_id = ppr
+            .and_then(|x| child(x, "pStyle"))
+            .and_then(|s| w_attr(s, "val"))
+            .unwrap_or("");
+        let level = self.para_styles.get(style_id).copied().or_else(|| {
+            style_id
+                .strip_prefix("Heading")
+                .and_then(|n| n.parse::<u8>().ok())
+                .filter(|n| (1..=6).contains(n))
+        });
+
+        if level == Some(0) {
+            self.close_lists_to(0);
+            let mut segs = Vec::new();
+            self.inline(p, &mut segs);
+            let line: String = segs
+                .iter()
+                .map(|s| match s {
+                    Segment::Text(t, _) => t.as_str(),
+                    Segment::Raw(_) => "",
+                })
+                .collect();
+            match self.code.as_mut() {
+                Some(code) => {
+                    code.push('\n');
+                    code.push_str(&line);
+                }
+                None => self.code = Some(line),
+            }
+            return;
+        }
+        self.flush_code();
+
+        let align = ppr
+            .and_then(|x| child(x, "jc"))
+            .and_then(|j| w_attr(j, "val"))
+            .and_then(|v| match v {
+                "center" => Some("center"),
+                "right" | "end" => Some("right"),
+                "both" | "distribute" => Some("justify"),
+                _ => None,
+            });
+        let style_attr = align
+            .map(|a| format!(" style=\"text-align:{a}\""))
+            .unwrap_or_default();
+        let content = self.inline_html(p);
+
+        let num = ppr.and_then(|x| child(x, "numPr")).and_then(|n| {
+            let id = child(n, "numId").and_then(|i| w_attr(i, "val"))?;
+            let ilvl = child(n, "ilvl")
+                .and_then(|i| w_attr(i, "val"))
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(0);
+            // numId 0 explicitly removes numbering
+            (id != "0").then(|| (id.to_string(), ilvl.min(8)))
+        });
+
+        let (num_id, ilvl) = match (num, level) {
+            (Some(n), None) => n,
+            _ => {
+                self.close_lists_to(0);
+                let tag = match level {
+                    Some(l) => format!("h{l}"),
+                    None => "p".into(),
+                };
+                self.out
+                    .push_str(&format!("<{tag}{style_attr}>{content}</{tag}>"));
+                return;
+            }
+        };
+
+        let (ordered, start) = self
+            .numbering
+            .get(&num_id)
+            .and_then(|levels| levels.get(ilvl).copied())
+            .unwrap_or((false, 1));
+        self.close_lists_to(ilvl + 1);
+        if let Some(top) = self.lists.last() {
+            if self.lists.len() == ilvl + 1 && (top.num_id != num_id || top.ordered != ordered) {
+                self.close_lists_to(ilvl);
+            }
+        }
+        if self.lists.len() == ilvl + 1 {
+            self.out.push_str("</li><li>");
+        } else {
+            while self.lists.len() < ilvl + 1 {
+                let open = if !ordered {
+                    "<ul><li>".to_string()
+                } else if start != 1 {
+                    format!("<ol start=\"{start}\"><li>")
+                } else {
+                    "<ol><li>".to_string()
+                };
+                self.out.push_str(&open);
+                self.lists.push(ListLevel {
+                    num_id: num_id.clone(),
+                    ordered,
+                });
+            }
+        }
+        self.out.push_str(&format!("<p{style_attr}>{content}</p>"));
+    }
+
+    fn table(&mut self, tbl: roxmltree::Node) {
+        self.close_lists_to(0);
+        self.flush_code();
+        for row in tbl.children().filter(|n| n.tag_name().name() == "tr") {
+            let cells: Vec<String> = row
+                .children()
+                .filter(|n| n.tag_name().name() == "tc")
+                .map(|tc| {
+                    tc.children()
+                        .filter(|n| n.tag_name().name() == "p")
+                        .map(|p| self.inline_html(p))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect();
+            self.out.push_str(&format!("<p>{}</p>", cells.join(" | ")));
+        }
+    }
+
+    fn body(&mut self, node: roxmltree::Node) {
+        for c in node.children().filter(|c| c.is_element()) {
+            match c.tag_name().name() {
+                "p" => self.paragraph(c),
+                "tbl" => self.table(c),
+                "sdt" | "sdtContent" | "customXml" => self.body(c),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn read_entry<R: Read + std::io::Seek>(zip: &mut ZipArchive<R>, name: &str) -> Option<String> {
+    let mut f = zip.by_name(name).ok()?;
+    let mut s = String::new();
+    f.read_to_string(&mut s).ok()?;
+    Some(s)
+}
+
+/// Convert a .docx package into editor HTML. Returns `(title, html)`.
+pub fn docx_to_html(bytes: &[u8]) -> Result<(Option<String>, String), String> {
+    let mut zip =
+        ZipArchive::new(Cursor::new(bytes)).map_err(|e| format!("not a valid .docx file: {e}"))?;
+
+    // Locate the main part through the package relationships
+    let main = read_entry(&mut zip, "_rels/.rels")
+        .and_then(|rels| {
+            let doc = roxmltree::Document::parse(&rels).ok()?;
+            let target = doc
+                .descendants()
+                .find(|n| {
+                    n.tag_name().name() == "Relationship"
+                        && n.attribute("Type")
+                            .is_some_and(|t| t.ends_with("/officeDocument"))
+                })?
+                .attribute("Target")?
+                .trim_start_matches('/')
+                .to_string();
+            Some(target)
+        })
+        .unwrap_or_else(|| "word/document.xml".into());
+    let dir = main.rsplit_once('/').map(|(d, _)| d).unwrap_or("");
+    let part = |name: &str| {
+        if dir.is_empty() {
+            name.to_string()
+        } else {
+            format!("{dir}/{name}")
+        }
+    };
+
+    let document_xml =
+        read_entry(&mut zip, &main).ok_or_else(|| "document.xml not found in .docx".to_string())?;
+    let styles = read_entry(&mut zip, &part("styles.xml"))
+        .map(|s| parse_styles(&s))
+        .unwrap_or_default();
+    let numbering = read_entry(&mut zip, &part("numbering.xml"))
+        .map(|s| parse_numbering(&s))
+        .unwrap_or_default();
+
+    let mut reader = DocxReader {
+        para_styles: styles,
+        numbering,
+        footnotes: HashMap::new(),
+        out: String::new(),
+        lists: Vec::new(),
+        code: None,
+    };
+
+    if let Some(xml) = read_entry(&mut zip, &part("footnotes.xml")) {
+        if let Ok(doc) = roxmltree::Document::parse(&xml) {
+            let mut notes = HashMap::new();
+            for note in doc
+                .descendants()
+                .filter(|n| n.tag_name().name() == "footnote")
+            {
+                if w_attr(note, "type").is_some() {
+                    continue;
+                }
+                let id = w_attr(note, "id").unwrap_or("").to_string();
+                let body: Vec<String> = note
+                    .children()
+                    .filter(|n| n.tag_name().name() == "p")
+                    .map(|p| reader.inline_html(p))
+                    .collect();
+                notes.insert(id, body.join(" ").trim().to_string());
+            }
+            reader.footnotes = notes;
+        }
+    }
+
+    let doc = roxmltree::Document::parse(&document_xml)
+        .map_err(|e| format!("invalid document.xml: {e}"))?;
+    let body = doc
+        .descendants()
+        .find(|n| n.tag_name().name() == "body")
+        .ok_or_else(|| "document.xml has no body".to_string())?;
+    reader.body(body);
+    reader.flush_code();
+    reader.close_lists_to(0);
+
+    let title = read_entry(&mut zip, "docProps/core.xml").and_then(|xml| {
+        let doc = roxmltree::Document::parse(&xml).ok()?;
+        let t = doc
+            .descendants()
+            .find(|n| n.tag_name().name() == "title")?
+            .text()?
+            .trim()
+            .to_string();
+        (!t.is_empty()).then_some(t)
+    });
+    Ok((title, reader.out))
+}
+
+// ---------- Commands ----------
+
+/// Export an .rpad document as a Word document.
+#[tauri::command]
+pub async fn export_docx(
+    app: AppHandle,
+    path: String,
+    dest: String,
+) -> Result<String, RosePadError> {
+    during("export_docx", async move {
+        paths::document(&app, &path)?;
+        paths::document(&app, &dest)?;
+        let archive = RpadArchive::open(Path::new(&path))?;
+        let mut image = |src: &str| {
+            let name = attachments::entry_for_src(src)?;
+            let entry = archive.entries.iter().find(|e| e.name == name)?;
+            Some((entry.name.clone(), entry.data.clone()))
+        };
+        let bytes = html_to_docx(&archive.document.html, &archive.manifest.title, &mut image)?;
+        let destp = PathBuf::from(&dest);
+        durable::write_atomic(&destp, &bytes)?;
+        Ok(destp.to_string_lossy().to_string())
+    })
+    .await
+}
+
+/// Convert a .docx file into a new .rpad project in `dest_dir` (defaults to the
+/// active workspace's root) and return its path.
+#[tauri::command]
+pub async fn import_docx(
+    app: AppHandle,
+    src: String,
+    dest_dir: Option<String>,
+) -> Result<String, RosePadError> {
+    during("import_docx", async move {
+        let srcp = paths::document(&app, &src)?;
+        let dest = paths::import_dir(&app, dest_dir.as_deref())?;
+        if !srcp.is_file() {
+            return Err(RosePadError::not_a_file(&srcp));
+        }
+        let ext = srcp
+            .extension()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        if ext == "doc" {
+            return Err("legacy .doc files are not supported; save the file as .docx first".into());
+        }
+        let bytes = fs::read(&srcp).map_err(|e| RosePadError::io(e, &srcp))?;
+        let (doc_title, html) = docx_to_html(&bytes)?;
+        let name = srcp
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("Untitled")
+            .to_string();
+        let target = unique_dest(dest.join(format!("{name}.rpad")));
+
+        let mut archive = RpadArchive::new(doc_title.unwrap_or(name));
+        archive.document.html = html;
+        archive.save(&target)?;
+        let target = target.to_string_lossy().to_string();
+        journal::record_created(&app, &target).await;
+        Ok(target)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(html: &str) -> String {
+        let bytes = html_to_docx(html, "T", &mut |_| None).unwrap();
+        docx_to_html(&bytes).unwrap().1
+    }
+
+    fn part(bytes: &[u8], name: &str) -> Option<String> {
+        read_entry(&mut ZipArchive::new(Cursor::new(bytes)).unwrap(), name)
+    }
+
+    // Just the signature and IHDR, which is all `image_size` looks at
+    fn png(w: u32, h: u32) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        data.extend(w.to_be_bytes());
+        data.extend(h.to_be_bytes());
+        data.extend([8, 6, 0, 0, 0]);
+        data
+    }
+
+    #[test]
+    fn alignment() {
+        let html = "<p style=\"text-align:center\">c</p><p style=\"text-align:right\">r</p>\
+<p style=\"text-align:justify\">j</p><h2 style=\"text-align:center\">H</h2><p>left</p>";
+        assert_eq!(round_trip(html), html);
+    }
+
+    #[test]
+    fn colors_highlight_and_font_size() {
+        let html = "<p><span style=\"color: #FF0000\">red</span> \
+<span style=\"background-color: #FFFF00\">named</span> \
+<span style=\"background-color: #123456\">shaded</span> \
+<span style=\"font-size: 18pt\">big</span></p>";
+        assert_eq!(round_trip(html), html);
+        // Word only knows a few highlight colors; the rest go through shading
+        let bytes = html_to_docx(html, "T", &mut |_| None).unwrap();
+        let doc = part(&bytes, "word/document.xml").unwrap();
+        assert!(doc.contains("<w:highlight w:val=\"yellow\"/>"));
+        assert!(doc.contains("w:fill=\"123456\""));
+        assert!(doc.contains("<w:sz w:val=\"36\"/>"));
+        // CSS spellings are normalized
+        assert_eq!(
+            round_trip("<p><span style=\"color: rgb(0, 128, 0)\">g</span></p>"),
+            "<p><span style=\"color: #008000\">g</span></p>"
+        );
+    }
+
+    #[test]
+    fn marks_headings_and_code() {
+        let html = "<h2>H</h2><p><strong>b</strong><em>i</em><u>u</u><s>s</s><code>c</code></p>\
+<pre><code>fn x() {\n  1 &lt; 2\n}</code></pre>";
+        assert_eq!(round_trip(html), html);
+    }
+
+    #[test]
+    fn lists() {
+        let html =
+            "<ul><li><p>a</p></li><li><p>b</p><ol start=\"3\"><li><p>c</p></li></ol></li></ul>\
+<ol><li><p>x</p></li><li><p>y</p></li></ol>";
+        assert_eq!(round_trip(html), html);
+        // Two lists in a row stay two lists, each numbered from its own start
+        let html = "<ol start=\"5\"><li><p>a</p></li></ol><ol><li><p>b</p></li></ol>";
+        assert_eq!(round_trip(html), html);
+    }
+
+    #[test]
+    fn footnotes() {
+        let html =
+            "<p>x<footnote>the <strong>note</strong></footnote> y<footnote>two</footnote></p>";
+        assert_eq!(round_trip(html), html);
+        let bytes = html_to_docx(html, "T", &mut |_| None).unwrap();
+        let notes = part(&bytes, "word/footnotes.xml").unwrap();
+        assert!(notes.contains("w:id=\"1\"") && notes.contains("w:id=\"2\""));
+    }
+
+    #[test]
+    fn images_are_embedded() {
+        let html = "<p><img src=\"assets/a.png\" alt=\"A &quot;pic&quot;\">\
+<img src=\"assets/a.png\"><img src=\"assets/b.webp\" alt=\"webp\"><img src=\"assets/gone.png\"></p>";
+        let mut asked = Vec::new();
+        let bytes = html_to_docx(html, "T", &mut |src| {
+            asked.push(src.to_string());
+            match src {
+                "assets/a.png" => Some(("assets/a.png".into(), png(3000, 1500))),
+                "assets/b.webp" => Some(("assets/b.webp".into(), vec![1, 2, 3])),
+                _ => None,
+            }
+        })
+        .unwrap();
+        // Each image is resolved and stored once
+        assert_eq!(asked, ["assets/a.png", "assets/b.webp", "assets/gone.png"]);
+        let mut zip = ZipArchive::new(Cursor::new(&bytes)).unwrap();
+        let mut media = Vec::new();
+        zip.by_name("word/media/image1.png")
+            .unwrap()
+            .read_to_end(&mut media)
+            .unwrap();
+        assert_eq!(media, png(3000, 1500));
+        assert!(zip.by_name("word/media/image2.webp").is_err());
+        let doc = part(&bytes, "word/document.xml").unwrap();
+        assert_eq!(doc.matches("<w:drawing>").count(), 2);
+        assert_eq!(doc.matches("r:embed=\"rId4\"").count(), 2);
+        assert!(doc.contains("descr=\"A &quot;pic&quot;\""));
+        assert!(doc.contains("<wp:docPr id=\"1\"") && doc.contains("<wp:docPr id=\"2\""));
+        // Wider than the text column, so scaled down keeping the aspect ratio
+        let cx = MAX_IMAGE_EMU;
+        assert!(doc.contains(&format!("cx=\"{cx}\" cy=\"{}\"", cx / 2)));
+        // Word cannot show WebP, so its alt text stands in
+        assert!(doc.contains(">[webp]</w:t>"));
+        let rels = part(&bytes, "word/_rels/document.xml.rels").unwrap();
+        assert!(rels.contains("Id=\"rId4\"") && rels.contains("Target=\"media/image1.png\""));
+        assert!(!rels.contains("rId5"));
+        let types = part(&bytes, "[Content_Types].xml").unwrap();
+        assert!(types.contains("<Default Extension=\"png\" ContentType=\"image/png\"/>"));
+        assert!(roxmltree::Document::parse(&doc).is_ok());
+    }
+
+    #[test]
+    fn image_sizes_from_headers() {
+        assert_eq!(image_size(&png(3, 2)), Some((3, 2)));
+        assert_eq!(image_size(b"GIF89a\x0a\x00\x05\x00"), Some((10, 5)));
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 4, 0, 0];
+        jpeg.extend([0xFF, 0xC0, 0, 11, 8, 0, 20, 0, 40, 3]);
+        assert_eq!(image_size(&jpeg), Some((40, 20)));
+        assert_eq!(image_size(b"not an image"), None);
+        assert_eq!(image_size(&png(0, 2)), None);
+    }
+}
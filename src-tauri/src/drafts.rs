@@ -0,0 +1,253 @@
+// Crash recovery for unsaved edits. The editor streams the state of each dirty
+// document here every few seconds; the journal lives in the config dir as one
+// JSON file per document, so it survives a webview crash or a forced quit. A
+// successful save drops the document's draft.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Manager};
+
+use crate::diff::read_document;
+use crate::durable;
+use crate::error::{during, RosePadError};
+use crate::paths;
+use crate::rpad;
+use crate::workspace::{version_of, DocVersionDto};
+
+const DRAFTS_DIR: &str = "drafts";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DraftRecord {
+    path: String,
+    /// The editor's HTML for the document.
+    content: String,
+    /// The on-disk version the edits started from, when known.
+    base: Option<DocVersionDto>,
+    updated_at_ms: i64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoverableDraftDto {
+    pub path: String,
+    pub updated_at_ms: i64,
+    pub base: Option<DocVersionDto>,
+    /// What is on disk now; `None` if the file is gone.
+    pub disk: Option<DocVersionDto>,
+    /// The file changed since the edits started, so restoring overwrites those changes.
+    pub disk_changed: bool,
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn drafts_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    Ok(dir.join(DRAFTS_DIR))
+}
+
+fn draft_file(app: &AppHandle, path: &str) -> Result<PathBuf, String> {
+    let hash = blake3::hash(path.as_bytes()).to_hex();
+    Ok(drafts_dir(app)?.join(format!("{}.json", &hash[..32])))
+}
+
+fn read_record(file: &Path) -> Option<DraftRecord> {
+    let raw = fs::read(file).ok()?;
+    serde_json::from_slice(&raw).ok()
+}
+
+/// Forget the draft of `path`, e.g. after it was saved.
+pub(crate) fn discard(app: &AppHandle, path: &str) -> Result<(), RosePadError> {
+    let file = draft_file(app, path)?;
+    match fs::remove_file(&file) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(RosePadError::io(e, &file)),
+        _ => Ok(()),
+    }
+}
+
+fn disk_version(path: &str) -> Option<DocVersionDto> {
+    let p = Path::new(path);
+    version_of(p, &read_document(p).ok()?).ok()
+}
+
+// Write `record` into `file`, replacing what was there. Drafts are plain JSON,
+// so an encrypted document's text is never written there
+fn store(file: &Path, record: &DraftRecord) -> Result<(), RosePadError> {
+    if rpad::read_encryption(Path::new(&record.path)).is_some() {
+        return Ok(());
+    }
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).map_err(|e| RosePadError::io(e, dir))?;
+    }
+    let bytes = serde_json::to_vec(record).map_err(|e| e.to_string())?;
+    durable::write_atomic(file, &bytes)
+}
+
+// Every readable draft in `dir`, compared with what is on disk now
+fn recoverable(dir: &Path) -> Vec<RecoverableDraftDto> {
+    let mut out = Vec::new();
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let Some(record) = read_record(&entry.path()) else {
+            continue;
+        };
+        let disk = disk_version(&record.path);
+        let disk_changed = match (&record.base, &disk) {
+            (Some(base), Some(disk)) => base.hash != disk.hash,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        out.push(RecoverableDraftDto {
+            path: record.path,
+            updated_at_ms: record.updated_at_ms,
+            base: record.base,
+            disk,
+            disk_changed,
+        });
+    }
+    out.sort_by_key(|d| std::cmp::Reverse(d.updated_at_ms));
+    out
+}
+
+/// Store the current state of an unsaved document, replacing its previous draft.
+#[tauri::command]
+pub async fn put_draft(
+    app: AppHandle,
+    path: String,
+    content: String,
+    base: Option<DocVersionDto>,
+) -> Result<(), RosePadError> {
+    during("put_draft", async move {
+        paths::document(&app, &path)?;
+        let file = draft_file(&app, &path)?;
+        let record = DraftRecord {
+            path,
+            content,
+            base,
+            updated_at_ms: now_ms(),
+        };
+        store(&file, &record)
+    })
+    .await
+}
+
+/// Drafts left behind by an earlier session, most recent first.
+#[tauri::command]
+pub async fn list_drafts(app: AppHandle) -> Result<Vec<RecoverableDraftDto>, RosePadError> {
+    during(
+        "list_drafts",
+        async move { Ok(recoverable(&drafts_dir(&app)?)) },
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn read_draft(app: AppHandle, path: String) -> Result<String, RosePadError> {
+    during("read_draft", async move {
+        read_record(&draft_file(&app, &path)?)
+            .map(|r| r.content)
+            .ok_or_else(|| RosePadError::not_found(Path::new(&path)))
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn discard_draft(app: AppHandle, path: String) -> Result<(), RosePadError> {
+    during("discard_draft", async move { discard(&app, &path) }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::{self, RpadEncryption};
+    use crate::rpad::RpadArchive;
+    use crate::scratch;
+
+    fn record(path: &Path, base: Option<DocVersionDto>, updated_at_ms: i64) -> DraftRecord {
+        DraftRecord {
+            path: path.to_string_lossy().to_string(),
+            content: "<p>unsaved</p>".into(),
+            base,
+            updated_at_ms,
+        }
+    }
+
+    #[test]
+    fn drafts_tell_whether_the_file_changed_since() {
+        let dir = scratch::dir("drafts-changed");
+        let drafts = dir.join(DRAFTS_DIR);
+        let doc = |name: &str| dir.join(name);
+        fs::write(doc("same.txt"), "a").unwrap();
+        fs::write(doc("edited.txt"), "a").unwrap();
+        fs::write(doc("unknown.txt"), "a").unwrap();
+        let base = disk_version(&doc("same.txt").to_string_lossy()).unwrap();
+        fs::write(doc("edited.txt"), "b").unwrap();
+
+        for (i, (name, base)) in [
+            ("same.txt", Some(base.clone())),
+            ("edited.txt", Some(base.clone())),
+            ("gone.txt", Some(base)),
+            // No base means nothing to compare with
+            ("unknown.txt", None),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let file = drafts.join(format!("{i}.json"));
+            store(&file, &record(&doc(name), base, i as i64)).unwrap();
+        }
+        fs::write(drafts.join("damaged.json"), "{").unwrap();
+
+        let listed: Vec<(String, bool, bool)> = recoverable(&drafts)
+            .into_iter()
+            .map(|d| {
+                let name = Path::new(&d.path).file_name().unwrap();
+                (
+                    name.to_string_lossy().to_string(),
+                    d.disk_changed,
+                    d.disk.is_some(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("unknown.txt".to_string(), false, true),
+                ("gone.txt".to_string(), true, false),
+                ("edited.txt".to_string(), true, true),
+                ("same.txt".to_string(), false, true),
+            ]
+        );
+        assert!(recoverable(&dir.join("missing")).is_empty());
+    }
+
+    #[test]
+    fn encrypted_documents_never_get_a_draft() {
+        let dir = scratch::dir("drafts-encrypted");
+        let enc = RpadEncryption::new(8, 1, 1);
+        encryption::remember(&enc, "hunter2").unwrap();
+        let mut archive = RpadArchive::new("Diary");
+        archive.manifest.encryption = Some(enc.clone());
+        let path = dir.join("diary.rpad");
+        archive.save(&path).unwrap();
+        encryption::forget(&enc);
+
+        let file = dir.join(DRAFTS_DIR).join("diary.json");
+        store(&file, &record(&path, None, 1)).unwrap();
+        assert!(!file.exists());
+
+        // A plain document does get one
+        let plain = dir.join("plain.txt");
+        fs::write(&plain, "a").unwrap();
+        store(&file, &record(&plain, None, 1)).unwrap();
+        assert_eq!(read_record(&file).unwrap().content, "<p>unsaved</p>");
+    }
+}
@@ -0,0 +1,297 @@
+// Crash-safe replacement of document files. Content goes to a uniquely named
+// temp file next to the target and is fsynced, the temp file is renamed over
+// the target, and the directory is fsynced so the rename itself survives a
+// power loss. The target is never deleted first, so at every point either the
+// old or the new version is on disk under its name.
+
+use ignore::WalkBuilder;
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::error::{during, RosePadError};
+use crate::paths;
+use crate::scan::{skip_dir_name, DEFAULT_MAX_DEPTH};
+
+static TEMP_SEQ: AtomicU64 = AtomicU64::new(0);
+
+// `.<name>.tmp-<pid>-<seq>`. Builds before this module used `.<name>.tmp[N]`
+// for .rpad files, which `target_of_temp` understands too.
+fn temp_path(target: &Path) -> Result<PathBuf, RosePadError> {
+    let no_name = || RosePadError::invalid_path(target, "does not name a file");
+    let parent = target.parent().ok_or_else(no_name)?;
+    let name = target
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .ok_or_else(no_name)?;
+    let seq = TEMP_SEQ.fetch_add(1, Ordering::Relaxed);
+    Ok(parent.join(format!(".{name}.tmp-{}-{seq}", std::process::id())))
+}
+
+/// A leftover temp file of ours.
+#[derive(Debug, PartialEq)]
+struct TempFile {
+    /// The file it was meant to replace.
+    target: PathBuf,
+    /// Written by a build that deleted the target before renaming, so with the
+    /// target missing it may be the only copy of the document.
+    legacy: bool,
+}
+
+fn target_of_temp(path: &Path) -> Option<TempFile> {
+    let name = path.file_name()?.to_str()?.strip_prefix('.')?;
+    let (target, suffix) = name.rsplit_once(".tmp")?;
+    let legacy = !suffix.starts_with('-');
+    let ours = if let Some(rest) = suffix.strip_prefix('-') {
+        let (pid, seq) = rest.split_once('-')?;
+        pid.parse::<u32>().is_ok() && seq.parse::<u64>().is_ok()
+    } else {
+        // The legacy name is generic enough that a user could have such a
+        // file, so only trust it next to an .rpad
+        suffix.chars().all(|c| c.is_ascii_digit()) && target.to_ascii_lowercase().ends_with(".rpad")
+    };
+    (ours && !target.is_empty()).then(|| TempFile {
+        target: path.with_file_name(target),
+        legacy,
+    })
+}
+
+// Temp files of this process may belong to a save that is still running
+fn is_own_temp(path: &Path) -> bool {
+    let marker = format!(".tmp-{}-", std::process::id());
+    path.file_name()
+        .and_then(|s| s.to_str())
+        .is_some_and(|n| n.contains(&marker))
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+// Directories cannot be opened for syncing on Windows; NTFS journals the rename
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(windows)]
+fn replace(from: &Path, to: &Path) -> io::Result<()> {
+    // std's rename replaces existing files on Windows too, but fails while
+    // another process (indexer, antivirus, sync client) briefly holds the target
+    let mut attempt = 0;
+    loop {
+        match fs::rename(from, to) {
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied && attempt < 5 => {
+                attempt += 1;
+                std::thread::sleep(std::time::Duration::from_millis(20 << attempt));
+            }
+            res => return res,
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn replace(from: &Path, to: &Path) -> io::Result<()> {
+    fs::rename(from, to)
+}
+
+/// Atomically replace (or create) `path` with `bytes`.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), RosePadError> {
+    write_atomic_with(path, |f| f.write_all(bytes))
+}
+
+/// Like `write_atomic`, for content streamed into the file by `fill`.
+pub(crate) fn write_atomic_with(
+    path: &Path,
+    fill: impl FnOnce(&mut fs::File) -> io::Result<()>,
+) -> Result<(), RosePadError> {
+    let tmp = temp_path(path)?;
+    let written = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&tmp)
+        .and_then(|mut f| {
+            fill(&mut f)?;
+            f.sync_all()
+        })
+        .and_then(|_| replace(&tmp, path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(RosePadError::io(e, path));
+    }
+    if let Some(parent) = path.parent() {
+        let _ = sync_dir(parent);
+    }
+    Ok(())
+}
+
+/// Clean up after writes interrupted by a crash somewhere under `root`. Temp
+/// files are removed, except a legacy one whose target is missing (those builds
+/// deleted the target before renaming), which is moved into place. A current
+/// temp file never stands in for a missing target: it only exists then because
+/// the first save of a new file was cut short, and may be truncated.
+/// Returns the paths that were restored.
+pub(crate) fn recover_temp_files(root: &Path) -> Vec<String> {
+    let walker = WalkBuilder::new(root)
+        .standard_filters(false)
+        .follow_links(false)
+        .max_depth(Some(DEFAULT_MAX_DEPTH + 1))
+        .filter_entry(|e| {
+            let is_dir = e.file_type().is_some_and(|t| t.is_dir());
+            !(is_dir && e.depth() > 0 && skip_dir_name(&e.file_name().to_string_lossy()))
+        })
+        .build();
+    let mut restored = Vec::new();
+    for entry in walker.flatten() {
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let Some(temp) = target_of_temp(entry.path()) else {
+            continue;
+        };
+        if is_own_temp(entry.path()) {
+            continue;
+        }
+        if !temp.legacy || temp.target.exists() {
+            let _ = fs::remove_file(entry.path());
+        } else if replace(entry.path(), &temp.target).is_ok() {
+            restored.push(temp.target.to_string_lossy().to_string());
+        }
+    }
+    restored
+}
+
+/// Run once per session before the workspace is scanned.
+#[tauri::command]
+pub async fn recover_interrupted_writes(
+    app: tauri::AppHandle,
+    workspace_root: String,
+) -> Result<Vec<String>, RosePadError> {
+    during("recover_interrupted_writes", async move {
+        let root = Path::new(&workspace_root);
+        if !root.is_dir() {
+            return Err(RosePadError::not_a_directory(root));
+        }
+        paths::workspace_root(&app, &workspace_root)?;
+        Ok(recover_temp_files(root))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::scratch::{self, Scratch};
+
+    fn scratch(name: &str) -> Scratch {
+        scratch::dir(&format!("durable-{name}"))
+    }
+
+    // A pid that is not this process, as left behind by a crashed session
+    fn other_pid() -> u32 {
+        std::process::id().wrapping_add(1)
+    }
+
+    #[test]
+    fn temp_names() {
+        let dir = Path::new("/w");
+        let temp = |name: &str| target_of_temp(&dir.join(name));
+        assert_eq!(
+            temp(".notes.txt.tmp-123-4"),
+            Some(TempFile {
+                target: dir.join("notes.txt"),
+                legacy: false
+            })
+        );
+        assert_eq!(
+            temp(".a.tmp.rpad.tmp-1-0"),
+            Some(TempFile {
+                target: dir.join("a.tmp.rpad"),
+                legacy: false
+            })
+        );
+        for legacy in [".doc.rpad.tmp", ".doc.rpad.tmp2", ".Doc.RPAD.tmp17"] {
+            let found = temp(legacy).unwrap();
+            assert!(found.legacy, "{legacy}");
+            assert_eq!(
+                found.target.file_name().unwrap().to_ascii_lowercase(),
+                "doc.rpad"
+            );
+        }
+        for other in [
+            "notes.txt.tmp-1-2",
+            ".notes.txt.tmp",
+            ".notes.txt.tmp3",
+            ".notes.txt.tmp-x-2",
+            ".notes.txt.tmp-1",
+            ".notes.txt.tmp-1-2-3",
+            ".doc.rpad.tmpx",
+            "..tmp-1-2",
+            ".tmp",
+        ] {
+            assert_eq!(temp(other), None, "{other}");
+        }
+    }
+
+    #[test]
+    fn writes_replace_and_leave_no_temp() {
+        let dir = scratch("write");
+        let file = dir.join("doc.txt");
+        write_atomic(&file, b"one").unwrap();
+        write_atomic(&file, b"two").unwrap();
+        assert_eq!(fs::read(&file).unwrap(), b"two");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn recovery_with_and_without_target() {
+        let dir = scratch("recover");
+        let pid = other_pid();
+        let write = |name: &str, body: &str| fs::write(dir.join(name), body).unwrap();
+        // Legacy temp, target deleted before the rename: the only copy
+        write(".lost.rpad.tmp", "lost");
+        // Legacy temp next to its target: stale
+        write("kept.rpad", "kept");
+        write(".kept.rpad.tmp1", "stale");
+        // Current temp from a first save that never finished
+        write(&format!(".new.txt.tmp-{pid}-0"), "trunc");
+        // Current temp next to its target: stale
+        write("old.txt", "old");
+        write(&format!(".old.txt.tmp-{pid}-1"), "half");
+        // A save of this process may still be running
+        let own = format!(".busy.txt.tmp-{}-9", std::process::id());
+        write(&own, "busy");
+        // Not ours
+        write(".notes.tmp", "user file");
+
+        let restored = recover_temp_files(&dir);
+        assert_eq!(
+            restored,
+            vec![dir.join("lost.rpad").to_string_lossy().to_string()]
+        );
+        assert_eq!(fs::read_to_string(dir.join("lost.rpad")).unwrap(), "lost");
+        assert_eq!(fs::read_to_string(dir.join("kept.rpad")).unwrap(), "kept");
+        assert_eq!(fs::read_to_string(dir.join("old.txt")).unwrap(), "old");
+        assert!(!dir.join("new.txt").exists());
+        let mut left: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(
+            left,
+            [
+                own.as_str(),
+                ".notes.tmp",
+                "kept.rpad",
+                "lost.rpad",
+                "old.txt"
+            ]
+        );
+    }
+}
@@ -0,0 +1,363 @@
+// Optional passphrase encryption of .rpad documents. The passphrase is
+// stretched with Argon2id into a 256-bit key and every entry except the
+// manifest is sealed with XChaCha20-Poly1305 under a fresh nonce. The entry
+// name and the encryption parameters are the associated data, so sealed
+// entries cannot be swapped around and the parameters cannot be edited
+// undetected. The manifest stays readable (title, version) and records the
+// cipher and KDF parameters under `encryption`. Keys of documents unlocked this
+// session live in memory only, looked up by the document's salt, and are
+// wiped when the document is locked.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path, sync::Mutex};
+use tauri::AppHandle;
+use zeroize::Zeroizing;
+
+use crate::error::{during, RosePadError};
+use crate::paths;
+use crate::rpad::{self, RpadArchive};
+use crate::{drafts, history};
+
+const CIPHER: &str = "xchacha20poly1305";
+const KDF: &str = "argon2id";
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+// Defaults for newly encrypted documents (64 MiB, 3 passes)
+const DEFAULT_MEMORY_KIB: u32 = 64 * 1024;
+const DEFAULT_ITERATIONS: u32 = 3;
+const DEFAULT_PARALLELISM: u32 = 1;
+// Parameters come from the file, so refuse ones that would stall or exhaust the machine
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_ITERATIONS: u32 = 64;
+const MAX_PARALLELISM: u32 = 16;
+
+/// The `encryption` block of an encrypted document's manifest.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RpadEncryption {
+    pub cipher: String,
+    pub kdf: String,
+    /// Hex-encoded Argon2 salt; unique per document.
+    pub salt: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+// Zeroed when dropped, e.g. when removed from `KEYS`
+type Key = Zeroizing<[u8; KEY_LEN]>;
+
+lazy_static! {
+    static ref KEYS: Mutex<HashMap<String, Key>> = Mutex::new(HashMap::new());
+}
+
+fn keys() -> std::sync::MutexGuard<'static, HashMap<String, Key>> {
+    match KEYS.lock() {
+        Ok(g) => g,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// An odd length leaves a short last pair, which fails the parse
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+impl RpadEncryption {
+    pub(crate) fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        RpadEncryption {
+            cipher: CIPHER.into(),
+            kdf: KDF.into(),
+            salt: to_hex(&salt),
+            memory_kib,
+            iterations,
+            parallelism,
+        }
+    }
+
+    fn derive_key(&self, passphrase: &str) -> Result<Key, String> {
+        if self.cipher != CIPHER || self.kdf != KDF {
+            return Err(format!(
+                "unsupported encryption ({} with {})",
+                self.cipher, self.kdf
+            ));
+        }
+        if self.memory_kib > MAX_MEMORY_KIB
+            || self.iterations > MAX_ITERATIONS
+            || self.parallelism > MAX_PARALLELISM
+        {
+            return Err("unsupported key derivation parameters".into());
+        }
+        let salt = from_hex(&self.salt).ok_or_else(|| "invalid salt".to_string())?;
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LEN),
+        )
+        .map_err(|e| e.to_string())?;
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| e.to_string())?;
+        Ok(key)
+    }
+
+    // What a sealed entry is bound to besides its key: its name and every
+    // parameter recorded in the manifest
+    fn associated_data(&self, name: &str) -> Vec<u8> {
+        format!(
+            "{name}\0{}\0{}\0{}\0{}\0{}\0{}",
+            self.cipher, self.kdf, self.salt, self.memory_kib, self.iterations, self.parallelism
+        )
+        .into_bytes()
+    }
+}
+
+/// Derive the key for `passphrase` and keep it for the rest of the session.
+pub(crate) fn remember(encryption: &RpadEncryption, passphrase: &str) -> Result<(), String> {
+    let key = encryption.derive_key(passphrase)?;
+    keys().insert(encryption.salt.clone(), key);
+    Ok(())
+}
+
+pub(crate) fn forget(encryption: &RpadEncryption) {
+    keys().remove(&encryption.salt);
+}
+
+pub(crate) fn is_unlocked(encryption: &RpadEncryption) -> bool {
+    keys().contains_key(&encryption.salt)
+}
+
+fn cipher_for(encryption: &RpadEncryption) -> Result<XChaCha20Poly1305, RosePadError> {
+    let keys = keys();
+    let key = keys
+        .get(&encryption.salt)
+        .ok_or_else(RosePadError::sealed)?;
+    Ok(XChaCha20Poly1305::new(key.as_ref().into()))
+}
+
+/// Encrypt the content of entry `name`; the nonce is stored in front.
+pub(crate) fn seal(
+    encryption: &RpadEncryption,
+    name: &str,
+    data: &[u8],
+) -> Result<Vec<u8>, RosePadError> {
+    let cipher = cipher_for(encryption)?;
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let sealed = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: data,
+                aad: &encryption.associated_data(name),
+            },
+        )
+        .map_err(|_| RosePadError::failed(format!("failed to encrypt {name}")))?;
+    let mut out = nonce.to_vec();
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+pub(crate) fn unseal(
+    encryption: &RpadEncryption,
+    name: &str,
+    data: &[u8],
+) -> Result<Vec<u8>, RosePadError> {
+    let cipher = cipher_for(encryption)?;
+    if data.len() < NONCE_LEN {
+        return Err(RosePadError::failed(format!("{name} is truncated")));
+    }
+    let (nonce, sealed) = data.split_at(NONCE_LEN);
+    cipher
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: sealed,
+                aad: &encryption.associated_data(name),
+            },
+        )
+        .map_err(|_| {
+            RosePadError::failed(format!(
+                "{name} could not be decrypted; wrong passphrase or damaged file"
+            ))
+        })
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionStatusDto {
+    pub encrypted: bool,
+    pub unlocked: bool,
+}
+
+fn encryption_of(path: &Path) -> Result<RpadEncryption, String> {
+    rpad::read_encryption(path).ok_or_else(|| "document is not encrypted".to_string())
+}
+
+#[tauri::command]
+pub async fn rpad_encryption_status(
+    app: AppHandle,
+    path: String,
+) -> Result<EncryptionStatusDto, RosePadError> {
+    during("rpad_encryption_status", async move {
+        paths::document(&app, &path)?;
+        Ok(match rpad::read_encryption(Path::new(&path)) {
+            Some(e) => EncryptionStatusDto {
+                encrypted: true,
+                unlocked: is_unlocked(&e),
+            },
+            None => EncryptionStatusDto {
+                encrypted: false,
+                unlocked: true,
+            },
+        })
+    })
+    .await
+}
+
+/// Encrypt an .rpad with `passphrase`. The document stays unlocked for the
+/// session. Its plaintext history and drafts are deleted.
+#[tauri::command]
+pub async fn encrypt_rpad(
+    app: AppHandle,
+    path: String,
+    passphrase: String,
+) -> Result<(), RosePadError> {
+    let passphrase = Zeroizing::new(passphrase);
+    during("encrypt_rpad", async move {
+        paths::document(&app, &path)?;
+        if passphrase.is_empty() {
+            return Err("passphrase must not be empty".into());
+        }
+        let p = Path::new(&path);
+        let mut archive = RpadArchive::open(p)?;
+        if archive.manifest.encryption.is_some() {
+            return Err("document is already encrypted".into());
+        }
+        let encryption =
+            RpadEncryption::new(DEFAULT_MEMORY_KIB, DEFAULT_ITERATIONS, DEFAULT_PARALLELISM);
+        remember(&encryption, &passphrase)?;
+        archive.manifest.encryption = Some(encryption);
+        archive.save(p)?;
+        let _ = history::forget(&app, &path);
+        let _ = drafts::discard(&app, &path);
+        Ok(())
+    })
+    .await
+}
+
+/// Check `passphrase` against the document and keep its key for the session.
+#[tauri::command]
+pub async fn unlock_rpad(
+    app: AppHandle,
+    path: String,
+    passphrase: String,
+) -> Result<(), RosePadError> {
+    let passphrase = Zeroizing::new(passphrase);
+    during("unlock_rpad", async move {
+        paths::document(&app, &path)?;
+        let p = Path::new(&path);
+        let encryption = encryption_of(p)?;
+        if is_unlocked(&encryption) {
+            return Ok(());
+        }
+        remember(&encryption, &passphrase)?;
+        if let Err(e) = RpadArchive::open(p) {
+            forget(&encryption);
+            return Err(e);
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn lock_rpad(app: AppHandle, path: String) -> Result<(), RosePadError> {
+    during("lock_rpad", async move {
+        paths::document(&app, &path)?;
+        forget(&encryption_of(Path::new(&path))?);
+        Ok(())
+    })
+    .await
+}
+
+/// Store an unlocked document in the clear again.
+#[tauri::command]
+pub async fn decrypt_rpad(app: AppHandle, path: String) -> Result<(), RosePadError> {
+    during("decrypt_rpad", async move {
+        paths::document(&app, &path)?;
+        let p = Path::new(&path);
+        let mut archive = RpadArchive::open(p)?;
+        let Some(encryption) = archive.manifest.encryption.take() else {
+            return Err("document is not encrypted".into());
+        };
+        archive.save(p)?;
+        forget(&encryption);
+        Ok(())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_bound_to_their_name_and_parameters() {
+        let enc = RpadEncryption::new(8, 1, 1);
+        remember(&enc, "hunter2").unwrap();
+        let sealed = seal(&enc, "document.html", b"secret").unwrap();
+        assert_eq!(unseal(&enc, "document.html", &sealed).unwrap(), b"secret");
+        assert!(unseal(&enc, "assets/a.png", &sealed).is_err());
+
+        // Same salt, so the cached key is found, but the parameters were edited
+        let edits: [fn(&mut RpadEncryption); 4] = [
+            |e| e.memory_kib = 16,
+            |e| e.iterations = 2,
+            |e| e.parallelism = 2,
+            |e| e.kdf = "argon2i".into(),
+        ];
+        for edit in edits {
+            let mut tampered = enc.clone();
+            edit(&mut tampered);
+            assert!(unseal(&tampered, "document.html", &sealed).is_err());
+        }
+        forget(&enc);
+    }
+
+    #[test]
+    fn locking_drops_the_key() {
+        let enc = RpadEncryption::new(8, 1, 1);
+        remember(&enc, "hunter2").unwrap();
+        assert!(is_unlocked(&enc));
+        let sealed = seal(&enc, "document.html", b"secret").unwrap();
+        forget(&enc);
+        assert!(!is_unlocked(&enc));
+        assert!(matches!(
+            unseal(&enc, "document.html", &sealed),
+            Err(RosePadError::Locked { .. })
+        ));
+        assert!(matches!(
+            seal(&enc, "document.html", b"x"),
+            Err(RosePadError::Locked { .. })
+        ));
+    }
+}
@@ -0,0 +1,359 @@
+// The error commands reject with. It serializes as
+// `{ code, message, path, operation, ...details }`: the UI branches on `code`
+// and shows `message` as is. Plain `String` errors from helpers that have no
+// better code convert into `failed`; there is deliberately no conversion back,
+// so a typed error cannot lose its code on the way to the UI.
+
+use serde::Serialize;
+use std::{fmt, future::Future, io, path::Path};
+
+use crate::workspace::DiskVersionDto;
+
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorContext {
+    /// Meant for the user; names the file rather than its full path.
+    pub message: String,
+    pub path: Option<String>,
+    /// The command that failed, e.g. `rename_project`.
+    pub operation: Option<&'static str>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "code", rename_all = "camelCase")]
+pub enum RosePadError {
+    NotFound(ErrorContext),
+    NotAFile(ErrorContext),
+    NotADirectory(ErrorContext),
+    AlreadyExists(ErrorContext),
+    PermissionDenied(ErrorContext),
+    /// The path resolves outside every registered workspace.
+    OutsideWorkspace(ErrorContext),
+    /// A root argument is not a registered workspace.
+    NotAWorkspace(ErrorContext),
+    InvalidName(ErrorContext),
+    /// Relative, contains `..` or a NUL byte, or ends in a broken symlink.
+    InvalidPath(ErrorContext),
+    UnsupportedType(ErrorContext),
+    /// The file changed on disk since the expected version; nothing was written.
+    Conflict {
+        #[serde(flatten)]
+        context: ErrorContext,
+        disk: DiskVersionDto,
+    },
+    /// The document is encrypted and was not unlocked this session; see `unlock_rpad`.
+    Locked {
+        #[serde(flatten)]
+        context: ErrorContext,
+        title: Option<String>,
+    },
+    RpcDisabled(ErrorContext),
+    /// Discord is not running or refused the connection.
+    RpcUnavailable(ErrorContext),
+    Io(ErrorContext),
+    Failed(ErrorContext),
+}
+
+// File name for messages, falling back to the whole path
+fn name_of(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .to_string()
+}
+
+fn at(path: &Path, message: String) -> ErrorContext {
+    ErrorContext {
+        message,
+        path: Some(path.to_string_lossy().to_string()),
+        operation: None,
+    }
+}
+
+fn plain(message: impl Into<String>) -> ErrorContext {
+    ErrorContext {
+        message: message.into(),
+        ..ErrorContext::default()
+    }
+}
+
+impl RosePadError {
+    pub fn not_found(path: &Path) -> Self {
+        Self::NotFound(at(
+            path,
+            format!("\"{}\" could not be found", name_of(path)),
+        ))
+    }
+
+    pub fn not_a_file(path: &Path) -> Self {
+        Self::NotAFile(at(path, format!("\"{}\" is not a file", name_of(path))))
+    }
+
+    pub fn not_a_directory(path: &Path) -> Self {
+        Self::NotADirectory(at(path, format!("\"{}\" is not a folder", name_of(path))))
+    }
+
+    pub fn already_exists(path: &Path) -> Self {
+        Self::AlreadyExists(at(path, format!("\"{}\" already exists", name_of(path))))
+    }
+
+    pub fn permission_denied(path: &Path) -> Self {
+        Self::PermissionDenied(at(
+            path,
+            format!("RosePad is not allowed to access \"{}\"", name_of(path)),
+        ))
+    }
+
+    pub fn outside_workspace(path: &Path) -> Self {
+        Self::OutsideWorkspace(at(
+            path,
+            format!("\"{}\" is outside the open workspaces", name_of(path)),
+        ))
+    }
+
+    pub fn not_a_workspace(path: &Path) -> Self {
+        Self::NotAWorkspace(at(
+            path,
+            format!("\"{}\" is not an open workspace", name_of(path)),
+        ))
+    }
+
+    /// No registered workspace has this id.
+    pub fn unknown_workspace(id: &str) -> Self {
+        Self::NotAWorkspace(plain(format!("There is no workspace with the id {id}")))
+    }
+
+    pub fn name_taken(name: &str) -> Self {
+        Self::AlreadyExists(plain(format!("The name \"{name}\" is already taken")))
+    }
+
+    pub fn invalid_name(name: &str) -> Self {
+        Self::InvalidName(plain(format!("\"{name}\" cannot be used as a name")))
+    }
+
+    pub fn invalid_path(path: &Path, reason: &str) -> Self {
+        Self::InvalidPath(at(path, format!("\"{}\" {reason}", path.display())))
+    }
+
+    pub fn unsupported_type(path: &Path) -> Self {
+        Self::UnsupportedType(at(
+            path,
+            format!("\"{}\" is not a supported file type", name_of(path)),
+        ))
+    }
+
+    pub fn conflict(path: &Path, disk: DiskVersionDto) -> Self {
+        Self::Conflict {
+            context: at(path, "The file was changed by another program".into()),
+            disk,
+        }
+    }
+
+    pub fn locked(path: &Path, title: Option<String>) -> Self {
+        Self::Locked {
+            context: at(path, "This document is encrypted and locked".into()),
+            title,
+        }
+    }
+
+    /// Like `locked`, for an archive read from memory; `rpad` fills in the
+    /// path when the archive came from a file.
+    pub fn sealed() -> Self {
+        Self::Locked {
+            context: plain("This document is encrypted and locked"),
+            title: None,
+        }
+    }
+
+    pub fn rpc_disabled() -> Self {
+        Self::RpcDisabled(plain("Discord Rich Presence is disabled"))
+    }
+
+    pub fn rpc_unavailable(detail: impl fmt::Display) -> Self {
+        Self::RpcUnavailable(plain(format!("Could not reach Discord: {detail}")))
+    }
+
+    pub fn failed(message: impl Into<String>) -> Self {
+        Self::Failed(plain(message))
+    }
+
+    /// Map an I/O error on `path` to the matching code.
+    pub fn io(err: io::Error, path: &Path) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => Self::not_found(path),
+            io::ErrorKind::PermissionDenied => Self::permission_denied(path),
+            io::ErrorKind::AlreadyExists => Self::already_exists(path),
+            _ => Self::Io(at(
+                path,
+                format!("Could not access \"{}\": {err}", name_of(path)),
+            )),
+        }
+    }
+
+    pub fn context(&self) -> &ErrorContext {
+        match self {
+            Self::Conflict { context, .. } | Self::Locked { context, .. } => context,
+            Self::NotFound(c)
+            | Self::NotAFile(c)
+            | Self::NotADirectory(c)
+            | Self::AlreadyExists(c)
+            | Self::PermissionDenied(c)
+            | Self::OutsideWorkspace(c)
+            | Self::NotAWorkspace(c)
+            | Self::InvalidName(c)
+            | Self::InvalidPath(c)
+            | Self::UnsupportedType(c)
+            | Self::RpcDisabled(c)
+            | Self::RpcUnavailable(c)
+            | Self::Io(c)
+            | Self::Failed(c) => c,
+        }
+    }
+
+    fn context_mut(&mut self) -> &mut ErrorContext {
+        match self {
+            Self::Conflict { context, .. } | Self::Locked { context, .. } => context,
+            Self::NotFound(c)
+            | Self::NotAFile(c)
+            | Self::NotADirectory(c)
+            | Self::AlreadyExists(c)
+            | Self::PermissionDenied(c)
+            | Self::OutsideWorkspace(c)
+            | Self::NotAWorkspace(c)
+            | Self::InvalidName(c)
+            | Self::InvalidPath(c)
+            | Self::UnsupportedType(c)
+            | Self::RpcDisabled(c)
+            | Self::RpcUnavailable(c)
+            | Self::Io(c)
+            | Self::Failed(c) => c,
+        }
+    }
+
+    /// Record the operation the error came from, unless one is set already.
+    pub fn during(mut self, operation: &'static str) -> Self {
+        self.context_mut().operation.get_or_insert(operation);
+        self
+    }
+}
+
+impl fmt::Display for RosePadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.context().message)
+    }
+}
+
+impl std::error::Error for RosePadError {}
+
+impl From<String> for RosePadError {
+    fn from(message: String) -> Self {
+        Self::failed(message)
+    }
+}
+
+impl From<&str> for RosePadError {
+    fn from(message: &str) -> Self {
+        Self::failed(message)
+    }
+}
+
+/// Run a command body and tag whatever it fails with as `operation`.
+pub(crate) async fn during<T>(
+    operation: &'static str,
+    body: impl Future<Output = Result<T, RosePadError>>,
+) -> Result<T, RosePadError> {
+    body.await.map_err(|e| e.during(operation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn to_json(err: &RosePadError) -> Value {
+        serde_json::to_value(err).unwrap()
+    }
+
+    #[test]
+    fn code_tag_and_context_fields() {
+        let err = RosePadError::not_found(Path::new("/ws/notes.rpad")).during("write_rpad_html");
+        assert_eq!(
+            to_json(&err),
+            json!({
+                "code": "notFound",
+                "message": "\"notes.rpad\" could not be found",
+                "path": "/ws/notes.rpad",
+                "operation": "write_rpad_html",
+            })
+        );
+        // The first operation sticks when commands nest
+        assert_eq!(
+            err.during("other").context().operation,
+            Some("write_rpad_html")
+        );
+
+        let v = to_json(&RosePadError::failed("boom"));
+        assert_eq!(v["code"], "failed");
+        assert_eq!(v["path"], Value::Null);
+        assert_eq!(v["operation"], Value::Null);
+        assert_eq!(
+            to_json(&RosePadError::rpc_disabled())["code"],
+            "rpcDisabled"
+        );
+        assert_eq!(
+            to_json(&RosePadError::outside_workspace(Path::new("/x")))["code"],
+            "outsideWorkspace"
+        );
+    }
+
+    #[test]
+    fn struct_variants_flatten_their_context() {
+        let disk = DiskVersionDto {
+            last_modified_ms: 5,
+            hash: "h".into(),
+            content: "on disk".into(),
+        };
+        let v = to_json(&RosePadError::conflict(Path::new("/ws/a.txt"), disk));
+        assert_eq!(v["code"], "conflict");
+        assert_eq!(v["path"], "/ws/a.txt");
+        assert_eq!(v["disk"]["lastModifiedMs"], 5);
+        assert_eq!(v["disk"]["content"], "on disk");
+
+        let v = to_json(&RosePadError::locked(
+            Path::new("/ws/s.rpad"),
+            Some("Secret".into()),
+        ));
+        assert_eq!(v["code"], "locked");
+        assert_eq!(v["title"], "Secret");
+        assert_eq!(v["message"], "This document is encrypted and locked");
+    }
+
+    #[test]
+    fn io_errors_keep_their_kind() {
+        let p = Path::new("/ws/a.txt");
+        let code = |kind| to_json(&RosePadError::io(io::Error::from(kind), p))["code"].clone();
+        assert_eq!(code(io::ErrorKind::NotFound), "notFound");
+        assert_eq!(code(io::ErrorKind::PermissionDenied), "permissionDenied");
+        assert_eq!(code(io::ErrorKind::AlreadyExists), "alreadyExists");
+        assert_eq!(code(io::ErrorKind::Other), "io");
+        assert_eq!(
+            to_json(&RosePadError::io(io::Error::from(io::ErrorKind::Other), p))["path"],
+            "/ws/a.txt"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn denied_writes_surface_as_permission_denied() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = crate::scratch::dir("error-ro");
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o500)).unwrap();
+        let target = dir.join("a.txt");
+        let written = crate::durable::write_atomic(&target, b"x");
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).unwrap();
+        // Root ignores the mode bits
+        if let Err(err) = written {
+            assert_eq!(to_json(&err)["code"], "permissionDenied", "{err}");
+        }
+    }
+}
@@ -0,0 +1,460 @@
+// Local version history. Every save stores the document's text (the HTML of an
+// .rpad; attachments are not versioned) as a blake3-addressed object under
+// `<config>/history`, plus a row in the index's `history_snapshots` table.
+// Identical content is stored once, and a save that matches the latest
+// snapshot of the same document is not recorded again.
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tauri::{AppHandle, Manager};
+
+use crate::attachments;
+use crate::diff::{self, is_rpad, read_document, DiffHunkDto, DiffMode};
+use crate::durable;
+use crate::error::{during, RosePadError};
+use crate::index::with_index;
+use crate::paths;
+use crate::workspace::{save_rpad_html, save_text};
+
+const HISTORY_DIR: &str = "history";
+const HOUR_MS: i64 = 60 * 60 * 1000;
+const DAY_MS: i64 = 24 * HOUR_MS;
+// Every save is kept for an hour, then the last one of each hour for a day,
+// then the last one of each day for a month
+const KEEP_ALL_MS: i64 = HOUR_MS;
+const KEEP_HOURLY_MS: i64 = DAY_MS;
+const KEEP_DAILY_MS: i64 = 30 * DAY_MS;
+
+const SNAPSHOT_COLUMNS: &str = "id,path,hash,saved_at_ms,size";
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotDto {
+    pub id: i64,
+    pub path: String,
+    pub hash: String,
+    pub saved_at_ms: i64,
+    pub size: u64,
+}
+
+fn snapshot_from_row(r: &Row) -> rusqlite::Result<SnapshotDto> {
+    Ok(SnapshotDto {
+        id: r.get(0)?,
+        path: r.get(1)?,
+        hash: r.get(2)?,
+        saved_at_ms: r.get(3)?,
+        size: r.get::<_, i64>(4)? as u64,
+    })
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn history_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    Ok(dir.join(HISTORY_DIR))
+}
+
+fn object_path(dir: &Path, hash: &str) -> PathBuf {
+    dir.join(&hash[..2]).join(hash)
+}
+
+fn write_object(dir: &Path, hash: &str, content: &str) -> Result<(), RosePadError> {
+    let path = object_path(dir, hash);
+    if path.exists() {
+        return Ok(());
+    }
+    let parent = path
+        .parent()
+        .ok_or_else(|| RosePadError::invalid_path(&path, "has no parent folder"))?;
+    fs::create_dir_all(parent).map_err(|e| RosePadError::io(e, parent))?;
+    // Objects are immutable, so a half-written one must never take the final name
+    durable::write_atomic(&path, content.as_bytes())
+}
+
+fn read_object(dir: &Path, hash: &str) -> Result<String, String> {
+    fs::read_to_string(object_path(dir, hash)).map_err(|e| format!("snapshot content missing: {e}"))
+}
+
+/// Ids of the snapshots the retention policy drops. `snapshots` is `(id, saved_at_ms)`,
+/// newest first; the newest one is always kept.
+pub(crate) fn expired(snapshots: &[(i64, i64)], now: i64) -> Vec<i64> {
+    let mut buckets = HashSet::new();
+    let mut out = Vec::new();
+    for &(id, saved_at) in snapshots.iter().skip(1) {
+        let age = now - saved_at;
+        let bucket = if age < KEEP_ALL_MS {
+            continue;
+        } else if age < KEEP_HOURLY_MS {
+            (0, saved_at / HOUR_MS)
+        } else if age < KEEP_DAILY_MS {
+            (1, saved_at / DAY_MS)
+        } else {
+            out.push(id);
+            continue;
+        };
+        // Newest first, so the first snapshot seen in a bucket is the one to keep
+        if !buckets.insert(bucket) {
+            out.push(id);
+        }
+    }
+    out
+}
+
+// Apply the retention policy to one document and return the hashes that no
+// snapshot references any more
+fn prune(conn: &Connection, path: &str, now: i64) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id,saved_at_ms,hash FROM history_snapshots WHERE path=?1 ORDER BY saved_at_ms DESC, id DESC")
+        .map_err(|e| e.to_string())?;
+    let rows: Vec<(i64, i64, String)> = stmt
+        .query_map([path], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    let times: Vec<(i64, i64)> = rows.iter().map(|(id, at, _)| (*id, *at)).collect();
+    let dropped: HashSet<i64> = expired(&times, now).into_iter().collect();
+    let mut orphans = Vec::new();
+    for (id, _, hash) in rows.iter().filter(|r| dropped.contains(&r.0)) {
+        conn.execute("DELETE FROM history_snapshots WHERE id=?1", [id])
+            .map_err(|e| e.to_string())?;
+        let used: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM history_snapshots WHERE hash=?1)",
+                [hash],
+                |r| r.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if !used && !orphans.contains(hash) {
+            orphans.push(hash.clone());
+        }
+    }
+    Ok(orphans)
+}
+
+// Apply the retention policy to every document with a history, including ones
+// that are never saved again
+fn prune_everything(conn: &Connection, now: i64) -> Result<Vec<String>, String> {
+    let paths: Vec<String> = {
+        let mut stmt = conn
+            .prepare("SELECT DISTINCT path FROM history_snapshots")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |r| r.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?;
+        rows
+    };
+    let mut orphans = Vec::new();
+    for path in paths {
+        for hash in prune(conn, &path, now)? {
+            if !orphans.contains(&hash) {
+                orphans.push(hash);
+            }
+        }
+    }
+    Ok(orphans)
+}
+
+/// Apply the retention policy to the whole history. `record` only prunes the
+/// document being saved, so this runs once at startup.
+pub(crate) fn prune_all(app: &AppHandle) -> Result<(), RosePadError> {
+    let dir = history_dir(app)?;
+    let orphans = with_index(app, |conn| prune_everything(conn, now_ms()))?;
+    for h in orphans {
+        let _ = fs::remove_file(object_path(&dir, &h));
+    }
+    Ok(())
+}
+
+/// Snapshot `content` as the latest version of `path`. Returns `None` when it is
+/// the same as the previous snapshot.
+pub(crate) fn record(
+    app: &AppHandle,
+    path: &str,
+    content: &str,
+) -> Result<Option<SnapshotDto>, RosePadError> {
+    let hash = blake3::hash(content.as_bytes()).to_hex().to_string();
+    let dir = history_dir(app)?;
+    let now = now_ms();
+    let latest: Option<String> = with_index(app, |conn| {
+        conn.query_row(
+            "SELECT hash FROM history_snapshots WHERE path=?1 ORDER BY saved_at_ms DESC, id DESC LIMIT 1",
+            [path],
+            |r| r.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())
+    })?;
+    if latest.as_deref() == Some(hash.as_str()) {
+        return Ok(None);
+    }
+    // Written before the row that refers to it, so a row never lacks its object
+    write_object(&dir, &hash, content)?;
+    let (snapshot, orphans) = with_index(app, |conn| {
+        conn.execute(
+            "INSERT INTO history_snapshots(path,hash,saved_at_ms,size) VALUES (?1,?2,?3,?4)",
+            params![path, hash, now, content.len() as i64],
+        )
+        .map_err(|e| e.to_string())?;
+        let snapshot = SnapshotDto {
+            id: conn.last_insert_rowid(),
+            path: path.to_string(),
+            hash: hash.clone(),
+            saved_at_ms: now,
+            size: content.len() as u64,
+        };
+        Ok((snapshot, prune(conn, path, now)?))
+    })?;
+    for h in orphans {
+        let _ = fs::remove_file(object_path(&dir, &h));
+    }
+    Ok(Some(snapshot))
+}
+
+/// Drop the whole history of a document, e.g. once it is encrypted.
+pub(crate) fn forget(app: &AppHandle, path: &str) -> Result<(), String> {
+    let dir = history_dir(app)?;
+    let orphans = with_index(app, |conn| {
+        let hashes: Vec<String> = {
+            let mut stmt = conn
+                .prepare("SELECT DISTINCT hash FROM history_snapshots WHERE path=?1")
+                .map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map([path], |r| r.get(0))
+                .map_err(|e| e.to_string())?
+                .collect::<Result<_, _>>()
+                .map_err(|e| e.to_string())?;
+            rows
+        };
+        conn.execute("DELETE FROM history_snapshots WHERE path=?1", [path])
+            .map_err(|e| e.to_string())?;
+        let mut orphans = Vec::new();
+        for hash in hashes {
+            let used: bool = conn
+                .query_row(
+                    "SELECT EXISTS(SELECT 1 FROM history_snapshots WHERE hash=?1)",
+                    [&hash],
+                    |r| r.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            if !used {
+                orphans.push(hash);
+            }
+        }
+        Ok(orphans)
+    })?;
+    for h in orphans {
+        let _ = fs::remove_file(object_path(&dir, &h));
+    }
+    Ok(())
+}
+
+fn get_snapshot(app: &AppHandle, id: i64) -> Result<SnapshotDto, String> {
+    with_index(app, |conn| {
+        conn.query_row(
+            &format!("SELECT {SNAPSHOT_COLUMNS} FROM history_snapshots WHERE id=?1"),
+            [id],
+            snapshot_from_row,
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "snapshot not found".to_string())
+    })
+}
+
+pub(crate) fn snapshot_content(app: &AppHandle, id: i64) -> Result<(SnapshotDto, String), String> {
+    let snapshot = get_snapshot(app, id)?;
+    let content = read_object(&history_dir(app)?, &snapshot.hash)?;
+    Ok((snapshot, content))
+}
+
+/// Snapshots of a document, newest first.
+#[tauri::command]
+pub async fn list_history(app: AppHandle, path: String) -> Result<Vec<SnapshotDto>, RosePadError> {
+    during("list_history", async move {
+        Ok(with_index(&app, |conn| {
+            let mut stmt = conn
+                .prepare(&format!(
+                    "SELECT {SNAPSHOT_COLUMNS} FROM history_snapshots WHERE path=?1 ORDER BY saved_at_ms DESC, id DESC"
+                ))
+                .map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map([&path], snapshot_from_row)
+                .map_err(|e| e.to_string())?
+                .collect::<Result<_, _>>()
+                .map_err(|e| e.to_string());
+            rows
+        })?)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn read_snapshot(app: AppHandle, id: i64) -> Result<String, RosePadError> {
+    during("read_snapshot", async move {
+        let (snapshot, content) = snapshot_content(&app, id)?;
+        if is_rpad(Path::new(&snapshot.path)) {
+            return Ok(attachments::for_editor(&snapshot.path, &content));
+        }
+        Ok(content)
+    })
+    .await
+}
+
+/// Changes from snapshot `id` to snapshot `against`, or to the file on disk when omitted.
+#[tauri::command]
+pub async fn diff_snapshot(
+    app: AppHandle,
+    id: i64,
+    against: Option<i64>,
+) -> Result<Vec<DiffHunkDto>, RosePadError> {
+    during("diff_snapshot", async move {
+        let (snapshot, old) = snapshot_content(&app, id)?;
+        let new = match against {
+            Some(other) => snapshot_content(&app, other)?.1,
+            None => read_document(Path::new(&snapshot.path))?,
+        };
+        let mode = if is_rpad(Path::new(&snapshot.path)) {
+            DiffMode::Blocks
+        } else {
+            DiffMode::Text
+        };
+        Ok(diff::diff_content(&old, &new, mode))
+    })
+    .await
+}
+
+/// Write a snapshot back to its document. What is on disk now is snapshotted
+/// first, so a restore can itself be undone from the history.
+#[tauri::command]
+pub async fn restore_snapshot(app: AppHandle, id: i64) -> Result<(), RosePadError> {
+    during("restore_snapshot", async move {
+        let (snapshot, content) = snapshot_content(&app, id)?;
+        // Snapshots of a removed workspace outlive it in the index
+        paths::document(&app, &snapshot.path)?;
+        if let Ok(current) = read_document(Path::new(&snapshot.path)) {
+            record(&app, &snapshot.path, &current)?;
+        }
+        if is_rpad(Path::new(&snapshot.path)) {
+            save_rpad_html(&app, &snapshot.path, content, None)?;
+        } else {
+            save_text(&app, &snapshot.path, &content)?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::memory_index;
+
+    // Mid-hour and mid-day, so the buckets below do not straddle a boundary
+    const NOW: i64 = 100 * DAY_MS + 12 * HOUR_MS + 30 * MINUTE_MS;
+    const MINUTE_MS: i64 = 60 * 1000;
+
+    // Snapshots given by age, newest first, with ids counting up from 1
+    fn dropped(ages: &[i64]) -> Vec<i64> {
+        let snapshots: Vec<(i64, i64)> = ages
+            .iter()
+            .enumerate()
+            .map(|(i, age)| (i as i64 + 1, NOW - age))
+            .collect();
+        expired(&snapshots, NOW)
+    }
+
+    #[test]
+    fn the_last_hour_is_kept_in_full() {
+        assert!(dropped(&[0, MINUTE_MS, 10 * MINUTE_MS, 59 * MINUTE_MS]).is_empty());
+    }
+
+    #[test]
+    fn one_per_hour_for_a_day() {
+        let ages = [
+            0,
+            2 * HOUR_MS,
+            2 * HOUR_MS + 10 * MINUTE_MS,
+            2 * HOUR_MS + 20 * MINUTE_MS,
+            5 * HOUR_MS,
+            23 * HOUR_MS,
+        ];
+        // The newest of the hour two hours back stays
+        assert_eq!(dropped(&ages), vec![3, 4]);
+    }
+
+    #[test]
+    fn one_per_day_for_a_month() {
+        let ages = [
+            0,
+            2 * DAY_MS,
+            2 * DAY_MS + HOUR_MS,
+            2 * DAY_MS + 3 * HOUR_MS,
+            3 * DAY_MS,
+            29 * DAY_MS,
+        ];
+        assert_eq!(dropped(&ages), vec![3, 4]);
+    }
+
+    #[test]
+    fn older_than_a_month_is_dropped() {
+        assert_eq!(dropped(&[0, 31 * DAY_MS, 400 * DAY_MS]), vec![2, 3]);
+    }
+
+    #[test]
+    fn the_newest_snapshot_is_always_kept() {
+        assert!(dropped(&[90 * DAY_MS]).is_empty());
+        assert_eq!(dropped(&[90 * DAY_MS, 91 * DAY_MS]), vec![2]);
+        assert!(dropped(&[]).is_empty());
+    }
+
+    fn insert(conn: &Connection, path: &str, hash: &str, age: i64) {
+        conn.execute(
+            "INSERT INTO history_snapshots(path,hash,saved_at_ms,size) VALUES (?1,?2,?3,1)",
+            params![path, hash, NOW - age],
+        )
+        .unwrap();
+    }
+
+    fn hashes(conn: &Connection, path: &str) -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT hash FROM history_snapshots WHERE path=?1 ORDER BY saved_at_ms DESC")
+            .unwrap();
+        let rows = stmt
+            .query_map([path], |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        rows
+    }
+
+    #[test]
+    fn pruning_covers_documents_that_are_not_saved_again() {
+        let conn = memory_index();
+        insert(&conn, "/w/a.txt", "a3", 40 * DAY_MS);
+        insert(&conn, "/w/a.txt", "a2", 50 * DAY_MS);
+        insert(&conn, "/w/a.txt", "shared", 60 * DAY_MS);
+        insert(&conn, "/w/b.txt", "b1", 0);
+        insert(&conn, "/w/b.txt", "shared", 10 * DAY_MS);
+
+        let mut orphans = prune_everything(&conn, NOW).unwrap();
+        orphans.sort();
+        // "shared" is still used by b.txt, so its object stays
+        assert_eq!(orphans, vec!["a2".to_string()]);
+        assert_eq!(hashes(&conn, "/w/a.txt"), vec!["a3"]);
+        assert_eq!(hashes(&conn, "/w/b.txt"), vec!["b1", "shared"]);
+        assert!(prune_everything(&conn, NOW).unwrap().is_empty());
+    }
+}
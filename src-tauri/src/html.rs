@@ -0,0 +1,468 @@
+// Small tolerant HTML reader for the markup the editor produces (see rSchema.ts).
+// It is not a general-purpose parser: it only needs to understand the subset of
+// HTML ProseMirror serializes, while not choking on hand-edited or imported files.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Element {
+    pub tag: String,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+const VOID_TAGS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+impl Element {
+    pub fn new(tag: &str) -> Self {
+        Element {
+            tag: tag.to_string(),
+            attrs: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Value of a single inline style property, e.g. `style("text-align")`.
+    pub fn style(&self, prop: &str) -> Option<String> {
+        let style = self.attr("style")?;
+        style.split(';').find_map(|decl| {
+            let (k, v) = decl.split_once(':')?;
+            if k.trim().eq_ignore_ascii_case(prop) {
+                let v = v.trim();
+                (!v.is_empty()).then(|| v.to_string())
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        collect_text(&self.children, &mut out);
+        out
+    }
+}
+
+fn collect_text(nodes: &[Node], out: &mut String) {
+    for n in nodes {
+        match n {
+            Node::Text(t) => out.push_str(t),
+            Node::Element(e) => collect_text(&e.children, out),
+        }
+    }
+}
+
+pub fn parse(html: &str) -> Vec<Node> {
+    let mut stack: Vec<Element> = vec![Element::new("#root")];
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map(|i| &after[i + 3..]).unwrap_or("");
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map(|i| &rest[i + 1..]).unwrap_or("");
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("</") {
+            let end = after.find('>').unwrap_or(after.len());
+            let name = after[..end].trim().to_ascii_lowercase();
+            rest = after.get(end + 1..).unwrap_or("");
+            // Only close if the tag is actually open; stray end tags are dropped
+            if let Some(pos) = stack.iter().rposition(|e| e.tag == name) {
+                if pos > 0 {
+                    while stack.len() > pos {
+                        close_top(&mut stack);
+                    }
+                }
+            }
+            continue;
+        }
+        if rest.starts_with('<')
+            && rest[1..]
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic())
+        {
+            let (el, self_closing, after) = parse_start_tag(&rest[1..]);
+            rest = after;
+            let is_void = self_closing || VOID_TAGS.contains(&el.tag.as_str());
+            if is_void {
+                push_child(&mut stack, Node::Element(el));
+            } else {
+                stack.push(el);
+            }
+            continue;
+        }
+        // Text up to the next tag (a lone '<' is treated as text)
+        let next = rest
+            .char_indices()
+            .skip(1)
+            .find(|(_, c)| *c == '<')
+            .map(|(i, _)| i)
+            .unwrap_or(rest.len());
+        let text = decode_entities(&rest[..next]);
+        if !text.is_empty() {
+            push_child(&mut stack, Node::Text(text));
+        }
+        rest = &rest[next..];
+    }
+
+    while stack.len() > 1 {
+        close_top(&mut stack);
+    }
+    stack.pop().map(|root| root.children).unwrap_or_default()
+}
+
+fn push_child(stack: &mut [Element], node: Node) {
+    if let Some(top) = stack.last_mut() {
+        // Merge adjacent text so callers see one run per gap between tags
+        if let (Node::Text(t), Some(Node::Text(prev))) = (&node, top.children.last_mut()) {
+            prev.push_str(t);
+            return;
+        }
+        top.children.push(node);
+    }
+}
+
+fn close_top(stack: &mut Vec<Element>) {
+    if let Some(el) = stack.pop() {
+        push_child(stack, Node::Element(el));
+    }
+}
+
+fn parse_start_tag(s: &str) -> (Element, bool, &str) {
+    let name_end = s
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .unwrap_or(s.len());
+    let mut el = Element::new(&s[..name_end].to_ascii_lowercase());
+    let mut rest = &s[name_end..];
+    let mut self_closing = false;
+
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(after) = rest.strip_prefix("/>") {
+            self_closing = true;
+            rest = after;
+            break;
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            rest = after;
+            break;
+        }
+        if let Some(after) = rest.strip_prefix('/') {
+            rest = after;
+            continue;
+        }
+        let key_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/')
+            .unwrap_or(rest.len());
+        let key = rest[..key_end].to_ascii_lowercase();
+        rest = rest[key_end..].trim_start();
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            if let Some(q) = after.chars().next().filter(|c| *c == '"' || *c == '\'') {
+                let body = &after[1..];
+                let end = body.find(q).unwrap_or(body.len());
+                value = decode_entities(&body[..end]);
+                rest = body.get(end + 1..).unwrap_or("");
+            } else {
+                let end = after
+                    .find(|c: char| c.is_whitespace() || c == '>')
+                    .unwrap_or(after.len());
+                value = decode_entities(&after[..end]);
+                rest = &after[end..];
+            }
+        }
+        if !key.is_empty() {
+            el.attrs.push((key, value));
+        }
+    }
+    (el, self_closing, rest)
+}
+
+// Elements whose children are diffed as separate blocks. Their own start and
+// end tags become blocks too, so the pieces still join back into valid HTML.
+const CONTAINER_TAGS: &[&str] = &[
+    "ul",
+    "ol",
+    "blockquote",
+    "table",
+    "thead",
+    "tbody",
+    "tfoot",
+    "tr",
+];
+
+/// Source slices of a document's blocks in order: top-level elements, the
+/// children of lists, quotes and tables, and the tags of those containers.
+/// Whitespace between blocks is dropped. Used to diff and merge documents
+/// block by block.
+pub fn split_blocks(html: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    // Open elements inside the current block; 0 means between blocks
+    let mut depth = 0usize;
+    let mut start = 0usize;
+    let mut pos = 0usize;
+    let mut emit = |start: &mut usize, pos: usize| {
+        let block = html[*start..pos].trim();
+        if !block.is_empty() {
+            out.push(block);
+        }
+        *start = pos;
+    };
+
+    while pos < html.len() {
+        let rest = &html[pos..];
+        if let Some(after) = rest.strip_prefix("<!--") {
+            pos += after.find("-->").map(|i| i + 7).unwrap_or(rest.len());
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            pos += rest.find('>').map(|i| i + 1).unwrap_or(rest.len());
+        } else if let Some(after) = rest.strip_prefix("</") {
+            pos += after.find('>').map(|i| i + 3).unwrap_or(rest.len());
+            // A closing tag between blocks belongs to a container
+            depth = depth.saturating_sub(1);
+        } else if rest.starts_with('<')
+            && rest[1..]
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic())
+        {
+            let (el, self_closing, after) = parse_start_tag(&rest[1..]);
+            pos = html.len() - after.len();
+            let is_void = self_closing || VOID_TAGS.contains(&el.tag.as_str());
+            let opens_container = depth == 0 && CONTAINER_TAGS.contains(&el.tag.as_str());
+            if !is_void && !opens_container {
+                depth += 1;
+            }
+        } else {
+            pos += rest
+                .char_indices()
+                .skip(1)
+                .find(|(_, c)| *c == '<')
+                .map(|(i, _)| i)
+                .unwrap_or(rest.len());
+        }
+        if depth == 0 {
+            emit(&mut start, pos);
+        }
+    }
+    emit(&mut start, html.len());
+    out
+}
+
+pub fn decode_entities(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_string();
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        let semi = tail.find(';').filter(|n| *n <= 10);
+        let decoded = semi.and_then(|n| {
+            let name = &tail[1..n];
+            let ch = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => {
+                    let num = name.strip_prefix('#')?;
+                    let code = match num.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => num.parse().ok()?,
+                    };
+                    char::from_u32(code)
+                }
+            };
+            ch.map(|c| (c, n + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &tail[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &tail[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+pub fn escape_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+pub fn escape_attr(s: &str) -> String {
+    escape_text(s).replace('"', "&quot;")
+}
+
+/// Parse the color values the editor writes (`#rgb`, `#rrggbb`, `#rrggbbaa`, `rgb()`/`rgba()`).
+pub fn css_color(value: &str) -> Option<(u8, u8, u8)> {
+    let v = value.trim();
+    if let Some(hex) = v.strip_prefix('#') {
+        let digit = |i: usize, len: usize| u8::from_str_radix(hex.get(i..i + len)?, 16).ok();
+        return match hex.len() {
+            3 | 4 => Some((digit(0, 1)? * 17, digit(1, 1)? * 17, digit(2, 1)? * 17)),
+            6 | 8 => Some((digit(0, 2)?, digit(2, 2)?, digit(4, 2)?)),
+            _ => None,
+        };
+    }
+    let lower = v.to_ascii_lowercase();
+    let inner = lower
+        .strip_prefix("rgba(")
+        .or_else(|| lower.strip_prefix("rgb("))?
+        .strip_suffix(')')?;
+    let mut parts = inner
+        .split([',', ' ', '/'])
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.trim()
+                .parse::<f32>()
+                .ok()
+                .map(|n| n.clamp(0.0, 255.0) as u8)
+        });
+    Some((parts.next()??, parts.next()??, parts.next()??))
+}
+
+/// Font size in points from a `font-size` value (`14pt`, `18px`).
+pub fn css_font_size_pt(value: &str) -> Option<f32> {
+    let v = value.trim().to_ascii_lowercase();
+    if let Some(n) = v.strip_suffix("pt") {
+        return n.trim().parse().ok();
+    }
+    if let Some(n) = v.strip_suffix("px") {
+        return n.trim().parse::<f32>().ok().map(|px| px * 0.75);
+    }
+    None
+}
+
+/// Plain text of a document, one line per block. Used for search indexing.
+pub fn plain_text(html: &str) -> String {
+    let mut out = String::new();
+    push_plain(&parse(html), &mut out);
+    out.trim_end().to_string()
+}
+
+fn push_plain(nodes: &[Node], out: &mut String) {
+    for n in nodes {
+        match n {
+            Node::Text(t) => out.push_str(t),
+            Node::Element(e) => match e.tag.as_str() {
+                "br" => out.push('\n'),
+                "footnote" => {
+                    out.push(' ');
+                    push_plain(&e.children, out);
+                    out.push(' ');
+                }
+                "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "li" | "pre" | "div"
+                | "blockquote" | "tr" => {
+                    if !out.is_empty() && !out.ends_with('\n') {
+                        out.push('\n');
+                    }
+                    push_plain(&e.children, out);
+                    if !out.ends_with('\n') {
+                        out.push('\n');
+                    }
+                }
+                _ => push_plain(&e.children, out),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_level_elements_are_blocks() {
+        assert_eq!(
+            split_blocks("<h1>T</h1>\n<p>a <b>b</b><br>c</p>  <p></p><pre><code>x</code></pre>"),
+            vec![
+                "<h1>T</h1>",
+                "<p>a <b>b</b><br>c</p>",
+                "<p></p>",
+                "<pre><code>x</code></pre>"
+            ]
+        );
+        assert!(split_blocks("").is_empty());
+        assert!(split_blocks(" \n ").is_empty());
+    }
+
+    #[test]
+    fn list_items_are_separate_blocks() {
+        assert_eq!(
+            split_blocks("<ul><li><p>a</p></li><li><p>b</p><ol><li>c</li></ol></li></ul>"),
+            vec![
+                "<ul>",
+                "<li><p>a</p></li>",
+                "<li><p>b</p><ol><li>c</li></ol></li>",
+                "</ul>"
+            ]
+        );
+        assert_eq!(
+            split_blocks("<ol start=\"3\">\n  <li>a</li>\n</ol><p>after</p>"),
+            vec!["<ol start=\"3\">", "<li>a</li>", "</ol>", "<p>after</p>"]
+        );
+    }
+
+    #[test]
+    fn void_tags_comments_and_stray_text() {
+        assert_eq!(
+            split_blocks("<img src=\"a.png\"><hr/><!-- note --><p>x</p>loose"),
+            vec![
+                "<img src=\"a.png\">",
+                "<hr/>",
+                "<!-- note -->",
+                "<p>x</p>",
+                "loose"
+            ]
+        );
+        // A tag-like `<` in text does not open an element
+        assert_eq!(split_blocks("<p>1 < 2</p>"), vec!["<p>1 < 2</p>"]);
+    }
+
+    #[test]
+    fn blocks_join_back_into_the_document() {
+        let html = "<p>é</p><ul><li>ü</li></ul><p>a</p>";
+        assert_eq!(split_blocks(html).concat(), html);
+        // An unclosed element runs to the end
+        assert_eq!(split_blocks("<p>a</p><p>b"), vec!["<p>a</p>", "<p>b"]);
+    }
+}
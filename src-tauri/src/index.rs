@@ -0,0 +1,1166 @@
+// The workspace index (rosepad.db). The backend is the only writer: scans and
+// analyze passes are applied here in one transaction each, and the UI reads
+// through the typed query commands below.
+
+use lazy_static::lazy_static;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tauri::{AppHandle, Manager};
+
+use crate::scan::{CachedFile, ScanCache};
+use crate::search::fts_query;
+use crate::textfile::TextFormatDto;
+use crate::workspace::{stable_id, AnalyzeResultDto, MovedPathDto, ProjectDto, ScanResultDto};
+
+// Applied in order; `PRAGMA user_version` records how many have run.
+// v1 is idempotent so databases created by older builds upgrade cleanly.
+const MIGRATIONS: &[&str] = &[
+    include_str!("schema_v1.sql"),
+    include_str!("schema_v2.sql"),
+    include_str!("schema_v3.sql"),
+    include_str!("schema_v4.sql"),
+    include_str!("schema_v5.sql"),
+    include_str!("schema_v6.sql"),
+];
+
+// Paths compare case-insensitively only where the file system usually does
+const FOLD_PATH_CASE: bool = cfg!(any(windows, target_os = "macos"));
+
+/// Normalized path expression used for workspace-root filtering.
+pub(crate) const PATH_NORM: &str = if FOLD_PATH_CASE {
+    "REPLACE(LOWER(path),'\\','/')"
+} else {
+    "REPLACE(path,'\\','/')"
+};
+
+/// [`PATH_NORM`] for another column.
+pub(crate) fn path_norm(column: &str) -> String {
+    if FOLD_PATH_CASE {
+        format!("REPLACE(LOWER({column}),'\\','/')")
+    } else {
+        format!("REPLACE({column},'\\','/')")
+    }
+}
+
+/// `path` normalized the way [`PATH_NORM`] normalizes the column.
+pub(crate) fn norm_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    if FOLD_PATH_CASE {
+        path.to_lowercase()
+    } else {
+        path
+    }
+}
+
+const PROJECT_COLUMNS: &str =
+    "id,kind,name,path,ext,title,last_modified_ms,size,parent_physical_folder,text_format,file_key,fingerprint";
+
+// The id of an indexed path never changes; see `resolve_ids`
+const UPSERT_PROJECT: &str = "INSERT INTO projects(id,path,name,kind,ext,title,last_modified_ms,size,parent_physical_folder,text_format,file_key,fingerprint)
+     VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9,?10,?11,?12)
+     ON CONFLICT(path) DO UPDATE SET name=excluded.name, kind=excluded.kind, ext=excluded.ext, title=excluded.title, last_modified_ms=excluded.last_modified_ms, size=excluded.size, parent_physical_folder=excluded.parent_physical_folder, text_format=excluded.text_format, file_key=excluded.file_key, fingerprint=excluded.fingerprint";
+
+const UPSERT_FOLDER: &str = "INSERT INTO physical_folders(path,name,parent_path) VALUES(?1,?2,?3)
+     ON CONFLICT(path) DO UPDATE SET name=excluded.name, parent_path=excluded.parent_path";
+
+lazy_static! {
+    static ref INDEX: Mutex<Option<Connection>> = Mutex::new(None);
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicalFolderDto {
+    pub id: String,
+    pub name: String,
+    pub path: String,
+    pub parent_path: Option<String>,
+    pub project_ids: Vec<String>,
+    /// Paths of the folders directly inside this one.
+    pub folder_ids: Vec<String>,
+    pub collapsed: bool,
+    pub color: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceTreeDto {
+    pub root_projects: Vec<String>,
+    /// Folders directly under the root; the rest are reached through `folder_ids`.
+    pub root_folders: Vec<String>,
+    /// Every folder in the workspace, parents before children.
+    pub physical_folders: Vec<PhysicalFolderDto>,
+    pub projects: Vec<ProjectDto>,
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), String> {
+    let version: usize = conn
+        .query_row("PRAGMA user_version", [], |r| r.get::<_, i64>(0))
+        .map_err(|e| e.to_string())? as usize;
+    for (i, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        tx.execute_batch(sql)
+            .map_err(|e| format!("index migration {} failed: {e}", i + 1))?;
+        tx.pragma_update(None, "user_version", (i + 1) as i64)
+            .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn open_index(path: &Path) -> Result<Connection, String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let mut conn = Connection::open(path).map_err(|e| e.to_string())?;
+    conn.busy_timeout(std::time::Duration::from_secs(5))
+        .map_err(|e| e.to_string())?;
+    conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA synchronous=NORMAL;")
+        .map_err(|e| e.to_string())?;
+    configure(&conn)?;
+    migrate(&mut conn)?;
+    Ok(conn)
+}
+
+// Settings every index connection needs. `LIKE` folds ASCII case by default,
+// which would let root filters match folders that differ only in case
+fn configure(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch("PRAGMA foreign_keys=ON;")
+        .map_err(|e| e.to_string())?;
+    conn.pragma_update(None, "case_sensitive_like", !FOLD_PATH_CASE)
+        .map_err(|e| e.to_string())
+}
+
+fn index_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    Ok(dir.join("rosepad.db"))
+}
+
+/// A migrated index that lives in memory, for tests.
+#[cfg(test)]
+pub(crate) fn memory_index() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    configure(&conn).unwrap();
+    migrate(&mut conn).unwrap();
+    conn
+}
+
+/// Run `f` against the shared index connection, opening and migrating it on first use.
+pub(crate) fn with_index<T>(
+    app: &AppHandle,
+    f: impl FnOnce(&mut Connection) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut guard = match INDEX.lock() {
+        Ok(g) => g,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    if guard.is_none() {
+        *guard = Some(open_index(&index_path(app)?)?);
+    }
+    match guard.as_mut() {
+        Some(conn) => f(conn),
+        None => Err("workspace index unavailable".into()),
+    }
+}
+
+/// `LIKE` pattern matching everything below `dir` (compare against [`PATH_NORM`]).
+pub(crate) fn like_prefix(dir: &str) -> String {
+    let mut s = norm_path(dir);
+    if !s.ends_with('/') {
+        s.push('/');
+    }
+    let escaped = s.replace('%', "\\%").replace('_', "\\_");
+    format!("{escaped}%")
+}
+
+fn root_filter() -> String {
+    format!("({PATH_NORM} = ?1 OR {PATH_NORM} LIKE ?2 ESCAPE '\\')")
+}
+
+fn root_params(root: &str) -> (String, String) {
+    (norm_path(root), like_prefix(root))
+}
+
+// Stored as JSON; an unreadable value just means it is detected again
+fn text_format_from_column(raw: Option<String>) -> Option<TextFormatDto> {
+    serde_json::from_str(raw.as_deref()?).ok()
+}
+
+fn project_from_row(r: &Row) -> rusqlite::Result<ProjectDto> {
+    Ok(ProjectDto {
+        id: r.get(0)?,
+        kind: r.get(1)?,
+        name: r.get(2)?,
+        path: r.get(3)?,
+        ext: r.get(4)?,
+        title: r.get(5)?,
+        last_modified_ms: r.get(6)?,
+        size: r.get(7)?,
+        parent_physical_folder: r.get(8)?,
+        text_format: text_format_from_column(r.get(9)?),
+        file_key: r.get(10)?,
+        fingerprint: r.get(11)?,
+    })
+}
+
+fn upsert_project(
+    tx: &rusqlite::Transaction,
+    p: &ProjectDto,
+    parent: Option<&str>,
+) -> Result<(), String> {
+    let text_format = match &p.text_format {
+        Some(f) => Some(serde_json::to_string(f).map_err(|e| e.to_string())?),
+        None => None,
+    };
+    tx.execute(
+        UPSERT_PROJECT,
+        params![
+            p.id,
+            p.path,
+            p.name,
+            p.kind,
+            p.ext,
+            p.title,
+            p.last_modified_ms,
+            p.size,
+            parent,
+            text_format,
+            p.file_key,
+            p.fingerprint
+        ],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+// Paths under `root` in `table` that are not in `seen`
+fn stale_paths(
+    tx: &Connection,
+    table: &str,
+    root: &str,
+    seen: &HashSet<&str>,
+) -> Result<Vec<String>, String> {
+    let (eq, like) = root_params(root);
+    let mut stmt = tx
+        .prepare(&format!("SELECT path FROM {table} WHERE {}", root_filter()))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![eq, like], |r| r.get::<_, String>(0))
+        .map_err(|e| e.to_string())?;
+    Ok(rows
+        .flatten()
+        .filter(|p| !seen.contains(p.as_str()))
+        .collect())
+}
+
+/// Replace everything indexed under `root` with a full scan result. Files
+/// renamed or moved since the last scan keep their ids (see `resolve_ids`).
+pub(crate) fn apply_scan(
+    conn: &mut Connection,
+    root: &str,
+    scan: &mut ScanResultDto,
+) -> Result<(), String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let scanned: Vec<String> = scan
+        .all_projects_mut()
+        .into_iter()
+        .map(|p| p.path.clone())
+        .collect();
+    let folders: Vec<String> = scan.all_folders().iter().map(|f| f.path.clone()).collect();
+    let gone = stale_paths(
+        &tx,
+        "projects",
+        root,
+        &scanned.iter().map(|p| p.as_str()).collect(),
+    )?;
+    let moved = resolve_ids(&tx, scan.all_projects_mut(), gone_files(&tx, &gone)?)?;
+    let gone_folders: HashSet<String> = stale_paths(
+        &tx,
+        "physical_folders",
+        root,
+        &folders.iter().map(|p| p.as_str()).collect(),
+    )?
+    .into_iter()
+    .collect();
+    let new_folders = unindexed_folders(&tx, folders.iter())?;
+    for m in folder_moves(&moved, &gone_folders, &new_folders) {
+        move_folder(&tx, &m.from, &m.to)?;
+    }
+    for m in &moved {
+        move_project_row(&tx, &m.from, &m.to)?;
+    }
+
+    let mut seen_projects: HashSet<&str> = HashSet::new();
+    let mut seen_folders: HashSet<&str> = HashSet::new();
+    for p in &scan.root_projects {
+        upsert_project(&tx, p, None)?;
+        seen_projects.insert(&p.path);
+    }
+    for folder in scan.all_folders() {
+        tx.execute(
+            UPSERT_FOLDER,
+            params![folder.path, folder.name, folder.parent_path],
+        )
+        .map_err(|e| e.to_string())?;
+        seen_folders.insert(&folder.path);
+        for p in &folder.projects {
+            upsert_project(&tx, p, Some(&folder.path))?;
+            seen_projects.insert(&p.path);
+        }
+    }
+
+    for path in stale_paths(&tx, "projects", root, &seen_projects)? {
+        tx.execute("DELETE FROM projects WHERE path=?1", [path])
+            .map_err(|e| e.to_string())?;
+    }
+    for path in stale_paths(&tx, "physical_folders", root, &seen_folders)? {
+        tx.execute("DELETE FROM physical_folders WHERE path=?1", [path])
+            .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())
+}
+
+/// Indexed files under `dir`, used by scans to skip unchanged files.
+pub(crate) fn load_scan_cache(conn: &Connection, dir: &str) -> Result<ScanCache, String> {
+    let (eq, like) = root_params(dir);
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT path,id,last_modified_ms,size,title,text_format,fingerprint FROM projects WHERE {}",
+            root_filter()
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![eq, like], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                CachedFile {
+                    id: r.get(1)?,
+                    last_modified_ms: r.get(2)?,
+                    size: r.get(3)?,
+                    title: r.get(4)?,
+                    text_format: text_format_from_column(r.get(5)?),
+                    fingerprint: r.get(6)?,
+                },
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+/// Apply the targeted upserts/deletes produced by `analyze_paths`. Deleted
+/// paths that turn out to have moved to one of the upserted paths are taken out
+/// of the delete lists and reported in `moved_projects`/`moved_folders` instead.
+pub(crate) fn apply_analyze(
+    conn: &mut Connection,
+    diff: &mut AnalyzeResultDto,
+) -> Result<(), String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let mut gone = diff.delete_project_paths.clone();
+    let mut gone_folders: HashSet<String> = HashSet::new();
+    for p in &diff.delete_physical_folders {
+        gone.extend(stale_paths(&tx, "projects", p, &HashSet::new())?);
+        gone_folders.extend(stale_paths(&tx, "physical_folders", p, &HashSet::new())?);
+    }
+    let moved = resolve_ids(
+        &tx,
+        diff.projects.iter_mut().collect(),
+        gone_files(&tx, &gone)?,
+    )?;
+    let new_folders = unindexed_folders(&tx, diff.physical_folders.iter().map(|f| &f.path))?;
+    diff.moved_folders = folder_moves(&moved, &gone_folders, &new_folders);
+    for m in &diff.moved_folders {
+        move_folder(&tx, &m.from, &m.to)?;
+    }
+    for m in &moved {
+        move_project_row(&tx, &m.from, &m.to)?;
+    }
+    diff.delete_project_paths
+        .retain(|p| !moved.iter().any(|m| &m.from == p));
+    diff.delete_physical_folders
+        .retain(|p| !diff.moved_folders.iter().any(|m| &m.from == p));
+    diff.moved_projects = moved;
+
+    // Removed folders take everything below them along
+    for p in &diff.delete_physical_folders {
+        for table in ["projects", "physical_folders"] {
+            tx.execute(
+                &format!("DELETE FROM {table} WHERE path=?1 OR {PATH_NORM} LIKE ?2 ESCAPE '\\'"),
+                params![p, like_prefix(p)],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    for f in &diff.physical_folders {
+        tx.execute(UPSERT_FOLDER, params![f.path, f.name, f.parent_path])
+            .map_err(|e| e.to_string())?;
+    }
+    for p in &diff.projects {
+        upsert_project(&tx, p, p.parent_physical_folder.as_deref())?;
+    }
+    for p in &diff.delete_project_paths {
+        tx.execute("DELETE FROM projects WHERE path=?1", [p])
+            .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())
+}
+
+// An indexed file that is no longer at its path; a new file may turn out to be it
+struct GoneFile {
+    id: String,
+    path: String,
+    kind: String,
+    file_key: Option<String>,
+    fingerprint: Option<String>,
+}
+
+fn gone_files(tx: &Connection, paths: &[String]) -> Result<Vec<GoneFile>, String> {
+    let mut stmt = tx
+        .prepare("SELECT id,path,kind,file_key,fingerprint FROM projects WHERE path=?1")
+        .map_err(|e| e.to_string())?;
+    let mut out = Vec::new();
+    for p in paths {
+        let row = stmt
+            .query_row([p], |r| {
+                Ok(GoneFile {
+                    id: r.get(0)?,
+                    path: r.get(1)?,
+                    kind: r.get(2)?,
+                    file_key: r.get(3)?,
+                    fingerprint: r.get(4)?,
+                })
+            })
+            .optional()
+            .map_err(|e| e.to_string())?;
+        out.extend(row);
+    }
+    Ok(out)
+}
+
+fn id_in_use(tx: &Connection, id: &str) -> Result<bool, String> {
+    tx.query_row("SELECT 1 FROM projects WHERE id=?1", [id], |_| Ok(()))
+        .optional()
+        .map(|r| r.is_some())
+        .map_err(|e| e.to_string())
+}
+
+/// Settle the ids of `projects` before they are written. An indexed path keeps
+/// its id. A new path that is one of the `gone` files after a rename or move
+/// takes over that file's id, and with it its tags: an .rpad is recognized by
+/// the id in its manifest, anything else by device and inode, or else by a
+/// fingerprint only one gone file has. Every other new path gets an id no row
+/// uses yet. Returns the moves that were found.
+fn resolve_ids(
+    tx: &Connection,
+    projects: Vec<&mut ProjectDto>,
+    mut gone: Vec<GoneFile>,
+) -> Result<Vec<MovedPathDto>, String> {
+    let mut moved = Vec::new();
+    let mut taken: HashSet<String> = HashSet::new();
+    for p in projects {
+        let indexed: Option<String> = tx
+            .query_row("SELECT id FROM projects WHERE path=?1", [&p.path], |r| {
+                r.get(0)
+            })
+            .optional()
+            .map_err(|e| e.to_string())?;
+        if let Some(id) = indexed {
+            p.id = id;
+            taken.insert(p.id.clone());
+            continue;
+        }
+        let same_kind = |g: &GoneFile| g.kind == p.kind;
+        let found = gone
+            .iter()
+            .position(|g| {
+                same_kind(g)
+                    && ((p.kind == "rpad" && g.id == p.id)
+                        || (p.file_key.is_some() && g.file_key == p.file_key))
+            })
+            .or_else(|| {
+                p.fingerprint.as_ref()?;
+                let mut same = gone
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, g)| same_kind(g) && g.fingerprint == p.fingerprint);
+                match (same.next(), same.next()) {
+                    (Some((i, _)), None) => Some(i),
+                    _ => None,
+                }
+            });
+        if let Some(i) = found {
+            let g = gone.swap_remove(i);
+            p.id = g.id;
+            moved.push(MovedPathDto {
+                from: g.path,
+                to: p.path.clone(),
+            });
+        } else if taken.contains(&p.id) || id_in_use(tx, &p.id)? {
+            // A copy of an .rpad, or a new file where a moved one used to be
+            let mut n = 1;
+            p.id = loop {
+                let id = stable_id(&format!("{}#{n}", p.path));
+                if !taken.contains(&id) && !id_in_use(tx, &id)? {
+                    break id;
+                }
+                n += 1;
+            };
+        }
+        taken.insert(p.id.clone());
+    }
+    Ok(moved)
+}
+
+fn unindexed_folders<'a>(
+    tx: &Connection,
+    paths: impl Iterator<Item = &'a String>,
+) -> Result<HashSet<String>, String> {
+    let mut stmt = tx
+        .prepare("SELECT 1 FROM physical_folders WHERE path=?1")
+        .map_err(|e| e.to_string())?;
+    let mut out = HashSet::new();
+    for p in paths {
+        if !stmt.exists([p]).map_err(|e| e.to_string())? {
+            out.insert(p.clone());
+        }
+    }
+    Ok(out)
+}
+
+// A gone folder moved to a new one if a file from below it turned up at the
+// same place below the new folder.
+fn folder_moves(
+    moved: &[MovedPathDto],
+    gone: &HashSet<String>,
+    new: &HashSet<String>,
+) -> Vec<MovedPathDto> {
+    let mut out: Vec<MovedPathDto> = Vec::new();
+    for m in moved {
+        let (from, to) = (Path::new(&m.from), Path::new(&m.to));
+        for dir in from.ancestors().skip(1) {
+            let Ok(rel) = from.strip_prefix(dir) else {
+                break;
+            };
+            if !to.ends_with(rel) {
+                break;
+            }
+            let Some(target) = to.ancestors().nth(rel.components().count()) else {
+                break;
+            };
+            let (dir, target) = (
+                dir.to_string_lossy().to_string(),
+                target.to_string_lossy().to_string(),
+            );
+            if gone.contains(&dir) && new.contains(&target) && !out.iter().any(|f| f.from == dir) {
+                out.push(MovedPathDto {
+                    from: dir,
+                    to: target,
+                });
+            }
+        }
+    }
+    // Moving a folder takes its subfolders along, so only the outermost counts
+    out.sort_by_key(|m| m.from.len());
+    let mut outermost: Vec<MovedPathDto> = Vec::new();
+    for m in out {
+        if !outermost
+            .iter()
+            .any(|o| Path::new(&m.from).starts_with(&o.from))
+        {
+            outermost.push(m);
+        }
+    }
+    outermost
+}
+
+// A file's row and history follow it to its new path. A row already indexed
+// there is the same file, picked up before the move was known.
+fn move_project_row(tx: &Connection, from: &str, to: &str) -> Result<(), String> {
+    let name = Path::new(to)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    tx.execute(
+        "DELETE FROM projects WHERE path=?1 AND EXISTS (SELECT 1 FROM projects WHERE path=?2)",
+        params![to, from],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE projects SET path=?1, name=?2 WHERE path=?3",
+        params![to, name, from],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE history_snapshots SET path=?1 WHERE path=?2",
+        params![to, from],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Keep the row (id, tags) and history of a file the app renamed or moved.
+pub(crate) fn move_project(app: &AppHandle, from: &str, to: &str) -> Result<(), String> {
+    with_index(app, |conn| {
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        move_project_row(&tx, from, to)?;
+        tx.commit().map_err(|e| e.to_string())
+    })
+}
+
+/// The id a path is indexed under.
+pub(crate) fn project_id(app: &AppHandle, path: &str) -> Option<String> {
+    with_index(app, |conn| {
+        conn.query_row("SELECT id FROM projects WHERE path=?1", [path], |r| {
+            r.get(0)
+        })
+        .optional()
+        .map_err(|e| e.to_string())
+    })
+    .ok()
+    .flatten()
+}
+
+// Moves a folder's rows, its files' rows and their history to the new path
+fn move_folder(tx: &Connection, old_path: &str, new_path: &str) -> Result<(), String> {
+    let new_name = Path::new(new_path)
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // Rows already under the new path were picked up before the move was known
+    for table in ["projects", "physical_folders"] {
+        tx.execute(
+            &format!("DELETE FROM {table} WHERE path=?1 OR {PATH_NORM} LIKE ?2 ESCAPE '\\'"),
+            params![new_path, like_prefix(new_path)],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.execute(
+        "UPDATE physical_folders SET path=?1, name=?2 WHERE path=?3",
+        params![new_path, new_name, old_path],
+    )
+    .map_err(|e| e.to_string())?;
+    // Descendants: swap the old prefix for the new one on path and parent_path
+    let n = old_path.chars().count() as i64;
+    for table in ["physical_folders", "projects", "history_snapshots"] {
+        tx.execute(
+            &format!(
+                "UPDATE {table} SET path=?1 || substr(path, ?2 + 1) WHERE {PATH_NORM} LIKE ?3 ESCAPE '\\'"
+            ),
+            params![new_path, n, like_prefix(old_path)],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    for (table, column) in [
+        ("physical_folders", "parent_path"),
+        ("projects", "parent_physical_folder"),
+    ] {
+        tx.execute(
+            &format!(
+                "UPDATE {table} SET {column}=?1 || substr({column}, ?2 + 1)
+                 WHERE {column}=?3 OR {} LIKE ?4 ESCAPE '\\'",
+                path_norm(column)
+            ),
+            params![new_path, n, old_path, like_prefix(old_path)],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Keep the rows (and colors, tags and history) of a renamed folder and
+/// everything below it instead of recreating them.
+pub(crate) fn rename_folder(app: &AppHandle, old_path: &str, new_path: &str) -> Result<(), String> {
+    with_index(app, |conn| {
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        move_folder(&tx, old_path, new_path)?;
+        tx.commit().map_err(|e| e.to_string())
+    })
+}
+
+/// Drop everything indexed under `root`, e.g. when its workspace is removed.
+pub(crate) fn forget_root(app: &AppHandle, root: &str) -> Result<(), String> {
+    let (eq, like) = root_params(root);
+    with_index(app, |conn| {
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        for table in ["projects", "physical_folders"] {
+            tx.execute(
+                &format!("DELETE FROM {table} WHERE {}", root_filter()),
+                params![eq, like],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())
+    })
+}
+
+fn read_tree(conn: &Connection, root: &str) -> Result<WorkspaceTreeDto, String> {
+    let (eq, like) = root_params(root);
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {PROJECT_COLUMNS} FROM projects WHERE {}",
+            root_filter()
+        ))
+        .map_err(|e| e.to_string())?;
+    let projects: Vec<ProjectDto> = stmt
+        .query_map(params![eq, like], project_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let root_projects = projects
+        .iter()
+        .filter(|p| p.parent_physical_folder.is_none())
+        .map(|p| p.id.clone())
+        .collect();
+
+    let mut folders_stmt = conn
+        .prepare(&format!(
+            "SELECT path,name,color,parent_path FROM physical_folders WHERE {}
+             ORDER BY LENGTH(path), name COLLATE NOCASE",
+            root_filter()
+        ))
+        .map_err(|e| e.to_string())?;
+    let folders: Vec<(String, String, Option<String>, Option<String>)> = folders_stmt
+        .query_map(params![eq, like], |r| {
+            Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut children: HashMap<&str, Vec<String>> = HashMap::new();
+    let mut root_folders = Vec::new();
+    for (path, _, _, parent) in &folders {
+        match parent {
+            Some(parent) => children.entry(parent).or_default().push(path.clone()),
+            None => root_folders.push(path.clone()),
+        }
+    }
+
+    let mut ids_stmt = conn
+        .prepare(
+            "SELECT id FROM projects WHERE parent_physical_folder=?1 ORDER BY name COLLATE NOCASE",
+        )
+        .map_err(|e| e.to_string())?;
+    let mut physical_folders = Vec::with_capacity(folders.len());
+    for (path, name, color, parent_path) in &folders {
+        let project_ids = ids_stmt
+            .query_map([path], |r| r.get::<_, String>(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?;
+        physical_folders.push(PhysicalFolderDto {
+            id: path.clone(),
+            name: name.clone(),
+            path: path.clone(),
+            parent_path: parent_path.clone(),
+            project_ids,
+            folder_ids: children.remove(path.as_str()).unwrap_or_default(),
+            collapsed: false,
+            color: color.clone(),
+        });
+    }
+
+    Ok(WorkspaceTreeDto {
+        root_projects,
+        root_folders,
+        physical_folders,
+        projects,
+    })
+}
+
+#[tauri::command]
+pub async fn get_workspace_tree(app: AppHandle, root: String) -> Result<WorkspaceTreeDto, String> {
+    with_index(&app, |conn| read_tree(conn, &root))
+}
+
+/// Search project names, titles and tags.
+#[tauri::command]
+pub async fn search_projects(
+    app: AppHandle,
+    query: String,
+    limit: Option<u32>,
+) -> Result<Vec<ProjectDto>, String> {
+    let fts = match fts_query(&query) {
+        Some(q) => q,
+        None => return Ok(Vec::new()),
+    };
+    let limit = limit.unwrap_or(50).clamp(1, 500);
+    with_index(&app, |conn| {
+        let mut stmt = conn
+            .prepare(
+                "SELECT p.id,p.kind,p.name,p.path,p.ext,p.title,p.last_modified_ms,p.size,p.parent_physical_folder,p.text_format,p.file_key,p.fingerprint
+                 FROM project_fts f JOIN projects p ON f.rowid = p.rowid
+                 WHERE project_fts MATCH ?1 ORDER BY rank LIMIT ?2",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![fts, limit], project_from_row)
+            .map_err(|e| e.to_string())?
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string());
+        rows
+    })
+}
+
+#[tauri::command]
+pub async fn project_exists(app: AppHandle, path: String) -> Result<bool, String> {
+    with_index(&app, |conn| {
+        conn.query_row(
+            "SELECT 1 FROM projects WHERE path=?1 LIMIT 1",
+            [&path],
+            |_| Ok(()),
+        )
+        .optional()
+        .map(|r| r.is_some())
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub async fn set_physical_folder_color(
+    app: AppHandle,
+    path: String,
+    color: Option<String>,
+) -> Result<(), String> {
+    let name = Path::new(&path)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or(&path)
+        .to_string();
+    with_index(&app, |conn| {
+        conn.execute(
+            "INSERT INTO physical_folders(path,name,color) VALUES(?1,?2,?3)
+             ON CONFLICT(path) DO UPDATE SET color=excluded.color",
+            params![path, name, color],
+        )
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    })
+}
+
+#[tauri::command]
+pub async fn list_tags(app: AppHandle) -> Result<Vec<String>, String> {
+    with_index(&app, |conn| {
+        let mut stmt = conn
+            .prepare("SELECT name FROM tags ORDER BY name COLLATE NOCASE")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |r| r.get::<_, String>(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string());
+        rows
+    })
+}
+
+#[tauri::command]
+pub async fn get_project_tags(app: AppHandle, project_id: String) -> Result<Vec<String>, String> {
+    with_index(&app, |conn| {
+        let mut stmt = conn
+            .prepare(
+                "SELECT tag_name FROM project_tags WHERE project_id=?1 ORDER BY tag_name COLLATE NOCASE",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([&project_id], |r| r.get::<_, String>(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string());
+        rows
+    })
+}
+
+/// Replace a project's tags. Tags no project uses any more are dropped.
+#[tauri::command]
+pub async fn set_project_tags(
+    app: AppHandle,
+    project_id: String,
+    tags: Vec<String>,
+) -> Result<(), String> {
+    let mut clean: Vec<String> = tags
+        .iter()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+    clean.sort();
+    clean.dedup();
+    with_index(&app, |conn| {
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        tx.execute(
+            "DELETE FROM project_tags WHERE project_id=?1",
+            [&project_id],
+        )
+        .map_err(|e| e.to_string())?;
+        for tag in &clean {
+            tx.execute("INSERT OR IGNORE INTO tags(name) VALUES(?1)", [tag])
+                .map_err(|e| e.to_string())?;
+            tx.execute(
+                "INSERT INTO project_tags(project_id,tag_name) VALUES(?1,?2)",
+                params![project_id, tag],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        tx.execute(
+            "DELETE FROM tags WHERE name NOT IN (SELECT tag_name FROM project_tags)",
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(
+        path: &str,
+        kind: &str,
+        file_key: Option<&str>,
+        fingerprint: Option<&str>,
+    ) -> ProjectDto {
+        let p = Path::new(path);
+        ProjectDto {
+            id: stable_id(path),
+            kind: kind.into(),
+            name: p.file_stem().unwrap().to_string_lossy().to_string(),
+            path: path.into(),
+            ext: p.extension().map(|e| e.to_string_lossy().to_string()),
+            title: None,
+            last_modified_ms: 0,
+            size: 0,
+            parent_physical_folder: p.parent().map(|d| d.to_string_lossy().to_string()),
+            text_format: None,
+            file_key: file_key.map(str::to_string),
+            fingerprint: fingerprint.map(str::to_string),
+        }
+    }
+
+    fn rpad(path: &str, id: &str) -> ProjectDto {
+        ProjectDto {
+            id: id.into(),
+            ..project(path, "rpad", None, None)
+        }
+    }
+
+    // Index `projects`, tagging each with its file name
+    fn seed(conn: &mut Connection, projects: &[ProjectDto]) {
+        let tx = conn.transaction().unwrap();
+        for p in projects {
+            upsert_project(&tx, p, None).unwrap();
+            tx.execute("INSERT OR IGNORE INTO tags(name) VALUES(?1)", [&p.name])
+                .unwrap();
+            tx.execute(
+                "INSERT INTO project_tags(project_id,tag_name) VALUES(?1,?2)",
+                params![p.id, p.name],
+            )
+            .unwrap();
+        }
+        tx.commit().unwrap();
+    }
+
+    // Settle the ids of `new` given that `gone` paths disappeared
+    fn resolve(conn: &Connection, gone: &[&str], new: &mut [ProjectDto]) -> Vec<MovedPathDto> {
+        let gone: Vec<String> = gone.iter().map(|p| p.to_string()).collect();
+        resolve_ids(
+            conn,
+            new.iter_mut().collect(),
+            gone_files(conn, &gone).unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn moved(from: &str, to: &str) -> MovedPathDto {
+        MovedPathDto {
+            from: from.into(),
+            to: to.into(),
+        }
+    }
+
+    fn id_of(conn: &Connection, path: &str) -> String {
+        conn.query_row("SELECT id FROM projects WHERE path=?1", [path], |r| {
+            r.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn renames_are_matched_by_id_key_or_fingerprint() {
+        let mut conn = memory_index();
+        seed(
+            &mut conn,
+            &[
+                rpad("/w/a.rpad", "doc-a"),
+                project("/w/n.txt", "text", Some("1:42"), Some("fp-n")),
+                project("/w/f.md", "text", None, Some("fp-f")),
+            ],
+        );
+        let ids = ["/w/a.rpad", "/w/n.txt", "/w/f.md"].map(|p| id_of(&conn, p));
+        let mut new = [
+            rpad("/w/b.rpad", "doc-a"),
+            // Edited since, but still the same inode
+            project("/w/m.txt", "text", Some("1:42"), Some("fp-changed")),
+            // Copied back from elsewhere: only the content matches
+            project("/w/g.md", "text", Some("9:9"), Some("fp-f")),
+        ];
+        let moves = resolve(&conn, &["/w/a.rpad", "/w/n.txt", "/w/f.md"], &mut new);
+        assert_eq!(
+            moves,
+            vec![
+                moved("/w/a.rpad", "/w/b.rpad"),
+                moved("/w/n.txt", "/w/m.txt"),
+                moved("/w/f.md", "/w/g.md"),
+            ]
+        );
+        let new_ids: Vec<String> = new.iter().map(|p| p.id.clone()).collect();
+        assert_eq!(new_ids, ids.to_vec());
+    }
+
+    #[test]
+    fn unrelated_files_are_not_matched() {
+        let mut conn = memory_index();
+        seed(
+            &mut conn,
+            &[
+                project("/w/a.txt", "text", Some("1:1"), Some("same")),
+                project("/w/b.txt", "text", Some("1:2"), Some("same")),
+                project("/w/c.png", "image", Some("1:3"), None),
+            ],
+        );
+        let mut new = [
+            // Two gone files share the fingerprint, so it says nothing
+            project("/w/x.txt", "text", Some("1:9"), Some("same")),
+            // The inode was reused by a file of another kind
+            project("/w/c.txt", "text", Some("1:3"), None),
+        ];
+        assert!(resolve(&conn, &["/w/a.txt", "/w/b.txt", "/w/c.png"], &mut new).is_empty());
+        assert_eq!(new[0].id, stable_id("/w/x.txt"));
+        assert_eq!(new[1].id, stable_id("/w/c.txt"));
+    }
+
+    #[test]
+    fn a_copied_rpad_gets_a_new_id() {
+        let mut conn = memory_index();
+        seed(&mut conn, &[rpad("/w/a.rpad", "doc-a")]);
+        // The copy carries the original's manifest id, and the original is still there
+        let mut new = [rpad("/w/a.rpad", "doc-a"), rpad("/w/a (1).rpad", "doc-a")];
+        assert!(resolve(&conn, &[], &mut new).is_empty());
+        assert_eq!(new[0].id, "doc-a");
+        assert_ne!(new[1].id, "doc-a");
+        // Two copies in one pass do not share an id either
+        let mut new = [rpad("/w/b.rpad", "doc-a"), rpad("/w/c.rpad", "doc-a")];
+        resolve(&conn, &[], &mut new);
+        assert_ne!(new[0].id, new[1].id);
+        assert!(new.iter().all(|p| p.id != "doc-a"));
+    }
+
+    #[test]
+    fn an_indexed_path_keeps_its_id() {
+        let mut conn = memory_index();
+        seed(&mut conn, &[project("/w/a.txt", "text", Some("1:1"), None)]);
+        let id = id_of(&conn, "/w/a.txt");
+        let mut new = [ProjectDto {
+            id: "something-else".into(),
+            ..project("/w/a.txt", "text", Some("2:2"), None)
+        }];
+        assert!(resolve(&conn, &[], &mut new).is_empty());
+        assert_eq!(new[0].id, id);
+    }
+
+    #[test]
+    fn roots_ignore_case_only_where_file_systems_do() {
+        let mut conn = memory_index();
+        seed(
+            &mut conn,
+            &[
+                project("/w/a.txt", "text", None, None),
+                project("/W/b.txt", "text", None, None),
+                project("/w2/c.txt", "text", None, None),
+                project("/W_x/d.txt", "text", None, None),
+            ],
+        );
+        let mut under = stale_paths(&conn, "projects", "/w", &HashSet::new()).unwrap();
+        under.sort();
+        if cfg!(any(windows, target_os = "macos")) {
+            assert_eq!(under, ["/W/b.txt", "/w/a.txt"]);
+        } else {
+            assert_eq!(under, ["/w/a.txt"]);
+        }
+        let under = stale_paths(&conn, "projects", "/W_x", &HashSet::new()).unwrap();
+        assert_eq!(under, ["/W_x/d.txt"]);
+    }
+
+    #[test]
+    fn tags_follow_a_file_moved_across_folders() {
+        let mut conn = memory_index();
+        seed(
+            &mut conn,
+            &[project("/w/x/a.txt", "text", Some("1:7"), Some("fp"))],
+        );
+        let id = id_of(&conn, "/w/x/a.txt");
+        let mut diff = AnalyzeResultDto {
+            projects: vec![project("/w/y/a.txt", "text", Some("1:7"), Some("fp"))],
+            delete_project_paths: vec!["/w/x/a.txt".into()],
+            physical_folders: Vec::new(),
+            delete_physical_folders: Vec::new(),
+            moved_projects: Vec::new(),
+            moved_folders: Vec::new(),
+        };
+        apply_analyze(&mut conn, &mut diff).unwrap();
+        assert_eq!(diff.moved_projects, vec![moved("/w/x/a.txt", "/w/y/a.txt")]);
+        assert!(diff.delete_project_paths.is_empty());
+        assert_eq!(id_of(&conn, "/w/y/a.txt"), id);
+        let tags: Vec<String> = conn
+            .prepare("SELECT tag_name FROM project_tags WHERE project_id=?1")
+            .unwrap()
+            .query_map([&id], |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(tags, vec!["a".to_string()]);
+        let rows: i64 = conn
+            .query_row("SELECT COUNT(*) FROM projects", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(rows, 1);
+    }
+
+    fn set(paths: &[&str]) -> HashSet<String> {
+        paths.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn only_the_outermost_moved_folder_counts() {
+        let files = [
+            moved("/w/old/sub/a.txt", "/w/new/sub/a.txt"),
+            moved("/w/old/b.txt", "/w/new/b.txt"),
+        ];
+        let gone = set(&["/w/old", "/w/old/sub"]);
+        let new = set(&["/w/new", "/w/new/sub"]);
+        assert_eq!(
+            folder_moves(&files, &gone, &new),
+            vec![moved("/w/old", "/w/new")]
+        );
+    }
+
+    #[test]
+    fn folder_moves_need_a_gone_and_a_new_folder() {
+        let files = [moved("/w/p/a/x.txt", "/w/q/a/x.txt")];
+        // Only the inner folder moved; its old parent is still there
+        assert_eq!(
+            folder_moves(&files, &set(&["/w/p/a"]), &set(&["/w/q", "/w/q/a"])),
+            vec![moved("/w/p/a", "/w/q/a")]
+        );
+        // A file moved between folders that both still exist
+        assert!(folder_moves(&files, &set(&[]), &set(&[])).is_empty());
+        // Renamed on the way, so it says nothing about its folder
+        let renamed = [moved("/w/p/a/x.txt", "/w/q/a/y.txt")];
+        assert!(folder_moves(&renamed, &set(&["/w/p/a"]), &set(&["/w/q/a"])).is_empty());
+    }
+}
@@ -0,0 +1,461 @@
+// Undo/redo for workspace file operations. Every rename, move, create and
+// delete records a `WorkspaceOp` with enough information to invert it, in the
+// journal of the workspace it happened in. Journals live in memory for the
+// session only; deletes stay recoverable past that through the trash.
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tauri::AppHandle;
+
+use crate::error::{during, RosePadError};
+use crate::index;
+use crate::paths;
+use crate::rpad::RpadArchive;
+use crate::trash;
+use crate::workspace::save_rpad_html;
+use crate::workspaces;
+
+const MAX_ENTRIES: usize = 200;
+
+#[derive(Clone, Debug)]
+pub(crate) enum WorkspaceOp {
+    // A file or folder rename/move within the workspace
+    Move {
+        from: String,
+        to: String,
+    },
+    // An .rpad title change; the file itself keeps its name
+    Retitle {
+        path: String,
+        from: String,
+        to: String,
+    },
+    // A new file or folder; `trash_id` is set while it is undone
+    Create {
+        root: String,
+        path: String,
+        trash_id: Option<String>,
+    },
+    // A file or folder moved to the workspace trash
+    Trash {
+        root: String,
+        path: String,
+        trash_id: String,
+    },
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+impl WorkspaceOp {
+    fn label(&self) -> String {
+        match self {
+            WorkspaceOp::Move { from, to } => {
+                let verb = if Path::new(from).parent() == Path::new(to).parent() {
+                    "Rename"
+                } else {
+                    "Move"
+                };
+                format!("{verb} {}", file_name(from))
+            }
+            WorkspaceOp::Retitle { from, .. } => format!("Rename {from}"),
+            WorkspaceOp::Create { path, .. } => format!("Create {}", file_name(path)),
+            WorkspaceOp::Trash { path, .. } => format!("Delete {}", file_name(path)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Journal {
+    undo: Vec<WorkspaceOp>,
+    redo: Vec<WorkspaceOp>,
+}
+
+#[derive(Clone, Copy)]
+enum Step {
+    Undo,
+    Redo,
+}
+
+impl Journal {
+    // The stack `step` takes from and the one it hands the op on to
+    fn stacks(&mut self, step: Step) -> (&mut Vec<WorkspaceOp>, &mut Vec<WorkspaceOp>) {
+        match step {
+            Step::Undo => (&mut self.undo, &mut self.redo),
+            Step::Redo => (&mut self.redo, &mut self.undo),
+        }
+    }
+
+    fn record(&mut self, op: WorkspaceOp) {
+        push_capped(&mut self.undo, op);
+        self.redo.clear();
+    }
+
+    fn take(&mut self, step: Step) -> Option<WorkspaceOp> {
+        self.stacks(step).0.pop()
+    }
+
+    // After a step ran: the op moves to the opposite stack, or on failure goes
+    // back where it was so the user can fix things up and retry
+    fn finish(
+        &mut self,
+        step: Step,
+        op: WorkspaceOp,
+        outcome: Result<WorkspaceOp, RosePadError>,
+    ) -> Result<(), RosePadError> {
+        let (from, to) = self.stacks(step);
+        match outcome {
+            Ok(done) => {
+                push_capped(to, done);
+                Ok(())
+            }
+            Err(e) => {
+                from.push(op);
+                Err(e)
+            }
+        }
+    }
+
+    fn state(&self) -> JournalStateDto {
+        JournalStateDto {
+            can_undo: !self.undo.is_empty(),
+            can_redo: !self.redo.is_empty(),
+            undo_label: self.undo.last().map(WorkspaceOp::label),
+            redo_label: self.redo.last().map(WorkspaceOp::label),
+        }
+    }
+}
+
+// A journal is locked for the whole of an undo or redo, file work included, so
+// a second step cannot take the next op, and a new op cannot land, halfway
+type SharedJournal = Arc<tauri::async_runtime::Mutex<Journal>>;
+
+lazy_static! {
+    // Keyed by workspace root
+    static ref JOURNALS: Mutex<HashMap<PathBuf, SharedJournal>> = Mutex::new(HashMap::new());
+}
+
+fn journal(root: &str) -> SharedJournal {
+    let mut journals = match JOURNALS.lock() {
+        Ok(g) => g,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    // Drops a trailing separator, so both spellings of a root share a journal
+    let key: PathBuf = Path::new(root).components().collect();
+    journals.entry(key).or_default().clone()
+}
+
+fn push_capped(stack: &mut Vec<WorkspaceOp>, op: WorkspaceOp) {
+    stack.push(op);
+    if stack.len() > MAX_ENTRIES {
+        stack.remove(0);
+    }
+}
+
+/// Record an operation that just happened in the workspace at `root`. A new
+/// operation drops that workspace's redo history.
+pub(crate) async fn record(root: &str, op: WorkspaceOp) {
+    journal(root).lock().await.record(op);
+}
+
+/// Record a document an import just created, in the journal of the workspace
+/// it landed in, so undo sends it to the trash like any new file.
+pub(crate) async fn record_created(app: &AppHandle, path: &str) {
+    if let Some(ws) = workspaces::workspace_of(app, Path::new(path)) {
+        let op = WorkspaceOp::Create {
+            root: ws.root.clone(),
+            path: path.to_string(),
+            trash_id: None,
+        };
+        record(&ws.root, op).await;
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalStateDto {
+    pub can_undo: bool,
+    pub can_redo: bool,
+    pub undo_label: Option<String>,
+    pub redo_label: Option<String>,
+}
+
+// Plain rename that refuses to clobber whatever took the old name since
+fn rename_back(app: &AppHandle, from: &str, to: &str) -> Result<(), RosePadError> {
+    // Ops are replayed from memory, and a workspace may have been removed since
+    paths::in_workspace(app, from)?;
+    paths::in_workspace(app, to)?;
+    let (src, dest) = (Path::new(from), Path::new(to));
+    if !src.exists() {
+        return Err(RosePadError::not_found(src));
+    }
+    if dest.exists() {
+        return Err(RosePadError::already_exists(dest));
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).map_err(|e| RosePadError::io(e, parent))?;
+    }
+    if src.is_dir() {
+        fs::rename(src, dest).map_err(|e| RosePadError::io(e, src))?;
+    } else {
+        // Moves between workspaces can cross file systems
+        workspaces::move_file(src, dest)?;
+    }
+    if dest.is_dir() {
+        let _ = index::rename_folder(app, from, to);
+    } else {
+        let _ = index::move_project(app, from, to);
+    }
+    Ok(())
+}
+
+async fn set_title(app: &AppHandle, path: &str, title: &str) -> Result<(), RosePadError> {
+    paths::in_workspace(app, path)?;
+    let html = RpadArchive::open(Path::new(path))?.document.html;
+    save_rpad_html(app, path, html, Some(title.to_string())).map(|_| ())
+}
+
+fn trash_path(app: &AppHandle, root: &str, path: &str) -> Result<String, RosePadError> {
+    paths::workspace_root(app, root)?;
+    Ok(trash::move_to_trash(Path::new(root), &PathBuf::from(path))?.id)
+}
+
+fn restore_path(app: &AppHandle, root: &str, trash_id: &str) -> Result<String, RosePadError> {
+    paths::workspace_root(app, root)?;
+    trash::restore(Path::new(root), trash_id)
+}
+
+// Each step returns the op as it should be stored on the opposite stack, since
+// restoring from the trash can land on a different path and trashing again
+// gets a new id
+async fn undo_op(app: &AppHandle, op: WorkspaceOp) -> Result<WorkspaceOp, RosePadError> {
+    Ok(match op {
+        WorkspaceOp::Move { from, to } => {
+            rename_back(app, &to, &from)?;
+            WorkspaceOp::Move { from, to }
+        }
+        WorkspaceOp::Retitle { path, from, to } => {
+            set_title(app, &path, &from).await?;
+            WorkspaceOp::Retitle { path, from, to }
+        }
+        WorkspaceOp::Create { root, path, .. } => {
+            let id = trash_path(app, &root, &path)?;
+            WorkspaceOp::Create {
+                root,
+                path,
+                trash_id: Some(id),
+            }
+        }
+        WorkspaceOp::Trash { root, trash_id, .. } => {
+            let path = restore_path(app, &root, &trash_id)?;
+            WorkspaceOp::Trash {
+                root,
+                path,
+                trash_id,
+            }
+        }
+    })
+}
+
+async fn redo_op(app: &AppHandle, op: WorkspaceOp) -> Result<WorkspaceOp, RosePadError> {
+    Ok(match op {
+        WorkspaceOp::Move { from, to } => {
+            rename_back(app, &from, &to)?;
+            WorkspaceOp::Move { from, to }
+        }
+        WorkspaceOp::Retitle { path, from, to } => {
+            set_title(app, &path, &to).await?;
+            WorkspaceOp::Retitle { path, from, to }
+        }
+        WorkspaceOp::Create { root, trash_id, .. } => {
+            let id = trash_id.ok_or("nothing to redo")?;
+            let path = restore_path(app, &root, &id)?;
+            WorkspaceOp::Create {
+                root,
+                path,
+                trash_id: None,
+            }
+        }
+        WorkspaceOp::Trash { root, path, .. } => {
+            let trash_id = trash_path(app, &root, &path)?;
+            WorkspaceOp::Trash {
+                root,
+                path,
+                trash_id,
+            }
+        }
+    })
+}
+
+/// Revert the most recent operation in the workspace at `workspace_root`.
+#[tauri::command]
+pub async fn undo_workspace_op(
+    app: AppHandle,
+    workspace_root: String,
+) -> Result<JournalStateDto, RosePadError> {
+    during("undo_workspace_op", async move {
+        paths::workspace_root(&app, &workspace_root)?;
+        let journal = journal(&workspace_root);
+        let mut j = journal.lock().await;
+        let op = j.take(Step::Undo).ok_or("nothing to undo")?;
+        let outcome = undo_op(&app, op.clone()).await;
+        j.finish(Step::Undo, op, outcome)?;
+        Ok(j.state())
+    })
+    .await
+}
+
+/// Re-apply the most recently undone operation in the workspace at `workspace_root`.
+#[tauri::command]
+pub async fn redo_workspace_op(
+    app: AppHandle,
+    workspace_root: String,
+) -> Result<JournalStateDto, RosePadError> {
+    during("redo_workspace_op", async move {
+        paths::workspace_root(&app, &workspace_root)?;
+        let journal = journal(&workspace_root);
+        let mut j = journal.lock().await;
+        let op = j.take(Step::Redo).ok_or("nothing to redo")?;
+        let outcome = redo_op(&app, op.clone()).await;
+        j.finish(Step::Redo, op, outcome)?;
+        Ok(j.state())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn workspace_journal(
+    app: AppHandle,
+    workspace_root: String,
+) -> Result<JournalStateDto, RosePadError> {
+    during("workspace_journal", async move {
+        paths::workspace_root(&app, &workspace_root)?;
+        Ok(journal(&workspace_root).lock().await.state())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mv(from: &str, to: &str) -> WorkspaceOp {
+        WorkspaceOp::Move {
+            from: from.into(),
+            to: to.into(),
+        }
+    }
+
+    fn sources(stack: &[WorkspaceOp]) -> Vec<String> {
+        stack
+            .iter()
+            .map(|op| match op {
+                WorkspaceOp::Move { from, .. } => from.clone(),
+                other => other.label(),
+            })
+            .collect()
+    }
+
+    // Run a step that succeeds and hands the op on unchanged
+    fn step(j: &mut Journal, step: Step) {
+        let op = j.take(step).unwrap();
+        j.finish(step, op.clone(), Ok(op)).unwrap();
+    }
+
+    #[test]
+    fn undo_and_redo_move_ops_between_the_stacks() {
+        let mut j = Journal::default();
+        j.record(mv("/w/a", "/w/b"));
+        j.record(mv("/w/c", "/w/d"));
+        step(&mut j, Step::Undo);
+        assert_eq!(sources(&j.undo), ["/w/a"]);
+        assert_eq!(sources(&j.redo), ["/w/c"]);
+        step(&mut j, Step::Undo);
+        assert!(j.undo.is_empty());
+        assert!(j.take(Step::Undo).is_none());
+        // Redo brings back the last undone op first
+        step(&mut j, Step::Redo);
+        assert_eq!(sources(&j.undo), ["/w/a"]);
+        assert_eq!(sources(&j.redo), ["/w/c"]);
+    }
+
+    #[test]
+    fn a_new_op_clears_the_redo_history() {
+        let mut j = Journal::default();
+        j.record(mv("/w/a", "/w/b"));
+        step(&mut j, Step::Undo);
+        assert_eq!(j.redo.len(), 1);
+        j.record(mv("/w/x", "/w/y"));
+        assert!(j.redo.is_empty());
+        assert_eq!(sources(&j.undo), ["/w/x"]);
+    }
+
+    #[test]
+    fn a_failed_step_can_be_retried() {
+        let mut j = Journal::default();
+        j.record(mv("/w/a", "/w/b"));
+        j.record(mv("/w/c", "/w/d"));
+        let op = j.take(Step::Undo).unwrap();
+        let err = RosePadError::already_exists(Path::new("/w/c"));
+        assert!(j.finish(Step::Undo, op, Err(err)).is_err());
+        assert_eq!(sources(&j.undo), ["/w/a", "/w/c"]);
+        assert!(j.redo.is_empty());
+        step(&mut j, Step::Undo);
+        let op = j.take(Step::Redo).unwrap();
+        assert!(j.finish(Step::Redo, op, Err("gone".into())).is_err());
+        assert_eq!(sources(&j.redo), ["/w/c"]);
+    }
+
+    #[test]
+    fn the_history_is_capped() {
+        let mut j = Journal::default();
+        for i in 0..MAX_ENTRIES + 5 {
+            j.record(mv(&format!("/w/{i}"), "/w/x"));
+        }
+        assert_eq!(j.undo.len(), MAX_ENTRIES);
+        // The oldest ops are the ones dropped
+        assert_eq!(sources(&j.undo[..1]), ["/w/5"]);
+        assert_eq!(
+            sources(&j.undo[MAX_ENTRIES - 1..]),
+            [format!("/w/{}", MAX_ENTRIES + 4)]
+        );
+        for _ in 0..MAX_ENTRIES {
+            step(&mut j, Step::Undo);
+        }
+        assert_eq!(j.redo.len(), MAX_ENTRIES);
+    }
+
+    #[test]
+    fn each_workspace_has_its_own_journal() {
+        let a = journal("/journal-test/a");
+        assert!(!Arc::ptr_eq(&a, &journal("/journal-test/b")));
+        assert!(Arc::ptr_eq(&a, &journal("/journal-test/a/")));
+        // While a step holds the journal, nothing else gets at its stacks
+        let held = a.try_lock().unwrap();
+        assert!(journal("/journal-test/a").try_lock().is_err());
+        drop(held);
+        assert!(a.try_lock().is_ok());
+    }
+
+    #[test]
+    fn labels_tell_renames_from_moves() {
+        assert_eq!(mv("/w/a.txt", "/w/b.txt").label(), "Rename a.txt");
+        assert_eq!(mv("/w/a.txt", "/w/sub/a.txt").label(), "Move a.txt");
+        let op = WorkspaceOp::Trash {
+            root: "/w".into(),
+            path: "/w/a.txt".into(),
+            trash_id: "1".into(),
+        };
+        assert_eq!(op.label(), "Delete a.txt");
+    }
+}
@@ -0,0 +1,517 @@
+// Paged access to text files too big to load into the editor (multi-hundred-MB
+// logs and the like). Opening a file indexes where each line starts; the UI
+// then reads line ranges, searches, and writes edited regions back without the
+// whole file ever crossing IPC or sitting in memory as one string.
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tauri::AppHandle;
+
+use crate::durable;
+use crate::error::{during, RosePadError};
+use crate::paths;
+use crate::textfile::{self, TextFormatDto};
+
+const INDEX_CHUNK: usize = 1 << 20;
+// Lines decoded per read while searching
+const SEARCH_BATCH: usize = 10_000;
+const MAX_READ_LINES: usize = 5_000;
+const DEFAULT_MAX_MATCHES: usize = 1_000;
+const PREVIEW_CHARS: usize = 200;
+
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
+
+struct LargeFile {
+    path: PathBuf,
+    format: TextFormatDto,
+    encoding: &'static Encoding,
+    // Byte offset where each line starts; never empty
+    starts: Vec<u64>,
+    len: u64,
+    trailing_newline: bool,
+    // What the file looked like when it was indexed, to refuse stale writes
+    modified: Option<std::time::SystemTime>,
+}
+
+// Entries are shared so reads and searches run without holding the lock
+lazy_static! {
+    static ref HANDLES: Mutex<HashMap<u64, Arc<LargeFile>>> = Mutex::new(HashMap::new());
+}
+
+fn handles() -> std::sync::MutexGuard<'static, HashMap<u64, Arc<LargeFile>>> {
+    match HANDLES.lock() {
+        Ok(g) => g,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LargeFileDto {
+    pub handle: u64,
+    pub path: String,
+    pub size: u64,
+    pub line_count: usize,
+    pub format: TextFormatDto,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LineRangeDto {
+    pub start: usize,
+    pub lines: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LineMatchDto {
+    pub line: usize,
+    /// Character offset of the match in the line.
+    pub column: usize,
+    pub preview: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LargeSearchDto {
+    pub matches: Vec<LineMatchDto>,
+    /// More matches exist past `max_matches`.
+    pub truncated: bool,
+}
+
+// How the ASCII control character `byte` is stored in `encoding`
+fn code_unit(encoding: &'static Encoding, byte: u8) -> Vec<u8> {
+    if encoding == UTF_16LE {
+        vec![byte, 0]
+    } else if encoding == UTF_16BE {
+        vec![0, byte]
+    } else {
+        vec![byte]
+    }
+}
+
+fn bom_len(format: &TextFormatDto, encoding: &'static Encoding) -> u64 {
+    match (format.bom, encoding == UTF_16LE || encoding == UTF_16BE) {
+        (false, _) => 0,
+        (true, true) => 2,
+        (true, false) => 3,
+    }
+}
+
+impl LargeFile {
+    fn open(path: &Path) -> Result<Self, String> {
+        let format = textfile::detect_file(path).ok_or_else(|| "cannot read file".to_string())?;
+        let encoding =
+            Encoding::for_label(format.encoding.as_bytes()).unwrap_or(encoding_rs::UTF_8);
+        let md = fs::metadata(path).map_err(|e| e.to_string())?;
+        let mut file = fs::File::open(path).map_err(|e| e.to_string())?;
+        let first = bom_len(&format, encoding);
+        file.seek(SeekFrom::Start(first))
+            .map_err(|e| e.to_string())?;
+
+        // A line ends at a line feed, a carriage return and line feed, or a bare
+        // carriage return; each of those is one byte, or one 16-bit unit in UTF-16
+        let (lf, cr) = (code_unit(encoding, b'\n'), code_unit(encoding, b'\r'));
+        let width = lf.len();
+        let mut starts = vec![first];
+        let mut buf = vec![0u8; INDEX_CHUNK];
+        let mut pos = first;
+        let mut after_cr = false;
+        loop {
+            let n = read_full(&mut file, &mut buf).map_err(|e| e.to_string())?;
+            if n == 0 {
+                break;
+            }
+            // INDEX_CHUNK is even, so 16-bit units never straddle two chunks
+            for (i, unit) in buf[..n].chunks_exact(width).enumerate() {
+                let at = pos + (i * width) as u64;
+                if unit == lf {
+                    starts.push(at + width as u64);
+                } else if after_cr {
+                    starts.push(at);
+                }
+                after_cr = unit == cr;
+            }
+            pos += n as u64;
+        }
+        if after_cr {
+            starts.push(pos);
+        }
+        let len = pos;
+        let trailing_newline = starts.len() > 1 && starts.last() == Some(&len);
+        if trailing_newline {
+            starts.pop();
+        }
+        Ok(LargeFile {
+            path: path.to_path_buf(),
+            format,
+            encoding,
+            starts,
+            len,
+            trailing_newline,
+            modified: md.modified().ok(),
+        })
+    }
+
+    fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    fn offset(&self, line: usize) -> u64 {
+        self.starts.get(line).copied().unwrap_or(self.len)
+    }
+
+    fn dto(&self, handle: u64) -> LargeFileDto {
+        LargeFileDto {
+            handle,
+            path: self.path.to_string_lossy().to_string(),
+            size: self.len,
+            line_count: self.line_count(),
+            format: self.format.clone(),
+        }
+    }
+
+    // Decoded lines `start..end`, without their line terminators
+    fn read_lines(&self, start: usize, end: usize) -> Result<Vec<String>, String> {
+        let end = end.min(self.line_count());
+        if start >= end {
+            return Ok(Vec::new());
+        }
+        let from = self.offset(start);
+        let mut bytes = vec![0u8; (self.offset(end) - from) as usize];
+        let mut file = fs::File::open(&self.path).map_err(|e| e.to_string())?;
+        file.seek(SeekFrom::Start(from))
+            .map_err(|e| e.to_string())?;
+        file.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+        Ok((start..end)
+            .map(|i| {
+                let a = (self.offset(i) - from) as usize;
+                let b = (self.offset(i + 1) - from) as usize;
+                let (text, _) = self.encoding.decode_without_bom_handling(&bytes[a..b]);
+                text.trim_end_matches(['\n', '\r']).to_string()
+            })
+            .collect())
+    }
+
+    // Where the terminator of `line` starts; only the last line can lack one
+    fn terminator_start(&self, line: usize) -> Result<u64, String> {
+        let (lf, cr) = (
+            code_unit(self.encoding, b'\n'),
+            code_unit(self.encoding, b'\r'),
+        );
+        let width = lf.len() as u64;
+        let end = self.offset(line + 1);
+        let from = end.saturating_sub(2 * width).max(self.offset(line));
+        let mut tail = vec![0u8; (end - from) as usize];
+        let mut file = fs::File::open(&self.path).map_err(|e| e.to_string())?;
+        file.seek(SeekFrom::Start(from))
+            .map_err(|e| e.to_string())?;
+        file.read_exact(&mut tail).map_err(|e| e.to_string())?;
+        if tail == [cr, lf].concat() {
+            Ok(end - 2 * width)
+        } else {
+            Ok(end - width)
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        match fs::metadata(&self.path) {
+            Ok(md) => md.len() != self.len || md.modified().ok() != self.modified,
+            Err(_) => true,
+        }
+    }
+}
+
+fn read_full(file: &mut fs::File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+fn get_handle(handle: u64) -> Result<Arc<LargeFile>, String> {
+    handles()
+        .get(&handle)
+        .cloned()
+        .ok_or_else(|| "file is not open".to_string())
+}
+
+/// Index a file for paged reading. Close the handle when done with it.
+#[tauri::command]
+pub async fn open_large_file(app: AppHandle, path: String) -> Result<LargeFileDto, RosePadError> {
+    during("open_large_file", async move {
+        // Saves go through the handle, so this check covers them too
+        paths::document(&app, &path)?;
+        let file = LargeFile::open(Path::new(&path))?;
+        let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+        let dto = file.dto(handle);
+        handles().insert(handle, Arc::new(file));
+        Ok(dto)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn close_large_file(handle: u64) -> Result<(), RosePadError> {
+    during("close_large_file", async move {
+        handles().remove(&handle);
+        Ok(())
+    })
+    .await
+}
+
+/// Lines `start..start + count`, clamped to the end of the file.
+#[tauri::command]
+pub async fn read_large_file_lines(
+    handle: u64,
+    start: usize,
+    count: usize,
+) -> Result<LineRangeDto, RosePadError> {
+    during("read_large_file_lines", async move {
+        let count = count.min(MAX_READ_LINES);
+        let lines = get_handle(handle)?.read_lines(start, start.saturating_add(count))?;
+        Ok(LineRangeDto { start, lines })
+    })
+    .await
+}
+
+/// Find `query` line by line, starting at line `from` (0 when omitted).
+#[tauri::command]
+pub async fn search_large_file(
+    handle: u64,
+    query: String,
+    case_sensitive: Option<bool>,
+    from: Option<usize>,
+    max_matches: Option<usize>,
+) -> Result<LargeSearchDto, RosePadError> {
+    during("search_large_file", async move {
+        if query.is_empty() {
+            return Ok(LargeSearchDto {
+                matches: Vec::new(),
+                truncated: false,
+            });
+        }
+        let case_sensitive = case_sensitive.unwrap_or(false);
+        let needle = if case_sensitive {
+            query
+        } else {
+            query.to_lowercase()
+        };
+        let max = max_matches.unwrap_or(DEFAULT_MAX_MATCHES).max(1);
+        let f = get_handle(handle)?;
+        let mut matches = Vec::new();
+        let mut start = from.unwrap_or(0);
+        while start < f.line_count() {
+            let lines = f.read_lines(start, start + SEARCH_BATCH)?;
+            for (i, line) in lines.iter().enumerate() {
+                let hay = if case_sensitive {
+                    std::borrow::Cow::Borrowed(line.as_str())
+                } else {
+                    std::borrow::Cow::Owned(line.to_lowercase())
+                };
+                let Some(byte_col) = hay.find(&needle) else {
+                    continue;
+                };
+                if matches.len() == max {
+                    return Ok(LargeSearchDto {
+                        matches,
+                        truncated: true,
+                    });
+                }
+                matches.push(LineMatchDto {
+                    line: start + i,
+                    column: hay[..byte_col].chars().count(),
+                    preview: line.chars().take(PREVIEW_CHARS).collect(),
+                });
+            }
+            start += SEARCH_BATCH;
+        }
+        Ok(LargeSearchDto {
+            matches,
+            truncated: false,
+        })
+    })
+    .await
+}
+
+// Replace lines `start..end` of `file` with `lines` on disk
+fn write_region(
+    file: &LargeFile,
+    start: usize,
+    end: usize,
+    lines: &[String],
+) -> Result<(), RosePadError> {
+    if start > end || end > file.line_count() {
+        return Err("line range out of bounds".into());
+    }
+    if file.is_stale() {
+        return Err("the file changed on disk; reopen it before saving".into());
+    }
+
+    let mut text = lines.join("\n");
+    if !lines.is_empty() {
+        // The last line keeps its missing terminator when the region reaches the end
+        if end < file.line_count() || file.trailing_newline {
+            text.push('\n');
+        }
+        // Appending after a last line that has no terminator
+        if start == file.line_count() && !file.trailing_newline {
+            text.insert(0, '\n');
+        }
+    }
+    let region = textfile::encode(
+        &text,
+        &TextFormatDto {
+            bom: false,
+            ..file.format.clone()
+        },
+    )?;
+    let (mut from, to) = (file.offset(start), file.offset(end));
+    // Deleting the last lines of a file that ends without a terminator makes
+    // the line before them the last one, so it loses its terminator too
+    if lines.is_empty()
+        && start > 0
+        && start < end
+        && end == file.line_count()
+        && !file.trailing_newline
+    {
+        from = file.terminator_start(start - 1)?;
+    }
+    let path = &file.path;
+    let mut src = fs::File::open(path).map_err(|e| RosePadError::io(e, path))?;
+    durable::write_atomic_with(path, |out| {
+        io::copy(&mut (&mut src).take(from), out)?;
+        out.write_all(&region)?;
+        src.seek(SeekFrom::Start(to))?;
+        io::copy(&mut src, out)?;
+        Ok(())
+    })
+}
+
+/// Replace lines `start..end` with `lines` and re-index the file. Everything
+/// outside the region is copied over byte for byte.
+///
+/// Unlike the other saves this takes no expected version and records no
+/// history: both would read and hash the whole file on every edit, which is
+/// what paging avoids. Instead it fails if the file's size or modification time
+/// changed since it was indexed.
+#[tauri::command]
+pub async fn save_large_file_region(
+    handle: u64,
+    start: usize,
+    end: usize,
+    lines: Vec<String>,
+) -> Result<LargeFileDto, RosePadError> {
+    during("save_large_file_region", async move {
+        let file = get_handle(handle)?;
+        write_region(&file, start, end, &lines)?;
+        let reindexed = LargeFile::open(&file.path)?;
+        let dto = reindexed.dto(handle);
+        handles().insert(handle, Arc::new(reindexed));
+        Ok(dto)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::{self, Scratch};
+
+    // The folder holding the file goes away when the returned `Scratch` drops
+    fn temp_file(name: &str, bytes: &[u8]) -> (Scratch, PathBuf) {
+        let dir = scratch::dir(&format!("largefile-{name}"));
+        let path = dir.join("big.txt");
+        fs::write(&path, bytes).unwrap();
+        (dir, path)
+    }
+
+    // Write a region of a file holding `before` and return what ends up on disk
+    fn edit(name: &str, before: &str, start: usize, end: usize, lines: &[&str]) -> String {
+        let (_dir, path) = temp_file(name, before.as_bytes());
+        let file = LargeFile::open(&path).unwrap();
+        let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+        write_region(&file, start, end, &lines).unwrap();
+        fs::read_to_string(&path).unwrap()
+    }
+
+    #[test]
+    fn regions_are_replaced_in_place() {
+        assert_eq!(edit("middle", "a\nb\nc\n", 1, 2, &["B"]), "a\nB\nc\n");
+        assert_eq!(edit("grow", "a\nb\nc\n", 1, 2, &["x", "y"]), "a\nx\ny\nc\n");
+        assert_eq!(edit("delete", "a\nb\nc\n", 1, 2, &[]), "a\nc\n");
+        assert_eq!(edit("insert", "a\nb\n", 1, 1, &["n"]), "a\nn\nb\n");
+        assert_eq!(edit("last", "a\nb", 1, 2, &["B"]), "a\nB");
+        assert_eq!(edit("delete-last", "a\nb", 1, 2, &[]), "a");
+        assert_eq!(edit("delete-last-two", "a\r\nb\r\nc", 1, 3, &[]), "a");
+        assert_eq!(edit("delete-all", "a\nb", 0, 2, &[]), "");
+        assert_eq!(edit("delete-last-lf", "a\nb\n", 1, 2, &[]), "a\n");
+        // Bare carriage returns end lines too
+        assert_eq!(edit("cr", "a\rb\rc\r", 1, 2, &["B"]), "a\rB\rc\r");
+        assert_eq!(edit("cr-delete-last", "a\rb", 1, 2, &[]), "a");
+        assert_eq!(edit("cr-append", "a\rb", 2, 2, &["c"]), "a\rb\rc");
+    }
+
+    #[test]
+    fn appending_at_the_end() {
+        assert_eq!(edit("append", "a\nb\n", 2, 2, &["c"]), "a\nb\nc\n");
+        // No terminator to append after, so one is added before the new lines
+        assert_eq!(edit("append-bare", "a\nb", 2, 2, &["c", "d"]), "a\nb\nc\nd");
+        assert_eq!(edit("append-nothing", "a\nb", 2, 2, &[]), "a\nb");
+    }
+
+    #[test]
+    fn line_endings_are_kept() {
+        assert_eq!(
+            edit("crlf", "a\r\nb\r\nc\r\n", 1, 2, &["B"]),
+            "a\r\nB\r\nc\r\n"
+        );
+        assert_eq!(edit("crlf-append", "a\r\nb", 2, 2, &["c"]), "a\r\nb\r\nc");
+
+        // Mixed endings, in UTF-16 as well
+        let utf16: Vec<u8> = [
+            &[0xFF, 0xFE][..],
+            &"a\rb\r\nc\nd\r"
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes)
+                .collect::<Vec<_>>(),
+        ]
+        .concat();
+        for (name, bytes) in [("mixed", b"a\rb\r\nc\nd\r".to_vec()), ("mixed-16", utf16)] {
+            let (_dir, path) = temp_file(name, &bytes);
+            let file = LargeFile::open(&path).unwrap();
+            assert!(file.trailing_newline, "{name}");
+            assert_eq!(
+                file.read_lines(0, 10).unwrap(),
+                ["a", "b", "c", "d"],
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn stale_and_out_of_range_writes_are_refused() {
+        let (_dir, path) = temp_file("stale", b"a\nb\n");
+        let file = LargeFile::open(&path).unwrap();
+        assert!(write_region(&file, 1, 5, &[]).is_err());
+        assert!(write_region(&file, 2, 1, &[]).is_err());
+        fs::write(&path, "changed\n").unwrap();
+        assert!(write_region(&file, 0, 1, &["x".into()]).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "changed\n");
+    }
+}
@@ -1,18 +1,44 @@
 use std::env;
+use std::path::Path;
 use std::sync::Mutex;
 
 use lazy_static::lazy_static;
 use tauri::{Emitter, Manager};
 
-use tauri_plugin_sql::{Migration, MigrationKind};
-
 #[cfg(not(debug_assertions))]
 use tauri_plugin_updater::UpdaterExt;
 
 mod discord_rpc;
 
+mod attachments;
+mod dialogs;
+mod diff;
+mod docx;
+mod drafts;
+mod durable;
+mod encryption;
+mod error;
+mod history;
+mod html;
+mod index;
+mod journal;
+mod largefile;
+mod markdown;
+mod paths;
+mod pdf;
+mod pdf_fonts;
+mod pdf_images;
+mod rpad;
+mod scan;
+#[cfg(test)]
+mod scratch;
+mod search;
 mod settings;
+mod textfile;
+mod trash;
+mod watcher;
 mod workspace;
+mod workspaces;
 
 lazy_static! {
     static ref PENDING_OPEN_PATHS: Mutex<Vec<String>> = Mutex::new(Vec::new());
@@ -27,12 +53,17 @@ fn enqueue_open_paths(args: &[String]) {
         Err(poisoned) => poisoned.into_inner(),
     };
     // Skip the executable path and only keep meaningful payload
-    guard.extend(
-        args.iter()
-            .skip(1)
-            .filter(|s| !s.is_empty())
-            .cloned(),
-    );
+    for arg in args.iter().skip(1).filter(|s| !s.is_empty()) {
+        // Files the app was launched with may be opened wherever they are, and
+        // folders may become workspaces
+        let path = Path::new(arg);
+        let _ = if path.is_dir() {
+            paths::grant_root(path)
+        } else {
+            paths::grant(path)
+        };
+        guard.push(arg.clone());
+    }
 }
 
 #[tauri::command]
@@ -62,24 +93,16 @@ async fn take_pending_open_paths() -> Vec<String> {
 pub fn run() {
     enqueue_open_paths(&env::args().collect::<Vec<_>>());
 
-    let migrations = vec![Migration {
-        version: 1,
-        description: "init",
-        sql: include_str!("schema_v1.sql"),
-        kind: MigrationKind::Up,
-    }];
     let _ = discord_rpc::connect_rpc();
     let mut builder = tauri::Builder::default()
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_opener::init())
-        .plugin(
-            tauri_plugin_sql::Builder::new()
-                .add_migrations("sqlite:rosepad.db", migrations)
-                .build(),
-        )
         .plugin(tauri_plugin_store::Builder::default().build())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
+        .register_uri_scheme_protocol(attachments::ASSET_SCHEME, |ctx, request| {
+            attachments::serve_asset(ctx.app_handle(), &request)
+        })
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
             enqueue_open_paths(&args);
@@ -107,19 +130,87 @@ pub fn run() {
             workspace::rename_physical_folder,
             workspace::delete_physical_folder,
             workspace::create_physical_folder,
-            workspace::watch_physical_folders,
+            watcher::start_watching,
+            watcher::stop_watching,
+            watcher::watcher_status,
+            workspaces::list_workspaces,
+            workspaces::active_workspace,
+            workspaces::add_workspace,
+            workspaces::rename_workspace,
+            workspaces::remove_workspace,
+            workspaces::switch_workspace,
+            workspaces::search_workspaces,
+            workspaces::move_to_workspace,
+            dialogs::open_file_dialog,
+            dialogs::save_file_dialog,
+            dialogs::open_folder_dialog,
             workspace::read_rpad_data,
             workspace::write_rpad_html,
             workspace::write_text_atomic,
+            workspace::document_version,
+            workspace::document_size,
+            textfile::read_text_document,
+            textfile::convert_text_format,
+            largefile::open_large_file,
+            largefile::close_large_file,
+            largefile::read_large_file_lines,
+            largefile::search_large_file,
+            largefile::save_large_file_region,
+            encryption::rpad_encryption_status,
+            encryption::encrypt_rpad,
+            encryption::unlock_rpad,
+            encryption::lock_rpad,
+            encryption::decrypt_rpad,
+            durable::recover_interrupted_writes,
+            drafts::put_draft,
+            drafts::list_drafts,
+            drafts::read_draft,
+            drafts::discard_draft,
             workspace::import_project,
             workspace::create_rpad_project,
+            attachments::add_rpad_attachment,
+            attachments::add_rpad_attachment_bytes,
+            attachments::list_rpad_attachments,
+            attachments::extract_rpad_attachment,
+            attachments::remove_rpad_attachment,
+            markdown::export_markdown,
+            markdown::import_markdown,
+            docx::export_docx,
+            docx::import_docx,
+            pdf::export_pdf,
+            search::search_workspace,
+            index::get_workspace_tree,
+            index::search_projects,
+            index::project_exists,
+            index::set_physical_folder_color,
+            index::list_tags,
+            index::get_project_tags,
+            index::set_project_tags,
+            trash::list_trash,
+            trash::restore_trash_item,
+            trash::empty_trash,
+            trash::purge_trash,
+            diff::diff_documents,
+            diff::merge_documents,
+            history::list_history,
+            history::read_snapshot,
+            history::diff_snapshot,
+            history::restore_snapshot,
+            journal::undo_workspace_op,
+            journal::redo_workspace_op,
+            journal::workspace_journal,
             discord_rpc::update_activity,
             discord_rpc::clear_activity,
             settings::settings,
             take_pending_open_paths
-        ])/*  */
+        ]) /*  */
         .setup(|app| {
-            #[cfg(not(debug_assertions))] {
+            let handle = app.handle().clone();
+            std::thread::spawn(move || {
+                let _ = history::prune_all(&handle);
+            });
+            #[cfg(not(debug_assertions))]
+            {
                 let handle = app.handle().clone();
                 tauri::async_runtime::spawn(async move {
                     let _ = update(handle).await;
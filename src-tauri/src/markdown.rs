@@ -0,0 +1,837 @@
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use tauri::AppHandle;
+
+use crate::attachments;
+use crate::durable;
+use crate::error::{during, RosePadError};
+use crate::html::{self, escape_attr, escape_text, Element, Node};
+use crate::journal;
+use crate::paths;
+use crate::rpad::{RpadArchive, RpadEntry};
+use crate::workspace::unique_dest;
+
+// Inline tags we let through verbatim when they appear as raw HTML in Markdown;
+// they map onto marks the editor understands (see rMarks.ts)
+const INLINE_HTML_TAGS: &[&str] = &["span", "u", "s", "strong", "em", "code"];
+
+// Styles carried across as inline <span> HTML since Markdown has no syntax for them
+const SPAN_STYLES: &[&str] = &["color", "background-color", "font-size"];
+
+// ---------- HTML → Markdown ----------
+
+struct MdWriter<'a> {
+    footnotes: Vec<String>,
+    image: &'a mut dyn FnMut(&str) -> String,
+}
+
+/// Convert editor HTML to CommonMark with GFM extensions (strikethrough, footnotes).
+/// `image` maps each `<img src>` to the destination written into the Markdown.
+pub fn html_to_markdown(input: &str, image: &mut dyn FnMut(&str) -> String) -> String {
+    let nodes = html::parse(input);
+    let mut w = MdWriter {
+        footnotes: Vec::new(),
+        image,
+    };
+    let mut out = w.blocks(&nodes).join("\n\n");
+    if !w.footnotes.is_empty() {
+        out.push_str("\n\n");
+        let defs: Vec<String> = w
+            .footnotes
+            .iter()
+            .enumerate()
+            .map(|(i, text)| format!("[^{}]: {}", i + 1, text))
+            .collect();
+        out.push_str(&defs.join("\n"));
+    }
+    out.push('\n');
+    out
+}
+
+fn is_block_tag(tag: &str) -> bool {
+    matches!(
+        tag,
+        "p" | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "ul"
+            | "ol"
+            | "li"
+            | "pre"
+            | "div"
+            | "blockquote"
+            | "hr"
+            | "table"
+            | "section"
+            | "article"
+    )
+}
+
+impl MdWriter<'_> {
+    fn blocks(&mut self, nodes: &[Node]) -> Vec<String> {
+        let mut out = Vec::new();
+        let mut pending_inline: Vec<Node> = Vec::new();
+
+        for node in nodes {
+            match node {
+                Node::Element(el) if is_block_tag(&el.tag) => {
+                    self.flush_inline(&mut pending_inline, &mut out);
+                    if let Some(block) = self.block(el) {
+                        out.push(block);
+                    }
+                }
+                Node::Text(t) if t.trim().is_empty() && pending_inline.is_empty() => {}
+                other => pending_inline.push(other.clone()),
+            }
+        }
+        self.flush_inline(&mut pending_inline, &mut out);
+        out
+    }
+
+    fn flush_inline(&mut self, pending: &mut Vec<Node>, out: &mut Vec<String>) {
+        if pending.is_empty() {
+            return;
+        }
+        let text = self.inline(pending);
+        pending.clear();
+        if !text.trim().is_empty() {
+            out.push(escape_block_start(text.trim()));
+        }
+    }
+
+    fn block(&mut self, el: &Element) -> Option<String> {
+        match el.tag.as_str() {
+            "p" | "li" => {
+                let text = self.inline(&el.children);
+                let text = text.trim();
+                (!text.is_empty()).then(|| escape_block_start(text))
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = el.tag[1..].parse::<usize>().unwrap_or(1);
+                let text = self.inline(&el.children);
+                Some(format!("{} {}", "#".repeat(level), text.trim()))
+            }
+            "ul" | "ol" => Some(self.list(el)),
+            "pre" => {
+                let code = el.text();
+                let code = code.strip_suffix('\n').unwrap_or(&code);
+                let fence = "`".repeat(longest_run(code, '`').max(2) + 1);
+                Some(format!("{fence}\n{code}\n{fence}"))
+            }
+            "hr" => Some("---".into()),
+            _ => {
+                let inner = self.blocks(&el.children);
+                (!inner.is_empty()).then(|| inner.join("\n\n"))
+            }
+        }
+    }
+
+    fn list(&mut self, el: &Element) -> String {
+        let ordered = el.tag == "ol";
+        let mut n = el
+            .attr("start")
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(1);
+        let mut items = Vec::new();
+        for child in &el.children {
+            let li = match child {
+                Node::Element(li) if li.tag == "li" => li,
+                _ => continue,
+            };
+            let marker = if ordered {
+                format!("{n}. ")
+            } else {
+                "- ".to_string()
+            };
+            n += 1;
+            let body = self.blocks(&li.children).join("\n\n");
+            let indent = " ".repeat(marker.len());
+            let mut lines = body.lines();
+            let mut item = format!("{marker}{}", lines.next().unwrap_or(""));
+            for line in lines {
+                item.push('\n');
+                if !line.is_empty() {
+                    item.push_str(&indent);
+                    item.push_str(line);
+                }
+            }
+            items.push(item);
+        }
+        items.join("\n")
+    }
+
+    fn inline(&mut self, nodes: &[Node]) -> String {
+        let mut out = String::new();
+        for node in nodes {
+            match node {
+                Node::Text(t) => out.push_str(&escape_md(&collapse_ws(t))),
+                Node::Element(el) => out.push_str(&self.inline_element(el)),
+            }
+        }
+        out
+    }
+
+    fn inline_element(&mut self, el: &Element) -> String {
+        match el.tag.as_str() {
+            "strong" | "b" => wrap(&self.inline(&el.children), "**", "**"),
+            "em" | "i" => wrap(&self.inline(&el.children), "*", "*"),
+            "s" | "del" | "strike" => wrap(&self.inline(&el.children), "~~", "~~"),
+            "u" => wrap(&self.inline(&el.children), "<u>", "</u>"),
+            "code" => code_span(&el.text()),
+            "br" => "\\\n".into(),
+            "footnote" => {
+                let text = self.inline(&el.children);
+                self.footnotes.push(text.trim().to_string());
+                format!("[^{}]", self.footnotes.len())
+            }
+            "img" => {
+                let src = (self.image)(el.attr("src").unwrap_or(""));
+                let alt = escape_md(el.attr("alt").unwrap_or(""));
+                format!("![{alt}]({})", link_dest(&src))
+            }
+            "a" => {
+                let text = self.inline(&el.children);
+                match el.attr("href") {
+                    Some(href) if !href.is_empty() => format!("[{text}]({})", link_dest(href)),
+                    _ => text,
+                }
+            }
+            "span" => {
+                let inner = self.inline(&el.children);
+                let style: Vec<String> = SPAN_STYLES
+                    .iter()
+                    .filter_map(|prop| el.style(prop).map(|v| format!("{prop}: {v}")))
+                    .collect();
+                if style.is_empty() || inner.trim().is_empty() {
+                    inner
+                } else {
+                    let open = format!("<span style=\"{}\">", escape_attr(&style.join("; ")));
+                    wrap(&inner, &open, "</span>")
+                }
+            }
+            _ => self.inline(&el.children),
+        }
+    }
+}
+
+// Emphasis markers must hug non-space text, so leading/trailing spaces move outside
+fn wrap(inner: &str, open: &str, close: &str) -> String {
+    let trimmed = inner.trim();
+    if trimmed.is_empty() {
+        return inner.to_string();
+    }
+    let lead = &inner[..inner.len() - inner.trim_start().len()];
+    let trail = &inner[inner.trim_end().len()..];
+    format!("{lead}{open}{trimmed}{close}{trail}")
+}
+
+fn collapse_ws(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut last_ws = false;
+    for c in s.chars() {
+        if c == '\n' || c == '\t' || c == '\r' || c == ' ' {
+            if !last_ws {
+                out.push(' ');
+            }
+            last_ws = true;
+        } else {
+            out.push(c);
+            last_ws = false;
+        }
+    }
+    out
+}
+
+fn escape_md(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '~' | '|'
+        ) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+// Text that would otherwise be read as a heading, list item or rule
+fn escape_block_start(s: &str) -> String {
+    let first = s.chars().next();
+    if matches!(first, Some('#') | Some('-') | Some('+') | Some('=')) {
+        return format!("\\{s}");
+    }
+    let digits = s.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 && matches!(s[digits..].chars().next(), Some('.') | Some(')')) {
+        return format!("{}\\{}", &s[..digits], &s[digits..]);
+    }
+    s.to_string()
+}
+
+fn longest_run(s: &str, ch: char) -> usize {
+    let mut best = 0;
+    let mut cur = 0;
+    for c in s.chars() {
+        if c == ch {
+            cur += 1;
+            best = best.max(cur);
+        } else {
+            cur = 0;
+        }
+    }
+    best
+}
+
+fn code_span(code: &str) -> String {
+    if code.is_empty() {
+        return String::new();
+    }
+    let ticks = "`".repeat(longest_run(code, '`') + 1);
+    if code.starts_with('`') || code.ends_with('`') {
+        format!("{ticks} {code} {ticks}")
+    } else {
+        format!("{ticks}{code}{ticks}")
+    }
+}
+
+fn link_dest(url: &str) -> String {
+    if url.contains([' ', '(', ')', '<', '>']) {
+        format!("<{}>", url.replace('<', "%3C").replace('>', "%3E"))
+    } else {
+        url.to_string()
+    }
+}
+
+// ---------- Markdown → HTML ----------
+
+struct HtmlWriter<'b> {
+    out: String,
+    footnotes: &'b HashMap<String, String>,
+    image: &'b mut dyn FnMut(&str) -> String,
+    // Footnote bodies are rendered without block wrappers
+    inline_only: bool,
+    // One entry per open <li>: whether we opened an implicit <p> for tight lists
+    items: Vec<bool>,
+    in_para: bool,
+    in_code_block: bool,
+    table_cell: Option<usize>,
+    capture: Option<(String, String)>,
+}
+
+/// Convert Markdown (CommonMark + GFM) into HTML shaped like the editor schema.
+/// `image` maps each image destination to the `src` stored in the document.
+pub fn markdown_to_html(md: &str, image: &mut dyn FnMut(&str) -> String) -> String {
+    let opts = Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_TASKLISTS;
+    let events: Vec<Event> = Parser::new_ext(md, opts).collect();
+
+    // First pass: render footnote definitions so references can embed them inline
+    let mut footnotes: HashMap<String, String> = HashMap::new();
+    let mut body: Vec<Event> = Vec::with_capacity(events.len());
+    let mut i = 0;
+    while i < events.len() {
+        if let Event::Start(Tag::FootnoteDefinition(label)) = &events[i] {
+            let label = label.to_string();
+            let start = i + 1;
+            while i < events.len() && !matches!(events[i], Event::End(TagEnd::FootnoteDefinition)) {
+                i += 1;
+            }
+            let empty = HashMap::new();
+            let mut w = HtmlWriter::new(&empty, image, true);
+            w.render(&events[start..i.min(events.len())]);
+            footnotes.insert(label, w.out.trim().to_string());
+        } else {
+            body.push(events[i].clone());
+        }
+        i += 1;
+    }
+
+    let mut w = HtmlWriter::new(&footnotes, image, false);
+    w.render(&body);
+    w.out
+}
+
+impl<'b> HtmlWriter<'b> {
+    fn new(
+        footnotes: &'b HashMap<String, String>,
+        image: &'b mut dyn FnMut(&str) -> String,
+        inline_only: bool,
+    ) -> Self {
+        HtmlWriter {
+            out: String::new(),
+            footnotes,
+            image,
+            inline_only,
+            items: Vec::new(),
+            in_para: false,
+            in_code_block: false,
+            table_cell: None,
+            capture: None,
+        }
+    }
+
+    fn block_tag(&mut self, tag: &str) {
+        if !self.inline_only {
+            self.out.push_str(tag);
+        }
+    }
+
+    // Leaving inline content inside a tight list item: close the implicit <p>
+    fn close_implicit_para(&mut self) {
+        if let Some(open) = self.items.last_mut() {
+            if *open {
+                *open = false;
+                self.in_para = false;
+                if !self.inline_only {
+                    self.out.push_str("</p>");
+                }
+            }
+        }
+    }
+
+    fn ensure_para(&mut self) {
+        if self.in_para || self.in_code_block || self.table_cell.is_some() {
+            return;
+        }
+        if let Some(open) = self.items.last_mut() {
+            *open = true;
+            self.in_para = true;
+            if !self.inline_only {
+                self.out.push_str("<p>");
+            }
+        }
+    }
+
+    fn text(&mut self, t: &str) {
+        if let Some((_, alt)) = self.capture.as_mut() {
+            alt.push_str(t);
+            return;
+        }
+        self.ensure_para();
+        self.out.push_str(&escape_text(t));
+    }
+
+    fn render(&mut self, events: &[Event]) {
+        for ev in events {
+            match ev {
+                Event::Start(tag) => self.start(tag),
+                Event::End(tag) => self.end(*tag),
+                Event::Text(t) => self.text(t),
+                Event::Code(c) => {
+                    self.ensure_para();
+                    self.out
+                        .push_str(&format!("<code>{}</code>", escape_text(c)));
+                }
+                Event::InlineMath(t) | Event::DisplayMath(t) => self.text(t),
+                Event::InlineHtml(raw) => {
+                    if allowed_inline_html(raw) {
+                        self.ensure_para();
+                        self.out.push_str(raw);
+                    }
+                }
+                Event::Html(raw) => {
+                    // Keep the words of raw HTML blocks, drop the markup
+                    let text: String = html::parse(raw)
+                        .iter()
+                        .map(|n| match n {
+                            Node::Text(t) => t.clone(),
+                            Node::Element(e) => e.text(),
+                        })
+                        .collect();
+                    if !text.trim().is_empty() {
+                        self.ensure_para();
+                        self.out.push_str(&escape_text(text.trim()));
+                    }
+                }
+                Event::FootnoteReference(label) => {
+                    self.ensure_para();
+                    match self.footnotes.get(label.as_ref()) {
+                        Some(body) => {
+                            self.out.push_str(&format!("<footnote>{body}</footnote>"));
+                        }
+                        None => self.out.push_str(&escape_text(&format!("[^{label}]"))),
+                    }
+                }
+                Event::SoftBreak => self.text(" "),
+                Event::HardBreak => {
+                    // No hard_break node in the schema: split into a new paragraph
+                    if self.in_para && !self.inline_only && self.table_cell.is_none() {
+                        self.out.push_str("</p><p>");
+                    } else {
+                        self.text(" ");
+                    }
+                }
+                Event::Rule => {}
+                Event::TaskListMarker(checked) => {
+                    self.text(if *checked { "☑ " } else { "☐ " });
+                }
+            }
+        }
+    }
+
+    fn start(&mut self, tag: &Tag) {
+        match tag {
+            Tag::Paragraph => {
+                self.close_implicit_para();
+                if self.table_cell.is_none() {
+                    if self.inline_only && self.out.ends_with(|c: char| !c.is_whitespace()) {
+                        self.out.push(' ');
+                    }
+                    self.block_tag("<p>");
+                    self.in_para = true;
+                }
+            }
+            Tag::Heading { level, .. } => {
+                self.close_implicit_para();
+                let n = *level as usize;
+                self.block_tag(&format!("<h{n}>"));
+            }
+            Tag::CodeBlock(_) => {
+                self.close_implicit_para();
+                self.block_tag("<pre><code>");
+                self.in_code_block = true;
+            }
+            Tag::List(start) => {
+                self.close_implicit_para();
+                match start {
+                    Some(1) => self.block_tag("<ol>"),
+                    Some(n) => self.block_tag(&format!("<ol start=\"{n}\">")),
+                    None => self.block_tag("<ul>"),
+                }
+            }
+            Tag::Item => {
+                self.block_tag("<li>");
+                self.items.push(false);
+            }
+            Tag::Table(_) => self.close_implicit_para(),
+            Tag::TableHead | Tag::TableRow => {
+                self.block_tag("<p>");
+                self.table_cell = Some(0);
+            }
+            Tag::TableCell => {
+                if let Some(n) = self.table_cell.as_mut() {
+                    if *n > 0 {
+                        self.out.push_str(" | ");
+                    }
+                    *n += 1;
+                }
+            }
+            Tag::Emphasis => {
+                self.ensure_para();
+                self.out.push_str("<em>");
+            }
+            Tag::Strong => {
+                self.ensure_para();
+                self.out.push_str("<strong>");
+            }
+            Tag::Strikethrough => {
+                self.ensure_para();
+                self.out.push_str("<s>");
+            }
+            Tag::Image { dest_url, .. } => {
+                self.ensure_para();
+                self.capture = Some((dest_url.to_string(), String::new()));
+            }
+            Tag::BlockQuote(_) => self.close_implicit_para(),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph if self.table_cell.is_none() => {
+                self.block_tag("</p>");
+                self.in_para = false;
+            }
+            TagEnd::Heading(level) => {
+                let n = level as usize;
+                self.block_tag(&format!("</h{n}>"));
+            }
+            TagEnd::CodeBlock => {
+                // The editor stores code without the trailing newline Markdown adds
+                if self.out.ends_with('\n') {
+                    self.out.pop();
+                }
+                self.block_tag("</code></pre>");
+                self.in_code_block = false;
+            }
+            TagEnd::List(ordered) => self.block_tag(if ordered { "</ol>" } else { "</ul>" }),
+            TagEnd::Item => {
+                self.close_implicit_para();
+                self.items.pop();
+                self.block_tag("</li>");
+            }
+            TagEnd::TableHead | TagEnd::TableRow => {
+                self.block_tag("</p>");
+                self.table_cell = None;
+            }
+            TagEnd::Emphasis => self.out.push_str("</em>"),
+            TagEnd::Strong => self.out.push_str("</strong>"),
+            TagEnd::Strikethrough => self.out.push_str("</s>"),
+            TagEnd::Image => {
+                if let Some((src, alt)) = self.capture.take() {
+                    let src = (self.image)(&src);
+                    if alt.is_empty() {
+                        self.out
+                            .push_str(&format!("<img src=\"{}\">", escape_attr(&src)));
+                    } else {
+                        self.out.push_str(&format!(
+                            "<img src=\"{}\" alt=\"{}\">",
+                            escape_attr(&src),
+                            escape_attr(&alt)
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn allowed_inline_html(raw: &str) -> bool {
+    let t = raw.trim();
+    let name = t
+        .trim_start_matches('<')
+        .trim_start_matches('/')
+        .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    t.starts_with('<') && INLINE_HTML_TAGS.contains(&name.as_str())
+}
+
+// ---------- Commands ----------
+
+/// Export an .rpad document to a Markdown file. Embedded images are written to
+/// a `<name>_assets` folder next to the Markdown file and linked relatively.
+#[tauri::command]
+pub async fn export_markdown(
+    app: AppHandle,
+    path: String,
+    dest: String,
+) -> Result<String, RosePadError> {
+    during("export_markdown", async move {
+        paths::document(&app, &path)?;
+        paths::document(&app, &dest)?;
+        let archive = RpadArchive::open(Path::new(&path))?;
+        let destp = PathBuf::from(&dest);
+        let parent = destp
+            .parent()
+            .ok_or_else(|| "invalid destination".to_string())?;
+        let stem = destp
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("document")
+            .to_string();
+        let assets_dir_name = format!("{stem}_assets");
+
+        let mut write_err: Option<RosePadError> = None;
+        let mut resolve = |src: &str| -> String {
+            let entry = attachments::entry_for_src(src)
+                .and_then(|name| archive.entries.iter().find(|e| e.name == name));
+            let entry = match entry {
+                Some(e) => e,
+                None => return src.to_string(),
+            };
+            let file = entry.name.rsplit('/').next().unwrap_or(&entry.name);
+            let dir = parent.join(&assets_dir_name);
+            let res = fs::create_dir_all(&dir)
+                .map_err(|e| RosePadError::io(e, &dir))
+                .and_then(|_| durable::write_atomic(&dir.join(file), &entry.data));
+            if let Err(e) = res {
+                write_err.get_or_insert(e);
+            }
+            format!("{assets_dir_name}/{file}")
+        };
+        let md = html_to_markdown(&archive.document.html, &mut resolve);
+        if let Some(e) = write_err {
+            return Err(e);
+        }
+        durable::write_atomic(&destp, md.as_bytes())?;
+        Ok(destp.to_string_lossy().to_string())
+    })
+    .await
+}
+
+/// Convert a Markdown file into a new .rpad project in `dest_dir` (defaults to
+/// the active workspace's root). Local images are embedded as attachments.
+#[tauri::command]
+pub async fn import_markdown(
+    app: AppHandle,
+    src: String,
+    dest_dir: Option<String>,
+) -> Result<String, RosePadError> {
+    during("import_markdown", async move {
+        let srcp = paths::document(&app, &src)?;
+        let dest = paths::import_dir(&app, dest_dir.as_deref())?;
+        if !srcp.is_file() {
+            return Err(RosePadError::not_a_file(&srcp));
+        }
+        let md = fs::read_to_string(&srcp).map_err(|e| RosePadError::io(e, &srcp))?;
+        let src_dir = srcp
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_else(|| PathBuf::from("."));
+        let name = srcp
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("Untitled")
+            .to_string();
+        let target = unique_dest(dest.join(format!("{name}.rpad")));
+        let target_s = target.to_string_lossy().to_string();
+
+        let mut entries: Vec<RpadEntry> = Vec::new();
+        let mut resolve = |url: &str| -> String {
+            if url.contains("://") || url.starts_with("data:") {
+                return url.to_string();
+            }
+            let decoded = percent_encoding::percent_decode_str(url).decode_utf8_lossy();
+            let local = src_dir.join(decoded.as_ref());
+            // Only images next to or below the Markdown file are embedded
+            if paths::inside_root(&src_dir.to_string_lossy(), &local).is_err() {
+                return url.to_string();
+            }
+            match fs::read(&local) {
+                Ok(data) => {
+                    let ext = local
+                        .extension()
+                        .and_then(|s| s.to_str())
+                        .unwrap_or("")
+                        .to_string();
+                    let entry = attachments::new_entry(data, &ext);
+                    // Stored relative to the document, like the editor's own images
+                    let src = entry.name.clone();
+                    if !entries.iter().any(|e| e.name == entry.name) {
+                        entries.push(entry);
+                    }
+                    src
+                }
+                Err(_) => url.to_string(),
+            }
+        };
+        let html = markdown_to_html(&md, &mut resolve);
+
+        let mut archive = RpadArchive::new(name);
+        archive.document.html = html;
+        archive.entries = entries;
+        archive.save(&target)?;
+        journal::record_created(&app, &target_s).await;
+        Ok(target_s)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_html(md: &str) -> String {
+        markdown_to_html(md, &mut |s| s.to_string())
+    }
+
+    fn to_md(html: &str) -> String {
+        html_to_markdown(html, &mut |s| s.to_string())
+    }
+
+    // Markdown → HTML → Markdown, checking the HTML on the way
+    fn round_trip(md: &str, html: &str) {
+        assert_eq!(to_html(md), html);
+        assert_eq!(to_md(html), md);
+    }
+
+    #[test]
+    fn headings() {
+        round_trip("# Title\n\n### Third\n", "<h1>Title</h1><h3>Third</h3>");
+    }
+
+    #[test]
+    fn lists() {
+        round_trip(
+            "- one\n- two\n\n  - nested\n\n1. a\n2. b\n",
+            "<ul><li><p>one</p></li><li><p>two</p><ul><li><p>nested</p></li></ul></li></ul>\
+<ol><li><p>a</p></li><li><p>b</p></li></ol>",
+        );
+        round_trip(
+            "3. c\n4. d\n",
+            "<ol start=\"3\"><li><p>c</p></li><li><p>d</p></li></ol>",
+        );
+    }
+
+    #[test]
+    fn code_blocks_and_marks() {
+        round_trip(
+            "```\nfn main() {}\n```\n",
+            "<pre><code>fn main() {}</code></pre>",
+        );
+        // The language tag has nowhere to go in the schema
+        assert_eq!(
+            to_html("```rust\nlet x = 1;\n```\n"),
+            "<pre><code>let x = 1;</code></pre>"
+        );
+        round_trip(
+            "**b** *i* ~~s~~ `c`\n",
+            "<p><strong>b</strong> <em>i</em> <s>s</s> <code>c</code></p>",
+        );
+    }
+
+    #[test]
+    fn gfm_tables_keep_their_text() {
+        // The editor has no tables, so each row becomes a paragraph of its cells
+        let html = to_html("| a | b |\n|:--|--:|\n| 1 | 2 |\n");
+        assert_eq!(html, "<p>a | b</p><p>1 | 2</p>");
+        assert_eq!(to_md(&html), "a \\| b\n\n1 \\| 2\n");
+        assert_eq!(to_html(&to_md(&html)), html);
+    }
+
+    #[test]
+    fn footnotes() {
+        round_trip(
+            "Text[^1] more.\n\n[^1]: The note.\n",
+            "<p>Text<footnote>The note.</footnote> more.</p>",
+        );
+        // Renumbered in order of use, whatever the labels were
+        assert_eq!(
+            to_md(&to_html("A[^x] B[^y]\n\n[^y]: second\n[^x]: first\n")),
+            "A[^1] B[^2]\n\n[^1]: first\n[^2]: second\n"
+        );
+    }
+
+    #[test]
+    fn inline_html_passthrough() {
+        round_trip(
+            "Some <span style=\"color: #ff0000\">red</span> and <u>under</u>\n",
+            "<p>Some <span style=\"color: #ff0000\">red</span> and <u>under</u></p>",
+        );
+        // Tags the editor has no mark for are dropped, their text kept
+        assert_eq!(
+            to_html("a <div>block</div> <script>x</script>\n"),
+            "<p>a block x</p>"
+        );
+        assert!(allowed_inline_html("<span class=\"x\">"));
+        assert!(allowed_inline_html("</u>"));
+        assert!(!allowed_inline_html("<img src=x onerror=alert(1)>"));
+    }
+
+    #[test]
+    fn images_go_through_the_resolver() {
+        let mut seen = Vec::new();
+        let html = markdown_to_html("![alt](pics/a%20b.png)\n", &mut |s| {
+            seen.push(s.to_string());
+            "assets/x.png".into()
+        });
+        assert_eq!(seen, ["pics/a%20b.png"]);
+        assert_eq!(html, "<p><img src=\"assets/x.png\" alt=\"alt\"></p>");
+        let md = html_to_markdown(&html, &mut |_| "doc_assets/x.png".into());
+        assert_eq!(md, "![alt](doc_assets/x.png)\n");
+    }
+}
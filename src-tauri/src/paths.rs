@@ -0,0 +1,451 @@
+// The path capability layer. Every command that touches the file system runs
+// the paths it was given through one of these checks before using them:
+//
+// - `workspace_root`: a root argument has to be a registered workspace.
+// - `picked_root`: a folder to register as a workspace has to have been picked
+//   in the folder dialog or passed on the command line (see `grant_root`).
+// - `inside_root` and `document`: a path has to lie inside a workspace, or be
+//   a file the user picked in a native dialog or passed on the command line
+//   this session (see `grant`). Import sources and export targets go through
+//   `document` too, so the webview cannot name an outside path on its own.
+// - `in_workspace`: a path inside a registered workspace, with no exception
+//   for granted files; `import_dir` is where an import creates its document.
+// - `sanitize_name`: a new file or folder name is reduced to one component.
+//
+// Symlinks are followed and a path is judged by where it resolves to, so a
+// link inside a workspace cannot lead out of it. Paths containing `..` are
+// refused outright rather than normalized.
+
+use lazy_static::lazy_static;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
+use tauri::AppHandle;
+
+use crate::error::RosePadError;
+use crate::workspaces;
+
+// Longest file name most file systems accept, in bytes
+const MAX_NAME_BYTES: usize = 255;
+
+// Device names Windows reserves in every folder, with or without an extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+lazy_static! {
+    // Resolved paths of files outside the workspaces the user opened this session
+    static ref GRANTED: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+    // Resolved folders the user picked this session, which may become workspaces
+    static ref GRANTED_ROOTS: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
+fn granted() -> std::sync::MutexGuard<'static, HashSet<PathBuf>> {
+    match GRANTED.lock() {
+        Ok(g) => g,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+fn granted_roots() -> std::sync::MutexGuard<'static, HashSet<PathBuf>> {
+    match GRANTED_ROOTS.lock() {
+        Ok(g) => g,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+fn plain(path: &Path) -> Result<(), RosePadError> {
+    if path.as_os_str().is_empty() || !path.is_absolute() {
+        return Err(RosePadError::invalid_path(path, "is not an absolute path"));
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(RosePadError::invalid_path(path, "must not contain '..'"));
+    }
+    if path.to_string_lossy().contains('\0') {
+        return Err(RosePadError::invalid_path(path, "contains a NUL byte"));
+    }
+    Ok(())
+}
+
+/// Resolve every symlink in the part of `path` that exists and append the
+/// rest, so paths that are about to be created can be checked too.
+pub(crate) fn resolve(path: &Path) -> Result<PathBuf, RosePadError> {
+    plain(path)?;
+    let mut missing = Vec::new();
+    let mut existing = path;
+    loop {
+        match existing.canonicalize() {
+            Ok(mut resolved) => {
+                resolved.extend(missing.iter().rev());
+                return Ok(resolved);
+            }
+            // A dangling link would be followed by whatever writes there next
+            Err(_) if fs::symlink_metadata(existing).is_ok() => {
+                return Err(RosePadError::invalid_path(existing, "is a broken symlink"));
+            }
+            Err(_) => {
+                let name = existing
+                    .file_name()
+                    .ok_or_else(|| RosePadError::not_found(path))?;
+                missing.push(name);
+                existing = existing
+                    .parent()
+                    .ok_or_else(|| RosePadError::not_found(path))?;
+            }
+        }
+    }
+}
+
+/// Check that `target` lies inside `root` once resolved; returns the resolved path.
+pub(crate) fn inside_root(root: &str, target: &Path) -> Result<PathBuf, RosePadError> {
+    let root = Path::new(root)
+        .canonicalize()
+        .map_err(|e| RosePadError::io(e, Path::new(root)))?;
+    let resolved = resolve(target)?;
+    if !resolved.starts_with(&root) {
+        return Err(RosePadError::outside_workspace(target));
+    }
+    Ok(resolved)
+}
+
+fn registered_roots(app: &AppHandle) -> Vec<PathBuf> {
+    workspaces::roots(app)
+        .iter()
+        .filter_map(|r| Path::new(r).canonicalize().ok())
+        .collect()
+}
+
+/// `root` has to be a registered workspace; returns it resolved.
+pub(crate) fn workspace_root(app: &AppHandle, root: &str) -> Result<PathBuf, RosePadError> {
+    let resolved = resolve(Path::new(root))?;
+    if !registered_roots(app).contains(&resolved) {
+        return Err(RosePadError::not_a_workspace(Path::new(root)));
+    }
+    Ok(resolved)
+}
+
+/// A file or folder a command reads or writes: inside a registered workspace,
+/// or granted this session.
+pub(crate) fn document(app: &AppHandle, path: &str) -> Result<PathBuf, RosePadError> {
+    admit(&registered_roots(app), path)
+}
+
+fn admit(roots: &[PathBuf], path: &str) -> Result<PathBuf, RosePadError> {
+    let resolved = resolve(Path::new(path))?;
+    if granted().contains(&resolved) || roots.iter().any(|root| resolved.starts_with(root)) {
+        return Ok(resolved);
+    }
+    Err(RosePadError::outside_workspace(Path::new(path)))
+}
+
+/// A path inside one of the registered workspaces; returns it resolved. Unlike
+/// `document`, granted files do not count.
+pub(crate) fn in_workspace(app: &AppHandle, path: &str) -> Result<PathBuf, RosePadError> {
+    let resolved = resolve(Path::new(path))?;
+    if !registered_roots(app)
+        .iter()
+        .any(|root| resolved.starts_with(root))
+    {
+        return Err(RosePadError::outside_workspace(Path::new(path)));
+    }
+    Ok(resolved)
+}
+
+/// The folder an import writes its new document to: `dest_dir`, or the root of
+/// the active workspace when none is given. Either way it has to be a folder
+/// inside a registered workspace; granted files do not extend to their folders.
+pub(crate) fn import_dir(app: &AppHandle, dest_dir: Option<&str>) -> Result<PathBuf, RosePadError> {
+    let dir = match dest_dir {
+        Some(d) => d.to_string(),
+        None => workspaces::active_root(app)
+            .ok_or_else(|| RosePadError::failed("Open a workspace to import into"))?,
+    };
+    let resolved = in_workspace(app, &dir)?;
+    if !resolved.is_dir() {
+        return Err(RosePadError::not_a_directory(Path::new(&dir)));
+    }
+    // Keep the spelling the caller used, as index rows do
+    Ok(PathBuf::from(dir))
+}
+
+/// Allow `document` access to a file outside the workspaces for the rest of
+/// the session. Only for paths the user chose: a file picked in the Open
+/// dialog or one the app was launched with.
+pub(crate) fn grant(path: &Path) -> Result<(), RosePadError> {
+    let resolved = resolve(path)?;
+    if !resolved.is_file() {
+        return Err(RosePadError::not_a_file(path));
+    }
+    granted().insert(resolved);
+    Ok(())
+}
+
+/// Like `grant`, for a path picked in the Save dialog, which need not exist yet.
+pub(crate) fn grant_save_target(path: &Path) -> Result<(), RosePadError> {
+    let resolved = resolve(path)?;
+    if resolved.is_dir() {
+        return Err(RosePadError::not_a_file(path));
+    }
+    granted().insert(resolved);
+    Ok(())
+}
+
+/// Allow a folder to be registered as a workspace. Only for folders the user
+/// chose: one picked in the folder dialog or one the app was launched with.
+pub(crate) fn grant_root(path: &Path) -> Result<(), RosePadError> {
+    let resolved = resolve(path)?;
+    if !resolved.is_dir() {
+        return Err(RosePadError::not_a_directory(path));
+    }
+    granted_roots().insert(resolved);
+    Ok(())
+}
+
+/// `root` has to be a folder granted with `grant_root`; returns it resolved.
+/// Folders inside or around a granted one do not count.
+pub(crate) fn picked_root(root: &str) -> Result<PathBuf, RosePadError> {
+    let resolved = resolve(Path::new(root))?;
+    if !granted_roots().contains(&resolved) {
+        return Err(RosePadError::permission_denied(Path::new(root)));
+    }
+    Ok(resolved)
+}
+
+/// Turn user input into a single, portable path component: separators and
+/// characters Windows forbids become `_`, and reserved device names get a
+/// `_` prefix. Names that are nothing but dots or blanks are refused.
+pub(crate) fn sanitize_name(name: &str) -> Result<String, RosePadError> {
+    let cleaned: String = name
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // Windows drops trailing dots and spaces, which would turn ".." into ""
+    let mut cleaned = cleaned.trim_end_matches(['.', ' ']).to_string();
+    if cleaned.is_empty() {
+        return Err(RosePadError::invalid_name(name));
+    }
+    let stem = cleaned.split('.').next().unwrap_or("").trim_end();
+    if RESERVED_NAMES.contains(&stem.to_ascii_uppercase().as_str()) {
+        cleaned.insert(0, '_');
+    }
+    if cleaned.len() > MAX_NAME_BYTES {
+        let mut end = MAX_NAME_BYTES;
+        while !cleaned.is_char_boundary(end) {
+            end -= 1;
+        }
+        cleaned.truncate(end);
+    }
+    Ok(cleaned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::scratch::{self, Scratch};
+
+    fn scratch(name: &str) -> Scratch {
+        let dir = scratch::dir(&format!("paths-{name}"));
+        fs::create_dir_all(dir.join("root/sub")).unwrap();
+        fs::create_dir_all(dir.join("outside")).unwrap();
+        fs::write(dir.join("outside/secret.txt"), "secret").unwrap();
+        fs::write(dir.join("root/note.txt"), "note").unwrap();
+        dir
+    }
+
+    fn root_of(dir: &Path) -> String {
+        dir.join("root").to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn names_become_one_component() {
+        for attack in [
+            "../evil",
+            "..\\evil",
+            "../../../../etc/passwd",
+            "/etc/passwd",
+            "C:\\Windows\\System32",
+            "sub/../../evil",
+            "a\0b",
+            "line\nbreak",
+        ] {
+            let name = sanitize_name(attack).unwrap();
+            assert_eq!(
+                Path::new(&name).components().count(),
+                1,
+                "{attack:?} -> {name:?}"
+            );
+            assert!(
+                !name.contains(['/', '\\', '\0', '\n']),
+                "{attack:?} -> {name:?}"
+            );
+            assert!(matches!(
+                Path::new(&name).components().next(),
+                Some(Component::Normal(_))
+            ));
+        }
+        assert_eq!(sanitize_name("../evil").unwrap(), ".._evil");
+        assert_eq!(sanitize_name("  Notes  ").unwrap(), "Notes");
+        assert_eq!(
+            sanitize_name("What? Why: *this*").unwrap(),
+            "What_ Why_ _this_"
+        );
+        assert_eq!(sanitize_name(".hidden").unwrap(), ".hidden");
+        assert_eq!(sanitize_name("Ünïcödé 📝").unwrap(), "Ünïcödé 📝");
+    }
+
+    #[test]
+    fn dot_and_blank_names_are_refused() {
+        for bad in ["", "   ", ".", "..", "...", ". .", "\t"] {
+            assert!(sanitize_name(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn reserved_and_long_names() {
+        assert_eq!(sanitize_name("con").unwrap(), "_con");
+        assert_eq!(sanitize_name("NUL.txt").unwrap(), "_NUL.txt");
+        assert_eq!(sanitize_name("lpt9 .md").unwrap(), "_lpt9 .md");
+        assert_eq!(sanitize_name("console").unwrap(), "console");
+        let long = "é".repeat(200);
+        let name = sanitize_name(&long).unwrap();
+        assert!(name.len() <= MAX_NAME_BYTES);
+        assert!(name.chars().all(|c| c == 'é'));
+    }
+
+    #[test]
+    fn traversal_out_of_the_root_is_refused() {
+        let dir = scratch("traversal");
+        let root = root_of(&dir);
+        let ok = inside_root(&root, &dir.join("root/note.txt")).unwrap();
+        assert_eq!(ok, dir.join("root/note.txt"));
+        // Not created yet, several levels deep
+        assert!(inside_root(&root, &dir.join("root/sub/new/deeper.txt")).is_ok());
+        for attack in [
+            dir.join("root/../outside/secret.txt"),
+            dir.join("root/sub/../../outside/secret.txt"),
+            dir.join("root/sub/../note.txt"),
+            dir.join("root/missing/../../outside"),
+            dir.join("outside/secret.txt"),
+            dir.join("rootsibling/file.txt"),
+            PathBuf::from("relative/note.txt"),
+            PathBuf::from(""),
+        ] {
+            assert!(inside_root(&root, &attack).is_err(), "{}", attack.display());
+        }
+        // A sibling that shares the root's name as a prefix is not inside it
+        fs::create_dir_all(dir.join("root2")).unwrap();
+        assert!(inside_root(&root, &dir.join("root2/x.txt")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_judged_by_their_target() {
+        use std::os::unix::fs::symlink;
+        let dir = scratch("symlinks");
+        let root = root_of(&dir);
+        symlink(dir.join("outside"), dir.join("root/escape")).unwrap();
+        symlink(
+            dir.join("outside/secret.txt"),
+            dir.join("root/secret-link.txt"),
+        )
+        .unwrap();
+        symlink(
+            dir.join("root/note.txt"),
+            dir.join("root/sub/inner-link.txt"),
+        )
+        .unwrap();
+        symlink(
+            dir.join("outside/not-there.txt"),
+            dir.join("root/dangling.txt"),
+        )
+        .unwrap();
+
+        assert!(inside_root(&root, &dir.join("root/escape/secret.txt")).is_err());
+        assert!(inside_root(&root, &dir.join("root/escape/new.txt")).is_err());
+        assert!(inside_root(&root, &dir.join("root/secret-link.txt")).is_err());
+        // Writing through a dangling link would create a file outside
+        assert!(inside_root(&root, &dir.join("root/dangling.txt")).is_err());
+        assert!(!dir.join("outside/not-there.txt").exists());
+        // Links that stay inside are fine
+        assert_eq!(
+            inside_root(&root, &dir.join("root/sub/inner-link.txt")).unwrap(),
+            dir.join("root/note.txt")
+        );
+        // A root that is itself reached through a link resolves the same way
+        symlink(dir.join("root"), dir.join("root-link")).unwrap();
+        let linked = dir.join("root-link").to_string_lossy().to_string();
+        assert!(inside_root(&linked, &dir.join("root/note.txt")).is_ok());
+        assert!(inside_root(&linked, &dir.join("root-link/escape/secret.txt")).is_err());
+    }
+
+    #[test]
+    fn outside_paths_need_a_grant() {
+        let dir = scratch("admit");
+        let roots = [dir.join("root")];
+        let path = |p: &str| dir.join(p).to_string_lossy().to_string();
+        assert!(admit(&roots, &path("root/note.txt")).is_ok());
+        assert!(admit(&roots, &path("root/sub/new.rpad")).is_ok());
+        // What write_rpad_html and the exporters would be handed by the webview
+        for target in [
+            "outside/secret.txt",
+            "outside/export.md",
+            "outside/new/doc.pdf",
+        ] {
+            assert!(matches!(
+                admit(&roots, &path(target)),
+                Err(RosePadError::OutsideWorkspace(_))
+            ));
+        }
+        assert!(admit(&[], &path("root/note.txt")).is_err());
+
+        grant_save_target(&dir.join("outside/export.md")).unwrap();
+        assert_eq!(
+            admit(&roots, &path("outside/export.md")).unwrap(),
+            dir.join("outside/export.md")
+        );
+        // Only the picked file, not its neighbours or its folder
+        assert!(admit(&roots, &path("outside/secret.txt")).is_err());
+        assert!(admit(&roots, &path("outside")).is_err());
+        assert!(admit(&roots, &path("outside/../outside/export.md")).is_err());
+        assert!(grant_save_target(&dir.join("outside")).is_err());
+        assert!(grant_save_target(Path::new("export.md")).is_err());
+    }
+
+    #[test]
+    fn grants_cover_single_files() {
+        let dir = scratch("grants");
+        assert!(grant(&dir.join("outside")).is_err());
+        assert!(grant(&dir.join("outside/missing.txt")).is_err());
+        grant(&dir.join("outside/secret.txt")).unwrap();
+        assert!(granted().contains(&dir.join("outside/secret.txt")));
+        assert!(!granted().contains(&dir.join("outside")));
+    }
+
+    #[test]
+    fn roots_need_a_folder_grant() {
+        let dir = scratch("roots");
+        let path = |p: &str| dir.join(p).to_string_lossy().to_string();
+        assert!(matches!(
+            picked_root(&path("root")),
+            Err(RosePadError::PermissionDenied(_))
+        ));
+        assert!(grant_root(&dir.join("root/note.txt")).is_err());
+        grant_root(&dir.join("root")).unwrap();
+        assert_eq!(picked_root(&path("root")).unwrap(), dir.join("root"));
+        // Neither its parent, its subfolders nor a way around it
+        for other in ["", "root/sub", "outside", "root/sub/.."] {
+            assert!(picked_root(&path(other)).is_err(), "{other:?}");
+        }
+        assert!(picked_root("/").is_err());
+    }
+}
@@ -0,0 +1,1426 @@
+// Headless PDF export. Lays the editor HTML out onto pages using the fonts in
+// `pdf_fonts` (DejaVu Sans, plus a system font for scripts it lacks), so no
+// browser is needed and the output is deterministic enough to assert on in
+// tests. Text no available font can show fails the export. Images are drawn
+// where `pdf_images` can decode them and shown as their alt text otherwise.
+
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use tauri::AppHandle;
+
+use crate::attachments;
+use crate::docx::ImageLookup;
+use crate::durable;
+use crate::error::{during, RosePadError};
+use crate::html::{self, css_color, css_font_size_pt, Element, Node};
+use crate::paths;
+use crate::pdf_fonts::{self, FALLBACK};
+use crate::pdf_images::{self, PdfImage};
+use crate::rpad::RpadArchive;
+
+type Rgb = (u8, u8, u8);
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PdfOptions {
+    /// `a4`, `a5`, `letter` or `legal`; ignored when both dimensions are given.
+    pub page_size: String,
+    pub page_width: Option<f32>,
+    pub page_height: Option<f32>,
+    pub margin_top: f32,
+    pub margin_right: f32,
+    pub margin_bottom: f32,
+    pub margin_left: f32,
+    pub font_size: f32,
+    /// Header/footer text; `{page}`, `{pages}` and `{title}` are substituted.
+    pub header: Option<String>,
+    pub footer: Option<String>,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        PdfOptions {
+            page_size: "a4".into(),
+            page_width: None,
+            page_height: None,
+            margin_top: 72.0,
+            margin_right: 72.0,
+            margin_bottom: 72.0,
+            margin_left: 72.0,
+            font_size: 12.0,
+            header: None,
+            footer: Some("{page} / {pages}".into()),
+        }
+    }
+}
+
+impl PdfOptions {
+    fn page_dims(&self) -> (f32, f32) {
+        if let (Some(w), Some(h)) = (self.page_width, self.page_height) {
+            return (w.max(72.0), h.max(72.0));
+        }
+        match self.page_size.to_ascii_lowercase().as_str() {
+            "letter" => (612.0, 792.0),
+            "legal" => (612.0, 1008.0),
+            "a5" => (419.53, 595.28),
+            _ => (595.28, 841.89),
+        }
+    }
+}
+
+// ---------- Fonts ----------
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+    Mono,
+}
+
+// Slots in `pdf_fonts`, which has a face for each
+const FONTS: [Font; 5] = [
+    Font::Regular,
+    Font::Bold,
+    Font::Italic,
+    Font::BoldItalic,
+    Font::Mono,
+];
+
+impl Font {
+    fn pick(bold: bool, italic: bool, mono: bool) -> Font {
+        match (mono, bold, italic) {
+            (true, _, _) => Font::Mono,
+            (_, true, true) => Font::BoldItalic,
+            (_, true, false) => Font::Bold,
+            (_, false, true) => Font::Italic,
+            _ => Font::Regular,
+        }
+    }
+
+    fn slot(self) -> usize {
+        FONTS.iter().position(|f| *f == self).unwrap_or(0)
+    }
+
+    /// The face that shows `c`: this font's own when it has the glyph, else the
+    /// first one that does.
+    fn face_for(self, c: char) -> usize {
+        std::iter::once(self.slot())
+            .chain(0..=FALLBACK)
+            .find(|slot| has_glyph(*slot, c))
+            .unwrap_or(self.slot())
+    }
+}
+
+fn has_glyph(slot: usize, c: char) -> bool {
+    pdf_fonts::font(slot).is_some_and(|f| f.glyph(c).is_some())
+}
+
+// Name pages use for the face in `slot`
+fn resource(slot: usize) -> String {
+    format!("F{}", slot + 1)
+}
+
+fn measure(slot: usize, text: &str, size: f32) -> f32 {
+    let Some(font) = pdf_fonts::font(slot) else {
+        return 0.0;
+    };
+    let units: f32 = text
+        .chars()
+        .filter_map(|c| font.glyph(c))
+        .map(|g| font.advance(g))
+        .sum();
+    units * size / 1000.0
+}
+
+fn collect_text<'a>(nodes: &'a [Node], out: &mut Vec<&'a str>) {
+    for node in nodes {
+        match node {
+            Node::Text(t) => out.push(t),
+            Node::Element(el) => collect_text(&el.children, out),
+        }
+    }
+}
+
+/// Refuse text that no font has glyphs for rather than leave gaps in the PDF.
+/// The error lists the characters in the order they first appear.
+fn check_covered<'a>(texts: impl IntoIterator<Item = &'a str>) -> Result<(), RosePadError> {
+    let mut missing: Vec<char> = Vec::new();
+    for c in texts.into_iter().flat_map(str::chars) {
+        if c.is_control() || missing.contains(&c) || (0..=FALLBACK).any(|s| has_glyph(s, c)) {
+            continue;
+        }
+        missing.push(c);
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+    const SHOWN: usize = 20;
+    let mut list: String = missing
+        .iter()
+        .take(SHOWN)
+        .map(|c| format!(" {c}"))
+        .collect();
+    if missing.len() > SHOWN {
+        list.push_str(&format!(" and {} more", missing.len() - SHOWN));
+    }
+    Err(RosePadError::failed(format!(
+        "The PDF export has no font for these characters:{list}"
+    )))
+}
+
+// ---------- Layout ----------
+
+#[derive(Clone, Debug, PartialEq)]
+struct Style {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strike: bool,
+    code: bool,
+    size: f32,
+    color: Option<Rgb>,
+    highlight: Option<Rgb>,
+    rise: f32,
+}
+
+impl Style {
+    fn new(size: f32) -> Self {
+        Style {
+            bold: false,
+            italic: false,
+            underline: false,
+            strike: false,
+            code: false,
+            size,
+            color: None,
+            highlight: None,
+            rise: 0.0,
+        }
+    }
+
+    fn font(&self) -> Font {
+        Font::pick(self.bold, self.italic, self.code)
+    }
+}
+
+// A run of text set in one face
+#[derive(Clone, Debug)]
+struct Piece {
+    text: String,
+    face: usize,
+    style: Style,
+    width: f32,
+    space: bool,
+    note: Option<usize>,
+    // (index into `Layout::images`, drawn height) for a picture
+    image: Option<(usize, f32)>,
+}
+
+impl Piece {
+    fn new(text: String, face: usize, style: Style, space: bool, note: Option<usize>) -> Piece {
+        Piece {
+            width: measure(face, &text, style.size),
+            text,
+            face,
+            style,
+            space,
+            note,
+            image: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Placed {
+    x: f32,
+    text: String,
+    face: usize,
+    style: Style,
+    width: f32,
+    image: Option<(usize, f32)>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Line {
+    items: Vec<Placed>,
+    height: f32,
+    ascent: f32,
+    space_before: f32,
+    notes: Vec<usize>,
+    code_bg: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+    Justify,
+}
+
+fn align_of(el: &Element) -> Align {
+    match el.style("text-align").as_deref().map(str::trim) {
+        Some("center") => Align::Center,
+        Some("right") | Some("end") => Align::Right,
+        Some("justify") => Align::Justify,
+        _ => Align::Left,
+    }
+}
+
+enum Token {
+    Text(String, Style),
+    Break,
+    Note(usize, Style),
+    // (index into `Layout::images`, width, height)
+    Image(usize, f32, f32, Style),
+}
+
+struct Layout<'a> {
+    width: f32,
+    // Room between the top and bottom margins
+    height: f32,
+    base: f32,
+    lines: Vec<Line>,
+    // Footnote bodies, already broken into lines at footnote size
+    notes: Vec<Vec<Line>>,
+    image: &'a mut ImageLookup<'a>,
+    // (src, decoded image) per picture drawn; XObject /Im{index + 1}
+    images: Vec<(String, PdfImage)>,
+    // Alt text shown in place of images that could not be drawn
+    alts: Vec<String>,
+}
+
+const HEADING_SCALE: [f32; 6] = [2.0, 1.6, 1.35, 1.15, 1.0, 0.9];
+const LIST_INDENT: f32 = 22.0;
+
+// CSS pixels, which image sizes are in, to points
+const PT_PER_PX: f32 = 0.75;
+
+impl Layout<'_> {
+    fn blocks(&mut self, nodes: &[Node], indent: f32) {
+        let mut pending: Vec<Node> = Vec::new();
+        for node in nodes {
+            match node {
+                Node::Element(el) if is_block(&el.tag) => {
+                    self.flush_inline(&mut pending, indent);
+                    self.block(el, indent, None);
+                }
+                Node::Text(t) if t.trim().is_empty() && pending.is_empty() => {}
+                other => pending.push(other.clone()),
+            }
+        }
+        self.flush_inline(&mut pending, indent);
+    }
+
+    fn flush_inline(&mut self, pending: &mut Vec<Node>, indent: f32) {
+        if pending.is_empty() {
+            return;
+        }
+        let style = Style::new(self.base);
+        let mut tokens = Vec::new();
+        self.tokens(pending, &style, &mut tokens);
+        pending.clear();
+        let lines = flow(&tokens, self.width - indent, indent, Align::Left, None);
+        self.push_block(lines, self.base * 0.5);
+    }
+
+    fn push_block(&mut self, mut lines: Vec<Line>, space_before: f32) {
+        if let Some(first) = lines.first_mut() {
+            first.space_before = space_before;
+        }
+        self.lines.extend(lines);
+    }
+
+    fn paragraph(&mut self, el: &Element, style: &Style, indent: f32, marker: Option<Piece>) {
+        let mut tokens = Vec::new();
+        self.tokens(&el.children, style, &mut tokens);
+        let lines = flow(&tokens, self.width - indent, indent, align_of(el), marker);
+        self.push_block(lines, style.size * 0.5);
+    }
+
+    fn block(&mut self, el: &Element, indent: f32, marker: Option<Piece>) {
+        match el.tag.as_str() {
+            "p" => self.paragraph(el, &Style::new(self.base), indent, marker),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = (el.tag.as_bytes()[1] - b'1') as usize;
+                let mut style = Style::new(self.base * HEADING_SCALE[level]);
+                style.bold = true;
+                self.paragraph(el, &style, indent, marker);
+            }
+            "ul" | "ol" => self.list(el, indent),
+            "pre" => {
+                let mut style = Style::new(self.base * 0.85);
+                style.code = true;
+                let code = el.text();
+                let code = code.strip_suffix('\n').unwrap_or(&code);
+                let mut lines = Vec::new();
+                for src in code.split('\n') {
+                    let tokens = [Token::Text(src.to_string(), style.clone())];
+                    let mut wrapped = flow(&tokens, self.width - indent, indent, Align::Left, None);
+                    if wrapped.is_empty() {
+                        wrapped.push(empty_line(&style));
+                    }
+                    lines.extend(wrapped);
+                }
+                for line in &mut lines {
+                    line.code_bg = true;
+                }
+                self.push_block(lines, self.base * 0.5);
+            }
+            _ => self.blocks(&el.children, indent),
+        }
+    }
+
+    fn list(&mut self, el: &Element, indent: f32) {
+        let ordered = el.tag == "ol";
+        let mut n = el
+            .attr("start")
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(1);
+        let inner = indent + LIST_INDENT;
+        for child in &el.children {
+            let li = match child {
+                Node::Element(li) if li.tag == "li" => li,
+                _ => continue,
+            };
+            let label = if ordered {
+                format!("{n}.")
+            } else {
+                "\u{2022}".to_string()
+            };
+            n += 1;
+            let style = Style::new(self.base);
+            let face = style.font().face_for('\u{2022}');
+            let mut marker = Some(Piece::new(label, face, style, false, None));
+            let mut pending: Vec<Node> = Vec::new();
+            for part in &li.children {
+                match part {
+                    Node::Element(b) if is_block(&b.tag) => {
+                        if !pending.is_empty() {
+                            let p = Element {
+                                tag: "p".into(),
+                                attrs: Vec::new(),
+                                children: std::mem::take(&mut pending),
+                            };
+                            self.block(&p, inner, marker.take());
+                        }
+                        self.block(b, inner, marker.take());
+                    }
+                    Node::Text(t) if t.trim().is_empty() && pending.is_empty() => {}
+                    other => pending.push(other.clone()),
+                }
+            }
+            if !pending.is_empty() || marker.is_some() {
+                let p = Element {
+                    tag: "p".into(),
+                    attrs: Vec::new(),
+                    children: pending,
+                };
+                self.block(&p, inner, marker.take());
+            }
+        }
+    }
+
+    fn tokens(&mut self, nodes: &[Node], style: &Style, out: &mut Vec<Token>) {
+        for node in nodes {
+            match node {
+                Node::Text(t) => out.push(Token::Text(t.clone(), style.clone())),
+                Node::Element(el) => {
+                    let mut s = style.clone();
+                    match el.tag.as_str() {
+                        "strong" | "b" => s.bold = true,
+                        "em" | "i" => s.italic = true,
+                        "u" => s.underline = true,
+                        "s" | "del" | "strike" => s.strike = true,
+                        "code" => s.code = true,
+                        "br" => {
+                            out.push(Token::Break);
+                            continue;
+                        }
+                        "img" => {
+                            if let Some(tok) = self.picture(el.attr("src").unwrap_or(""), &s) {
+                                out.push(tok);
+                            } else if let Some(alt) = el.attr("alt").filter(|a| !a.is_empty()) {
+                                self.alts.push(alt.to_string());
+                                out.push(Token::Text(format!("[{alt}]"), s));
+                            }
+                            continue;
+                        }
+                        "footnote" => {
+                            self.footnote(el);
+                            let mut mark = style.clone();
+                            mark.size = style.size * 0.65;
+                            mark.rise = style.size * 0.35;
+                            out.push(Token::Note(self.notes.len(), mark));
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    if let Some(c) = el.style("color").and_then(|v| css_color(&v)) {
+                        s.color = Some(c);
+                    }
+                    if let Some(c) = el.style("background-color").and_then(|v| css_color(&v)) {
+                        s.highlight = Some(c);
+                    }
+                    if let Some(pt) = el.style("font-size").and_then(|v| css_font_size_pt(&v)) {
+                        s.size = pt.clamp(4.0, 144.0);
+                    }
+                    self.tokens(&el.children, &s, out);
+                }
+            }
+        }
+    }
+
+    // An image token for `src`, decoding the image once; sized like the editor
+    // shows it, shrunk to fit the page
+    fn picture(&mut self, src: &str, style: &Style) -> Option<Token> {
+        let index = match self.images.iter().position(|(s, _)| s == src) {
+            Some(i) => i,
+            None => {
+                let (_, data) = (self.image)(src)?;
+                let image = pdf_images::decode(&data)?;
+                self.images.push((src.to_string(), image));
+                self.images.len() - 1
+            }
+        };
+        let image = &self.images[index].1;
+        let (w, h) = (
+            image.width as f32 * PT_PER_PX,
+            image.height as f32 * PT_PER_PX,
+        );
+        let scale = (self.width / w).min(self.height / h).min(1.0);
+        Some(Token::Image(index, w * scale, h * scale, style.clone()))
+    }
+
+    fn footnote(&mut self, el: &Element) {
+        let number = self.notes.len() + 1;
+        let style = Style::new(self.base * 0.8);
+        let mut tokens = vec![Token::Text(format!("{number} "), style.clone())];
+        // Nested footnotes would recurse into the same list; flatten them to text
+        tokens.push(Token::Text(el.text(), style));
+        let lines = flow(&tokens, self.width, 0.0, Align::Left, None);
+        self.notes.push(lines);
+    }
+}
+
+fn is_block(tag: &str) -> bool {
+    matches!(
+        tag,
+        "p" | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "ul"
+            | "ol"
+            | "li"
+            | "pre"
+            | "div"
+            | "blockquote"
+            | "table"
+    )
+}
+
+fn empty_line(style: &Style) -> Line {
+    Line {
+        height: style.size * 1.25,
+        ascent: style.size * 0.9,
+        ..Line::default()
+    }
+}
+
+// Split tokens into measured pieces: runs of spaces and runs of non-space text.
+fn pieces(tokens: &[Token]) -> Vec<Option<Piece>> {
+    let mut out = Vec::new();
+    for tok in tokens {
+        match tok {
+            Token::Break => out.push(None),
+            Token::Image(index, width, height, style) => out.push(Some(Piece {
+                text: String::new(),
+                face: style.font().slot(),
+                style: style.clone(),
+                width: *width,
+                space: false,
+                note: None,
+                image: Some((*index, *height)),
+            })),
+            Token::Note(n, style) => {
+                let face = style.font().face_for('0');
+                out.push(Some(Piece::new(
+                    n.to_string(),
+                    face,
+                    style.clone(),
+                    false,
+                    Some(n - 1),
+                )))
+            }
+            Token::Text(t, style) => {
+                let font = style.font();
+                let mut cur = String::new();
+                let mut cur_space = false;
+                let mut cur_face = font.slot();
+                for c in t.chars() {
+                    // Line breaks and tabs included
+                    let c = if c.is_control() { ' ' } else { c };
+                    let space = c == ' ';
+                    let face = font.face_for(c);
+                    if !cur.is_empty() && (space != cur_space || face != cur_face) {
+                        let text = std::mem::take(&mut cur);
+                        out.push(Some(Piece::new(
+                            text,
+                            cur_face,
+                            style.clone(),
+                            cur_space,
+                            None,
+                        )));
+                    }
+                    cur_space = space;
+                    cur_face = face;
+                    cur.push(c);
+                }
+                if !cur.is_empty() {
+                    out.push(Some(Piece::new(
+                        cur,
+                        cur_face,
+                        style.clone(),
+                        cur_space,
+                        None,
+                    )));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Greedy line breaking. Breaks only at spaces; words wider than the line are
+/// split by character.
+fn flow(
+    tokens: &[Token],
+    width: f32,
+    indent: f32,
+    align: Align,
+    marker: Option<Piece>,
+) -> Vec<Line> {
+    // Group consecutive non-space pieces into words so style changes mid-word
+    // do not become break opportunities
+    let mut words: Vec<Option<Vec<Piece>>> = Vec::new();
+    for p in pieces(tokens) {
+        match p {
+            None => words.push(None),
+            Some(p) if p.space => words.push(Some(vec![p])),
+            Some(p) => match words.last_mut() {
+                Some(Some(w)) if !w[0].space => w.push(p),
+                _ => words.push(Some(vec![p])),
+            },
+        }
+    }
+
+    let base_style = tokens
+        .iter()
+        .find_map(|t| match t {
+            Token::Text(_, s) => Some(s.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| Style::new(12.0));
+
+    let mut lines = Vec::new();
+    let mut cur: Vec<Piece> = Vec::new();
+    let mut cur_w = 0.0f32;
+    let mut wrapped = false;
+
+    let finish = |cur: &mut Vec<Piece>, lines: &mut Vec<Line>, last: bool| {
+        while cur.last().is_some_and(|p| p.space) {
+            cur.pop();
+        }
+        lines.push(place(
+            std::mem::take(cur),
+            width,
+            indent,
+            align,
+            last,
+            &base_style,
+        ));
+    };
+
+    for word in words {
+        let word = match word {
+            None => {
+                finish(&mut cur, &mut lines, true);
+                cur_w = 0.0;
+                wrapped = false;
+                continue;
+            }
+            Some(w) => w,
+        };
+        let is_space = word[0].space;
+        let ww: f32 = word.iter().map(|p| p.width).sum();
+        if is_space && cur.is_empty() && wrapped {
+            continue;
+        }
+        if !is_space && !cur.is_empty() && cur_w + ww > width {
+            finish(&mut cur, &mut lines, false);
+            cur_w = 0.0;
+            wrapped = true;
+        }
+        if !is_space && ww > width {
+            for piece in word {
+                for chunk in split_piece(piece, width) {
+                    if !cur.is_empty() && cur_w + chunk.width > width {
+                        finish(&mut cur, &mut lines, false);
+                        cur_w = 0.0;
+                        wrapped = true;
+                    }
+                    cur_w += chunk.width;
+                    cur.push(chunk);
+                }
+            }
+            continue;
+        }
+        cur_w += ww;
+        cur.extend(word);
+    }
+    if !cur.is_empty() || lines.is_empty() {
+        finish(&mut cur, &mut lines, true);
+    }
+
+    if let (Some(m), Some(first)) = (marker, lines.first_mut()) {
+        let x = indent - m.width - 6.0;
+        first.items.insert(
+            0,
+            Placed {
+                x: x.max(0.0),
+                text: m.text,
+                face: m.face,
+                width: m.width,
+                style: m.style,
+                image: None,
+            },
+        );
+    }
+    lines
+}
+
+fn split_piece(mut piece: Piece, width: f32) -> Vec<Piece> {
+    // Pictures shrink to the line instead
+    if let Some((_, height)) = &mut piece.image {
+        *height *= width / piece.width;
+        piece.width = width;
+        return vec![piece];
+    }
+    let chunk = |text: String, width: f32| Piece {
+        text,
+        width,
+        face: piece.face,
+        style: piece.style.clone(),
+        space: piece.space,
+        note: piece.note,
+        image: None,
+    };
+    let mut out = Vec::new();
+    let mut cur = String::new();
+    let mut w = 0.0;
+    for c in piece.text.chars() {
+        let cw = measure(piece.face, c.encode_utf8(&mut [0; 4]), piece.style.size);
+        if !cur.is_empty() && w + cw > width {
+            out.push(chunk(std::mem::take(&mut cur), w));
+            w = 0.0;
+        }
+        cur.push(c);
+        w += cw;
+    }
+    if !cur.is_empty() {
+        out.push(chunk(cur, w));
+    }
+    out
+}
+
+fn place(
+    pieces: Vec<Piece>,
+    width: f32,
+    indent: f32,
+    align: Align,
+    last: bool,
+    base: &Style,
+) -> Line {
+    let used: f32 = pieces.iter().map(|p| p.width).sum();
+    let max_size = pieces
+        .iter()
+        .filter(|p| p.style.rise == 0.0)
+        .map(|p| p.style.size)
+        .fold(0.0f32, f32::max);
+    let size = if max_size > 0.0 { max_size } else { base.size };
+    // Pictures sit on the baseline
+    let picture = pieces
+        .iter()
+        .filter_map(|p| p.image.map(|(_, h)| h))
+        .fold(0.0f32, f32::max);
+    let spaces = pieces.iter().filter(|p| p.space).count();
+    let slack = (width - used).max(0.0);
+    let (mut x, extra) = match align {
+        Align::Left => (indent, 0.0),
+        Align::Center => (indent + slack / 2.0, 0.0),
+        Align::Right => (indent + slack, 0.0),
+        Align::Justify if !last && spaces > 0 => (indent, slack / spaces as f32),
+        Align::Justify => (indent, 0.0),
+    };
+    let mut line = Line {
+        height: (size * 1.25).max(picture + size * 0.35),
+        ascent: (size * 0.9).max(picture),
+        ..Line::default()
+    };
+    for p in pieces {
+        if let Some(n) = p.note {
+            line.notes.push(n);
+        }
+        let w = if p.space { p.width + extra } else { p.width };
+        x += w;
+        // Neighbours in the same style and face share one text operation unless
+        // justification spreads them
+        if let Some(prev) = line.items.last_mut().filter(|i| {
+            i.style == p.style && i.face == p.face && i.image.is_none() && p.image.is_none()
+        }) {
+            if extra == 0.0 {
+                prev.text.push_str(&p.text);
+                prev.width += w;
+                continue;
+            }
+        }
+        line.items.push(Placed {
+            x: x - w,
+            text: p.text,
+            face: p.face,
+            style: p.style,
+            width: w,
+            image: p.image,
+        });
+    }
+    line
+}
+
+// ---------- Pagination ----------
+
+#[derive(Default, Debug)]
+struct Page {
+    // (top of line, line)
+    lines: Vec<(f32, Line)>,
+    notes: Vec<usize>,
+}
+
+const NOTE_SEPARATOR: f32 = 12.0;
+
+fn paginate(layout: &Layout, top: f32, bottom: f32) -> Vec<Page> {
+    let note_height = |n: &usize| -> f32 {
+        layout
+            .notes
+            .get(*n)
+            .map(|ls| ls.iter().map(|l| l.height).sum())
+            .unwrap_or(0.0)
+    };
+    let mut pages = Vec::new();
+    let mut page = Page::default();
+    let mut cursor = top;
+    let mut notes_h = 0.0;
+
+    for line in &layout.lines {
+        let notes_needed = |page: &Page| {
+            let h: f32 = line.notes.iter().map(note_height).sum();
+            if h > 0.0 && page.notes.is_empty() {
+                h + NOTE_SEPARATOR
+            } else {
+                h
+            }
+        };
+        let gap = if page.lines.is_empty() {
+            0.0
+        } else {
+            line.space_before
+        };
+        if !page.lines.is_empty()
+            && cursor - gap - line.height < bottom + notes_h + notes_needed(&page)
+        {
+            pages.push(std::mem::take(&mut page));
+            cursor = top;
+            notes_h = 0.0;
+        } else {
+            cursor -= gap;
+        }
+        notes_h += notes_needed(&page);
+        page.lines.push((cursor, line.clone()));
+        page.notes.extend(line.notes.iter().copied());
+        cursor -= line.height;
+    }
+    pages.push(page);
+    pages
+}
+
+// ---------- PDF output ----------
+
+fn num(v: f32) -> String {
+    let s = format!("{v:.2}");
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" {
+        "0".into()
+    } else {
+        s.to_string()
+    }
+}
+
+// Glyphs each face drew, with the character each one shows
+type UsedGlyphs = BTreeMap<usize, BTreeMap<u16, char>>;
+
+// Composite fonts take two-byte glyph ids
+fn glyph_string(face: usize, text: &str, used: &mut UsedGlyphs) -> String {
+    let font = pdf_fonts::font(face);
+    let mut out = String::from("<");
+    for c in text.chars() {
+        let glyph = font.and_then(|f| f.glyph(c)).unwrap_or(0);
+        if glyph != 0 {
+            used.entry(face).or_default().insert(glyph, c);
+        }
+        out.push_str(&format!("{glyph:04X}"));
+    }
+    out.push('>');
+    out
+}
+
+// Document info strings are UTF-16BE so titles keep characters WinAnsi lacks
+fn pdf_text_string(s: &str) -> String {
+    let mut out = String::from("<FEFF");
+    for unit in s.encode_utf16() {
+        out.push_str(&format!("{unit:04X}"));
+    }
+    out.push('>');
+    out
+}
+
+fn color(c: Rgb) -> String {
+    format!(
+        "{} {} {}",
+        num(c.0 as f32 / 255.0),
+        num(c.1 as f32 / 255.0),
+        num(c.2 as f32 / 255.0)
+    )
+}
+
+fn draw_line(
+    out: &mut Vec<u8>,
+    used: &mut UsedGlyphs,
+    line: &Line,
+    top: f32,
+    left: f32,
+    width: f32,
+) {
+    let baseline = top - line.ascent;
+    if line.code_bg {
+        out.extend(
+            format!(
+                "0.95 0.95 0.95 rg {} {} {} {} re f\n",
+                num(left - 4.0),
+                num(top - line.height),
+                num(width + 8.0),
+                num(line.height)
+            )
+            .as_bytes(),
+        );
+    }
+    for item in &line.items {
+        let s = &item.style;
+        let x = left + item.x;
+        if let Some((index, height)) = item.image {
+            out.extend(
+                format!(
+                    "q {} 0 0 {} {} {} cm /Im{} Do Q\n",
+                    num(item.width),
+                    num(height),
+                    num(x),
+                    num(baseline),
+                    index + 1
+                )
+                .as_bytes(),
+            );
+            continue;
+        }
+        if let Some(hl) = s.highlight {
+            out.extend(
+                format!(
+                    "{} rg {} {} {} {} re f\n",
+                    color(hl),
+                    num(x),
+                    num(baseline + s.rise - s.size * 0.22),
+                    num(item.width),
+                    num(s.size * 1.12)
+                )
+                .as_bytes(),
+            );
+        }
+        let fill = color(s.color.unwrap_or((0, 0, 0)));
+        if !item.text.chars().all(|c| c == ' ') {
+            out.extend(
+                format!(
+                    "BT /{} {} Tf {fill} rg {} {} Td {} Tj ET\n",
+                    resource(item.face),
+                    num(s.size),
+                    num(x),
+                    num(baseline + s.rise),
+                    glyph_string(item.face, &item.text, used)
+                )
+                .as_bytes(),
+            );
+        }
+        let stroke = |y: f32| {
+            format!(
+                "{fill} RG {} w {} {} m {} {} l S\n",
+                num((s.size * 0.06).max(0.5)),
+                num(x),
+                num(y),
+                num(x + item.width),
+                num(y)
+            )
+        };
+        if s.underline {
+            out.extend(stroke(baseline + s.rise - s.size * 0.12).as_bytes());
+        }
+        if s.strike {
+            out.extend(stroke(baseline + s.rise + s.size * 0.3).as_bytes());
+        }
+    }
+}
+
+// One centred grey line with its baseline at `y`
+fn draw_caption(out: &mut Vec<u8>, used: &mut UsedGlyphs, text: &str, y: f32, page_w: f32) {
+    let mut style = Style::new(9.0);
+    style.color = Some((102, 102, 102));
+    let tokens = [Token::Text(text.to_string(), style)];
+    let Some(line) = flow(&tokens, f32::MAX, 0.0, Align::Left, None).pop() else {
+        return;
+    };
+    let w: f32 = line.items.iter().map(|i| i.width).sum();
+    draw_line(out, used, &line, y + line.ascent, (page_w - w) / 2.0, w);
+}
+
+fn substitute(template: &str, page: usize, pages: usize, title: &str) -> String {
+    template
+        .replace("{page}", &page.to_string())
+        .replace("{pages}", &pages.to_string())
+        .replace("{title}", title)
+}
+
+fn layout<'a>(
+    input: &str,
+    opts: &PdfOptions,
+    image: &'a mut ImageLookup<'a>,
+) -> (Layout<'a>, Vec<Page>) {
+    let (page_w, page_h) = opts.page_dims();
+    let width = (page_w - opts.margin_left - opts.margin_right).max(36.0);
+    let height = (page_h - opts.margin_top - opts.margin_bottom).max(36.0);
+    let mut layout = Layout {
+        width,
+        height,
+        base: opts.font_size.clamp(4.0, 72.0),
+        lines: Vec::new(),
+        notes: Vec::new(),
+        image,
+        images: Vec::new(),
+        alts: Vec::new(),
+    };
+    layout.blocks(&html::parse(input), 0.0);
+    let pages = paginate(&layout, page_h - opts.margin_top, opts.margin_bottom);
+    (layout, pages)
+}
+
+/// Render editor HTML to a PDF document. `image` maps an `<img src>` to the
+/// attachment's file name and bytes, as for DOCX. Fails when the text, the alt
+/// text of an image that is not drawn, or a header or footer has characters no
+/// font can show.
+pub fn render_pdf(
+    input: &str,
+    title: &str,
+    opts: &PdfOptions,
+    image: &mut ImageLookup<'_>,
+) -> Result<Vec<u8>, RosePadError> {
+    let (page_w, page_h) = opts.page_dims();
+    let (layout, pages) = layout(input, opts, image);
+    let left = opts.margin_left;
+
+    let mut texts = Vec::new();
+    let nodes = html::parse(input);
+    collect_text(&nodes, &mut texts);
+    let captions: Vec<String> = [&opts.header, &opts.footer]
+        .into_iter()
+        .flatten()
+        .map(|t| substitute(t, 1, 1, title))
+        .collect();
+    check_covered(
+        texts
+            .into_iter()
+            .chain(layout.alts.iter().map(String::as_str))
+            .chain(captions.iter().map(String::as_str)),
+    )?;
+
+    let mut used = UsedGlyphs::new();
+    let mut contents: Vec<Vec<u8>> = Vec::new();
+    for (i, page) in pages.iter().enumerate() {
+        let mut out = Vec::new();
+        for (top, line) in &page.lines {
+            draw_line(&mut out, &mut used, line, *top, left, layout.width);
+        }
+        if !page.notes.is_empty() {
+            let note_lines: Vec<&Line> = page
+                .notes
+                .iter()
+                .filter_map(|n| layout.notes.get(*n))
+                .flatten()
+                .collect();
+            let total: f32 = note_lines.iter().map(|l| l.height).sum();
+            let mut top = opts.margin_bottom + total;
+            out.extend(
+                format!(
+                    "0 0 0 RG 0.5 w {} {} m {} {} l S\n",
+                    num(left),
+                    num(top + NOTE_SEPARATOR / 2.0),
+                    num(left + layout.width / 3.0),
+                    num(top + NOTE_SEPARATOR / 2.0)
+                )
+                .as_bytes(),
+            );
+            for line in note_lines {
+                draw_line(&mut out, &mut used, line, top, left, layout.width);
+                top -= line.height;
+            }
+        }
+        if let Some(h) = opts.header.as_deref().filter(|h| !h.is_empty()) {
+            let text = substitute(h, i + 1, pages.len(), title);
+            draw_caption(
+                &mut out,
+                &mut used,
+                &text,
+                page_h - opts.margin_top / 2.0,
+                page_w,
+            );
+        }
+        if let Some(f) = opts.footer.as_deref().filter(|f| !f.is_empty()) {
+            let text = substitute(f, i + 1, pages.len(), title);
+            draw_caption(
+                &mut out,
+                &mut used,
+                &text,
+                opts.margin_bottom / 2.0 - 3.0,
+                page_w,
+            );
+        }
+        contents.push(out);
+    }
+
+    // Object layout: 1 catalog, 2 page tree, 3 info, five per font used, the
+    // images, then page + content pairs
+    const PER_FONT: usize = 5;
+    let font_base = 4;
+    let page_base = font_base
+        + used.len() * PER_FONT
+        + layout
+            .images
+            .iter()
+            .map(|(_, i)| i.object_count())
+            .sum::<usize>();
+    let mut objects: Vec<Vec<u8>> = Vec::new();
+    let kids: Vec<String> = (0..pages.len())
+        .map(|i| format!("{} 0 R", page_base + i * 2))
+        .collect();
+    objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+    objects.push(
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            pages.len()
+        )
+        .into_bytes(),
+    );
+    objects.push(
+        format!(
+            "<< /Title {} /Producer (RosePad) >>",
+            pdf_text_string(title)
+        )
+        .into_bytes(),
+    );
+    let mut font_refs: Vec<String> = Vec::new();
+    for (i, (slot, glyphs)) in used.iter().enumerate() {
+        let first = font_base + i * PER_FONT;
+        let font = pdf_fonts::font(*slot)
+            .ok_or_else(|| RosePadError::failed("A font used by the document is unavailable"))?;
+        objects.extend(pdf_fonts::embed(font, glyphs, first)?);
+        font_refs.push(format!("/{} {first} 0 R", resource(*slot)));
+    }
+    let mut image_refs: Vec<String> = Vec::new();
+    for (i, (_, image)) in layout.images.iter().enumerate() {
+        let first = objects.len() + 1;
+        objects.extend(image.embed(first));
+        image_refs.push(format!("/Im{} {first} 0 R", i + 1));
+    }
+    let images = if image_refs.is_empty() {
+        String::new()
+    } else {
+        format!(" /XObject << {} >>", image_refs.join(" "))
+    };
+    for (i, content) in contents.into_iter().enumerate() {
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << {} >>{images} >> /Contents {} 0 R >>",
+                num(page_w),
+                num(page_h),
+                font_refs.join(" "),
+                page_base + i * 2 + 1
+            )
+            .into_bytes(),
+        );
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend(content);
+        stream.extend(b"\nendstream");
+        objects.push(stream);
+    }
+
+    let mut pdf: Vec<u8> = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, body) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n", i + 1).as_bytes());
+        pdf.extend(body);
+        pdf.extend(b"\nendobj\n");
+    }
+    let xref = pdf.len();
+    pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for off in offsets {
+        pdf.extend(format!("{off:010} 00000 n \n").as_bytes());
+    }
+    pdf.extend(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R /Info 3 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .as_bytes(),
+    );
+    Ok(pdf)
+}
+
+/// Export an .rpad document to PDF without going through the webview.
+#[tauri::command]
+pub async fn export_pdf(
+    app: AppHandle,
+    path: String,
+    dest: String,
+    options: Option<PdfOptions>,
+) -> Result<String, RosePadError> {
+    during("export_pdf", async move {
+        paths::document(&app, &path)?;
+        paths::document(&app, &dest)?;
+        let archive = RpadArchive::open(Path::new(&path))?;
+        let opts = options.unwrap_or_default();
+        let mut image = |src: &str| {
+            let name = attachments::entry_for_src(src)?;
+            let entry = archive.entries.iter().find(|e| e.name == name)?;
+            Some((entry.name.clone(), entry.data.clone()))
+        };
+        let bytes = render_pdf(
+            &archive.document.html,
+            &archive.manifest.title,
+            &opts,
+            &mut image,
+        )?;
+        let destp = PathBuf::from(&dest);
+        durable::write_atomic(&destp, &bytes)?;
+        Ok(destp.to_string_lossy().to_string())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filler(paragraphs: usize) -> String {
+        (0..paragraphs)
+            .map(|i| format!("<p>Paragraph {i} with enough words to take up a little room.</p>"))
+            .collect()
+    }
+
+    // The glyph ids `draw_line` writes for `text` set in the face in `slot`
+    fn shown(text: &str, slot: usize) -> String {
+        let font = pdf_fonts::font(slot).unwrap();
+        text.chars()
+            .map(|c| format!("{:04X}", font.glyph(c).unwrap()))
+            .collect()
+    }
+
+    fn count(haystack: &[u8], needle: &str) -> usize {
+        haystack
+            .windows(needle.len())
+            .filter(|w| *w == needle.as_bytes())
+            .count()
+    }
+
+    #[test]
+    fn output_is_a_well_formed_pdf() {
+        let pdf = render_pdf("<p>Hello</p>", "Doc", &PdfOptions::default(), &mut |_| None).unwrap();
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+
+        let text = String::from_utf8_lossy(&pdf);
+        let start: usize = text
+            .rsplit("startxref\n")
+            .next()
+            .and_then(|s| s.lines().next())
+            .and_then(|s| s.parse().ok())
+            .unwrap();
+        assert!(pdf[start..].starts_with(b"xref"));
+        assert!(text.contains(&format!("<{}> Tj", shown("Hello", 0))));
+        // Only the face the text uses is embedded
+        assert_eq!(count(&pdf, "/Subtype /Type0"), 1);
+        assert!(text.contains("+DejaVuSans /Encoding /Identity-H"));
+        assert!(text.contains("/Font << /F1 4 0 R >>"));
+    }
+
+    #[test]
+    fn long_documents_paginate_with_numbered_footers() {
+        let opts = PdfOptions {
+            footer: Some("Page {page} of {pages}".into()),
+            header: Some("{title}".into()),
+            ..PdfOptions::default()
+        };
+        let pdf = render_pdf(&filler(120), "Report", &opts, &mut |_| None).unwrap();
+        let pages = count(&pdf, "/Type /Page ");
+        assert!(pages > 1);
+        assert!(count(&pdf, &format!("/Count {pages}")) == 1);
+        assert!(count(&pdf, &shown(&format!("Page {pages} of {pages}"), 0)) == 1);
+        assert_eq!(count(&pdf, &shown("Report", 0)), pages);
+    }
+
+    #[test]
+    fn lines_stay_inside_the_margins() {
+        let opts = PdfOptions::default();
+        let long = "word ".repeat(400) + &"x".repeat(300);
+        let mut no_images = |_: &str| None;
+        let (layout, _) = layout(
+            &format!("<p style=\"text-align:justify\">{long}</p>"),
+            &opts,
+            &mut no_images,
+        );
+        assert!(layout.lines.len() > 5);
+        for line in &layout.lines {
+            for item in &line.items {
+                assert!(item.x >= 0.0);
+                assert!(item.x + item.width <= layout.width + 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn footnotes_land_on_the_page_that_references_them() {
+        let opts = PdfOptions::default();
+        for filler_count in [10, 25, 31, 32, 33, 40] {
+            let html = format!(
+                "{}<p>Claim<footnote>Source for the claim</footnote> here.</p>{}",
+                filler(filler_count),
+                filler(20)
+            );
+            let (_, pages) = layout(&html, &opts, &mut |_| None);
+            let with_ref: Vec<usize> = pages
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| p.lines.iter().any(|(_, l)| l.notes.contains(&0)))
+                .map(|(i, _)| i)
+                .collect();
+            assert_eq!(with_ref.len(), 1);
+            assert_eq!(pages[with_ref[0]].notes, vec![0]);
+
+            // Body text never overlaps the footnote area
+            let page = &pages[with_ref[0]];
+            let (top, last) = page.lines.last().unwrap();
+            assert!(top - last.height >= opts.margin_bottom);
+        }
+    }
+
+    #[test]
+    fn marks_become_drawing_operators() {
+        let html = "<p><strong>b</strong><span style=\" color: #ff0000\">r</span>\
+<span style=\"background-color: #ffff00\">h</span><span style=\"font-size: 20pt\">big</span>\
+<u>u</u></p><pre><code>let x = (1);</code></pre>";
+        let pdf = render_pdf(html, "t", &PdfOptions::default(), &mut |_| None).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("/F2 12 Tf"));
+        assert!(text.contains("1 0 0 rg"));
+        assert!(text.contains("1 1 0 rg"));
+        assert!(text.contains("/F1 20 Tf"));
+        assert!(text.contains("/F5 10.2 Tf"));
+        assert!(text.contains(&shown("let", 4)));
+        assert!(text.contains(" l S"));
+    }
+
+    #[test]
+    fn images_are_drawn_or_stand_in_as_alt_text() {
+        // A JPEG down to its frame header, 40 x 20 px
+        let jpeg = vec![0xFF, 0xD8, 0xFF, 0xC0, 0, 11, 8, 0, 20, 0, 40, 3];
+        let mut image = |src: &str| match src {
+            "a.jpg" => Some(("a.jpg".to_string(), jpeg.clone())),
+            "b.gif" => Some(("b.gif".to_string(), b"GIF89a".to_vec())),
+            _ => None,
+        };
+        let opts = PdfOptions::default();
+        let html = "<p><img src=\"a.jpg\" alt=\"chart\"> and <img src=\"a.jpg\">\
+<img src=\"b.gif\" alt=\"logo\"></p>";
+        let pdf = render_pdf(html, "t", &opts, &mut image).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        // Stored once, drawn twice at 0.75 pt per pixel
+        assert_eq!(count(&pdf, "/Subtype /Image"), 1);
+        assert!(text.contains("/XObject << /Im1 "));
+        assert_eq!(count(&pdf, "/Im1 Do"), 2);
+        assert!(text.contains("q 30 0 0 15 72 "));
+        assert!(text.contains(&shown("[logo]", 0)));
+        assert!(!text.contains(&shown("chart", 0)));
+
+        // Only alt text that gets printed has to be covered
+        let html = "<p><img src=\"b.gif\" alt=\"\u{E000}\"></p>";
+        assert!(render_pdf(html, "t", &opts, &mut image).is_err());
+        let html = "<p><img src=\"a.jpg\" alt=\"\u{E000}\"></p>";
+        assert!(render_pdf(html, "t", &opts, &mut image).is_ok());
+    }
+
+    #[test]
+    fn any_script_with_a_font_renders() {
+        let html = "<p>Café “quoted”</p><p>Привет αβ</p>";
+        let pdf = render_pdf(html, "t", &PdfOptions::default(), &mut |_| None).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains(&shown("Café", 0)));
+        assert!(text.contains(&shown("Привет", 0)));
+        assert!(text.contains(&shown("αβ", 0)));
+        assert!(text.contains("/ToUnicode"));
+    }
+
+    #[test]
+    fn text_no_font_covers_is_refused_not_replaced() {
+        let html = "<p>Plain \u{E000} text</p><p>\u{10FFFD} and \u{E000}</p>";
+        let msg = render_pdf(html, "t", &PdfOptions::default(), &mut |_| None)
+            .unwrap_err()
+            .to_string();
+        // Each character is listed once, and covered ones not at all
+        assert!(msg.ends_with(": \u{E000} \u{10FFFD}"), "{msg}");
+
+        // Headers and footers are checked too, including the title they show
+        let opts = PdfOptions {
+            header: Some("{title}".into()),
+            ..PdfOptions::default()
+        };
+        assert!(render_pdf("<p>x</p>", "\u{E000}", &opts, &mut |_| None).is_err());
+        assert!(
+            render_pdf("<p>x</p>", "\u{E000}", &PdfOptions::default(), &mut |_| {
+                None
+            })
+            .is_ok()
+        );
+    }
+}
@@ -0,0 +1,284 @@
+// Fonts for the PDF export. The DejaVu Sans family ships with the app (see
+// `fonts/LICENSE`) and covers Latin, Greek, Cyrillic and more; characters it
+// lacks, such as CJK, come from a system font when one of the usual TrueType
+// ones is installed. A document embeds only the glyphs it uses, as a TrueType
+// subset behind a composite font with a ToUnicode map, so the text in the PDF
+// can still be searched and copied.
+
+use flate2::{write::ZlibEncoder, Compression};
+use lazy_static::lazy_static;
+use std::{collections::BTreeMap, fs, io::Write, path::Path};
+use ttf_parser::{name_id, Face};
+
+/// Slot of the system font, after the bundled faces.
+pub(crate) const FALLBACK: usize = 5;
+
+// Same order as `pdf::FONTS`
+const BUNDLED: [(&str, &[u8]); 5] = [
+    ("DejaVuSans", include_bytes!("../fonts/DejaVuSans.ttf")),
+    (
+        "DejaVuSans-Bold",
+        include_bytes!("../fonts/DejaVuSans-Bold.ttf"),
+    ),
+    (
+        "DejaVuSans-Oblique",
+        include_bytes!("../fonts/DejaVuSans-Oblique.ttf"),
+    ),
+    (
+        "DejaVuSans-BoldOblique",
+        include_bytes!("../fonts/DejaVuSans-BoldOblique.ttf"),
+    ),
+    (
+        "DejaVuSansMono",
+        include_bytes!("../fonts/DejaVuSansMono.ttf"),
+    ),
+];
+
+// System fonts tried in order for characters DejaVu Sans lacks. Only fonts
+// with TrueType outlines qualify, since those are what `embed` writes
+const FALLBACK_FONTS: &[&str] = &[
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\simsun.ttc",
+    "C:\\Windows\\Fonts\\malgun.ttf",
+    "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+    "/Library/Fonts/Arial Unicode.ttf",
+    "/usr/share/fonts/truetype/droid/DroidSansFallbackFull.ttf",
+    "/usr/share/fonts/truetype/wqy/wqy-zenhei.ttc",
+    "/usr/share/fonts/wenquanyi/wqy-zenhei/wqy-zenhei.ttc",
+    "/usr/share/fonts/truetype/arphic/uming.ttc",
+];
+
+pub(crate) struct PdfFont {
+    name: String,
+    data: &'static [u8],
+    face: Face<'static>,
+}
+
+lazy_static! {
+    static ref FACES: Vec<PdfFont> = BUNDLED
+        .iter()
+        .map(|(name, data)| PdfFont {
+            name: name.to_string(),
+            data,
+            face: Face::parse(data, 0).expect("bundled font"),
+        })
+        .collect();
+    // Read on first use, which only happens when DejaVu Sans lacks a character;
+    // it stays loaded for the rest of the session
+    static ref SYSTEM_FONT: Option<PdfFont> = FALLBACK_FONTS.iter().find_map(|p| load(Path::new(p)));
+}
+
+fn load(path: &Path) -> Option<PdfFont> {
+    let bytes = fs::read(path).ok()?;
+    Face::parse(&bytes, 0).ok()?.tables().glyf?;
+    let data: &'static [u8] = Box::leak(bytes.into_boxed_slice());
+    let face = Face::parse(data, 0).ok()?;
+    let name = face
+        .names()
+        .into_iter()
+        .filter(|n| n.name_id == name_id::POST_SCRIPT_NAME)
+        .find_map(|n| n.to_string())
+        .map(|n| {
+            n.chars()
+                .filter(char::is_ascii_alphanumeric)
+                .collect::<String>()
+        })
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| "Fallback".into());
+    Some(PdfFont { name, data, face })
+}
+
+/// The font in `slot`: one of the bundled faces, or `FALLBACK` when a system
+/// font was found.
+pub(crate) fn font(slot: usize) -> Option<&'static PdfFont> {
+    if slot == FALLBACK {
+        return SYSTEM_FONT.as_ref();
+    }
+    FACES.get(slot)
+}
+
+impl PdfFont {
+    pub(crate) fn glyph(&self, c: char) -> Option<u16> {
+        self.face.glyph_index(c).map(|g| g.0).filter(|g| *g != 0)
+    }
+
+    /// Advance width of a glyph in 1/1000 em.
+    pub(crate) fn advance(&self, glyph: u16) -> f32 {
+        let units = self
+            .face
+            .glyph_hor_advance(ttf_parser::GlyphId(glyph))
+            .unwrap_or(0);
+        self.scale(units as f32)
+    }
+
+    fn scale(&self, units: f32) -> f32 {
+        units * 1000.0 / self.face.units_per_em() as f32
+    }
+
+    // A prefix derived from the glyphs, as PDF asks of subset font names
+    fn subset_name(&self, used: &BTreeMap<u16, char>) -> String {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(self.name.as_bytes());
+        for glyph in used.keys() {
+            hasher.update(&glyph.to_be_bytes());
+        }
+        let tag: String = hasher.finalize().as_bytes()[..6]
+            .iter()
+            .map(|b| (b'A' + b % 26) as char)
+            .collect();
+        format!("{tag}+{}", self.name)
+    }
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Writing into a Vec cannot fail
+    let _ = encoder.write_all(data);
+    encoder.finish().unwrap_or_default()
+}
+
+fn stream(dict: &str, data: Vec<u8>) -> Vec<u8> {
+    let mut out = format!(
+        "<< {dict} /Filter /FlateDecode /Length {} >>\nstream\n",
+        data.len()
+    )
+    .into_bytes();
+    out.extend(data);
+    out.extend(b"\nendstream");
+    out
+}
+
+// Maps each glyph back to the character it shows
+fn to_unicode(used: &BTreeMap<u16, char>) -> Vec<u8> {
+    let mut cmap = String::from(
+        "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+/CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+/CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+    );
+    let pairs: Vec<(&u16, &char)> = used.iter().collect();
+    for chunk in pairs.chunks(100) {
+        cmap.push_str(&format!("{} beginbfchar\n", chunk.len()));
+        for (glyph, c) in chunk {
+            let units: String = c
+                .encode_utf16(&mut [0; 2])
+                .iter()
+                .map(|u| format!("{u:04X}"))
+                .collect();
+            cmap.push_str(&format!("<{glyph:04X}> <{units}>\n"));
+        }
+        cmap.push_str("endbfchar\n");
+    }
+    cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend");
+    cmap.into_bytes()
+}
+
+/// The five objects that embed `font` with the glyphs in `used`, numbered from
+/// `first`: the Type0 font (the one pages refer to), its CIDFont, the font
+/// descriptor, the subset font file and the ToUnicode map.
+pub(crate) fn embed(
+    font: &PdfFont,
+    used: &BTreeMap<u16, char>,
+    first: usize,
+) -> Result<Vec<Vec<u8>>, String> {
+    let glyphs: Vec<u16> = used.keys().copied().collect();
+    let subset = subsetter::subset(font.data, 0, subsetter::Profile::pdf(&glyphs))
+        .map_err(|e| format!("could not embed the font {}: {e}", font.name))?;
+    let name = font.subset_name(used);
+    let face = &font.face;
+    let widths: String = glyphs
+        .iter()
+        .map(|g| format!("{g} [{}]", font.advance(*g).round()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let bbox = face.global_bounding_box();
+    let ascent = font.scale(face.ascender() as f32).round();
+    let descent = font.scale(face.descender() as f32).round();
+    let cap_height = face
+        .capital_height()
+        .map(|h| font.scale(h as f32).round())
+        .unwrap_or(ascent);
+    // Nonsymbolic, plus fixed pitch and italic where they apply
+    let flags = 32 | u32::from(face.is_monospaced()) | if face.is_italic() { 64 } else { 0 };
+    Ok(vec![
+        format!(
+            "<< /Type /Font /Subtype /Type0 /BaseFont /{name} /Encoding /Identity-H \
+/DescendantFonts [{} 0 R] /ToUnicode {} 0 R >>",
+            first + 1,
+            first + 4
+        )
+        .into_bytes(),
+        format!(
+            "<< /Type /Font /Subtype /CIDFontType2 /BaseFont /{name} \
+/CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> \
+/FontDescriptor {} 0 R /W [{widths}] /CIDToGIDMap /Identity >>",
+            first + 2
+        )
+        .into_bytes(),
+        format!(
+            "<< /Type /FontDescriptor /FontName /{name} /Flags {flags} \
+/FontBBox [{} {} {} {}] /ItalicAngle {} /Ascent {ascent} /Descent {descent} \
+/CapHeight {cap_height} /StemV 80 /FontFile2 {} 0 R >>",
+            font.scale(bbox.x_min as f32).round(),
+            font.scale(bbox.y_min as f32).round(),
+            font.scale(bbox.x_max as f32).round(),
+            font.scale(bbox.y_max as f32).round(),
+            face.italic_angle().round(),
+            first + 3
+        )
+        .into_bytes(),
+        stream(&format!("/Length1 {}", subset.len()), deflate(&subset)),
+        stream("", deflate(&to_unicode(used))),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_bundled_faces_cover_latin_greek_and_cyrillic() {
+        for slot in 0..FALLBACK {
+            let font = font(slot).unwrap();
+            for c in "Aé€Жщαω".chars() {
+                assert!(font.glyph(c).is_some(), "{} lacks {c}", font.name);
+            }
+        }
+        assert!(font(0).unwrap().glyph('\u{E000}').is_none());
+        let mono = font(4).unwrap();
+        let (i, m) = (mono.glyph('i').unwrap(), mono.glyph('m').unwrap());
+        assert_eq!(mono.advance(i), mono.advance(m));
+    }
+
+    #[test]
+    fn embedding_subsets_the_font_and_maps_glyphs_back() {
+        let font = font(0).unwrap();
+        let used: BTreeMap<u16, char> = "Жa".chars().map(|c| (font.glyph(c).unwrap(), c)).collect();
+        let objects = embed(font, &used, 10).unwrap();
+        assert_eq!(objects.len(), 5);
+        let dict = String::from_utf8_lossy(&objects[0]);
+        assert!(
+            dict.contains("/DescendantFonts [11 0 R] /ToUnicode 14 0 R"),
+            "{dict}"
+        );
+        let name = dict
+            .split("/BaseFont /")
+            .nth(1)
+            .unwrap()
+            .split(' ')
+            .next()
+            .unwrap();
+        assert!(name.ends_with("+DejaVuSans") && name.len() == 7 + "DejaVuSans".len());
+
+        let cmap = to_unicode(&used);
+        let cmap = String::from_utf8_lossy(&cmap);
+        let zhe = font.glyph('Ж').unwrap();
+        assert!(cmap.contains(&format!("<{zhe:04X}> <0416>")), "{cmap}");
+        // Characters outside the BMP become surrogate pairs
+        let emoji = BTreeMap::from([(7u16, '😀')]);
+        assert!(String::from_utf8_lossy(&to_unicode(&emoji)).contains("<0007> <D83DDE00>"));
+
+        // Far smaller than the whole face
+        assert!(objects[3].len() < BUNDLED[0].1.len() / 10);
+    }
+}
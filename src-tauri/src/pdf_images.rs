@@ -0,0 +1,319 @@
+// Images for the PDF export. JPEG data goes into the PDF as is; PNG data is
+// passed through with its predictors when it has no alpha channel, and is
+// unfiltered and split into colour and soft mask when it does. Other formats,
+// interlaced PNGs and 16-bit PNGs with alpha are not drawn.
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::io::{Read, Write};
+
+pub(crate) struct PdfImage {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    // Everything in the image dictionary besides size, type and length
+    dict: String,
+    data: Vec<u8>,
+    // 8-bit alpha, deflated
+    mask: Option<Vec<u8>>,
+}
+
+/// Decode an attachment into something a PDF can draw, or `None` when its
+/// format is not supported.
+pub(crate) fn decode(data: &[u8]) -> Option<PdfImage> {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        jpeg(data)
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png(data)
+    } else {
+        None
+    }
+}
+
+fn be16(data: &[u8], i: usize) -> Option<u32> {
+    Some(u16::from_be_bytes(data.get(i..i + 2)?.try_into().ok()?) as u32)
+}
+
+fn be32(data: &[u8], i: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(i..i + 4)?.try_into().ok()?))
+}
+
+fn jpeg(data: &[u8]) -> Option<PdfImage> {
+    // Walk the segments up to the first start-of-frame marker, noting whether
+    // an Adobe segment says CMYK values are stored inverted
+    let mut adobe = false;
+    let mut i = 2;
+    let (width, height, components) = loop {
+        if *data.get(i)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(i + 1)?;
+        let len = be16(data, i + 2)? as usize;
+        if marker == 0xEE && data.get(i + 4..i + 9) == Some(b"Adobe") {
+            adobe = true;
+        }
+        if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            break (be16(data, i + 7)?, be16(data, i + 5)?, *data.get(i + 9)?);
+        }
+        i += 2 + len;
+    };
+    let space = match components {
+        1 => "/DeviceGray",
+        3 => "/DeviceRGB",
+        4 if adobe => "/DeviceCMYK /Decode [1 0 1 0 1 0 1 0]",
+        4 => "/DeviceCMYK",
+        _ => return None,
+    };
+    (width > 0 && height > 0).then(|| PdfImage {
+        width,
+        height,
+        dict: format!("/ColorSpace {space} /BitsPerComponent 8 /Filter /DCTDecode"),
+        data: data.to_vec(),
+        mask: None,
+    })
+}
+
+fn png(data: &[u8]) -> Option<PdfImage> {
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut idat = Vec::new();
+    let mut i = 8;
+    while let Some(len) = be32(data, i) {
+        let kind = data.get(i + 4..i + 8)?;
+        let body = data.get(i + 8..i + 8 + len as usize)?;
+        match kind {
+            b"IHDR" if body.len() >= 13 => header = Some(body),
+            b"PLTE" => palette = body,
+            b"IDAT" => idat.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        i += 12 + len as usize;
+    }
+    let header = header?;
+    let (width, height) = (be32(header, 0)?, be32(header, 4)?);
+    let (depth, color, interlaced) = (header[8], header[9], header[12] != 0);
+    if width == 0 || height == 0 || interlaced || idat.is_empty() {
+        return None;
+    }
+    let (colors, space) = match color {
+        0 | 4 => (1, "/DeviceGray".to_string()),
+        2 | 6 => (3, "/DeviceRGB".to_string()),
+        3 if !palette.is_empty() => {
+            let table: String = palette.iter().map(|b| format!("{b:02X}")).collect();
+            (
+                1,
+                format!("[/Indexed /DeviceRGB {} <{table}>]", palette.len() / 3 - 1),
+            )
+        }
+        _ => return None,
+    };
+    if color != 4 && color != 6 {
+        // The PNG predictors are PDF predictors too
+        return Some(PdfImage {
+            width,
+            height,
+            dict: format!(
+                "/ColorSpace {space} /BitsPerComponent {depth} /Filter /FlateDecode \
+/DecodeParms << /Predictor 15 /Colors {colors} /BitsPerComponent {depth} /Columns {width} >>"
+            ),
+            data: idat,
+            mask: None,
+        });
+    }
+    if depth != 8 {
+        return None;
+    }
+    let channels = colors + 1;
+    let pixels = unfilter(&idat, width as usize, height as usize, channels)?;
+    let mut rgb = Vec::with_capacity(pixels.len() / channels * colors);
+    let mut alpha = Vec::with_capacity(pixels.len() / channels);
+    for px in pixels.chunks_exact(channels) {
+        rgb.extend_from_slice(&px[..colors]);
+        alpha.push(px[colors]);
+    }
+    Some(PdfImage {
+        width,
+        height,
+        dict: format!("/ColorSpace {space} /BitsPerComponent 8 /Filter /FlateDecode"),
+        data: deflate(&rgb),
+        mask: Some(deflate(&alpha)),
+    })
+}
+
+// Undo the per-row PNG filters of 8-bit samples
+fn unfilter(idat: &[u8], width: usize, height: usize, bpp: usize) -> Option<Vec<u8>> {
+    let stride = width.checked_mul(bpp)?;
+    let expected = (stride + 1).checked_mul(height)?;
+    let mut raw = Vec::new();
+    ZlibDecoder::new(idat)
+        .take(expected as u64)
+        .read_to_end(&mut raw)
+        .ok()?;
+    if raw.len() != expected {
+        return None;
+    }
+    let mut out = vec![0u8; stride * height];
+    for y in 0..height {
+        let filter = raw[y * (stride + 1)];
+        let src = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (done, row) = out.split_at_mut(y * stride);
+        let row = &mut row[..stride];
+        let prev = (y > 0).then(|| &done[(y - 1) * stride..]);
+        for x in 0..stride {
+            let a = if x >= bpp { row[x - bpp] } else { 0 };
+            let b = prev.map_or(0, |p| p[x]);
+            let c = if x >= bpp {
+                prev.map_or(0, |p| p[x - bpp])
+            } else {
+                0
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return None,
+            };
+            row[x] = src[x].wrapping_add(predicted);
+        }
+    }
+    Some(out)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Writing into a Vec cannot fail
+    let _ = encoder.write_all(data);
+    encoder.finish().unwrap_or_default()
+}
+
+fn xobject(width: u32, height: u32, dict: &str, data: &[u8]) -> Vec<u8> {
+    let mut out = format!(
+        "<< /Type /XObject /Subtype /Image /Width {width} /Height {height} {dict} /Length {} >>\nstream\n",
+        data.len()
+    )
+    .into_bytes();
+    out.extend(data);
+    out.extend(b"\nendstream");
+    out
+}
+
+impl PdfImage {
+    /// How many objects `embed` writes.
+    pub(crate) fn object_count(&self) -> usize {
+        1 + usize::from(self.mask.is_some())
+    }
+
+    /// The image XObject, numbered `first`, followed by its soft mask if it
+    /// has one.
+    pub(crate) fn embed(&self, first: usize) -> Vec<Vec<u8>> {
+        let Some(mask) = &self.mask else {
+            return vec![xobject(self.width, self.height, &self.dict, &self.data)];
+        };
+        let dict = format!("{} /SMask {} 0 R", self.dict, first + 1);
+        vec![
+            xobject(self.width, self.height, &dict, &self.data),
+            xobject(
+                self.width,
+                self.height,
+                "/ColorSpace /DeviceGray /BitsPerComponent 8 /Filter /FlateDecode",
+                mask,
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(kind: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut out = (body.len() as u32).to_be_bytes().to_vec();
+        out.extend(kind);
+        out.extend(body);
+        // Not checked on the way in
+        out.extend([0; 4]);
+        out
+    }
+
+    fn png_file(width: u32, height: u32, color: u8, rows: &[u8]) -> Vec<u8> {
+        let mut header = width.to_be_bytes().to_vec();
+        header.extend(height.to_be_bytes());
+        header.extend([8, color, 0, 0, 0]);
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        data.extend(chunk(b"IHDR", &header));
+        data.extend(chunk(b"IDAT", &deflate(rows)));
+        data.extend(chunk(b"IEND", &[]));
+        data
+    }
+
+    #[test]
+    fn opaque_pngs_keep_their_compressed_rows() {
+        let rows = [0, 10, 20, 30, 2, 1, 1, 1];
+        let image = decode(&png_file(1, 2, 2, &rows)).unwrap();
+        assert_eq!((image.width, image.height), (1, 2));
+        assert!(image.dict.contains("/Predictor 15 /Colors 3"));
+        assert_eq!(image.object_count(), 1);
+        let mut raw = Vec::new();
+        ZlibDecoder::new(&image.data[..])
+            .read_to_end(&mut raw)
+            .unwrap();
+        assert_eq!(raw, rows);
+    }
+
+    #[test]
+    fn alpha_becomes_a_soft_mask() {
+        // Row 1 unfiltered, row 2 with the Up filter, row 3 with Paeth
+        let rows = [
+            0, 10, 20, 30, 255, 40, 50, 60, 0, //
+            2, 1, 1, 1, 0, 1, 1, 1, 128, //
+            4, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let image = decode(&png_file(2, 3, 6, &rows)).unwrap();
+        let mut rgb = Vec::new();
+        ZlibDecoder::new(&image.data[..])
+            .read_to_end(&mut rgb)
+            .unwrap();
+        assert_eq!(
+            rgb,
+            [10, 20, 30, 40, 50, 60, 11, 21, 31, 41, 51, 61, 11, 21, 31, 41, 51, 61]
+        );
+        let objects = image.embed(7);
+        assert_eq!(objects.len(), 2);
+        assert!(String::from_utf8_lossy(&objects[0]).contains("/SMask 8 0 R"));
+        let mut alpha = Vec::new();
+        ZlibDecoder::new(&image.mask.unwrap()[..])
+            .read_to_end(&mut alpha)
+            .unwrap();
+        assert_eq!(alpha, [255, 0, 255, 128, 255, 128]);
+    }
+
+    #[test]
+    fn jpegs_pass_through() {
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 4, 0, 0];
+        data.extend([0xFF, 0xC0, 0, 11, 8, 0, 20, 0, 40, 3]);
+        let image = decode(&data).unwrap();
+        assert_eq!((image.width, image.height), (40, 20));
+        assert!(image.dict.contains("/DeviceRGB") && image.dict.contains("/DCTDecode"));
+        assert_eq!(image.data, data);
+
+        assert!(decode(b"GIF89a\x0a\x00\x05\x00").is_none());
+        assert!(decode(&png_file(0, 2, 2, &[])).is_none());
+    }
+}
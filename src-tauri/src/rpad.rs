@@ -0,0 +1,548 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs,
+    io::{Cursor, Read, Seek, Write},
+    path::Path,
+};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::durable;
+use crate::encryption::{self, RpadEncryption};
+use crate::error::RosePadError;
+
+/// Current on-disk format version written by this build.
+pub const FORMAT_VERSION: u32 = 2;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const DATA_ENTRY: &str = "data.json";
+
+// Entries v1 builds may have used for the document body, in lookup order
+const LEGACY_DATA_ENTRIES: &[&str] = &[
+    DATA_ENTRY,
+    "content.json",
+    "document.json",
+    "data/data.json",
+];
+const LEGACY_HTML_ENTRY: &str = "content.html";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RpadManifest {
+    pub title: String,
+    pub version: u32,
+    /// The document's workspace id, kept so it survives renames and moves.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Set when every entry but the manifest is sealed; see `encryption`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<RpadEncryption>,
+    // Keys written by other tools/builds are carried forward untouched
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl RpadManifest {
+    pub fn new(title: impl Into<String>) -> Self {
+        RpadManifest {
+            title: title.into(),
+            version: FORMAT_VERSION,
+            id: None,
+            encryption: None,
+            extra: serde_json::Map::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RpadDocument {
+    pub html: String,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A zip entry that is not part of the typed model (attachments, tool data, ...).
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpadEntry {
+    pub name: String,
+    pub data: Vec<u8>,
+    pub method: CompressionMethod,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpadArchive {
+    pub manifest: RpadManifest,
+    pub document: RpadDocument,
+    pub entries: Vec<RpadEntry>,
+}
+
+// Untyped view of an archive used while migrating between format versions
+struct RawArchive {
+    manifest: serde_json::Value,
+    entries: BTreeMap<String, (Vec<u8>, CompressionMethod)>,
+}
+
+type Migration = fn(&mut RawArchive) -> Result<(), String>;
+
+// MIGRATIONS[n] upgrades a version n+1 archive to version n+2
+const MIGRATIONS: &[Migration] = &[migrate_v1_to_v2];
+
+/// v1 archives stored the body under several names, sometimes as raw HTML or
+/// plain text. v2 always stores `{ "html": ... }` in `data.json`.
+fn migrate_v1_to_v2(raw: &mut RawArchive) -> Result<(), String> {
+    let legacy = LEGACY_DATA_ENTRIES
+        .iter()
+        .chain(std::iter::once(&LEGACY_HTML_ENTRY))
+        .find_map(|name| raw.entries.remove(*name).map(|(bytes, _)| (*name, bytes)));
+    let html = match legacy {
+        Some((name, bytes)) => {
+            let s = String::from_utf8(bytes).map_err(|_| format!("{name} is not valid UTF-8"))?;
+            if name == LEGACY_HTML_ENTRY {
+                s
+            } else {
+                match serde_json::from_str::<serde_json::Value>(&s) {
+                    Ok(v) => match v.get("html").and_then(|x| x.as_str()) {
+                        Some(h) => h.to_string(),
+                        None => s,
+                    },
+                    Err(_) => s,
+                }
+            }
+        }
+        None => String::new(),
+    };
+    let data = serde_json::json!({ "html": html });
+    raw.entries.insert(
+        DATA_ENTRY.to_string(),
+        (data.to_string().into_bytes(), CompressionMethod::Deflated),
+    );
+    Ok(())
+}
+
+impl Default for RawArchive {
+    fn default() -> Self {
+        RawArchive {
+            manifest: serde_json::json!({}),
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl RpadArchive {
+    pub fn new(title: impl Into<String>) -> Self {
+        RpadArchive {
+            manifest: RpadManifest::new(title),
+            document: RpadDocument::default(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Open an archive from disk, migrating older formats to [`FORMAT_VERSION`].
+    pub fn open(path: &Path) -> Result<Self, RosePadError> {
+        let file = fs::File::open(path).map_err(|e| RosePadError::io(e, path))?;
+        Self::from_reader(file).map_err(|e| at_document(e, path))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RosePadError> {
+        Self::from_reader(Cursor::new(bytes))
+    }
+
+    pub fn from_reader<R: Read + Seek>(reader: R) -> Result<Self, RosePadError> {
+        let mut zip =
+            ZipArchive::new(reader).map_err(|e| format!("failed to read .rpad archive: {e}"))?;
+        let mut raw = RawArchive::default();
+        for i in 0..zip.len() {
+            let mut entry = zip.by_index(i).map_err(|e| e.to_string())?;
+            if entry.is_dir() {
+                continue;
+            }
+            let name = entry.name().to_string();
+            let mut buf = Vec::new();
+            entry
+                .read_to_end(&mut buf)
+                .map_err(|e| format!("failed to read entry {name}: {e}"))?;
+            if name == MANIFEST_ENTRY {
+                raw.manifest = serde_json::from_slice(&buf)
+                    .map_err(|e| format!("invalid manifest.json: {e}"))?;
+                continue;
+            }
+            raw.entries.insert(name, (buf, entry.compression()));
+        }
+
+        // Files written before the version field existed are v1
+        let version = match raw.manifest.get("version") {
+            None | Some(serde_json::Value::Null) => 1,
+            Some(v) => v
+                .as_u64()
+                .filter(|v| *v >= 1)
+                .ok_or_else(|| format!("invalid manifest version: {v}"))?,
+        };
+        if version > FORMAT_VERSION as u64 {
+            return Err(format!(
+                "this document uses .rpad format v{version}, but this version of RosePad only supports up to v{FORMAT_VERSION}; please update RosePad to open it"
+            )
+            .into());
+        }
+        // Encrypted archives need the document's key, which `encryption` has
+        // once the document was unlocked
+        let sealed_by = match raw.manifest.get("encryption") {
+            None | Some(serde_json::Value::Null) => None,
+            Some(v) => Some(
+                serde_json::from_value::<RpadEncryption>(v.clone())
+                    .map_err(|e| format!("invalid manifest encryption: {e}"))?,
+            ),
+        };
+        if let Some(enc) = &sealed_by {
+            for (name, (data, method)) in raw.entries.iter_mut() {
+                *data = encryption::unseal(enc, name, data)?;
+                *method = CompressionMethod::Deflated;
+            }
+        }
+        for migrate in &MIGRATIONS[(version as usize - 1)..] {
+            migrate(&mut raw)?;
+        }
+
+        let mut manifest_obj = match raw.manifest {
+            serde_json::Value::Object(m) => m,
+            _ => return Err("invalid manifest.json: expected an object".into()),
+        };
+        manifest_obj.insert("version".into(), FORMAT_VERSION.into());
+        if !manifest_obj.get("title").is_some_and(|t| t.is_string()) {
+            manifest_obj.insert("title".into(), "Untitled".into());
+        }
+        let manifest: RpadManifest = serde_json::from_value(manifest_obj.into())
+            .map_err(|e| format!("invalid manifest.json: {e}"))?;
+
+        let (data, _) = raw
+            .entries
+            .remove(DATA_ENTRY)
+            .ok_or_else(|| "data not found in .rpad".to_string())?;
+        let document: RpadDocument =
+            serde_json::from_slice(&data).map_err(|e| format!("invalid data.json: {e}"))?;
+
+        let entries = raw
+            .entries
+            .into_iter()
+            .map(|(name, (data, method))| RpadEntry { name, data, method })
+            .collect();
+
+        Ok(RpadArchive {
+            manifest,
+            document,
+            entries,
+        })
+    }
+
+    // Sealed data does not compress, so encrypted entries are stored as is
+    fn entry_body<'a>(
+        &self,
+        name: &str,
+        data: &'a [u8],
+        method: CompressionMethod,
+    ) -> Result<(Cow<'a, [u8]>, CompressionMethod), RosePadError> {
+        match &self.manifest.encryption {
+            Some(enc) => Ok((
+                Cow::Owned(encryption::seal(enc, name, data)?),
+                CompressionMethod::Stored,
+            )),
+            None => Ok((Cow::Borrowed(data), method)),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, RosePadError> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+        for entry in &self.entries {
+            let (data, method) = self.entry_body(&entry.name, &entry.data, entry.method)?;
+            let opts = FileOptions::default().compression_method(method);
+            zip.start_file(&entry.name, opts)
+                .map_err(|e| format!("failed to start preserved entry {}: {e}", entry.name))?;
+            zip.write_all(&data)
+                .map_err(|e| format!("failed to write preserved entry {}: {e}", entry.name))?;
+        }
+
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        let mut manifest = self.manifest.clone();
+        manifest.version = FORMAT_VERSION;
+        let manifest = serde_json::to_vec(&manifest).map_err(|e| e.to_string())?;
+        zip.start_file(MANIFEST_ENTRY, options)
+            .map_err(|e| e.to_string())?;
+        zip.write_all(&manifest).map_err(|e| e.to_string())?;
+
+        let data = serde_json::to_vec(&self.document).map_err(|e| e.to_string())?;
+        let (data, method) = self.entry_body(DATA_ENTRY, &data, CompressionMethod::Deflated)?;
+        zip.start_file(DATA_ENTRY, options.compression_method(method))
+            .map_err(|e| e.to_string())?;
+        zip.write_all(&data).map_err(|e| e.to_string())?;
+
+        let cursor = zip.finish().map_err(|e| e.to_string())?;
+        Ok(cursor.into_inner())
+    }
+
+    /// Write the archive next to `path` and swap it into place.
+    pub fn save(&self, path: &Path) -> Result<(), RosePadError> {
+        let bytes = self.to_bytes().map_err(|e| at_document(e, path))?;
+        durable::write_atomic(path, &bytes)
+    }
+}
+
+/// Read one entry, e.g. an attachment, without inflating the rest of the
+/// archive. `None` when there is no such entry.
+pub fn read_entry(path: &Path, name: &str) -> Result<Option<Vec<u8>>, RosePadError> {
+    let file = fs::File::open(path).map_err(|e| RosePadError::io(e, path))?;
+    read_entry_from(file, name).map_err(|e| at_document(e, path))
+}
+
+// A locked error names the document it came from, so the UI can offer to unlock it
+fn at_document(err: RosePadError, path: &Path) -> RosePadError {
+    match err {
+        RosePadError::Locked { .. } => RosePadError::locked(path, read_title(path)),
+        err => err,
+    }
+}
+
+fn read_entry_from<R: Read + Seek>(reader: R, name: &str) -> Result<Option<Vec<u8>>, RosePadError> {
+    let mut zip =
+        ZipArchive::new(reader).map_err(|e| format!("failed to read .rpad archive: {e}"))?;
+    let sealed_by = match zip.by_name(MANIFEST_ENTRY) {
+        Ok(manifest) => serde_json::from_reader::<_, serde_json::Value>(manifest)
+            .map_err(|e| format!("invalid manifest.json: {e}"))?
+            .get("encryption")
+            .filter(|v| !v.is_null())
+            .map(|v| serde_json::from_value::<RpadEncryption>(v.clone()))
+            .transpose()
+            .map_err(|e| format!("invalid manifest encryption: {e}"))?,
+        Err(_) => None,
+    };
+    let mut buf = Vec::new();
+    match zip.by_name(name) {
+        Ok(mut entry) => entry
+            .read_to_end(&mut buf)
+            .map_err(|e| format!("failed to read entry {name}: {e}"))?,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.to_string().into()),
+    };
+    match sealed_by {
+        Some(enc) => encryption::unseal(&enc, name, &buf).map(Some),
+        None => Ok(Some(buf)),
+    }
+}
+
+fn read_manifest_value(path: &Path) -> Option<serde_json::Value> {
+    let file = fs::File::open(path).ok()?;
+    let mut zip = ZipArchive::new(file).ok()?;
+    let mut f = zip.by_name(MANIFEST_ENTRY).ok()?;
+    let mut s = String::new();
+    let _ = f.read_to_string(&mut s).ok()?;
+    serde_json::from_str(&s).ok()
+}
+
+/// Read only the manifest title; cheap enough for workspace scans.
+pub fn read_title(path: &Path) -> Option<String> {
+    read_manifest_value(path)?
+        .get("title")
+        .and_then(|t| t.as_str())
+        .map(|s| s.to_string())
+}
+
+/// The id stored in the manifest, if the document has been saved with one.
+pub fn read_id(path: &Path) -> Option<String> {
+    read_manifest_value(path)?
+        .get("id")
+        .and_then(|t| t.as_str())
+        .map(|s| s.to_string())
+}
+
+/// The encryption block of an encrypted .rpad; works while it is locked.
+pub fn read_encryption(path: &Path) -> Option<RpadEncryption> {
+    let v = read_manifest_value(path)?.get("encryption")?.clone();
+    serde_json::from_value(v).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy_archive(manifest: &str, entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let opts = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.start_file(MANIFEST_ENTRY, opts).unwrap();
+        zip.write_all(manifest.as_bytes()).unwrap();
+        for (name, body) in entries {
+            zip.start_file(*name, opts).unwrap();
+            zip.write_all(body.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn round_trip_preserves_everything() {
+        let mut archive = RpadArchive::new("Notes");
+        archive.document.html = "<p>Hello <strong>world</strong></p>".into();
+        archive
+            .manifest
+            .extra
+            .insert("author".into(), "someone".into());
+        archive.entries.push(RpadEntry {
+            name: "assets/blob.bin".into(),
+            data: vec![0, 1, 2, 3],
+            method: CompressionMethod::Stored,
+        });
+
+        let bytes = archive.to_bytes().unwrap();
+        let reread = RpadArchive::from_bytes(&bytes).unwrap();
+        assert_eq!(reread, archive);
+        assert_eq!(reread.manifest.version, FORMAT_VERSION);
+    }
+
+    #[test]
+    fn v1_data_json_is_upgraded() {
+        let bytes = legacy_archive(
+            r#"{"title":"Old","version":1}"#,
+            &[("data.json", r#"{"html":"<p>v1</p>"}"#)],
+        );
+        let archive = RpadArchive::from_bytes(&bytes).unwrap();
+        assert_eq!(archive.manifest.title, "Old");
+        assert_eq!(archive.manifest.version, FORMAT_VERSION);
+        assert_eq!(archive.document.html, "<p>v1</p>");
+    }
+
+    #[test]
+    fn v1_legacy_entries_are_migrated() {
+        let bytes = legacy_archive(
+            r#"{"title":"Legacy"}"#,
+            &[("content.json", r#"{"html":"<p>legacy</p>"}"#)],
+        );
+        let archive = RpadArchive::from_bytes(&bytes).unwrap();
+        assert_eq!(archive.document.html, "<p>legacy</p>");
+        assert!(archive.entries.is_empty());
+
+        let bytes = legacy_archive(
+            r#"{"title":"Raw","version":1}"#,
+            &[("content.html", "<h1>raw</h1>")],
+        );
+        let archive = RpadArchive::from_bytes(&bytes).unwrap();
+        assert_eq!(archive.document.html, "<h1>raw</h1>");
+
+        // Migrated archives write back in the current layout
+        let reread = RpadArchive::from_bytes(&archive.to_bytes().unwrap()).unwrap();
+        assert_eq!(reread, archive);
+    }
+
+    #[test]
+    fn v1_raw_data_json_is_wrapped() {
+        let bytes = legacy_archive(
+            r#"{"title":"Plain","version":1}"#,
+            &[("data.json", "just text")],
+        );
+        let archive = RpadArchive::from_bytes(&bytes).unwrap();
+        assert_eq!(archive.document.html, "just text");
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let manifest = format!(r#"{{"title":"Future","version":{}}}"#, FORMAT_VERSION + 1);
+        let bytes = legacy_archive(&manifest, &[("data.json", r#"{"html":""}"#)]);
+        let err = RpadArchive::from_bytes(&bytes).unwrap_err();
+        assert!(err.to_string().contains("update RosePad"), "{err}");
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        // Minimal KDF cost keeps the test fast
+        let enc = RpadEncryption::new(8, 1, 1);
+        encryption::remember(&enc, "hunter2").unwrap();
+        let mut archive = RpadArchive::new("Secret title");
+        archive.document.html = "<p>confidential</p>".into();
+        archive.manifest.encryption = Some(enc.clone());
+        archive.entries.push(RpadEntry {
+            name: "assets/note.txt".into(),
+            data: b"attached secret".to_vec(),
+            method: CompressionMethod::Deflated,
+        });
+
+        let bytes = archive.to_bytes().unwrap();
+        let raw = String::from_utf8_lossy(&bytes);
+        assert!(!raw.contains("confidential") && !raw.contains("attached secret"));
+
+        let reread = RpadArchive::from_bytes(&bytes).unwrap();
+        assert_eq!(reread, archive);
+
+        // Without the key only the manifest is usable
+        encryption::forget(&enc);
+        assert!(matches!(
+            RpadArchive::from_bytes(&bytes),
+            Err(RosePadError::Locked { title: None, .. })
+        ));
+        assert!(matches!(
+            archive.to_bytes(),
+            Err(RosePadError::Locked { .. })
+        ));
+
+        encryption::remember(&enc, "wrong").unwrap();
+        assert!(RpadArchive::from_bytes(&bytes).is_err());
+        encryption::forget(&enc);
+    }
+
+    #[test]
+    fn a_locked_file_names_its_document() {
+        let enc = RpadEncryption::new(8, 1, 1);
+        encryption::remember(&enc, "hunter2").unwrap();
+        let mut archive = RpadArchive::new("Diary");
+        archive.manifest.encryption = Some(enc.clone());
+        let dir = crate::scratch::dir("rpad-locked");
+        let path = dir.join("diary.rpad");
+        archive.save(&path).unwrap();
+        encryption::forget(&enc);
+
+        for err in [
+            RpadArchive::open(&path).unwrap_err(),
+            read_entry(&path, DATA_ENTRY).unwrap_err(),
+            archive.save(&path).unwrap_err(),
+        ] {
+            let RosePadError::Locked { context, title } = err else {
+                panic!("{err:?}");
+            };
+            assert_eq!(context.path, Some(path.to_string_lossy().to_string()));
+            assert_eq!(title.as_deref(), Some("Diary"));
+        }
+    }
+
+    #[test]
+    fn single_entries_are_read_on_their_own() {
+        let mut archive = RpadArchive::new("Pictures");
+        archive.entries.push(RpadEntry {
+            name: "assets/a.png".into(),
+            data: vec![1, 2, 3],
+            method: CompressionMethod::Stored,
+        });
+        archive.entries.push(RpadEntry {
+            name: "assets/b.txt".into(),
+            data: b"bee".to_vec(),
+            method: CompressionMethod::Deflated,
+        });
+        let bytes = archive.to_bytes().unwrap();
+        let read = |name| read_entry_from(Cursor::new(&bytes), name).unwrap();
+        assert_eq!(read("assets/a.png"), Some(vec![1, 2, 3]));
+        assert_eq!(read("assets/b.txt"), Some(b"bee".to_vec()));
+        assert_eq!(read("assets/c.png"), None);
+
+        let enc = RpadEncryption::new(8, 1, 1);
+        encryption::remember(&enc, "hunter2").unwrap();
+        archive.manifest.encryption = Some(enc.clone());
+        let bytes = archive.to_bytes().unwrap();
+        let read = |name| read_entry_from(Cursor::new(&bytes), name);
+        assert_eq!(read("assets/b.txt").unwrap(), Some(b"bee".to_vec()));
+        encryption::forget(&enc);
+        assert!(read("assets/b.txt").is_err());
+    }
+
+    #[test]
+    fn missing_data_is_an_error() {
+        let bytes = legacy_archive(r#"{"title":"Empty","version":2}"#, &[]);
+        assert!(RpadArchive::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,616 @@
+// Recursive workspace walking shared by `scan_workspace`, `analyze_paths` and
+// the file watcher. Ignore handling follows gitignore semantics: `.gitignore`
+// and `.rosepadignore` files anywhere in the tree, plus per-workspace
+// include/exclude globs from settings, which win over both.
+
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    overrides::{Override, OverrideBuilder},
+    Match, WalkBuilder, WalkState,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::paths;
+use crate::textfile::TextFormatDto;
+use crate::workspace::{project_from_file, FolderNodeDto, ProjectDto};
+
+/// Folder levels below the root that are scanned when no depth is configured.
+pub const DEFAULT_MAX_DEPTH: usize = 16;
+
+/// Workspace-specific ignore file, read alongside `.gitignore`.
+pub const IGNORE_FILE: &str = ".rosepadignore";
+
+// Files found between two `scan:progress` updates
+const PROGRESS_EVERY: usize = 250;
+
+// Never worth listing, whatever the ignore files say
+const ALWAYS_IGNORED: &[&str] = &["node_modules", "__pycache__"];
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScanOptions {
+    /// Folder levels to descend into below the root; 0 only lists root files.
+    pub max_depth: Option<usize>,
+    /// Globs that are always listed, even when ignored. If any are given,
+    /// files matching none of them are left out.
+    pub include: Vec<String>,
+    /// Globs that are never listed.
+    pub exclude: Vec<String>,
+}
+
+/// Whether `path` is an ignore file whose edits change what a scan lists.
+pub fn is_ignore_file(path: &Path) -> bool {
+    matches!(
+        path.file_name().and_then(|s| s.to_str()),
+        Some(".gitignore") | Some(IGNORE_FILE)
+    )
+}
+
+// A symlinked file is listed only if it points at a file inside the root
+fn links_inside(root: &Path, link: &Path) -> bool {
+    link.is_file() && paths::inside_root(&root.to_string_lossy(), link).is_ok()
+}
+
+pub(crate) fn skip_dir_name(name: &str) -> bool {
+    // Hidden folders (.git, .cache, ...) are never part of the workspace
+    name.starts_with('.') || ALWAYS_IGNORED.contains(&name)
+}
+
+/// What an earlier scan recorded for a file (its indexed row).
+#[derive(Clone)]
+pub(crate) struct CachedFile {
+    pub id: String,
+    pub last_modified_ms: i64,
+    pub size: i64,
+    pub title: Option<String>,
+    pub text_format: Option<TextFormatDto>,
+    pub fingerprint: Option<String>,
+}
+
+/// Earlier scan results keyed by path. Indexed files keep their id; files whose
+/// mtime and size still match also reuse the cached title, text format and
+/// fingerprint instead of reopening the file.
+pub(crate) type ScanCache = HashMap<String, CachedFile>;
+
+enum Found {
+    Folder(PathBuf),
+    File(Box<ProjectDto>),
+}
+
+pub(crate) struct ScanRules {
+    root: PathBuf,
+    max_depth: usize,
+    overrides: Override,
+    // Per-folder matchers built from that folder's ignore files
+    matchers: RefCell<HashMap<PathBuf, Gitignore>>,
+}
+
+impl ScanRules {
+    pub(crate) fn new(root: &Path, opts: &ScanOptions) -> Result<Self, String> {
+        let mut builder = OverrideBuilder::new(root);
+        builder.case_insensitive(true).map_err(|e| e.to_string())?;
+        for glob in opts
+            .include
+            .iter()
+            .map(|g| g.trim())
+            .filter(|g| !g.is_empty())
+        {
+            builder
+                .add(glob)
+                .map_err(|e| format!("invalid include pattern: {e}"))?;
+        }
+        for glob in opts
+            .exclude
+            .iter()
+            .map(|g| g.trim())
+            .filter(|g| !g.is_empty())
+        {
+            let glob = glob.trim_start_matches('!');
+            builder
+                .add(&format!("!{glob}"))
+                .map_err(|e| format!("invalid exclude pattern: {e}"))?;
+        }
+        let overrides = builder.build().map_err(|e| e.to_string())?;
+        Ok(ScanRules {
+            root: root.to_path_buf(),
+            max_depth: opts.max_depth.unwrap_or(DEFAULT_MAX_DEPTH),
+            overrides,
+            matchers: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Walker for `dir`, which sits `depth` folder levels below the root.
+    fn walker(&self, dir: &Path, depth: usize) -> WalkBuilder {
+        let mut builder = WalkBuilder::new(dir);
+        builder
+            .hidden(false)
+            .parents(true)
+            .git_ignore(true)
+            .git_exclude(true)
+            .git_global(true)
+            // Plain folders with a .gitignore (no repo) should be honored too
+            .require_git(false)
+            .follow_links(false)
+            .add_custom_ignore_filename(IGNORE_FILE)
+            .overrides(self.overrides.clone())
+            .max_depth(Some((self.max_depth + 1).saturating_sub(depth)))
+            .filter_entry(|e| {
+                let is_dir = e.file_type().is_some_and(|t| t.is_dir());
+                !(is_dir && e.depth() > 0 && skip_dir_name(&e.file_name().to_string_lossy()))
+            });
+        builder
+    }
+
+    fn matcher(&self, dir: &Path) -> Gitignore {
+        let mut cache = self.matchers.borrow_mut();
+        cache
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| {
+                let mut builder = GitignoreBuilder::new(dir);
+                // Later files take precedence, so .rosepadignore can re-include
+                for name in [".gitignore", IGNORE_FILE] {
+                    let file = dir.join(name);
+                    if file.is_file() {
+                        let _ = builder.add(file);
+                    }
+                }
+                builder.build().unwrap_or_else(|_| Gitignore::empty())
+            })
+            .clone()
+    }
+
+    /// Whether `path` lies inside the scanned part of the workspace: within the
+    /// depth limit and not ignored itself or through one of its folders.
+    pub(crate) fn includes(&self, path: &Path, is_dir: bool) -> bool {
+        let rel = match path.strip_prefix(&self.root) {
+            Ok(r) => r,
+            Err(_) => return false,
+        };
+        let parts: Vec<&std::ffi::OsStr> = rel
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(s) => Some(s),
+                _ => None,
+            })
+            .collect();
+        if parts.is_empty() || (!is_dir && is_ignore_file(path)) {
+            return false;
+        }
+        let folders = if is_dir { parts.len() } else { parts.len() - 1 };
+        if folders > self.max_depth {
+            return false;
+        }
+        let dir_parts = if is_dir { parts.len() } else { parts.len() - 1 };
+        if parts[..dir_parts]
+            .iter()
+            .any(|p| skip_dir_name(&p.to_string_lossy()))
+        {
+            return false;
+        }
+
+        // Settings overrides are checked first and are final; an excluded
+        // folder hides everything inside it
+        let mut dir = self.root.clone();
+        for part in &parts[..parts.len() - 1] {
+            dir.push(part);
+            if self.overrides.matched(&dir, true).is_ignore() {
+                return false;
+            }
+        }
+        match self.overrides.matched(path, is_dir) {
+            Match::Ignore(_) => return false,
+            Match::Whitelist(_) => return true,
+            Match::None => {}
+        }
+
+        // Deeper ignore files override shallower ones
+        let mut ignored = false;
+        let mut dir = self.root.clone();
+        for part in std::iter::once(None).chain(parts[..parts.len() - 1].iter().map(Some)) {
+            if let Some(part) = part {
+                dir.push(part);
+            }
+            match self.matcher(&dir).matched_path_or_any_parents(path, is_dir) {
+                Match::Ignore(_) => ignored = true,
+                Match::Whitelist(_) => ignored = false,
+                Match::None => {}
+            }
+        }
+        !ignored
+    }
+
+    /// Walk `dir` (which sits `depth` folder levels below the root) and return
+    /// its project files and its subfolders, each with their own contents.
+    /// Folders are read in parallel; `progress` is called with the number of
+    /// files and folders found so far.
+    pub(crate) fn scan_dir(
+        &self,
+        dir: &Path,
+        depth: usize,
+        cache: &ScanCache,
+        progress: &(dyn Fn(usize, usize) + Sync),
+    ) -> (Vec<ProjectDto>, Vec<FolderNodeDto>) {
+        let found: Mutex<Vec<Found>> = Mutex::new(Vec::new());
+        let files = AtomicUsize::new(0);
+        let folders = AtomicUsize::new(0);
+        let (root, max_depth) = (self.root.as_path(), self.max_depth);
+
+        self.walker(dir, depth).build_parallel().run(|| {
+            let (found, files, folders) = (&found, &files, &folders);
+            Box::new(move |entry| {
+                let entry = match entry {
+                    Ok(e) if e.depth() > 0 => e,
+                    _ => return WalkState::Continue,
+                };
+                let p = entry.path();
+                let file_type = match entry.file_type() {
+                    Some(t) => t,
+                    None => return WalkState::Continue,
+                };
+                let item = if file_type.is_dir() {
+                    if depth + entry.depth() > max_depth {
+                        return WalkState::Continue;
+                    }
+                    folders.fetch_add(1, Ordering::Relaxed);
+                    Found::Folder(p.to_path_buf())
+                } else if (file_type.is_file() || (file_type.is_symlink() && links_inside(root, p)))
+                    && !is_ignore_file(p)
+                {
+                    // Symlinked folders are not followed so link cycles cannot trap the walk
+                    let parent = p
+                        .parent()
+                        .filter(|pp| *pp != root)
+                        .map(|pp| pp.to_string_lossy().to_string());
+                    match project_from_file(p, parent, cache) {
+                        Some(project) => {
+                            let n = files.fetch_add(1, Ordering::Relaxed) + 1;
+                            if n % PROGRESS_EVERY == 0 {
+                                progress(n, folders.load(Ordering::Relaxed));
+                            }
+                            Found::File(Box::new(project))
+                        }
+                        None => return WalkState::Continue,
+                    }
+                } else {
+                    return WalkState::Continue;
+                };
+                match found.lock() {
+                    Ok(mut g) => g.push(item),
+                    Err(poisoned) => poisoned.into_inner().push(item),
+                }
+                WalkState::Continue
+            })
+        });
+
+        let found = match found.into_inner() {
+            Ok(v) => v,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let mut projects = Vec::new();
+        let mut nodes: HashMap<PathBuf, FolderNodeDto> = HashMap::new();
+        let mut file_entries = Vec::new();
+        for item in found {
+            match item {
+                Found::Folder(path) => {
+                    let node = self.empty_node(&path);
+                    nodes.insert(path, node);
+                }
+                Found::File(project) => file_entries.push(*project),
+            }
+        }
+        for project in file_entries {
+            let parent = Path::new(&project.path).parent().unwrap_or(dir);
+            if parent == dir {
+                projects.push(project);
+            } else if let Some(node) = nodes.get_mut(parent) {
+                node.projects.push(project);
+            }
+        }
+        projects.sort_by(|a, b| a.path.cmp(&b.path));
+        progress(files.into_inner(), folders.into_inner());
+        (projects, assemble(dir, nodes))
+    }
+
+    /// Scan a single folder into a tree node.
+    pub(crate) fn scan_folder(&self, dir: &Path, depth: usize, cache: &ScanCache) -> FolderNodeDto {
+        let (projects, folders) = self.scan_dir(dir, depth, cache, &|_, _| {});
+        FolderNodeDto {
+            projects,
+            folders,
+            ..self.empty_node(dir)
+        }
+    }
+
+    fn empty_node(&self, dir: &Path) -> FolderNodeDto {
+        FolderNodeDto {
+            path: dir.to_string_lossy().to_string(),
+            name: dir
+                .file_name()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            parent_path: dir
+                .parent()
+                .filter(|p| *p != self.root)
+                .map(|p| p.to_string_lossy().to_string()),
+            projects: Vec::new(),
+            folders: Vec::new(),
+        }
+    }
+}
+
+// Hang every walked folder under its parent, deepest first; returns the
+// folders directly inside `top`
+fn assemble(top: &Path, mut nodes: HashMap<PathBuf, FolderNodeDto>) -> Vec<FolderNodeDto> {
+    let mut paths: Vec<PathBuf> = nodes.keys().cloned().collect();
+    paths.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+    let mut top_level = Vec::new();
+    for path in paths {
+        let node = match nodes.remove(&path) {
+            Some(n) => n,
+            None => continue,
+        };
+        match path
+            .parent()
+            .and_then(|pp| if pp == top { None } else { nodes.get_mut(pp) })
+        {
+            Some(parent) => parent.folders.push(node),
+            None => top_level.push(node),
+        }
+    }
+    // The parallel walk finds entries in no particular order
+    sort_nodes(&mut top_level);
+    top_level
+}
+
+fn sort_nodes(nodes: &mut [FolderNodeDto]) {
+    nodes.sort_by(|a, b| a.path.cmp(&b.path));
+    for node in nodes {
+        node.projects.sort_by(|a, b| a.path.cmp(&b.path));
+        sort_nodes(&mut node.folders);
+    }
+}
+
+/// Number of folder levels between `root` and `dir` (0 for the root itself).
+pub(crate) fn depth_of(root: &Path, dir: &Path) -> usize {
+    dir.strip_prefix(root)
+        .map(|r| {
+            r.components()
+                .filter(|c| matches!(c, Component::Normal(_)))
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch;
+    use std::fs;
+
+    fn write(root: &Path, files: &[(&str, &str)]) {
+        for (rel, text) in files {
+            let path = root.join(rel);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+    }
+
+    fn rules(
+        root: &Path,
+        include: &[&str],
+        exclude: &[&str],
+        max_depth: Option<usize>,
+    ) -> ScanRules {
+        let opts = ScanOptions {
+            max_depth,
+            include: include.iter().map(|g| g.to_string()).collect(),
+            exclude: exclude.iter().map(|g| g.to_string()).collect(),
+        };
+        ScanRules::new(root, &opts).unwrap()
+    }
+
+    // Everything a scan lists, relative to the root; folders end in a slash
+    fn listed(rules: &ScanRules) -> Vec<String> {
+        fn add(
+            root: &Path,
+            out: &mut Vec<String>,
+            projects: &[ProjectDto],
+            folders: &[FolderNodeDto],
+        ) {
+            let rel = |p: &str| {
+                Path::new(p)
+                    .strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            };
+            out.extend(projects.iter().map(|p| rel(&p.path)));
+            for folder in folders {
+                out.push(format!("{}/", rel(&folder.path)));
+                add(root, out, &folder.projects, &folder.folders);
+            }
+        }
+        let (projects, folders) = rules.scan_dir(&rules.root, 0, &ScanCache::new(), &|_, _| {});
+        let mut out = Vec::new();
+        add(&rules.root, &mut out, &projects, &folders);
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn the_walk_descends_to_the_depth_limit() {
+        let root = scratch::dir("scan-depth");
+        write(
+            &root,
+            &[
+                ("a.txt", "a"),
+                ("one/b.txt", "b"),
+                ("one/two/c.txt", "c"),
+                ("one/two/three/d.txt", "d"),
+                ("node_modules/x.txt", "x"),
+                (".hidden/y.txt", "y"),
+                ("photo.png", "not really"),
+            ],
+        );
+        assert_eq!(
+            listed(&rules(&root, &[], &[], None)),
+            [
+                "a.txt",
+                "one/",
+                "one/b.txt",
+                "one/two/",
+                "one/two/c.txt",
+                "one/two/three/",
+                "one/two/three/d.txt"
+            ]
+        );
+        assert_eq!(
+            listed(&rules(&root, &[], &[], Some(1))),
+            ["a.txt", "one/", "one/b.txt"]
+        );
+        assert_eq!(listed(&rules(&root, &[], &[], Some(0))), ["a.txt"]);
+
+        // A folder deeper down is scanned with its own depth taken into account
+        let rules = rules(&root, &[], &[], Some(2));
+        let node = rules.scan_folder(&root.join("one/two"), 2, &ScanCache::new());
+        assert_eq!(node.projects.len(), 1);
+        assert!(node.folders.is_empty());
+        assert_eq!(
+            node.parent_path,
+            Some(root.join("one").to_string_lossy().to_string())
+        );
+    }
+
+    #[test]
+    fn deeper_and_workspace_ignore_files_take_precedence() {
+        let root = scratch::dir("scan-ignore");
+        write(
+            &root,
+            &[
+                (".gitignore", "*.log\ndrafts/\n"),
+                (IGNORE_FILE, "!keep.log\n"),
+                ("a.log", ""),
+                ("keep.log", ""),
+                ("a.txt", ""),
+                ("drafts/x.txt", ""),
+                ("sub/.gitignore", "!b.log\n"),
+                ("sub/b.log", ""),
+                ("sub/c.log", ""),
+            ],
+        );
+        let rules = rules(&root, &[], &[], None);
+        assert_eq!(listed(&rules), ["a.txt", "keep.log", "sub/", "sub/b.log"]);
+        // Checking single paths agrees with the walk
+        for (rel, is_dir, expected) in [
+            ("a.log", false, false),
+            ("keep.log", false, true),
+            ("drafts", true, false),
+            ("drafts/x.txt", false, false),
+            ("sub/b.log", false, true),
+            ("sub/c.log", false, false),
+            ("sub/.gitignore", false, false),
+        ] {
+            assert_eq!(rules.includes(&root.join(rel), is_dir), expected, "{rel}");
+        }
+    }
+
+    #[test]
+    fn settings_overrides_win_over_ignore_files() {
+        let root = scratch::dir("scan-overrides");
+        write(
+            &root,
+            &[
+                (".gitignore", "*.log\n"),
+                ("a.log", ""),
+                ("a.txt", ""),
+                ("private/secret.txt", ""),
+                ("private/inner/deep.log", ""),
+                ("sub/b.log", ""),
+            ],
+        );
+        // An excluded folder hides everything inside it, even included files
+        let set = rules(&root, &["*.log"], &["private"], None);
+        assert_eq!(listed(&set), ["a.log", "sub/", "sub/b.log"]);
+        assert!(!set.includes(&root.join("private"), true));
+        assert!(!set.includes(&root.join("private/inner/deep.log"), false));
+        assert!(set.includes(&root.join("a.log"), false));
+        assert!(!set.includes(&root.join("a.txt"), false));
+
+        let set = rules(&root, &[], &["!*.TXT"], None);
+        assert_eq!(listed(&set), ["private/", "private/inner/", "sub/"]);
+
+        let opts = ScanOptions {
+            include: vec!["[".into()],
+            ..Default::default()
+        };
+        assert!(ScanRules::new(&root, &opts)
+            .err()
+            .unwrap()
+            .starts_with("invalid include pattern"));
+    }
+
+    #[test]
+    fn single_paths_respect_depth_and_skipped_folders() {
+        let root = scratch::dir("scan-includes");
+        let rules = rules(&root, &[], &[], Some(1));
+        assert!(rules.includes(&root.join("a.txt"), false));
+        assert!(rules.includes(&root.join("one"), true));
+        assert!(rules.includes(&root.join("one/b.txt"), false));
+        assert!(!rules.includes(&root.join("one/two"), true));
+        assert!(!rules.includes(&root.join("one/two/c.txt"), false));
+        assert!(!rules.includes(&root.join(".git/config"), false));
+        assert!(!rules.includes(&root.join("node_modules/x.txt"), false));
+        assert!(!rules.includes(&root.join(IGNORE_FILE), false));
+        assert!(!rules.includes(&root, true));
+        assert!(!rules.includes(&std::env::temp_dir().join("elsewhere.txt"), false));
+    }
+
+    #[test]
+    fn unchanged_files_reuse_what_the_cache_recorded() {
+        let root = scratch::dir("scan-cache");
+        write(&root, &[("note.txt", "caf\u{e9}\r\n")]);
+        let path = root.join("note.txt");
+        let rules = rules(&root, &[], &[], None);
+        let (fresh, _) = rules.scan_dir(&root, 0, &ScanCache::new(), &|_, _| {});
+        let scan = |cached: CachedFile| {
+            let cache = ScanCache::from([(path.to_string_lossy().to_string(), cached)]);
+            let (mut projects, _) = rules.scan_dir(&root, 0, &cache, &|_, _| {});
+            projects.pop().unwrap()
+        };
+        let cached = CachedFile {
+            id: "kept".into(),
+            last_modified_ms: fresh[0].last_modified_ms,
+            size: fresh[0].size,
+            title: Some("From the index".into()),
+            text_format: None,
+            fingerprint: Some("fp".into()),
+        };
+
+        // A hit skips reading the file: nothing is detected, the title is kept
+        let hit = scan(cached.clone());
+        assert_eq!(hit.id, "kept");
+        assert_eq!(hit.title.as_deref(), Some("From the index"));
+        assert_eq!(hit.text_format, None);
+        assert_eq!(hit.fingerprint.as_deref(), Some("fp"));
+
+        // A changed size means the file is read again, under the same id
+        let miss = scan(CachedFile {
+            size: cached.size + 1,
+            ..cached
+        });
+        assert_eq!(miss.id, "kept");
+        assert_eq!(miss.title, None);
+        let format = miss.text_format.unwrap();
+        assert_eq!(format.line_ending, crate::textfile::LineEnding::Crlf);
+        assert_ne!(miss.fingerprint.as_deref(), Some("fp"));
+    }
+}
@@ -0,0 +1,69 @@
+DROP TABLE IF EXISTS virtual_folder_projects;
+DROP TABLE IF EXISTS virtual_folders;
+
+CREATE TABLE IF NOT EXISTS project_content (
+  rowid INTEGER PRIMARY KEY,
+  path TEXT NOT NULL UNIQUE,
+  title TEXT,
+  last_modified_ms INTEGER NOT NULL,
+  size INTEGER NOT NULL,
+  body TEXT NOT NULL
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS content_fts USING fts5(
+  body, content='project_content', content_rowid='rowid'
+);
+
+CREATE TRIGGER IF NOT EXISTS project_content_ai AFTER INSERT ON project_content BEGIN
+  INSERT INTO content_fts(rowid,body) VALUES (new.rowid,new.body);
+END;
+
+CREATE TRIGGER IF NOT EXISTS project_content_au AFTER UPDATE ON project_content BEGIN
+  INSERT INTO content_fts(content_fts,rowid,body) VALUES('delete',old.rowid,old.body);
+  INSERT INTO content_fts(rowid,body) VALUES (new.rowid,new.body);
+END;
+
+CREATE TRIGGER IF NOT EXISTS project_content_ad AFTER DELETE ON project_content BEGIN
+  INSERT INTO content_fts(content_fts,rowid,body) VALUES('delete',old.rowid,old.body);
+END;
+
+-- project_fts used to be an external-content table over `projects`, which has no
+-- `tags` column, so the tag triggers could never update it. Keep a standalone copy instead.
+DROP TRIGGER IF EXISTS projects_ai;
+DROP TRIGGER IF EXISTS projects_au;
+DROP TRIGGER IF EXISTS projects_ad;
+DROP TRIGGER IF EXISTS project_tags_ai;
+DROP TRIGGER IF EXISTS project_tags_ad;
+DROP TABLE IF EXISTS project_fts;
+
+CREATE VIRTUAL TABLE project_fts USING fts5(name, title, tags);
+
+INSERT INTO project_fts(rowid,name,title,tags)
+SELECT rowid,name,title,COALESCE((SELECT group_concat(tag_name,' ') FROM project_tags WHERE project_id=projects.id),'') FROM projects;
+
+CREATE TRIGGER projects_ai AFTER INSERT ON projects BEGIN
+  INSERT INTO project_fts(rowid,name,title,tags)
+  VALUES (new.rowid,new.name,new.title,COALESCE((SELECT group_concat(tag_name,' ') FROM project_tags WHERE project_id=new.id),''));
+END;
+
+CREATE TRIGGER projects_au AFTER UPDATE ON projects BEGIN
+  DELETE FROM project_fts WHERE rowid=old.rowid;
+  INSERT INTO project_fts(rowid,name,title,tags)
+  VALUES (new.rowid,new.name,new.title,COALESCE((SELECT group_concat(tag_name,' ') FROM project_tags WHERE project_id=new.id),''));
+END;
+
+CREATE TRIGGER projects_ad AFTER DELETE ON projects BEGIN
+  DELETE FROM project_fts WHERE rowid=old.rowid;
+END;
+
+CREATE TRIGGER project_tags_ai AFTER INSERT ON project_tags BEGIN
+  DELETE FROM project_fts WHERE rowid=(SELECT rowid FROM projects WHERE id=new.project_id);
+  INSERT INTO project_fts(rowid,name,title,tags)
+  SELECT rowid,name,title,COALESCE((SELECT group_concat(tag_name,' ') FROM project_tags WHERE project_id=new.project_id),'') FROM projects WHERE id=new.project_id;
+END;
+
+CREATE TRIGGER project_tags_ad AFTER DELETE ON project_tags BEGIN
+  DELETE FROM project_fts WHERE rowid=(SELECT rowid FROM projects WHERE id=old.project_id);
+  INSERT INTO project_fts(rowid,name,title,tags)
+  SELECT rowid,name,title,COALESCE((SELECT group_concat(tag_name,' ') FROM project_tags WHERE project_id=old.project_id),'') FROM projects WHERE id=old.project_id;
+END;
@@ -0,0 +1,3 @@
+ALTER TABLE physical_folders ADD COLUMN parent_path TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_folders_parent ON physical_folders(parent_path);
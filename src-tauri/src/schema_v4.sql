@@ -0,0 +1,12 @@
+-- Version history: one row per saved snapshot; the content itself lives in
+-- blake3-addressed files next to the database (see history.rs)
+CREATE TABLE IF NOT EXISTS history_snapshots (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  path TEXT NOT NULL,
+  hash TEXT NOT NULL,
+  saved_at_ms INTEGER NOT NULL,
+  size INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_history_path ON history_snapshots(path, saved_at_ms);
+CREATE INDEX IF NOT EXISTS idx_history_hash ON history_snapshots(hash);
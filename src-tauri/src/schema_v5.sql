@@ -0,0 +1,3 @@
+-- Encoding, BOM and line endings of text files as JSON (see textfile.rs);
+-- NULL for other kinds and for rows written before detection existed
+ALTER TABLE projects ADD COLUMN text_format TEXT;
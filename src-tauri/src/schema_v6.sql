@@ -0,0 +1,7 @@
+-- What recognizes a file after it was renamed or moved outside the app (see
+-- index::resolve_ids): device and inode where the platform has them, and a hash
+-- of the size and first bytes. NULL for rows written before they existed
+ALTER TABLE projects ADD COLUMN file_key TEXT;
+ALTER TABLE projects ADD COLUMN fingerprint TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_projects_file_key ON projects(file_key);
@@ -0,0 +1,64 @@
+// Scratch folders for unit tests. Each test gets a folder of its own, and it is
+// removed again when the test ends, whether the test passed or not.
+
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty folder under the system temp dir, deleted on drop. Dereferences to
+/// its resolved path, so symlinked temp dirs compare equal to resolved paths.
+pub(crate) struct Scratch(PathBuf);
+
+/// A fresh folder for a test; `name` only helps to tell leftovers apart.
+pub(crate) fn dir(name: &str) -> Scratch {
+    let dir = std::env::temp_dir().join(format!(
+        "rosepad-{name}-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    Scratch(dir.canonicalize().unwrap())
+}
+
+impl Deref for Scratch {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for Scratch {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folders_are_fresh_and_removed_on_drop() {
+        let a = dir("scratch");
+        let b = dir("scratch");
+        assert_ne!(*a, *b);
+        fs::write(a.join("x.txt"), "x").unwrap();
+        let path = a.to_path_buf();
+        drop(a);
+        assert!(!path.exists());
+        assert!(b.is_dir());
+    }
+}
@@ -0,0 +1,412 @@
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::Read,
+    path::Path,
+};
+use tauri::{AppHandle, Emitter};
+
+use crate::index::{like_prefix, norm_path, path_norm, with_index, PATH_NORM};
+use crate::rpad::RpadArchive;
+use crate::workspace::{stable_id, ProjectDto};
+use crate::{docx, html, textfile};
+
+// Large text files are only indexed up to this many bytes
+const MAX_TEXT_BYTES: u64 = 4 * 1024 * 1024;
+const MAX_MATCHES: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TextRangeDto {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHitDto {
+    pub id: String,
+    pub path: String,
+    pub name: String,
+    pub title: Option<String>,
+    pub score: f64,
+    pub snippet: String,
+    /// Highlighted ranges inside `snippet`.
+    pub highlights: Vec<TextRangeDto>,
+    /// Match positions inside the document's plain text.
+    pub matches: Vec<TextRangeDto>,
+}
+
+/// Changes to apply to the content index after a scan or analyze pass.
+#[derive(Default)]
+pub(crate) struct IndexUpdate {
+    pub upserts: Vec<ProjectDto>,
+    pub delete_paths: Vec<String>,
+    pub delete_prefixes: Vec<String>,
+    /// Full scans drop every indexed file under this root that was not upserted.
+    pub prune_root: Option<String>,
+}
+
+fn read_text_file(path: &Path) -> Option<String> {
+    let f = fs::File::open(path).ok()?;
+    let mut buf = Vec::new();
+    f.take(MAX_TEXT_BYTES).read_to_end(&mut buf).ok()?;
+    let (text, format) = textfile::decode_head(&buf);
+    // NUL bytes mean this is not really text, unless it is UTF-16
+    if !format.encoding.starts_with("UTF-16") && buf.contains(&0) {
+        return None;
+    }
+    Some(text)
+}
+
+/// Plain text of a project file for indexing, if its kind has extractable text.
+pub(crate) fn extract_text(path: &Path, kind: &str) -> Option<String> {
+    match kind {
+        "rpad" => {
+            let archive = RpadArchive::open(path).ok()?;
+            // The index is not encrypted, so encrypted documents are only found by title
+            if archive.manifest.encryption.is_some() {
+                return None;
+            }
+            Some(html::plain_text(&archive.document.html))
+        }
+        "doc" => {
+            let is_docx = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| e.eq_ignore_ascii_case("docx"));
+            if !is_docx {
+                return None;
+            }
+            let bytes = fs::read(path).ok()?;
+            let (_, body) = docx::docx_to_html(&bytes).ok()?;
+            Some(html::plain_text(&body))
+        }
+        "txt" => read_text_file(path),
+        _ => None,
+    }
+}
+
+fn apply_update(app: &AppHandle, update: IndexUpdate) -> Result<usize, String> {
+    // Find out what is stale first so extraction runs without holding the lock
+    let known: HashMap<String, (i64, i64)> = with_index(app, |conn| {
+        let mut stmt = conn
+            .prepare("SELECT last_modified_ms, size FROM project_content WHERE path=?1")
+            .map_err(|e| e.to_string())?;
+        let mut known = HashMap::new();
+        for p in &update.upserts {
+            if let Ok(row) = stmt.query_row([&p.path], |r| Ok((r.get(0)?, r.get(1)?))) {
+                known.insert(p.path.clone(), row);
+            }
+        }
+        Ok(known)
+    })?;
+
+    let mut fresh: Vec<(&ProjectDto, String)> = Vec::new();
+    for p in &update.upserts {
+        if known.get(&p.path) == Some(&(p.last_modified_ms, p.size)) {
+            continue;
+        }
+        let body = extract_text(Path::new(&p.path), &p.kind).unwrap_or_default();
+        fresh.push((p, body));
+    }
+
+    with_index(app, |conn| {
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        for (p, body) in &fresh {
+            tx.execute(
+                "INSERT INTO project_content(path,title,last_modified_ms,size,body) VALUES (?1,?2,?3,?4,?5)
+                 ON CONFLICT(path) DO UPDATE SET title=excluded.title, last_modified_ms=excluded.last_modified_ms, size=excluded.size, body=excluded.body",
+                params![p.path, p.title, p.last_modified_ms, p.size, body],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        for path in &update.delete_paths {
+            tx.execute("DELETE FROM project_content WHERE path=?1", [path])
+                .map_err(|e| e.to_string())?;
+        }
+        for dir in &update.delete_prefixes {
+            tx.execute(
+                &format!("DELETE FROM project_content WHERE {PATH_NORM} LIKE ?1 ESCAPE '\\'"),
+                [like_prefix(dir)],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        if let Some(root) = &update.prune_root {
+            let keep: HashSet<&str> = update.upserts.iter().map(|p| p.path.as_str()).collect();
+            let stale: Vec<String> = {
+                let mut stmt = tx
+                    .prepare(&format!(
+                        "SELECT path FROM project_content WHERE {PATH_NORM} LIKE ?1 ESCAPE '\\'"
+                    ))
+                    .map_err(|e| e.to_string())?;
+                let rows = stmt
+                    .query_map([like_prefix(root)], |r| r.get::<_, String>(0))
+                    .map_err(|e| e.to_string())?;
+                rows.flatten()
+                    .filter(|p| !keep.contains(p.as_str()))
+                    .collect()
+            };
+            for path in stale {
+                tx.execute("DELETE FROM project_content WHERE path=?1", [path])
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        tx.commit().map_err(|e| e.to_string())
+    })?;
+    Ok(fresh.len())
+}
+
+/// Refresh the content index in the background; emits `search:indexed` when
+/// done, or `search:failed` with the reason.
+pub(crate) fn schedule_update(app: &AppHandle, update: IndexUpdate) {
+    let handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let _ = match apply_update(&handle, update) {
+            Ok(changed) => handle.emit("search:indexed", changed),
+            Err(e) => handle.emit("search:failed", e),
+        };
+    });
+}
+
+// Turn free-form input into an FTS5 query: every word must match, as a prefix
+pub(crate) fn fts_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = search_terms(input)
+        .into_iter()
+        .map(|t| format!("\"{t}\"*"))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+fn search_terms(input: &str) -> Vec<String> {
+    input
+        .split(|c: char| c.is_whitespace() || c == '"')
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+// Lowercase char-by-char so indices stay aligned with the original text
+fn fold(text: &str) -> Vec<char> {
+    text.chars()
+        .map(|c| c.to_lowercase().next().unwrap_or(c))
+        .collect()
+}
+
+/// Positions (in UTF-16 code units, to match JS string indices) of every token
+/// in `text` that starts with one of `terms`.
+fn find_matches(text: &str, terms: &[String]) -> Vec<TextRangeDto> {
+    let chars = fold(text);
+    let mut utf16 = Vec::with_capacity(chars.len() + 1);
+    let mut acc = 0;
+    for c in text.chars() {
+        utf16.push(acc);
+        acc += c.len_utf16();
+    }
+    utf16.push(acc);
+
+    let terms: Vec<Vec<char>> = terms.iter().map(|t| fold(t)).collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < chars.len() && out.len() < MAX_MATCHES {
+        let at_word_start =
+            chars[i].is_alphanumeric() && (i == 0 || !chars[i - 1].is_alphanumeric());
+        if at_word_start && terms.iter().any(|t| chars[i..].starts_with(t)) {
+            let mut end = i;
+            while end < chars.len() && chars[end].is_alphanumeric() {
+                end += 1;
+            }
+            out.push(TextRangeDto {
+                start: utf16[i],
+                end: utf16[end],
+            });
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+    out
+}
+
+// FTS5 snippet() output with \u{2}/\u{3} around hits → clean text + ranges
+fn split_snippet(raw: &str) -> (String, Vec<TextRangeDto>) {
+    let mut text = String::new();
+    let mut ranges = Vec::new();
+    let mut pos = 0;
+    let mut open = None;
+    for c in raw.chars() {
+        match c {
+            '\u{2}' => open = Some(pos),
+            '\u{3}' => {
+                if let Some(start) = open.take() {
+                    ranges.push(TextRangeDto { start, end: pos });
+                }
+            }
+            '\n' => {
+                text.push(' ');
+                pos += 1;
+            }
+            _ => {
+                text.push(c);
+                pos += c.len_utf16();
+            }
+        }
+    }
+    (text, ranges)
+}
+
+pub(crate) fn run_search(
+    conn: &Connection,
+    query: &str,
+    root: Option<&str>,
+    limit: u32,
+) -> Result<Vec<SearchHitDto>, String> {
+    let fts = match fts_query(query) {
+        Some(q) => q,
+        None => return Ok(Vec::new()),
+    };
+    let terms = search_terms(query);
+    let root_norm = root.map(norm_path);
+    let root_like = root.map(like_prefix);
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT c.path, c.title, bm25(content_fts), snippet(content_fts, 0, char(2), char(3), '…', 16), c.body, p.id
+             FROM content_fts JOIN project_content c ON c.rowid = content_fts.rowid
+             LEFT JOIN projects p ON p.path = c.path
+             WHERE content_fts MATCH ?1
+               AND (?2 IS NULL OR {norm} = ?2 OR {norm} LIKE ?3 ESCAPE '\\')
+             ORDER BY rank LIMIT ?4",
+            norm = path_norm("c.path")
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![fts, root_norm, root_like, limit], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, Option<String>>(1)?,
+                r.get::<_, f64>(2)?,
+                r.get::<_, String>(3)?,
+                r.get::<_, String>(4)?,
+                r.get::<_, Option<String>>(5)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut hits = Vec::new();
+    for row in rows {
+        let (path, title, bm25, raw_snippet, body, id) = row.map_err(|e| e.to_string())?;
+        let (snippet, highlights) = split_snippet(&raw_snippet);
+        let name = Path::new(&path)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+            .to_string();
+        hits.push(SearchHitDto {
+            // Ids belong to the project row; content can briefly outlive it
+            id: id.unwrap_or_else(|| stable_id(&path)),
+            matches: find_matches(&body, &terms),
+            name,
+            title,
+            // bm25() is lower-is-better; flip it so callers can sort descending
+            score: -bm25,
+            snippet,
+            highlights,
+            path,
+        });
+    }
+    Ok(hits)
+}
+
+/// Full-text search over document contents, optionally limited to one workspace.
+#[tauri::command]
+pub async fn search_workspace(
+    app: AppHandle,
+    query: String,
+    root: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<SearchHitDto>, String> {
+    let limit = limit.unwrap_or(50).clamp(1, 500);
+    tauri::async_runtime::spawn_blocking(move || {
+        with_index(&app, |conn| {
+            run_search(conn, &query, root.as_deref(), limit)
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::memory_index;
+
+    fn range(start: usize, end: usize) -> TextRangeDto {
+        TextRangeDto { start, end }
+    }
+
+    #[test]
+    fn input_becomes_a_prefix_query_per_word() {
+        assert_eq!(
+            fts_query("  Hello \"wor\"ld ").as_deref(),
+            Some("\"hello\"* \"wor\"* \"ld\"*")
+        );
+        assert_eq!(
+            fts_query("ÉTÉ Straße").as_deref(),
+            Some("\"été\"* \"straße\"*")
+        );
+        assert_eq!(fts_query(" \" \t"), None);
+    }
+
+    #[test]
+    fn matches_are_whole_words_in_utf16_units() {
+        // The emoji takes two UTF-16 units, so everything after it shifts by one
+        let text = "😀 Ça va, ÇA! naça çava";
+        assert_eq!(
+            find_matches(text, &search_terms("ça")),
+            vec![range(3, 5), range(10, 12), range(19, 23)]
+        );
+        assert_eq!(
+            find_matches("Größe und große Grüße", &search_terms("GRÖ grü")),
+            vec![range(0, 5), range(16, 21)]
+        );
+        assert!(find_matches("nothing here", &search_terms("xyz")).is_empty());
+    }
+
+    #[test]
+    fn snippet_markers_become_ranges() {
+        let (text, ranges) = split_snippet("…un \u{2}café\u{3}\nau 😀 \u{2}lait\u{3} \u{2}");
+        assert_eq!(text, "…un café au 😀 lait ");
+        assert_eq!(ranges, vec![range(4, 8), range(15, 19)]);
+    }
+
+    #[test]
+    fn search_finds_content_inside_the_root() {
+        let conn = memory_index();
+        for (path, body) in [
+            ("/w/notes/a.txt", "Ein schönes Café am Meer"),
+            ("/w2/b.txt", "Café"),
+        ] {
+            conn.execute(
+                "INSERT INTO project_content(path,title,last_modified_ms,size,body) VALUES (?1,NULL,0,0,?2)",
+                params![path, body],
+            )
+            .unwrap();
+        }
+
+        let hits = run_search(&conn, "CAFÉ", Some("/w"), 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        let hit = &hits[0];
+        assert_eq!(hit.path, "/w/notes/a.txt");
+        assert_eq!(hit.name, "a");
+        assert_eq!(hit.id, stable_id("/w/notes/a.txt"));
+        assert_eq!(hit.matches, vec![range(12, 16)]);
+        assert_eq!(hit.snippet, "Ein schönes Café am Meer");
+        assert_eq!(hit.highlights, vec![range(12, 16)]);
+
+        assert_eq!(run_search(&conn, "caf", None, 10).unwrap().len(), 2);
+        assert!(run_search(&conn, "\"", None, 10).unwrap().is_empty());
+    }
+}
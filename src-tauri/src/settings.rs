@@ -1,27 +1,37 @@
 use tauri::Manager;
 
+use crate::error::RosePadError;
+
 #[tauri::command]
-pub async fn settings(app: tauri::AppHandle) {
+pub async fn settings(app: tauri::AppHandle) -> Result<(), RosePadError> {
     if let Some(win) = app.get_webview_window("settings") {
         let _ = win.unminimize();
         let _ = win.show();
         let _ = win.set_focus();
-        return;
+        return Ok(());
     }
 
+    let main = app
+        .get_webview_window("main")
+        .ok_or_else(|| RosePadError::failed("The main window is not open").during("settings"))?;
     tauri::WebviewWindowBuilder::new(
         &app,
         "settings",
         tauri::WebviewUrl::App("/#/settings".into()),
     )
-    .parent(&app.get_webview_window("main").unwrap())
-    .expect("No parent found")
-    .title("RosePad Settings")
-    .inner_size(400.0, 600.0)
-    .min_inner_size(400.0, 500.0)
-    .center()
-    .focused(true)
-    .decorations(false)
-    .build()
-    .unwrap();
+    .parent(&main)
+    .and_then(|builder| {
+        builder
+            .title("RosePad Settings")
+            .inner_size(400.0, 600.0)
+            .min_inner_size(400.0, 500.0)
+            .center()
+            .focused(true)
+            .decorations(false)
+            .build()
+    })
+    .map_err(|e| {
+        RosePadError::failed(format!("Could not open the settings window: {e}")).during("settings")
+    })?;
+    Ok(())
 }
@@ -0,0 +1,385 @@
+// Reading and writing plain text files in whatever encoding they came in. The
+// editor always works on UTF-8 text with `\n` line breaks; on disk a file keeps
+// its encoding, byte order mark and line-ending style unless it is converted
+// explicitly with `convert_text_format`.
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use serde::{Deserialize, Serialize};
+use std::{fs, io::Read, path::Path};
+use tauri::AppHandle;
+
+use crate::durable;
+use crate::error::{during, RosePadError};
+use crate::history;
+use crate::paths;
+use crate::workspace::{version_of, DocVersionDto};
+
+// Scans only look at the start of a file
+const DETECT_BYTES: u64 = 64 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+    Cr,
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TextFormatDto {
+    /// WHATWG encoding name, e.g. `UTF-8`, `UTF-16LE` or `windows-1252`.
+    pub encoding: String,
+    pub bom: bool,
+    pub line_ending: LineEnding,
+}
+
+impl Default for TextFormatDto {
+    fn default() -> Self {
+        TextFormatDto {
+            encoding: UTF_8.name().into(),
+            bom: false,
+            line_ending: LineEnding::Lf,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocumentDto {
+    /// Decoded text with `\n` line breaks.
+    pub content: String,
+    pub format: TextFormatDto,
+}
+
+// UTF-16 without a BOM shows up as a NUL in every other byte of mostly-ASCII text
+fn sniff_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let pairs = bytes.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+    let zeros_at = |offset: usize| {
+        bytes
+            .chunks_exact(2)
+            .filter(|pair| pair[offset] == 0)
+            .count()
+    };
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    if odd * 3 > pairs && even * 20 < pairs {
+        Some(UTF_16LE)
+    } else if even * 3 > pairs && odd * 20 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+// `bytes` may be cut off mid-character when only the head of a file was read
+fn is_utf8(bytes: &[u8], truncated: bool) -> bool {
+    match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(e) => truncated && e.error_len().is_none(),
+    }
+}
+
+fn dominant_line_ending(text: &str) -> LineEnding {
+    let (mut crlf, mut lf, mut cr) = (0usize, 0usize, 0usize);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' if chars.peek() == Some(&'\n') => {
+                chars.next();
+                crlf += 1;
+            }
+            '\r' => cr += 1,
+            '\n' => lf += 1,
+            _ => {}
+        }
+    }
+    if crlf > lf && crlf >= cr {
+        LineEnding::Crlf
+    } else if cr > lf && cr > crlf {
+        LineEnding::Cr
+    } else {
+        LineEnding::Lf
+    }
+}
+
+fn normalize_line_endings(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+// Files that are neither UTF-8 nor UTF-16 are taken to be Windows-1252, the
+// usual legacy encoding; it maps every byte, so decoding never fails
+fn decode_bytes(bytes: &[u8], truncated: bool) -> (String, TextFormatDto) {
+    let (encoding, bom_len) = match Encoding::for_bom(bytes) {
+        Some((enc, len)) => (enc, len),
+        // NULs are valid UTF-8, so UTF-16 has to be ruled out first
+        None => match sniff_utf16(bytes) {
+            Some(enc) => (enc, 0),
+            None if is_utf8(bytes, truncated) => (UTF_8, 0),
+            None => (WINDOWS_1252, 0),
+        },
+    };
+    let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+    let format = TextFormatDto {
+        encoding: encoding.name().into(),
+        bom: bom_len > 0,
+        line_ending: dominant_line_ending(&text),
+    };
+    (normalize_line_endings(&text), format)
+}
+
+/// Decode a whole file's bytes.
+pub(crate) fn decode(bytes: &[u8]) -> (String, TextFormatDto) {
+    decode_bytes(bytes, false)
+}
+
+/// Decode the start of a file that was cut off after `bytes`.
+pub(crate) fn decode_head(bytes: &[u8]) -> (String, TextFormatDto) {
+    decode_bytes(bytes, true)
+}
+
+/// Encode editor text (any line endings) in `format`. Fails instead of writing
+/// replacement characters when the text does not fit the encoding.
+pub(crate) fn encode(text: &str, format: &TextFormatDto) -> Result<Vec<u8>, String> {
+    let encoding = Encoding::for_label(format.encoding.as_bytes())
+        .ok_or_else(|| format!("unknown encoding {}", format.encoding))?;
+    let text = normalize_line_endings(text);
+    let text = match format.line_ending {
+        LineEnding::Lf => text,
+        other => text.replace('\n', other.as_str()),
+    };
+    let mut out = Vec::with_capacity(text.len() + 3);
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        let le = encoding == UTF_16LE;
+        if format.bom {
+            out.extend_from_slice(if le { &[0xFF, 0xFE] } else { &[0xFE, 0xFF] });
+        }
+        for unit in text.encode_utf16() {
+            out.extend_from_slice(&if le {
+                unit.to_le_bytes()
+            } else {
+                unit.to_be_bytes()
+            });
+        }
+        return Ok(out);
+    }
+    if encoding.output_encoding() != encoding {
+        return Err(format!("cannot write text as {}", encoding.name()));
+    }
+    if format.bom && encoding == UTF_8 {
+        out.extend_from_slice(&[0xEF, 0xBB, 0xBF]);
+    }
+    let (bytes, _, unmappable) = encoding.encode(&text);
+    if unmappable {
+        return Err(format!(
+            "the text contains characters that {} cannot store; convert the file to UTF-8 to keep them",
+            encoding.name()
+        ));
+    }
+    out.extend_from_slice(&bytes);
+    Ok(out)
+}
+
+/// The format of a text file, judged from its first bytes.
+pub(crate) fn detect_file(path: &Path) -> Option<TextFormatDto> {
+    let f = fs::File::open(path).ok()?;
+    let mut buf = Vec::new();
+    f.take(DETECT_BYTES).read_to_end(&mut buf).ok()?;
+    Some(decode_head(&buf).1)
+}
+
+pub(crate) fn read_text(path: &Path) -> Result<TextDocumentDto, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let (content, format) = decode(&bytes);
+    Ok(TextDocumentDto { content, format })
+}
+
+/// Write `text` to `path` in the format the file already has (UTF-8 with `\n`
+/// for new files). Returns the text as it will read back.
+pub(crate) fn write_text(path: &Path, text: &str) -> Result<String, RosePadError> {
+    let format = match fs::read(path) {
+        Ok(bytes) => decode(&bytes).1,
+        Err(_) => TextFormatDto::default(),
+    };
+    durable::write_atomic(path, &encode(text, &format)?)?;
+    Ok(normalize_line_endings(text))
+}
+
+#[tauri::command]
+pub async fn read_text_document(
+    app: AppHandle,
+    path: String,
+) -> Result<TextDocumentDto, RosePadError> {
+    during("read_text_document", async move {
+        paths::document(&app, &path)?;
+        Ok(read_text(Path::new(&path))?)
+    })
+    .await
+}
+
+/// Re-encode a text file, e.g. to UTF-8 or to another line-ending style.
+#[tauri::command]
+pub async fn convert_text_format(
+    app: AppHandle,
+    path: String,
+    format: TextFormatDto,
+) -> Result<DocVersionDto, RosePadError> {
+    during("convert_text_format", async move {
+        paths::document(&app, &path)?;
+        let p = Path::new(&path);
+        let doc = read_text(p)?;
+        // Store the canonical name so the index shows e.g. `windows-1252` for `latin1`
+        let encoding = Encoding::for_label(format.encoding.as_bytes())
+            .ok_or_else(|| format!("unknown encoding {}", format.encoding))?;
+        let format = TextFormatDto {
+            encoding: encoding.name().into(),
+            ..format
+        };
+        durable::write_atomic(p, &encode(&doc.content, &format)?)?;
+        let _ = history::record(&app, &path, &doc.content);
+        version_of(p, &doc.content)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(encoding: &str, bom: bool, line_ending: LineEnding) -> TextFormatDto {
+        TextFormatDto {
+            encoding: encoding.into(),
+            bom,
+            line_ending,
+        }
+    }
+
+    fn utf16(text: &str, le: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|u| if le { u.to_le_bytes() } else { u.to_be_bytes() })
+            .collect()
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        let (text, f) = decode(b"\xEF\xBB\xBFhi");
+        assert_eq!(text, "hi");
+        assert_eq!(f, format("UTF-8", true, LineEnding::Lf));
+
+        let (text, f) = decode(&[&[0xFF, 0xFE][..], &utf16("hé", true)].concat());
+        assert_eq!(text, "hé");
+        assert_eq!(f, format("UTF-16LE", true, LineEnding::Lf));
+
+        let (text, f) = decode(&[&[0xFE, 0xFF][..], &utf16("hé", false)].concat());
+        assert_eq!(text, "hé");
+        assert_eq!(f, format("UTF-16BE", true, LineEnding::Lf));
+    }
+
+    #[test]
+    fn utf16_without_a_bom_is_sniffed() {
+        let (text, f) = decode(&utf16("hello\nworld", true));
+        assert_eq!(text, "hello\nworld");
+        assert_eq!(f.encoding, "UTF-16LE");
+        assert!(!f.bom);
+        assert_eq!(decode(&utf16("hello", false)).1.encoding, "UTF-16BE");
+
+        // Too short to tell, or NULs on both sides: not UTF-16
+        assert_eq!(sniff_utf16(b"a\0"), None);
+        assert_eq!(sniff_utf16(b"\0\0\0\0a\0b\0"), None);
+        assert_eq!(sniff_utf16(b"plain ascii"), None);
+    }
+
+    #[test]
+    fn legacy_bytes_fall_back_to_windows_1252() {
+        let (text, f) = decode(b"caf\xe9 \x80 5");
+        assert_eq!(text, "café € 5");
+        assert_eq!(f, format("windows-1252", false, LineEnding::Lf));
+        assert_eq!(decode("café".as_bytes()).1.encoding, "UTF-8");
+        assert_eq!(decode(b"").1, TextFormatDto::default());
+    }
+
+    #[test]
+    fn a_cut_off_head_is_still_utf8() {
+        let bytes = "café".as_bytes();
+        let head = &bytes[..bytes.len() - 1];
+        assert_eq!(decode_head(head).1.encoding, "UTF-8");
+        // The whole file ending like that is not valid UTF-8
+        assert_eq!(decode(head).1.encoding, "windows-1252");
+        // Only an incomplete character at the very end is forgiven
+        assert_eq!(decode_head(b"\xe9abc").1.encoding, "windows-1252");
+    }
+
+    #[test]
+    fn line_endings_are_detected_and_normalized() {
+        let (text, f) = decode(b"a\r\nb\r\nc");
+        assert_eq!(text, "a\nb\nc");
+        assert_eq!(f.line_ending, LineEnding::Crlf);
+        let (text, f) = decode(b"a\rb\r");
+        assert_eq!(text, "a\nb\n");
+        assert_eq!(f.line_ending, LineEnding::Cr);
+        // Mixed files take the most common style
+        assert_eq!(decode(b"a\nb\nc\r\n").1.line_ending, LineEnding::Lf);
+        assert_eq!(decode(b"a\r\nb\r\nc\n").1.line_ending, LineEnding::Crlf);
+    }
+
+    #[test]
+    fn files_round_trip_byte_for_byte() {
+        let files: Vec<Vec<u8>> = vec![
+            b"plain\nutf-8\n".to_vec(),
+            "crlf ü\r\nline\r\n".as_bytes().to_vec(),
+            b"old mac\rline".to_vec(),
+            b"\xEF\xBB\xBFbom\r\n".to_vec(),
+            b"caf\xe9\r\n\x80\r\n".to_vec(),
+            [&[0xFF, 0xFE][..], &utf16("le ü\r\nx", true)].concat(),
+            utf16("be without bom\nx", false),
+        ];
+        for bytes in files {
+            let (text, f) = decode(&bytes);
+            assert_eq!(encode(&text, &f).unwrap(), bytes, "{f:?}");
+        }
+    }
+
+    #[test]
+    fn encode_uses_the_target_line_ending() {
+        let f = format("UTF-8", false, LineEnding::Crlf);
+        assert_eq!(encode("a\nb\r\nc\rd", &f).unwrap(), b"a\r\nb\r\nc\r\nd");
+        let f = format("UTF-8", false, LineEnding::Lf);
+        assert_eq!(encode("a\r\nb", &f).unwrap(), b"a\nb");
+    }
+
+    #[test]
+    fn encode_refuses_what_the_encoding_cannot_store() {
+        let f = format("windows-1252", false, LineEnding::Lf);
+        assert_eq!(encode("café €", &f).unwrap(), b"caf\xe9 \x80");
+        assert!(encode("中文", &f).unwrap_err().contains("windows-1252"));
+        // Decode-only and unknown encodings
+        assert!(encode("a", &format("csiso2022kr", false, LineEnding::Lf)).is_err());
+        assert!(encode("a", &format("no-such-encoding", false, LineEnding::Lf)).is_err());
+    }
+
+    #[test]
+    fn writes_keep_the_file_format() {
+        let dir = crate::scratch::dir("textfile-write");
+        let legacy = dir.join("legacy.txt");
+        fs::write(&legacy, b"d\xe9j\xe0\r\n").unwrap();
+        assert_eq!(write_text(&legacy, "ü\nnew").unwrap(), "ü\nnew");
+        assert_eq!(fs::read(&legacy).unwrap(), b"\xfc\r\nnew");
+
+        let fresh = dir.join("fresh.txt");
+        write_text(&fresh, "a\r\nb").unwrap();
+        assert_eq!(fs::read(&fresh).unwrap(), b"a\nb");
+    }
+}
@@ -0,0 +1,438 @@
+// Workspace-local recycle bin. Deleted projects and folders are moved into
+// `<root>/.rosepad/trash/<id>/` next to a `meta.json` that records where they
+// came from. Hidden folders are never scanned, so trashed items drop out of
+// the workspace tree like a real delete.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tauri::AppHandle;
+
+use crate::error::{during, RosePadError};
+use crate::journal::{self, WorkspaceOp};
+use crate::paths::{self, inside_root};
+use crate::workspace::unique_dest;
+
+pub const TRASH_DIR: &str = ".rosepad/trash";
+const META_FILE: &str = "meta.json";
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashEntryDto {
+    pub id: String,
+    pub name: String,
+    pub original_path: String,
+    pub deleted_at_ms: i64,
+    pub is_dir: bool,
+    pub size: u64,
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn trash_dir(root: &Path) -> PathBuf {
+    root.join(TRASH_DIR)
+}
+
+// Ids come back from the UI, so only accept what `move_to_trash` generates
+fn entry_dir(root: &Path, id: &str) -> Result<PathBuf, RosePadError> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(RosePadError::invalid_name(id));
+    }
+    Ok(trash_dir(root).join(id))
+}
+
+// Where the trashed item of an entry lies. `name` comes from the metadata too,
+// so it has to be a single plain component, and the entry folder itself has
+// to resolve inside the trash rather than through a link out of it
+fn trashed_item(root: &Path, dir: &Path, name: &str) -> Result<PathBuf, RosePadError> {
+    let mut components = Path::new(name).components();
+    if !matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) {
+        return Err(RosePadError::invalid_name(name));
+    }
+    let dir = inside_root(&trash_dir(root).to_string_lossy(), dir)?;
+    Ok(dir.join(name))
+}
+
+fn size_of(path: &Path) -> u64 {
+    let md = match fs::symlink_metadata(path) {
+        Ok(m) => m,
+        Err(_) => return 0,
+    };
+    if !md.is_dir() {
+        return md.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|e| size_of(&e.path())).sum())
+        .unwrap_or(0)
+}
+
+// Create the folder of a new entry. Deleting the same path twice within a
+// millisecond gives the same `base` id, so later ones get a numbered suffix
+fn create_entry_dir(root: &Path, base: &str) -> Result<(String, PathBuf), RosePadError> {
+    let trash = trash_dir(root);
+    fs::create_dir_all(&trash).map_err(|e| RosePadError::io(e, &trash))?;
+    let mut n = 0;
+    loop {
+        let id = if n == 0 {
+            base.to_string()
+        } else {
+            format!("{base}-{n}")
+        };
+        let dir = entry_dir(root, &id)?;
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok((id, dir)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(RosePadError::io(e, &dir)),
+        }
+    }
+}
+
+/// Move `target` (a file or folder inside `root`) into the workspace trash.
+pub(crate) fn move_to_trash(root: &Path, target: &Path) -> Result<TrashEntryDto, RosePadError> {
+    let checked = inside_root(&root.to_string_lossy(), target)?;
+    let root_canon = root.canonicalize().map_err(|e| RosePadError::io(e, root))?;
+    if checked == root_canon || checked.starts_with(root_canon.join(".rosepad")) {
+        return Err(RosePadError::invalid_path(
+            target,
+            "cannot be moved to the trash",
+        ));
+    }
+    let name = target
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .ok_or_else(|| RosePadError::invalid_path(target, "does not name a file"))?;
+
+    let deleted_at_ms = now_ms();
+    let hash = blake3::hash(target.to_string_lossy().as_bytes()).to_hex();
+    let (id, dir) = create_entry_dir(root, &format!("{deleted_at_ms}-{}", &hash[..8]))?;
+
+    let entry = TrashEntryDto {
+        id,
+        name: name.clone(),
+        original_path: target.to_string_lossy().to_string(),
+        deleted_at_ms,
+        is_dir: target.is_dir(),
+        size: size_of(target),
+    };
+    let meta = serde_json::to_vec_pretty(&entry).map_err(|e| e.to_string())?;
+    let meta_path = dir.join(META_FILE);
+    fs::write(&meta_path, meta).map_err(|e| RosePadError::io(e, &meta_path))?;
+    if let Err(e) = fs::rename(target, dir.join(&name)) {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(RosePadError::io(e, target));
+    }
+    Ok(entry)
+}
+
+fn read_entry(dir: &Path) -> Option<TrashEntryDto> {
+    let raw = fs::read(dir.join(META_FILE)).ok()?;
+    serde_json::from_slice(&raw).ok()
+}
+
+/// Everything in the trash, most recently deleted first.
+pub(crate) fn list(root: &Path) -> Vec<TrashEntryDto> {
+    let mut entries: Vec<TrashEntryDto> = fs::read_dir(trash_dir(root))
+        .map(|it| it.flatten().filter_map(|e| read_entry(&e.path())).collect())
+        .unwrap_or_default();
+    entries.sort_by_key(|e| std::cmp::Reverse(e.deleted_at_ms));
+    entries
+}
+
+/// Put a trashed item back at its original path (or next to it if that name is
+/// taken now) and return where it ended up.
+pub(crate) fn restore(root: &Path, id: &str) -> Result<String, RosePadError> {
+    let dir = entry_dir(root, id)?;
+    let entry = read_entry(&dir).ok_or_else(|| RosePadError::not_found(&dir))?;
+    let trashed = trashed_item(root, &dir, &entry.name)?;
+    let original = PathBuf::from(&entry.original_path);
+    let parent = original
+        .parent()
+        .ok_or_else(|| RosePadError::invalid_path(&original, "has no parent folder"))?;
+    // The metadata lives in the workspace, so do not trust it blindly: the
+    // closest folder that still exists must be inside the root
+    if original
+        .components()
+        .any(|c| matches!(c, Component::ParentDir))
+    {
+        return Err(RosePadError::outside_workspace(&original));
+    }
+    let existing = parent
+        .ancestors()
+        .find(|a| a.is_dir())
+        .ok_or_else(|| RosePadError::outside_workspace(&original))?;
+    let _ = inside_root(&root.to_string_lossy(), existing)?;
+    fs::create_dir_all(parent).map_err(|e| RosePadError::io(e, parent))?;
+
+    let dest = unique_dest(original);
+    fs::rename(&trashed, &dest).map_err(|e| RosePadError::io(e, &trashed))?;
+    let _ = fs::remove_dir_all(&dir);
+    Ok(dest.to_string_lossy().to_string())
+}
+
+fn remove(root: &Path, id: &str) -> Result<(), RosePadError> {
+    let dir = entry_dir(root, id)?;
+    fs::remove_dir_all(&dir).map_err(|e| RosePadError::io(e, &dir))
+}
+
+/// Permanently delete items trashed more than `max_age_ms` ago.
+pub(crate) fn purge_older_than(root: &Path, max_age_ms: i64) -> usize {
+    let cutoff = now_ms() - max_age_ms;
+    list(root)
+        .into_iter()
+        .filter(|e| e.deleted_at_ms < cutoff)
+        .filter(|e| remove(root, &e.id).is_ok())
+        .count()
+}
+
+#[tauri::command]
+pub async fn list_trash(
+    app: AppHandle,
+    workspace_root: String,
+) -> Result<Vec<TrashEntryDto>, RosePadError> {
+    during("list_trash", async move {
+        paths::workspace_root(&app, &workspace_root)?;
+        Ok(list(Path::new(&workspace_root)))
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn restore_trash_item(
+    app: AppHandle,
+    workspace_root: String,
+    id: String,
+) -> Result<String, RosePadError> {
+    during("restore_trash_item", async move {
+        paths::workspace_root(&app, &workspace_root)?;
+        let path = restore(Path::new(&workspace_root), &id)?;
+        // Undoing a restore sends the item back to the trash
+        journal::record(
+            &workspace_root,
+            WorkspaceOp::Create {
+                root: workspace_root.clone(),
+                path: path.clone(),
+                trash_id: None,
+            },
+        )
+        .await;
+        Ok(path)
+    })
+    .await
+}
+
+/// Permanently delete the given items, or the whole trash when `ids` is omitted.
+#[tauri::command]
+pub async fn empty_trash(
+    app: AppHandle,
+    workspace_root: String,
+    ids: Option<Vec<String>>,
+) -> Result<usize, RosePadError> {
+    during("empty_trash", async move {
+        paths::workspace_root(&app, &workspace_root)?;
+        let root = Path::new(&workspace_root);
+        let ids = match ids {
+            Some(ids) => ids,
+            None => list(root).into_iter().map(|e| e.id).collect(),
+        };
+        let mut removed = 0;
+        for id in ids {
+            remove(root, &id)?;
+            removed += 1;
+        }
+        Ok(removed)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn purge_trash(
+    app: AppHandle,
+    workspace_root: String,
+    max_age_days: u32,
+) -> Result<usize, RosePadError> {
+    during("purge_trash", async move {
+        paths::workspace_root(&app, &workspace_root)?;
+        Ok(purge_older_than(
+            Path::new(&workspace_root),
+            i64::from(max_age_days) * DAY_MS,
+        ))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::{self, Scratch};
+
+    fn temp_root(name: &str) -> Scratch {
+        scratch::dir(&format!("trash-{name}"))
+    }
+
+    // A trash entry written by hand, as a tampered or older workspace might have it
+    fn fake_entry(root: &Path, id: &str, original: &Path, deleted_at_ms: i64) {
+        let dir = trash_dir(root).join(id);
+        fs::create_dir_all(&dir).unwrap();
+        let name = original.file_name().unwrap().to_string_lossy().to_string();
+        fs::write(dir.join(&name), "trashed").unwrap();
+        let entry = TrashEntryDto {
+            id: id.into(),
+            name,
+            original_path: original.to_string_lossy().to_string(),
+            deleted_at_ms,
+            is_dir: false,
+            size: 7,
+        };
+        fs::write(dir.join(META_FILE), serde_json::to_vec(&entry).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn the_same_id_twice_gets_a_suffix() {
+        let root = temp_root("suffix");
+        let (first, _) = create_entry_dir(&root, "1-abc").unwrap();
+        let (second, dir) = create_entry_dir(&root, "1-abc").unwrap();
+        let (third, _) = create_entry_dir(&root, "1-abc").unwrap();
+        assert_eq!(
+            [first.as_str(), second.as_str(), third.as_str()],
+            ["1-abc", "1-abc-1", "1-abc-2"]
+        );
+        assert!(dir.is_dir());
+    }
+
+    #[test]
+    fn trashing_a_path_twice_keeps_both() {
+        let root = temp_root("twice");
+        let file = root.join("a.txt");
+        fs::write(&file, "one").unwrap();
+        let first = move_to_trash(&root, &file).unwrap();
+        fs::write(&file, "two").unwrap();
+        let second = move_to_trash(&root, &file).unwrap();
+        assert_ne!(first.id, second.id);
+        assert_eq!(list(&root).len(), 2);
+        let restored = restore(&root, &first.id).unwrap();
+        assert_eq!(fs::read_to_string(restored).unwrap(), "one");
+    }
+
+    #[test]
+    fn restoring_over_a_taken_name_picks_a_new_one() {
+        let root = temp_root("taken");
+        let file = root.join("notes.txt");
+        fs::write(&file, "old").unwrap();
+        let entry = move_to_trash(&root, &file).unwrap();
+        fs::write(&file, "new").unwrap();
+        let restored = restore(&root, &entry.id).unwrap();
+        assert_ne!(Path::new(&restored), file);
+        assert_eq!(fs::read_to_string(&restored).unwrap(), "old");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+        assert!(list(&root).is_empty());
+    }
+
+    #[test]
+    fn a_deleted_folder_is_recreated_on_restore() {
+        let root = temp_root("parent");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/a.txt"), "a").unwrap();
+        let entry = move_to_trash(&root, &root.join("sub/a.txt")).unwrap();
+        fs::remove_dir(root.join("sub")).unwrap();
+        restore(&root, &entry.id).unwrap();
+        assert!(root.join("sub/a.txt").is_file());
+    }
+
+    #[test]
+    fn metadata_cannot_lead_out_of_the_workspace() {
+        let root = temp_root("escape");
+        let outside = temp_root("escape-outside");
+        fake_entry(&root, "1-dots", &root.join("sub/../../evil.txt"), 1);
+        fake_entry(&root, "2-abs", &outside.join("evil.txt"), 2);
+        for id in ["1-dots", "2-abs"] {
+            let err = restore(&root, id);
+            assert!(
+                matches!(err, Err(RosePadError::OutsideWorkspace(_))),
+                "{id}"
+            );
+            assert!(trash_dir(&root).join(id).is_dir());
+        }
+        assert!(!outside.join("evil.txt").exists());
+    }
+
+    #[test]
+    fn the_trashed_name_cannot_lead_out_of_the_entry() {
+        let root = temp_root("name");
+        let outside = temp_root("name-outside");
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        let escape = format!("../../../../{}", outside.join("secret.txt").display());
+        for (id, name) in [
+            ("1-up", escape.as_str()),
+            ("2-abs", &*outside.join("secret.txt").to_string_lossy()),
+            ("3-nested", "sub/a.txt"),
+            ("4-dot", "."),
+        ] {
+            fake_entry(&root, id, &root.join("a.txt"), 1);
+            let meta = trash_dir(&root).join(id).join(META_FILE);
+            let mut entry: TrashEntryDto =
+                serde_json::from_slice(&fs::read(&meta).unwrap()).unwrap();
+            entry.name = name.to_string();
+            fs::write(&meta, serde_json::to_vec(&entry).unwrap()).unwrap();
+            assert!(restore(&root, id).is_err(), "{name}");
+        }
+        assert!(outside.join("secret.txt").is_file());
+        assert!(!root.join("a.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn an_entry_linked_out_of_the_trash_is_refused() {
+        let root = temp_root("linked");
+        let outside = temp_root("linked-outside");
+        fake_entry(&outside, "1-link", &root.join("a.txt"), 1);
+        fs::create_dir_all(trash_dir(&root)).unwrap();
+        std::os::unix::fs::symlink(
+            trash_dir(&outside).join("1-link"),
+            trash_dir(&root).join("1-link"),
+        )
+        .unwrap();
+        assert!(matches!(
+            restore(&root, "1-link"),
+            Err(RosePadError::OutsideWorkspace(_))
+        ));
+        assert!(trash_dir(&outside).join("1-link/a.txt").is_file());
+        assert!(!root.join("a.txt").exists());
+    }
+
+    #[test]
+    fn ids_from_the_ui_are_checked() {
+        let root = temp_root("ids");
+        for id in ["", "..", "../x", "a/b", "a b"] {
+            assert!(restore(&root, id).is_err(), "{id:?}");
+            assert!(remove(&root, id).is_err(), "{id:?}");
+        }
+    }
+
+    #[test]
+    fn purging_removes_only_old_entries() {
+        let root = temp_root("purge");
+        fake_entry(
+            &root,
+            "1-old",
+            &root.join("old.txt"),
+            now_ms() - 40 * DAY_MS,
+        );
+        fake_entry(&root, "2-new", &root.join("new.txt"), now_ms() - DAY_MS);
+        assert_eq!(purge_older_than(&root, 30 * DAY_MS), 1);
+        let left: Vec<String> = list(&root).into_iter().map(|e| e.id).collect();
+        assert_eq!(left, vec!["2-new"]);
+        assert_eq!(purge_older_than(&root, 30 * DAY_MS), 0);
+    }
+}
@@ -0,0 +1,586 @@
+// Watching the workspace folders for changes made outside the app. Raw notify
+// events are collected on a worker thread until the tree has been quiet for a
+// moment, coalesced per path and sent to the UI as one `fs:changed` batch, so a
+// checkout that touches thousands of files does not flood the frontend. Every
+// open workspace has its own watcher, keyed by its root.
+
+use lazy_static::lazy_static;
+use notify::{
+    event::{ModifyKind, RenameMode},
+    EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use tauri::{AppHandle, Emitter};
+
+use crate::error::{during, RosePadError};
+use crate::paths;
+use crate::scan::{self, ScanOptions, ScanRules};
+
+// A batch goes out once nothing happened for DEBOUNCE, or after MAX_DELAY at the latest
+const DEBOUNCE: Duration = Duration::from_millis(250);
+const MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// Net change to one path over a batch of file system events.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum FsChange {
+    Created { path: String },
+    Modified { path: String },
+    Removed { path: String },
+    Renamed { from: String, to: String },
+}
+
+/// Payload of `fs:changed`: one batch from the watcher of `root`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FsChangeBatchDto {
+    pub root: String,
+    pub changes: Vec<FsChange>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WatcherStatusDto {
+    pub root: String,
+    pub folders: Vec<String>,
+}
+
+struct ActiveWatcher {
+    // Dropping it closes the event channel, which ends the batching thread
+    _watcher: RecommendedWatcher,
+    folders: Vec<String>,
+}
+
+// What one watcher is watching, used to drop events for ignored paths
+struct WatchScope {
+    root: String,
+    folders: Vec<String>,
+    options: ScanOptions,
+}
+
+lazy_static! {
+    static ref WATCHERS: Mutex<HashMap<String, ActiveWatcher>> = Mutex::new(HashMap::new());
+}
+
+fn watchers() -> std::sync::MutexGuard<'static, HashMap<String, ActiveWatcher>> {
+    match WATCHERS.lock() {
+        Ok(g) => g,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Folds raw notify events into one `FsChange` per path, e.g. a file created
+/// and deleted again within a batch disappears, and a deleted then recreated
+/// file is reported as modified.
+#[derive(Default)]
+pub(crate) struct Coalescer {
+    // In order of first appearance; `None` once an entry cancelled out
+    changes: Vec<Option<FsChange>>,
+    // Current path of each entry -> its index in `changes`
+    by_path: HashMap<String, usize>,
+    // Rename halves waiting for their other half, by notify tracker id
+    rename_from: HashMap<usize, String>,
+    // Some backends report a rename both in halves and as a whole
+    last_rename: Option<(String, String)>,
+}
+
+impl Coalescer {
+    pub(crate) fn push(&mut self, event: &notify::Event) {
+        let paths: Vec<String> = event
+            .paths
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        match event.kind {
+            EventKind::Access(_) => {}
+            EventKind::Create(_) => paths.into_iter().for_each(|p| self.created(p)),
+            EventKind::Remove(_) => paths.into_iter().for_each(|p| self.removed(p)),
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths.len() == 2 => {
+                let mut paths = paths.into_iter();
+                if let (Some(from), Some(to)) = (paths.next(), paths.next()) {
+                    self.renamed(from, to);
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                for p in paths {
+                    match event.tracker() {
+                        Some(t) => {
+                            self.rename_from.insert(t, p);
+                        }
+                        None => self.removed(p),
+                    }
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                for p in paths {
+                    match event.tracker().and_then(|t| self.rename_from.remove(&t)) {
+                        Some(from) => self.renamed(from, p),
+                        None => self.created(p),
+                    }
+                }
+            }
+            // The backend could not tell which side of a rename this is
+            EventKind::Modify(ModifyKind::Name(_)) => {
+                for p in paths {
+                    if Path::new(&p).exists() {
+                        self.created(p)
+                    } else {
+                        self.removed(p)
+                    }
+                }
+            }
+            _ => paths.into_iter().for_each(|p| self.modified(p)),
+        }
+    }
+
+    fn add(&mut self, path: String, change: FsChange) {
+        self.by_path.insert(path, self.changes.len());
+        self.changes.push(Some(change));
+    }
+
+    fn entry(&mut self, path: &str) -> Option<&mut FsChange> {
+        let i = *self.by_path.get(path)?;
+        self.changes[i].as_mut()
+    }
+
+    fn created(&mut self, path: String) {
+        match self.entry(&path) {
+            None => self.add(path.clone(), FsChange::Created { path }),
+            Some(c @ FsChange::Removed { .. }) => *c = FsChange::Modified { path },
+            Some(_) => {}
+        }
+    }
+
+    fn modified(&mut self, path: String) {
+        match self.entry(&path) {
+            None => self.add(path.clone(), FsChange::Modified { path }),
+            Some(c @ FsChange::Removed { .. }) => *c = FsChange::Modified { path },
+            Some(_) => {}
+        }
+    }
+
+    fn removed(&mut self, path: String) {
+        let Some(&i) = self.by_path.get(&path) else {
+            self.add(path.clone(), FsChange::Removed { path });
+            return;
+        };
+        match self.changes[i].take() {
+            // Came and went within the batch
+            Some(FsChange::Created { .. }) => {
+                self.by_path.remove(&path);
+            }
+            Some(FsChange::Renamed { from, .. }) => {
+                self.by_path.remove(&path);
+                self.by_path.insert(from.clone(), i);
+                self.changes[i] = Some(FsChange::Removed { path: from });
+            }
+            _ => self.changes[i] = Some(FsChange::Removed { path }),
+        }
+    }
+
+    fn renamed(&mut self, from: String, to: String) {
+        let pair = (from, to);
+        if self.last_rename.as_ref() == Some(&pair) {
+            return;
+        }
+        self.last_rename = Some(pair.clone());
+        let (from, to) = pair;
+        // Whatever was at `to` was replaced
+        if let Some(i) = self.by_path.remove(&to) {
+            self.changes[i] = None;
+        }
+        let Some(i) = self.by_path.remove(&from) else {
+            self.add(to.clone(), FsChange::Renamed { from, to });
+            return;
+        };
+        let change = match self.changes[i].take() {
+            Some(FsChange::Created { .. }) => FsChange::Created { path: to.clone() },
+            // Moved back where it started
+            Some(FsChange::Renamed { from: origin, .. }) if origin == to => {
+                FsChange::Modified { path: to.clone() }
+            }
+            Some(FsChange::Renamed { from: origin, .. }) => FsChange::Renamed {
+                from: origin,
+                to: to.clone(),
+            },
+            _ => FsChange::Renamed {
+                from,
+                to: to.clone(),
+            },
+        };
+        self.by_path.insert(to, i);
+        self.changes[i] = Some(change);
+    }
+
+    /// The batch's changes. Renames whose other half never arrived count as
+    /// removals (the file was moved out of the watched folders).
+    pub(crate) fn finish(mut self) -> Vec<FsChange> {
+        let unmatched: Vec<String> = self.rename_from.drain().map(|(_, p)| p).collect();
+        for p in unmatched {
+            self.removed(p);
+        }
+        self.changes.into_iter().flatten().collect()
+    }
+}
+
+// Keep only the changed paths a scan would list (plus ignore-file edits, which
+// change what is listed)
+fn relevant_paths(scope: &WatchScope, paths: Vec<String>) -> Vec<String> {
+    let WatchScope {
+        folders, options, ..
+    } = scope;
+    let mut rules: HashMap<&str, Option<ScanRules>> = HashMap::new();
+    paths
+        .into_iter()
+        .filter(|raw| {
+            let p = Path::new(raw);
+            if scan::is_ignore_file(p) {
+                return true;
+            }
+            let folder = match folders.iter().find(|f| p.starts_with(f.as_str())) {
+                Some(f) => f.as_str(),
+                None => return true,
+            };
+            if p == Path::new(folder) {
+                return true;
+            }
+            let rules = rules
+                .entry(folder)
+                .or_insert_with(|| ScanRules::new(Path::new(folder), options).ok());
+            match rules {
+                Some(r) if p.exists() => r.includes(p, p.is_dir()),
+                // Deleted: it may have been a file or a folder
+                Some(r) => r.includes(p, false) || r.includes(p, true),
+                None => true,
+            }
+        })
+        .collect()
+}
+
+// A rename across the ignore boundary shows up as a creation or removal
+fn relevant_changes(scope: &WatchScope, changes: Vec<FsChange>) -> Vec<FsChange> {
+    let mut paths = Vec::new();
+    for c in &changes {
+        match c {
+            FsChange::Created { path }
+            | FsChange::Modified { path }
+            | FsChange::Removed { path } => paths.push(path.clone()),
+            FsChange::Renamed { from, to } => {
+                paths.push(from.clone());
+                paths.push(to.clone());
+            }
+        }
+    }
+    let keep: std::collections::HashSet<String> =
+        relevant_paths(scope, paths).into_iter().collect();
+    changes
+        .into_iter()
+        .filter_map(|c| match c {
+            FsChange::Renamed { from, to } => match (keep.contains(&from), keep.contains(&to)) {
+                (true, true) => Some(FsChange::Renamed { from, to }),
+                (false, true) => Some(FsChange::Created { path: to }),
+                (true, false) => Some(FsChange::Removed { path: from }),
+                (false, false) => None,
+            },
+            FsChange::Created { ref path }
+            | FsChange::Modified { ref path }
+            | FsChange::Removed { ref path } => keep.contains(path).then_some(c),
+        })
+        .collect()
+}
+
+fn run_batches(app: AppHandle, scope: WatchScope, events: mpsc::Receiver<notify::Event>) {
+    // Ends once the watcher is stopped and the channel closes
+    while let Ok(first) = events.recv() {
+        let started = Instant::now();
+        let mut batch = Coalescer::default();
+        batch.push(&first);
+        loop {
+            let left = MAX_DELAY.saturating_sub(started.elapsed());
+            if left.is_zero() {
+                break;
+            }
+            match events.recv_timeout(DEBOUNCE.min(left)) {
+                Ok(event) => batch.push(&event),
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                // Stopped; nobody is listening for this batch anymore
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        let changes = relevant_changes(&scope, batch.finish());
+        if !changes.is_empty() {
+            let _ = app.emit(
+                "fs:changed",
+                FsChangeBatchDto {
+                    root: scope.root.clone(),
+                    changes,
+                },
+            );
+        }
+    }
+}
+
+/// Stop the watcher of `root`, if there is one.
+pub(crate) fn stop(root: &str) {
+    watchers().remove(root);
+}
+
+fn watch_error(folder: &str, err: notify::Error) -> RosePadError {
+    let path = Path::new(folder);
+    match err.kind {
+        notify::ErrorKind::Io(e) => RosePadError::io(e, path),
+        notify::ErrorKind::PathNotFound => RosePadError::not_found(path),
+        other => RosePadError::failed(format!(
+            "Could not watch \"{}\" for changes: {other:?}",
+            path.display()
+        )),
+    }
+}
+
+/// Watch `folders` (inside the workspace at `root`, which defaults to the
+/// first folder) recursively, replacing whatever that workspace watched
+/// before. Fails if any folder cannot be watched, leaving the previous watcher
+/// in place.
+#[tauri::command]
+pub async fn start_watching(
+    app: AppHandle,
+    folders: Vec<String>,
+    options: Option<ScanOptions>,
+    root: Option<String>,
+) -> Result<(), RosePadError> {
+    during("start_watching", async move {
+        let root = root
+            .or_else(|| folders.first().cloned())
+            .ok_or("There is nothing to watch")?;
+        paths::workspace_root(&app, &root)?;
+        for f in &folders {
+            paths::inside_root(&root, Path::new(f))?;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let mut w: RecommendedWatcher =
+            notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+                if let Ok(event) = res {
+                    let _ = tx.send(event);
+                }
+            })
+            .map_err(|e| RosePadError::failed(format!("Could not start watching files: {e}")))?;
+        for f in &folders {
+            w.watch(Path::new(f), RecursiveMode::Recursive)
+                .map_err(|e| watch_error(f, e))?;
+        }
+
+        let mut guard = watchers();
+        // Dropping the old watcher lets its batching thread wind down
+        guard.remove(&root);
+        let scope = WatchScope {
+            root: root.clone(),
+            folders: folders.clone(),
+            options: options.unwrap_or_default(),
+        };
+        thread::spawn(move || run_batches(app, scope, rx));
+        guard.insert(
+            root,
+            ActiveWatcher {
+                _watcher: w,
+                folders,
+            },
+        );
+        Ok(())
+    })
+    .await
+}
+
+/// Stop the watcher of `root`, or every watcher when no root is given.
+#[tauri::command]
+pub async fn stop_watching(root: Option<String>) -> Result<(), RosePadError> {
+    match root {
+        Some(r) => stop(&r),
+        None => watchers().clear(),
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn watcher_status() -> Result<Vec<WatcherStatusDto>, RosePadError> {
+    let mut status: Vec<WatcherStatusDto> = watchers()
+        .iter()
+        .map(|(root, w)| WatcherStatusDto {
+            root: root.clone(),
+            folders: w.folders.clone(),
+        })
+        .collect();
+    status.sort_by(|a, b| a.root.cmp(&b.root));
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, Event, RemoveKind};
+
+    enum Ev {
+        Create(&'static str),
+        Write(&'static str),
+        Remove(&'static str),
+        Rename(&'static str, &'static str),
+        From(usize, &'static str),
+        To(usize, &'static str),
+        // The old half of a rename from a backend without tracker ids
+        FromUntracked(&'static str),
+        // A rename the backend could not attribute to either side
+        Any(&'static str),
+    }
+
+    fn event(ev: &Ev) -> Event {
+        let path = |p: &str| format!("/rosepad-watch/{p}").into();
+        match *ev {
+            Ev::Create(p) => Event::new(EventKind::Create(CreateKind::File)).add_path(path(p)),
+            Ev::Write(p) => Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content)))
+                .add_path(path(p)),
+            Ev::Remove(p) => Event::new(EventKind::Remove(RemoveKind::File)).add_path(path(p)),
+            Ev::Rename(a, b) => Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+                .add_path(path(a))
+                .add_path(path(b)),
+            Ev::From(t, p) => Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::From)))
+                .add_path(path(p))
+                .set_tracker(t),
+            Ev::To(t, p) => Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::To)))
+                .add_path(path(p))
+                .set_tracker(t),
+            Ev::FromUntracked(p) => {
+                Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::From))).add_path(path(p))
+            }
+            Ev::Any(p) => {
+                Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Any))).add_path(path(p))
+            }
+        }
+    }
+
+    fn p(name: &str) -> String {
+        format!("/rosepad-watch/{name}")
+    }
+
+    fn created(name: &str) -> FsChange {
+        FsChange::Created { path: p(name) }
+    }
+
+    fn modified(name: &str) -> FsChange {
+        FsChange::Modified { path: p(name) }
+    }
+
+    fn removed(name: &str) -> FsChange {
+        FsChange::Removed { path: p(name) }
+    }
+
+    fn renamed(from: &str, to: &str) -> FsChange {
+        FsChange::Renamed {
+            from: p(from),
+            to: p(to),
+        }
+    }
+
+    #[test]
+    fn coalesces_event_sequences() {
+        use Ev::*;
+        let cases: Vec<(&str, Vec<Ev>, Vec<FsChange>)> = vec![
+            ("single create", vec![Create("a")], vec![created("a")]),
+            (
+                "writes to a new file",
+                vec![Create("a"), Write("a"), Write("a")],
+                vec![created("a")],
+            ),
+            ("create then delete", vec![Create("a"), Remove("a")], vec![]),
+            (
+                "delete then recreate",
+                vec![Remove("a"), Create("a")],
+                vec![modified("a")],
+            ),
+            (
+                "write then delete",
+                vec![Write("a"), Remove("a")],
+                vec![removed("a")],
+            ),
+            ("rename", vec![Rename("a", "b")], vec![renamed("a", "b")]),
+            (
+                "rename chain",
+                vec![Rename("a", "b"), Rename("b", "c")],
+                vec![renamed("a", "c")],
+            ),
+            (
+                "rename back",
+                vec![Rename("a", "b"), Rename("b", "a")],
+                vec![modified("a")],
+            ),
+            (
+                "rename of a new file",
+                vec![Create("a"), Rename("a", "b")],
+                vec![created("b")],
+            ),
+            (
+                "renamed then deleted",
+                vec![Rename("a", "b"), Remove("b")],
+                vec![removed("a")],
+            ),
+            (
+                "rename over a new file",
+                vec![Create("b"), Rename("a", "b")],
+                vec![renamed("a", "b")],
+            ),
+            (
+                "rename halves",
+                vec![From(1, "a"), To(1, "b")],
+                vec![renamed("a", "b")],
+            ),
+            (
+                "interleaved halves",
+                vec![From(1, "a"), From(2, "x"), To(2, "y"), To(1, "b")],
+                vec![renamed("x", "y"), renamed("a", "b")],
+            ),
+            (
+                "moved out of the watched folders",
+                vec![From(1, "a")],
+                vec![removed("a")],
+            ),
+            (
+                "moved into the watched folders",
+                vec![To(1, "b")],
+                vec![created("b")],
+            ),
+            (
+                "halves without a tracker",
+                vec![FromUntracked("a")],
+                vec![removed("a")],
+            ),
+            (
+                "rename reported in halves and whole",
+                vec![From(1, "a"), To(1, "b"), Rename("a", "b")],
+                vec![renamed("a", "b")],
+            ),
+            (
+                "rename reported twice",
+                vec![Rename("a", "b"), Rename("a", "b")],
+                vec![renamed("a", "b")],
+            ),
+            (
+                "unattributed rename of a missing path",
+                vec![Any("gone")],
+                vec![removed("gone")],
+            ),
+        ];
+        for (name, events, expected) in cases {
+            let mut batch = Coalescer::default();
+            for ev in &events {
+                batch.push(&event(ev));
+            }
+            assert_eq!(batch.finish(), expected, "{name}");
+        }
+    }
+}
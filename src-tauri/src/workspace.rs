@@ -1,14 +1,29 @@
-use lazy_static::lazy_static;
-use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
 use std::{
+    collections::HashSet,
     fs,
-    io::{Read, Write},
+    io::Read,
     path::{Path, PathBuf},
 };
 use tauri::{AppHandle, Emitter};
-use zip::{write::FileOptions, ZipArchive, ZipWriter};
+
+use crate::attachments;
+use crate::diff;
+use crate::drafts;
+use crate::encryption;
+use crate::error::{during, RosePadError};
+use crate::history;
+use crate::index;
+use crate::journal::{self, WorkspaceOp};
+use crate::paths;
+use crate::rpad::{self, RpadArchive};
+use crate::scan::{self, ScanCache, ScanOptions, ScanRules};
+use crate::search::{self, IndexUpdate};
+use crate::textfile::{self, TextFormatDto};
+use crate::trash::{self, TrashEntryDto};
+
+// How much of a file its fingerprint covers
+const FINGERPRINT_BYTES: u64 = 64 * 1024;
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -22,6 +37,13 @@ pub struct ProjectDto {
     pub last_modified_ms: i64,
     pub size: i64,
     pub parent_physical_folder: Option<String>,
+    /// Encoding, BOM and line endings; only for text files.
+    pub text_format: Option<TextFormatDto>,
+    // Used by the index to recognize the file after a rename; see `index::resolve_ids`
+    #[serde(skip)]
+    pub file_key: Option<String>,
+    #[serde(skip)]
+    pub fingerprint: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -29,13 +51,90 @@ pub struct ProjectDto {
 pub struct PhysicalFolderScanDto {
     pub path: String,
     pub name: String,
+    pub parent_path: Option<String>,
+}
+
+/// A scanned folder with its files and subfolders.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderNodeDto {
+    pub path: String,
+    pub name: String,
+    /// `None` for folders directly under the workspace root.
+    pub parent_path: Option<String>,
+    pub projects: Vec<ProjectDto>,
+    pub folders: Vec<FolderNodeDto>,
+}
+
+impl FolderNodeDto {
+    /// This folder and all folders below it, parents first.
+    pub fn flatten(&self) -> Vec<&FolderNodeDto> {
+        let mut out = vec![self];
+        let mut i = 0;
+        while i < out.len() {
+            let node = out[i];
+            out.extend(node.folders.iter());
+            i += 1;
+        }
+        out
+    }
+
+    fn summary(&self) -> PhysicalFolderScanDto {
+        PhysicalFolderScanDto {
+            path: self.path.clone(),
+            name: self.name.clone(),
+            parent_path: self.parent_path.clone(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ScanResultDto {
     pub root_projects: Vec<ProjectDto>,
-    pub physical_folders: Vec<(PhysicalFolderScanDto, Vec<ProjectDto>)>,
+    /// Top-level folders; nested folders hang off each node.
+    pub physical_folders: Vec<FolderNodeDto>,
+}
+
+impl ScanResultDto {
+    /// Every folder in the scan, parents first.
+    pub fn all_folders(&self) -> Vec<&FolderNodeDto> {
+        self.physical_folders
+            .iter()
+            .flat_map(|f| f.flatten())
+            .collect()
+    }
+
+    pub fn all_projects(&self) -> Vec<ProjectDto> {
+        let mut out = self.root_projects.clone();
+        for f in self.all_folders() {
+            out.extend(f.projects.iter().cloned());
+        }
+        out
+    }
+
+    pub fn all_projects_mut(&mut self) -> Vec<&mut ProjectDto> {
+        let mut out: Vec<&mut ProjectDto> = self.root_projects.iter_mut().collect();
+        let mut stack: Vec<&mut FolderNodeDto> = self.physical_folders.iter_mut().collect();
+        while let Some(FolderNodeDto {
+            projects, folders, ..
+        }) = stack.pop()
+        {
+            out.extend(projects.iter_mut());
+            stack.extend(folders.iter_mut());
+        }
+        out
+    }
+}
+
+/// Payload of `scan:progress`, sent while a scan walks the workspace.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanProgressDto {
+    pub root: String,
+    pub files: usize,
+    pub folders: usize,
+    pub done: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -45,29 +144,19 @@ pub struct AnalyzeResultDto {
     pub delete_project_paths: Vec<String>,
     pub physical_folders: Vec<PhysicalFolderScanDto>,
     pub delete_physical_folders: Vec<String>,
+    /// Files that were renamed or moved; they keep their id, tags and history.
+    #[serde(default)]
+    pub moved_projects: Vec<MovedPathDto>,
+    /// Folders that were renamed or moved; they keep their color.
+    #[serde(default)]
+    pub moved_folders: Vec<MovedPathDto>,
 }
 
-fn canonicalize_allow_missing(p: &Path) -> Result<PathBuf, String> {
-    if p.exists() {
-        return p.canonicalize().map_err(|e| e.to_string());
-    }
-    let parent = p.parent().ok_or_else(|| "path has no parent".to_string())?;
-    let parent_canon = parent.canonicalize().map_err(|e| e.to_string())?;
-    let name = p
-        .file_name()
-        .ok_or_else(|| "path has no filename".to_string())?;
-    Ok(parent_canon.join(name))
-}
-
-fn ensure_inside_root(root: &str, target: &Path) -> Result<PathBuf, String> {
-    let root_canon = Path::new(root)
-        .canonicalize()
-        .map_err(|e| format!("workspace root invalid: {e}"))?;
-    let tgt = canonicalize_allow_missing(target)?;
-    if !tgt.starts_with(&root_canon) {
-        return Err("path is outside workspace root".into());
-    }
-    Ok(tgt)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MovedPathDto {
+    pub from: String,
+    pub to: String,
 }
 
 fn mtime_ms(md: &fs::Metadata) -> i64 {
@@ -81,18 +170,6 @@ fn mtime_ms(md: &fs::Metadata) -> i64 {
     }
 }
 
-fn read_rpad_title(path: &Path) -> Option<String> {
-    let file = fs::File::open(path).ok()?;
-    let mut zip = zip::ZipArchive::new(file).ok()?;
-    let mut f = zip.by_name("manifest.json").ok()?;
-    let mut s = String::new();
-    let _ = f.read_to_string(&mut s).ok()?;
-    let v: serde_json::Value = serde_json::from_str(&s).ok()?;
-    v.get("title")
-        .and_then(|t| t.as_str())
-        .map(|s| s.to_string())
-}
-
 fn detect_kind_ext(ext: &str) -> (&'static str, Option<String>) {
     match ext {
         "rpad" => ("rpad", None),
@@ -113,462 +190,543 @@ fn detect_kind_ext(ext: &str) -> (&'static str, Option<String>) {
     }
 }
 
-fn stable_id(path: &str) -> String {
+pub(crate) fn stable_id(path: &str) -> String {
     let h = blake3::hash(path.as_bytes());
     format!("{}", h.to_hex())
 }
 
-#[tauri::command]
-pub async fn scan_workspace(root: String) -> Result<ScanResultDto, String> {
-    use std::path::PathBuf;
-    let root_path = PathBuf::from(&root);
-    if !root_path.is_dir() {
-        return Err("workspace root is not a directory".into());
-    }
-
-    let mut root_projects: Vec<ProjectDto> = Vec::new();
-    let mut physical_folders: Vec<(PhysicalFolderScanDto, Vec<ProjectDto>)> = Vec::new();
+// Device and inode; they survive a rename or move within one file system
+#[cfg(unix)]
+fn file_key(md: &fs::Metadata) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    Some(format!("{}:{}", md.dev(), md.ino()))
+}
 
-    let entries = match fs::read_dir(&root_path) {
-        Ok(it) => it,
-        Err(e) => return Err(format!("cannot read workspace root: {e}")),
-    };
+#[cfg(not(unix))]
+fn file_key(_md: &fs::Metadata) -> Option<String> {
+    None
+}
 
-    for entry in entries {
-        let entry = match entry {
-            Ok(x) => x,
-            Err(_) => continue,
-        };
-        let p = entry.path();
-        let name = entry.file_name().to_string_lossy().to_string();
-
-        if p.is_dir() {
-            let mut items: Vec<ProjectDto> = Vec::new();
-            if let Ok(children) = fs::read_dir(&p) {
-                for child in children {
-                    let child = match child {
-                        Ok(x) => x,
-                        Err(_) => continue,
-                    };
-                    let cp = child.path();
-                    if !cp.is_file() {
-                        continue;
-                    }
+// Size plus a hash of the first bytes: enough to tell a moved file from the
+// others that disappeared in the same batch, without reading whole files
+fn fingerprint(p: &Path, size: u64) -> Option<String> {
+    let mut head = Vec::new();
+    fs::File::open(p)
+        .ok()?
+        .take(FINGERPRINT_BYTES)
+        .read_to_end(&mut head)
+        .ok()?;
+    let mut h = blake3::Hasher::new();
+    h.update(&size.to_le_bytes());
+    h.update(&head);
+    Some(h.finalize().to_hex()[..32].to_string())
+}
 
-                    if !allowed_ext(&cp) {
-                        continue;
-                    }
-                    let ext = cp
-                        .extension()
-                        .and_then(|s| s.to_str())
-                        .map(|s| s.to_lowercase())
-                        .unwrap_or_else(|| "".to_string());
-
-                    let md = match fs::metadata(&cp) {
-                        Ok(m) => m,
-                        Err(_) => continue,
-                    };
-                    let name = cp
-                        .file_stem()
-                        .and_then(|s| s.to_str())
-                        .unwrap_or("")
-                        .to_string();
-                    let (kind, ext_out) = detect_kind_ext(&ext);
-                    let path_s = cp.to_string_lossy().to_string();
-                    let id = stable_id(&path_s);
-                    let mtime = mtime_ms(&md);
-                    let size = md.len() as i64;
-                    let title = if kind == "rpad" {
-                        read_rpad_title(&cp)
-                    } else {
-                        None
-                    };
-
-                    items.push(ProjectDto {
-                        id,
-                        kind: kind.into(),
-                        name,
-                        path: path_s,
-                        ext: ext_out,
-                        title,
-                        last_modified_ms: mtime,
-                        size,
-                        parent_physical_folder: Some(p.to_string_lossy().to_string()),
-                    });
-                }
-            } // unreadable subdir → skip it
-            physical_folders.push((
-                PhysicalFolderScanDto {
-                    path: p.to_string_lossy().to_string(),
-                    name,
-                },
-                items,
-            ));
-        } else if p.is_file() {
-            if !allowed_ext(&p) {
-                continue;
-            }
-            let ext = p
-                .extension()
-                .and_then(|s| s.to_str())
-                .map(|s| s.to_lowercase())
-                .unwrap_or_else(|| "".to_string());
-
-            let md = match fs::metadata(&p) {
-                Ok(m) => m,
-                Err(_) => continue,
-            };
-            let name_s = p
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or("")
-                .to_string();
-            let (kind, ext_out) = detect_kind_ext(&ext);
-            let path_s = p.to_string_lossy().to_string();
-            let id = stable_id(&path_s);
-            let mtime = mtime_ms(&md);
-            let size = md.len() as i64;
-            let title = if kind == "rpad" {
-                read_rpad_title(&p)
-            } else {
-                None
-            };
-
-            root_projects.push(ProjectDto {
-                id,
-                kind: kind.into(),
-                name: name_s,
-                path: path_s,
-                ext: ext_out,
-                title,
-                last_modified_ms: mtime,
-                size,
-                parent_physical_folder: None,
-            });
-        }
+/// Build the project entry for a file, or `None` if it is not listed in the workspace.
+pub(crate) fn project_from_file(
+    p: &Path,
+    parent: Option<String>,
+    cache: &ScanCache,
+) -> Option<ProjectDto> {
+    if !allowed_ext(p) {
+        return None;
     }
+    let md = fs::metadata(p).ok()?;
+    let ext = p
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_lowercase())
+        .unwrap_or_default();
+    let (kind, ext_out) = detect_kind_ext(&ext);
+    let path_s = p.to_string_lossy().to_string();
+    let (mtime, size) = (mtime_ms(&md), md.len() as i64);
+    let cached = cache.get(&path_s);
+    let (title, text_format, fingerprint) = match cached {
+        Some(c) if c.last_modified_ms == mtime && c.size == size => (
+            c.title.clone(),
+            c.text_format.clone(),
+            c.fingerprint.clone().or_else(|| fingerprint(p, md.len())),
+        ),
+        _ if kind == "rpad" => (rpad::read_title(p), None, fingerprint(p, md.len())),
+        _ if kind == "txt" => (None, textfile::detect_file(p), fingerprint(p, md.len())),
+        _ => (None, None, fingerprint(p, md.len())),
+    };
+    // New files get a provisional id; the index settles it when the file is written
+    let id = match cached {
+        Some(c) => c.id.clone(),
+        None if kind == "rpad" => rpad::read_id(p).unwrap_or_else(|| stable_id(&path_s)),
+        None => stable_id(&path_s),
+    };
+    Some(ProjectDto {
+        id,
+        kind: kind.into(),
+        name: p
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+            .to_string(),
+        path: path_s,
+        ext: ext_out,
+        title,
+        last_modified_ms: mtime,
+        size,
+        parent_physical_folder: parent,
+        text_format,
+        file_key: file_key(&md),
+        fingerprint,
+    })
+}
 
-    Ok(ScanResultDto {
-        root_projects,
-        physical_folders,
+#[tauri::command]
+pub async fn scan_workspace(
+    app: AppHandle,
+    root: String,
+    options: Option<ScanOptions>,
+) -> Result<ScanResultDto, RosePadError> {
+    during("scan_workspace", async move {
+        let root_path = PathBuf::from(&root);
+        if !root_path.is_dir() {
+            return Err(RosePadError::not_a_directory(&root_path));
+        }
+        fs::read_dir(&root_path).map_err(|e| RosePadError::io(e, &root_path))?;
+        paths::workspace_root(&app, &root)?;
+
+        let rules = ScanRules::new(&root_path, &options.unwrap_or_default())?;
+        let cache = index::with_index(&app, |conn| index::load_scan_cache(conn, &root))?;
+        let progress = |files: usize, folders: usize| {
+            let _ = app.emit(
+                "scan:progress",
+                ScanProgressDto {
+                    root: root.clone(),
+                    files,
+                    folders,
+                    done: false,
+                },
+            );
+        };
+        let (root_projects, physical_folders) = rules.scan_dir(&root_path, 0, &cache, &progress);
+        let mut result = ScanResultDto {
+            root_projects,
+            physical_folders,
+        };
+        index::with_index(&app, |conn| index::apply_scan(conn, &root, &mut result))?;
+
+        let upserts = result.all_projects();
+        let _ = app.emit(
+            "scan:progress",
+            ScanProgressDto {
+                root: root.clone(),
+                files: upserts.len(),
+                folders: result.all_folders().len(),
+                done: true,
+            },
+        );
+        search::schedule_update(
+            &app,
+            IndexUpdate {
+                upserts,
+                prune_root: Some(root.clone()),
+                ..IndexUpdate::default()
+            },
+        );
+        Ok(result)
     })
+    .await
 }
 
 #[tauri::command]
 pub async fn rename_project(
-    _app: AppHandle,
+    app: AppHandle,
     workspace_root: String,
     old_path: String,
     new_name: String,
-) -> Result<String, String> {
-    let p = PathBuf::from(&old_path);
-    let _ = ensure_inside_root(&workspace_root, &p)?;
-    if !p.is_file() {
-        return Err("not a file".into());
-    }
-    let ext = p
-        .extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("")
-        .to_ascii_lowercase();
-    // For .rpad, prefer updating the manifest title and keep the file name as-is (allows duplicate display names)
-    if ext == "rpad" {
-        // Preserve HTML content; fail fast if we cannot read
-        let html = read_rpad_data(old_path.clone()).await?;
-        // Overwrite the archive with the same path, updating title and keeping attachments
-        write_rpad_html(old_path.clone(), html, Some(new_name.clone())).await?;
-        return Ok(p.to_string_lossy().to_string());
-    }
+) -> Result<String, RosePadError> {
+    during("rename_project", async move {
+        paths::workspace_root(&app, &workspace_root)?;
+        let p = PathBuf::from(&old_path);
+        let _ = paths::inside_root(&workspace_root, &p)?;
+        if !p.is_file() {
+            return Err(RosePadError::not_a_file(&p));
+        }
+        let ext = p
+            .extension()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        // For .rpad, prefer updating the manifest title and keep the file name as-is (allows duplicate display names)
+        if ext == "rpad" {
+            // Preserve HTML content; fail fast if we cannot read
+            let archive = RpadArchive::open(&p)?;
+            let old_title = archive.manifest.title;
+            // Overwrite the archive with the same path, updating title and keeping attachments
+            save_rpad_html(
+                &app,
+                &old_path,
+                archive.document.html,
+                Some(new_name.clone()),
+            )?;
+            journal::record(
+                &workspace_root,
+                WorkspaceOp::Retitle {
+                    path: old_path,
+                    from: old_title,
+                    to: new_name,
+                },
+            )
+            .await;
+            return Ok(p.to_string_lossy().to_string());
+        }
 
-    // For non-rpad files, perform a physical rename but ensure uniqueness
-    let parent = p.parent().ok_or_else(|| "no parent".to_string())?;
-    let target = if ext.is_empty() {
-        parent.join(&new_name)
-    } else {
-        parent.join(format!("{}.{}", new_name, ext))
-    };
-    let dest = unique_dest(target);
-    fs::rename(&p, &dest).map_err(|e| e.to_string())?;
-    Ok(dest.to_string_lossy().to_string())
+        // For non-rpad files, perform a physical rename but ensure uniqueness
+        let new_name = paths::sanitize_name(&new_name)?;
+        let parent = p
+            .parent()
+            .ok_or_else(|| RosePadError::invalid_path(&p, "has no parent folder"))?;
+        let target = if ext.is_empty() {
+            parent.join(&new_name)
+        } else {
+            parent.join(format!("{}.{}", new_name, ext))
+        };
+        let dest = unique_dest(target);
+        fs::rename(&p, &dest).map_err(|e| RosePadError::io(e, &p))?;
+        let dest = dest.to_string_lossy().to_string();
+        let _ = index::move_project(&app, &old_path, &dest);
+        journal::record(
+            &workspace_root,
+            WorkspaceOp::Move {
+                from: old_path,
+                to: dest.clone(),
+            },
+        )
+        .await;
+        Ok(dest)
+    })
+    .await
 }
 
+/// Move a project to the workspace trash; see `trash::restore_trash_item`.
 #[tauri::command]
-pub async fn delete_project(_app: AppHandle, workspace_root: String, path: String) -> Result<(), String> {
-    let p = PathBuf::from(&path);
-    let _ = ensure_inside_root(&workspace_root, &p)?;
-    fs::remove_file(&p).map_err(|e| e.to_string())
+pub async fn delete_project(
+    app: AppHandle,
+    workspace_root: String,
+    path: String,
+) -> Result<TrashEntryDto, RosePadError> {
+    during("delete_project", async move {
+        paths::workspace_root(&app, &workspace_root)?;
+        let p = PathBuf::from(&path);
+        let _ = paths::inside_root(&workspace_root, &p)?;
+        if !p.is_file() {
+            return Err(RosePadError::not_a_file(&p));
+        }
+        let entry = trash::move_to_trash(Path::new(&workspace_root), &p)?;
+        journal::record(
+            &workspace_root,
+            WorkspaceOp::Trash {
+                root: workspace_root.clone(),
+                path,
+                trash_id: entry.id.clone(),
+            },
+        )
+        .await;
+        Ok(entry)
+    })
+    .await
 }
 
 #[tauri::command]
 pub async fn move_project(
-    _app: AppHandle,
+    app: AppHandle,
     workspace_root: String,
     old_path: String,
     dest_dir: String,
-) -> Result<String, String> {
-    let src = PathBuf::from(&old_path);
-    let _ = ensure_inside_root(&workspace_root, &src)?;
-    if !src.is_file() {
-        return Err("not a file".into());
-    }
-    let dest = PathBuf::from(&dest_dir);
-    let dest_checked = ensure_inside_root(&workspace_root, &dest)?;
-    if !dest.is_dir() {
-        return Err("destination is not a directory".into());
-    }
-    let file_name = src
-        .file_name()
-        .ok_or_else(|| "invalid source".to_string())?;
-    let candidate = dest_checked.join(file_name);
-    let new_path = unique_dest(candidate);
-    fs::rename(&src, &new_path).map_err(|e| e.to_string())?;
-    Ok(new_path.to_string_lossy().to_string())
+) -> Result<String, RosePadError> {
+    during("move_project", async move {
+        paths::workspace_root(&app, &workspace_root)?;
+        let src = PathBuf::from(&old_path);
+        let _ = paths::inside_root(&workspace_root, &src)?;
+        if !src.is_file() {
+            return Err(RosePadError::not_a_file(&src));
+        }
+        let dest = PathBuf::from(&dest_dir);
+        let dest_checked = paths::inside_root(&workspace_root, &dest)?;
+        if !dest.is_dir() {
+            return Err(RosePadError::not_a_directory(&dest));
+        }
+        let file_name = src
+            .file_name()
+            .ok_or_else(|| RosePadError::not_a_file(&src))?;
+        let candidate = dest_checked.join(file_name);
+        let new_path = unique_dest(candidate);
+        fs::rename(&src, &new_path).map_err(|e| RosePadError::io(e, &src))?;
+        let new_path = new_path.to_string_lossy().to_string();
+        let _ = index::move_project(&app, &old_path, &new_path);
+        journal::record(
+            &workspace_root,
+            WorkspaceOp::Move {
+                from: old_path,
+                to: new_path.clone(),
+            },
+        )
+        .await;
+        Ok(new_path)
+    })
+    .await
 }
 
 #[tauri::command]
 pub async fn rename_physical_folder(
-    _app: AppHandle,
+    app: AppHandle,
     workspace_root: String,
     path: String,
     new_name: String,
-) -> Result<String, String> {
-    let p = PathBuf::from(&path);
-    let _ = ensure_inside_root(&workspace_root, &p)?;
-    if !p.is_dir() {
-        return Err("not a directory".into());
-    }
-    let parent = p.parent().ok_or_else(|| "no parent".to_string())?;
-    let new_path = parent.join(&new_name);
-    fs::rename(&p, &new_path).map_err(|e| e.to_string())?;
-    Ok(new_path.to_string_lossy().to_string())
+) -> Result<String, RosePadError> {
+    during("rename_physical_folder", async move {
+        paths::workspace_root(&app, &workspace_root)?;
+        let p = PathBuf::from(&path);
+        let _ = paths::inside_root(&workspace_root, &p)?;
+        if !p.is_dir() {
+            return Err(RosePadError::not_a_directory(&p));
+        }
+        let parent = p
+            .parent()
+            .ok_or_else(|| RosePadError::invalid_path(&p, "has no parent folder"))?;
+        let new_path = parent.join(paths::sanitize_name(&new_name)?);
+        fs::rename(&p, &new_path).map_err(|e| RosePadError::io(e, &p))?;
+        let new_path = new_path.to_string_lossy().to_string();
+        // Carry the folder's row (and its color) over to the new path
+        let _ = index::rename_folder(&app, &path, &new_path);
+        journal::record(
+            &workspace_root,
+            WorkspaceOp::Move {
+                from: path,
+                to: new_path.clone(),
+            },
+        )
+        .await;
+        Ok(new_path)
+    })
+    .await
 }
 
+/// Move a folder and everything in it to the workspace trash.
 #[tauri::command]
-pub async fn delete_physical_folder(_app: AppHandle, workspace_root: String, path: String) -> Result<(), String> {
-    let p = PathBuf::from(&path);
-    let _ = ensure_inside_root(&workspace_root, &p)?;
-    if !p.is_dir() {
-        return Err("not a directory".into());
-    }
-    fs::remove_dir_all(&p).map_err(|e| e.to_string())?;
-    Ok(())
+pub async fn delete_physical_folder(
+    app: AppHandle,
+    workspace_root: String,
+    path: String,
+) -> Result<TrashEntryDto, RosePadError> {
+    during("delete_physical_folder", async move {
+        paths::workspace_root(&app, &workspace_root)?;
+        let p = PathBuf::from(&path);
+        let _ = paths::inside_root(&workspace_root, &p)?;
+        if !p.is_dir() {
+            return Err(RosePadError::not_a_directory(&p));
+        }
+        let entry = trash::move_to_trash(Path::new(&workspace_root), &p)?;
+        journal::record(
+            &workspace_root,
+            WorkspaceOp::Trash {
+                root: workspace_root.clone(),
+                path,
+                trash_id: entry.id.clone(),
+            },
+        )
+        .await;
+        Ok(entry)
+    })
+    .await
 }
 
 #[tauri::command]
 pub async fn create_physical_folder(
-    _app: AppHandle,
+    app: AppHandle,
     root: String,
     name: String,
-) -> Result<String, String> {
-    let r = PathBuf::from(&root);
-    let root_checked = ensure_inside_root(&root, &r)?;
-    if !root_checked.is_dir() {
-        return Err("root is not a directory".into());
-    }
-    let new_path = root_checked.join(&name);
-    if new_path.exists() {
-        return Ok(new_path.to_string_lossy().to_string());
+) -> Result<String, RosePadError> {
+    during("create_physical_folder", async move {
+        let root_checked = paths::workspace_root(&app, &root)?;
+        if !root_checked.is_dir() {
+            return Err(RosePadError::not_a_directory(&root_checked));
+        }
+        let new_path = root_checked.join(paths::sanitize_name(&name)?);
+        if new_path.exists() {
+            return Ok(new_path.to_string_lossy().to_string());
+        }
+        fs::create_dir_all(&new_path).map_err(|e| RosePadError::io(e, &new_path))?;
+        let new_path = new_path.to_string_lossy().to_string();
+        journal::record(
+            &root,
+            WorkspaceOp::Create {
+                root: root.clone(),
+                path: new_path.clone(),
+                trash_id: None,
+            },
+        )
+        .await;
+        Ok(new_path)
+    })
+    .await
+}
+
+fn check_unlocked(p: &Path) -> Result<(), RosePadError> {
+    if rpad::read_encryption(p).is_some_and(|e| !encryption::is_unlocked(&e)) {
+        return Err(RosePadError::locked(p, rpad::read_title(p)));
     }
-    fs::create_dir_all(&new_path).map_err(|e| e.to_string())?;
-    Ok(new_path.to_string_lossy().to_string())
+    Ok(())
 }
 
 #[tauri::command]
-pub async fn watch_physical_folders(
-    app: tauri::AppHandle,
-    folders: Vec<String>,
-) -> Result<(), String> {
-    let handle = app.clone();
-
-    // Single global watcher instance; replace watched folders on subsequent calls
-    lazy_static! {
-        static ref WATCHER: Mutex<Option<(RecommendedWatcher, Vec<String>)>> = Mutex::new(None);
-    }
+pub async fn read_rpad_data(app: AppHandle, path: String) -> Result<String, RosePadError> {
+    during("read_rpad_data", async move {
+        paths::document(&app, &path)?;
+        let p = Path::new(&path);
+        check_unlocked(p)?;
+        let archive = RpadArchive::open(p)?;
+        Ok(attachments::for_editor(&path, &archive.document.html))
+    })
+    .await
+}
 
-    let mut guard = WATCHER
-        .lock()
-        .map_err(|_| "watcher lock poisoned".to_string())?;
-    if let Some((ref mut watcher, ref mut prev)) = *guard {
-        // Unwatch previous folders
-        for p in prev.drain(..) {
-            let _ = watcher.unwatch(Path::new(&p));
-        }
-        // Watch new folders
-        for f in &folders {
-            let _ = watcher.watch(Path::new(&f), RecursiveMode::Recursive);
-        }
-        *prev = folders;
-        return Ok(());
-    }
+/// What a document looked like when the UI loaded or last saved it. `hash` is
+/// the blake3 of its text (the HTML of an .rpad), as in the version history.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocVersionDto {
+    pub last_modified_ms: i64,
+    pub hash: String,
+}
 
-    // Create a new watcher
-    let mut watcher: RecommendedWatcher =
-        notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
-            if let Ok(event) = res {
-                let paths: Vec<String> = event
-                    .paths
-                    .iter()
-                    .map(|p| p.to_string_lossy().to_string())
-                    .collect();
-                let _ = handle.emit("fs:changed", paths);
-            }
-        })
-        .map_err(|e| format!("failed to create file watcher: {e}"))?;
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskVersionDto {
+    pub last_modified_ms: i64,
+    pub hash: String,
+    pub content: String,
+}
 
-    for f in &folders {
-        let _ = watcher.watch(Path::new(&f), RecursiveMode::Recursive);
-    }
+fn content_hash(content: &str) -> String {
+    blake3::hash(content.as_bytes()).to_hex().to_string()
+}
 
-    *guard = Some((watcher, folders));
-    Ok(())
+pub(crate) fn version_of(p: &Path, content: &str) -> Result<DocVersionDto, RosePadError> {
+    let md = fs::metadata(p).map_err(|e| RosePadError::io(e, p))?;
+    Ok(DocVersionDto {
+        last_modified_ms: mtime_ms(&md),
+        hash: content_hash(content),
+    })
 }
 
-#[tauri::command]
-pub async fn read_rpad_data(path: String) -> Result<String, String> {
-    let p = Path::new(&path);
-    let file = fs::File::open(p).map_err(|e| e.to_string())?;
-    let mut zip = ZipArchive::new(file).map_err(|e| e.to_string())?;
-
-    // Try common data entries
-    for candidate in [
-        "data.json",
-        "content.json",
-        "document.json",
-        "data/data.json",
-    ] {
-        if let Ok(mut f) = zip.by_name(candidate) {
-            let mut s = String::new();
-            f.read_to_string(&mut s).map_err(|e| e.to_string())?;
-            // If it's JSON with { html }, return that; else return raw
-            if let Ok(v) = serde_json::from_str::<serde_json::Value>(&s) {
-                if let Some(h) = v.get("html").and_then(|x| x.as_str()) {
-                    return Ok(h.to_string());
-                }
-            }
-            return Ok(s);
-        }
+// Refuse to save over changes made by another program. A matching hash wins over
+// a differing mtime so a mere touch is not a conflict. A missing file is not a
+// conflict either: saving just recreates it.
+fn check_expected(p: &Path, expected: Option<&DocVersionDto>) -> Result<(), RosePadError> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    if !p.exists() {
+        return Ok(());
     }
-    // Fallback: try a literal HTML file if present
-    if let Ok(mut f) = zip.by_name("content.html") {
-        let mut s = String::new();
-        f.read_to_string(&mut s).map_err(|e| e.to_string())?;
-        return Ok(s);
+    let content = diff::read_document(p)?;
+    let disk = version_of(p, &content)?;
+    let changed = if expected.hash.is_empty() {
+        disk.last_modified_ms != expected.last_modified_ms
+    } else {
+        disk.hash != expected.hash
+    };
+    if changed {
+        let content = if diff::is_rpad(p) {
+            attachments::for_editor(&p.to_string_lossy(), &content)
+        } else {
+            content
+        };
+        return Err(RosePadError::conflict(
+            p,
+            DiskVersionDto {
+                last_modified_ms: disk.last_modified_ms,
+                hash: disk.hash,
+                content,
+            },
+        ));
     }
-    Err("data not found in .rpad".into())
+    Ok(())
 }
 
 #[tauri::command]
-pub async fn write_rpad_html(
-    path: String,
+pub async fn document_version(app: AppHandle, path: String) -> Result<DocVersionDto, RosePadError> {
+    during("document_version", async move {
+        paths::document(&app, &path)?;
+        let p = Path::new(&path);
+        version_of(p, &diff::read_document(p)?)
+    })
+    .await
+}
+
+/// Size of a document in bytes, to decide whether the editor pages it in.
+#[tauri::command]
+pub async fn document_size(app: AppHandle, path: String) -> Result<u64, RosePadError> {
+    during("document_size", async move {
+        let resolved = paths::document(&app, &path)?;
+        let meta = fs::metadata(&resolved).map_err(|e| RosePadError::io(e, &resolved))?;
+        Ok(meta.len())
+    })
+    .await
+}
+
+/// Write the HTML (and optionally the title) of an .rpad, keeping its attachments.
+pub(crate) fn save_rpad_html(
+    app: &AppHandle,
+    path: &str,
     html: String,
     title: Option<String>,
-) -> Result<(), String> {
-    let p = Path::new(&path);
-    let parent = p.parent().ok_or_else(|| "invalid path".to_string())?;
-
-    let temp_path = {
-        let mut i = 0usize;
-        let mut candidate = parent.join(format!(
-            ".{}.tmp",
-            p.file_name()
-                .and_then(|s| s.to_str())
-                .unwrap_or("rosepad")
-        ));
-        while candidate.exists() {
-            i += 1;
-            candidate = parent.join(format!(
-                ".{}.tmp{}",
-                p.file_name()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or("rosepad"),
-                i
-            ));
-        }
-        candidate
+) -> Result<DocVersionDto, RosePadError> {
+    let p = Path::new(path);
+
+    // Load the existing archive (migrating if needed) so attachments and unknown entries survive
+    let mut archive = if p.exists() {
+        check_unlocked(p)?;
+        let file = fs::File::open(p).map_err(|e| RosePadError::io(e, p))?;
+        RpadArchive::from_reader(file)
+            .map_err(|e| format!("failed to read existing archive: {e}"))?
+    } else {
+        RpadArchive::new("Untitled")
     };
-
-    let mut preserved: Vec<(String, Vec<u8>, zip::CompressionMethod)> = Vec::new();
-    let mut existing_title: Option<String> = None;
-    let mut existing_version: Option<i64> = None;
-
-    if p.exists() {
-        let file = fs::File::open(p).map_err(|e| e.to_string())?;
-        let mut archive = ZipArchive::new(file).map_err(|e| format!("failed to read existing archive: {e}"))?;
-        for i in 0..archive.len() {
-            let mut entry = archive.by_index(i).map_err(|e| e.to_string())?;
-            let name = entry.name().to_string();
-            if name == "manifest.json" {
-                let mut buf = String::new();
-                let _ = entry.read_to_string(&mut buf);
-                if let Ok(v) = serde_json::from_str::<serde_json::Value>(&buf) {
-                    if existing_title.is_none() {
-                        existing_title = v.get("title").and_then(|x| x.as_str()).map(|s| s.to_string());
-                    }
-                    if existing_version.is_none() {
-                        existing_version = v.get("version").and_then(|x| x.as_i64());
-                    }
-                }
-                continue;
-            }
-            if name == "data.json" {
-                continue;
-            }
-            let mut buf = Vec::new();
-            entry
-                .read_to_end(&mut buf)
-                .map_err(|e| format!("failed to copy existing entry {name}: {e}"))?;
-            let method = entry.compression();
-            preserved.push((name, buf, method));
-        }
+    if let Some(t) = title {
+        archive.manifest.title = t;
     }
-
-    let chosen_title = title.or(existing_title).unwrap_or_else(|| "Untitled".to_string());
-    let version = existing_version.unwrap_or(1);
-
-    {
-        let file = fs::File::create(&temp_path).map_err(|e| e.to_string())?;
-        let mut zip = ZipWriter::new(file);
-
-        for (name, data, method) in preserved {
-            let opts = FileOptions::default().compression_method(method);
-            let entry_name = name;
-            zip.start_file(&entry_name, opts)
-                .map_err(|e| format!("failed to start preserved entry {entry_name}: {e}"))?;
-            zip.write_all(&data)
-                .map_err(|e| format!("failed to write preserved entry {entry_name}: {e}"))?;
-        }
-
-        let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
-
-        let manifest = serde_json::json!({
-          "title": chosen_title,
-          "version": version
-        });
-        zip.start_file("manifest.json", options)
-            .map_err(|e| e.to_string())?;
-        zip.write(manifest.to_string().as_bytes())
-            .map_err(|e| e.to_string())?;
-
-        let data = serde_json::json!({ "html": html });
-        zip.start_file("data.json", options)
-            .map_err(|e| e.to_string())?;
-        zip.write(data.to_string().as_bytes())
-            .map_err(|e| e.to_string())?;
-
-        zip.finish().map_err(|e| e.to_string())?;
+    // Carry the workspace id in the file so a rename outside the app keeps it
+    if let Some(id) = index::project_id(app, path) {
+        archive.manifest.id = Some(id);
+    } else if archive.manifest.id.is_none() {
+        archive.manifest.id = Some(stable_id(path));
     }
-
-    if let Err(e) = fs::rename(&temp_path, p) {
-        // Attempt replace if target exists
-        if p.exists() {
-            let _ = fs::remove_file(p);
-            fs::rename(&temp_path, p).map_err(|e2| format!("failed to replace file: {e2}"))?;
-        } else {
-            let _ = fs::remove_file(&temp_path);
-            return Err(e.to_string());
-        }
+    archive.document.html = attachments::to_stored(&html);
+    archive.save(p)?;
+    // History is best effort; a save never fails because of it. Encrypted
+    // documents get none, as it would keep their text in the clear
+    if archive.manifest.encryption.is_none() {
+        let _ = history::record(app, path, &archive.document.html);
     }
-    Ok(())
+    version_of(p, &archive.document.html)
+}
+
+/// Save an .rpad. With `expected`, fails with `RosePadError::Conflict` instead of
+/// overwriting changes made on disk since then.
+#[tauri::command]
+pub async fn write_rpad_html(
+    app: AppHandle,
+    path: String,
+    html: String,
+    title: Option<String>,
+    expected: Option<DocVersionDto>,
+) -> Result<DocVersionDto, RosePadError> {
+    during("write_rpad_html", async move {
+        paths::document(&app, &path)?;
+        check_expected(Path::new(&path), expected.as_ref())?;
+        let version = save_rpad_html(&app, &path, html, title)?;
+        let _ = drafts::discard(&app, &path);
+        Ok(version)
+    })
+    .await
 }
 
 fn allowed_ext(p: &Path) -> bool {
@@ -596,7 +754,7 @@ fn allowed_ext(p: &Path) -> bool {
     true
 }
 
-fn unique_dest(dest: PathBuf) -> PathBuf {
+pub(crate) fn unique_dest(dest: PathBuf) -> PathBuf {
     if !dest.exists() {
         return dest;
     }
@@ -670,244 +828,276 @@ fn files_equal(a: &Path, b: &Path) -> bool {
 
 #[tauri::command]
 pub async fn import_project(
+    app: AppHandle,
     root: String,
     src: String,
     copy: Option<bool>,
-) -> Result<String, String> {
-    let rootp = PathBuf::from(&root);
-    let srcp = PathBuf::from(&src);
-
-    if !rootp.is_dir() {
-        return Err("workspace root is not a directory".into());
-    }
-    if !srcp.is_file() {
-        return Err("selected import path is not a file".into());
-    }
-    if !allowed_ext(&srcp) {
-        return Err("unsupported file type".into());
-    }
+) -> Result<String, RosePadError> {
+    during("import_project", async move {
+        let rootp = PathBuf::from(&root);
+        let srcp = PathBuf::from(&src);
+        paths::workspace_root(&app, &root)?;
+        paths::document(&app, &src)?;
+
+        if !rootp.is_dir() {
+            return Err(RosePadError::not_a_directory(&rootp));
+        }
+        if !srcp.is_file() {
+            return Err(RosePadError::not_a_file(&srcp));
+        }
+        if !allowed_ext(&srcp) {
+            return Err(RosePadError::unsupported_type(&srcp));
+        }
 
-    // If already inside workspace, just return as-is.
-    if srcp.starts_with(&rootp) {
-        return Ok(srcp.to_string_lossy().to_string());
-    }
+        // If already inside workspace, just return as-is.
+        if paths::inside_root(&root, &srcp).is_ok() {
+            return Ok(srcp.to_string_lossy().to_string());
+        }
 
-    // Respect user preference: default to not copying external files; copy only when explicitly requested.
-    let should_copy = copy.unwrap_or(false);
-    if !should_copy {
-        return Ok(srcp.to_string_lossy().to_string());
-    }
+        // Respect user preference: default to not copying external files; copy only when explicitly requested.
+        // `document` above already required a grant from the Open dialog or the
+        // command line, so it may be edited where it is.
+        let should_copy = copy.unwrap_or(false);
+        if !should_copy {
+            return Ok(srcp.to_string_lossy().to_string());
+        }
 
-    let fname = srcp.file_name().ok_or("invalid filename")?;
-    let dest0 = rootp.join(fname);
-    let dest = if dest0.exists() {
-        if files_equal(&srcp, &dest0) {
-            dest0
+        let fname = srcp
+            .file_name()
+            .ok_or_else(|| RosePadError::not_a_file(&srcp))?;
+        let dest0 = rootp.join(fname);
+        let dest = if dest0.exists() {
+            if files_equal(&srcp, &dest0) {
+                dest0
+            } else {
+                unique_dest(dest0)
+            }
         } else {
-            unique_dest(dest0)
-        }
-    } else {
-        dest0
-    };
+            dest0
+        };
 
-    fs::copy(&srcp, &dest).map_err(|e| e.to_string())?;
-    Ok(dest.to_string_lossy().to_string())
+        let created = !dest.exists();
+        fs::copy(&srcp, &dest).map_err(|e| RosePadError::io(e, &srcp))?;
+        let dest = dest.to_string_lossy().to_string();
+        if created {
+            journal::record(
+                &root,
+                WorkspaceOp::Create {
+                    root: root.clone(),
+                    path: dest.clone(),
+                    trash_id: None,
+                },
+            )
+            .await;
+        }
+        Ok(dest)
+    })
+    .await
 }
 
 /// Create a new .rpad project with a unique file name and the given title
 #[tauri::command]
-pub async fn create_rpad_project(dest_dir: String, name: String) -> Result<String, String> {
-    let dest = PathBuf::from(&dest_dir);
-    if !dest.is_dir() {
-        return Err("destination is not a directory".into());
-    }
-    let base = dest.join(format!("{}.rpad", name));
-    let unique = unique_dest(base);
-    let path_s = unique.to_string_lossy().to_string();
-    // Write empty HTML with title; creates the archive file
-    write_rpad_html(path_s.clone(), String::new(), Some(name)).await?;
-    Ok(path_s)
+pub async fn create_rpad_project(
+    app: AppHandle,
+    dest_dir: String,
+    name: String,
+    workspace_root: Option<String>,
+) -> Result<String, RosePadError> {
+    during("create_rpad_project", async move {
+        match &workspace_root {
+            Some(root) => {
+                paths::workspace_root(&app, root)?;
+                paths::inside_root(root, Path::new(&dest_dir))?;
+            }
+            None => {
+                paths::document(&app, &dest_dir)?;
+            }
+        }
+        let dest = PathBuf::from(&dest_dir);
+        if !dest.is_dir() {
+            return Err(RosePadError::not_a_directory(&dest));
+        }
+        // The name stays the title as typed; only the file name is sanitized
+        let base = dest.join(format!("{}.rpad", paths::sanitize_name(&name)?));
+        let unique = unique_dest(base);
+        let path_s = unique.to_string_lossy().to_string();
+        // Write empty HTML with title; creates the archive file
+        save_rpad_html(&app, &path_s, String::new(), Some(name))?;
+        let root = workspace_root.unwrap_or(dest_dir);
+        journal::record(
+            &root,
+            WorkspaceOp::Create {
+                root: root.clone(),
+                path: path_s.clone(),
+                trash_id: None,
+            },
+        )
+        .await;
+        Ok(path_s)
+    })
+    .await
+}
+
+/// Write plain text durably (see `durable`) so a crash never leaves a truncated file.
+pub(crate) fn save_text(
+    app: &AppHandle,
+    path: &str,
+    contents: &str,
+) -> Result<DocVersionDto, RosePadError> {
+    let p = Path::new(path);
+    // Keeps the file's encoding, BOM and line endings
+    let contents = textfile::write_text(p, contents)?;
+    let _ = history::record(app, path, &contents);
+    version_of(p, &contents)
 }
 
-/// Atomically write plain text to disk to avoid truncated files on crash.
+/// Save a text file; `expected` works as in `write_rpad_html`.
 #[tauri::command]
-pub async fn write_text_atomic(path: String, contents: String) -> Result<(), String> {
-    let p = Path::new(&path);
-    let parent = p.parent().ok_or_else(|| "invalid path".to_string())?;
-    let mut tmp = parent.join("rosepad.txt.tmp");
-    let mut i = 0usize;
-    while tmp.exists() {
-        i += 1;
-        tmp = parent.join(format!("rosepad.txt.tmp{}", i));
-    }
-    {
-        let mut f = fs::File::create(&tmp).map_err(|e| e.to_string())?;
-        f.write_all(contents.as_bytes()).map_err(|e| e.to_string())?;
-        let _ = f.sync_all();
-    }
-    if let Err(e) = fs::rename(&tmp, p) {
-        if p.exists() {
-            let _ = fs::remove_file(p);
-            fs::rename(&tmp, p).map_err(|e2| e2.to_string())?;
-        } else {
-            let _ = fs::remove_file(&tmp);
-            return Err(e.to_string());
+pub async fn write_text_atomic(
+    app: AppHandle,
+    path: String,
+    contents: String,
+    expected: Option<DocVersionDto>,
+) -> Result<DocVersionDto, RosePadError> {
+    during("write_text_atomic", async move {
+        paths::document(&app, &path)?;
+        check_expected(Path::new(&path), expected.as_ref())?;
+        let version = save_text(&app, &path, &contents)?;
+        let _ = drafts::discard(&app, &path);
+        Ok(version)
+    })
+    .await
+}
+
+// A change deep in the tree may be the first sign of its parent folders
+fn push_ancestors(
+    root: &Path,
+    dir: &Path,
+    seen: &mut HashSet<String>,
+    out: &mut Vec<PhysicalFolderScanDto>,
+) {
+    let chain: Vec<&Path> = dir
+        .ancestors()
+        .take_while(|d| *d != root && d.starts_with(root))
+        .collect();
+    for d in chain.into_iter().rev() {
+        let path = d.to_string_lossy().to_string();
+        if seen.insert(path.clone()) {
+            out.push(PhysicalFolderScanDto {
+                path,
+                name: d
+                    .file_name()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                parent_path: d
+                    .parent()
+                    .filter(|pp| *pp != root)
+                    .map(|pp| pp.to_string_lossy().to_string()),
+            });
         }
     }
-    Ok(())
 }
 
 // Analyze a set of changed paths and produce targeted upserts/deletes.
 #[tauri::command]
-pub async fn analyze_paths(root: String, paths: Vec<String>) -> Result<AnalyzeResultDto, String> {
-    let rootp = PathBuf::from(&root);
-    let mut projects: Vec<ProjectDto> = Vec::new();
-    let mut delete_project_paths: Vec<String> = Vec::new();
-    let mut physical_folders: Vec<PhysicalFolderScanDto> = Vec::new();
-    let mut delete_physical_folders: Vec<String> = Vec::new();
-
-    for raw in paths {
-        let p = PathBuf::from(&raw);
-        // If path is inside the workspace root
-        let inside = p.starts_with(&rootp) || p == rootp;
-        if !inside {
-            continue;
-        }
-
-        if p.is_file() {
-            // File changed: upsert if allowed and exists; otherwise mark deletion for known extensions
-            if allowed_ext(&p) {
-                if let Ok(md) = fs::metadata(&p) {
-                    let ext = p
-                        .extension()
-                        .and_then(|s| s.to_str())
-                        .unwrap_or("")
-                        .to_ascii_lowercase();
-                    let (kind, ext_out) = detect_kind_ext(&ext);
-                    let name = p
-                        .file_stem()
-                        .and_then(|s| s.to_str())
-                        .unwrap_or("")
-                        .to_string();
-                    let path_s = p.to_string_lossy().to_string();
-                    let id = stable_id(&path_s);
-                    let mtime = mtime_ms(&md);
-                    let size = md.len() as i64;
-                    let title = if kind == "rpad" {
-                        read_rpad_title(&p)
-                    } else {
-                        None
-                    };
-
-                    // Determine parent_physical_folder only for direct children of a top-level folder under root
-                    let parent = p.parent().map(|x| x.to_path_buf());
-                    let parent_physical = match parent {
-                        Some(pp) if pp != rootp => {
-                            // if pp is an immediate child of root
-                            match pp.parent() {
-                                Some(grand) if grand == rootp => {
-                                    Some(pp.to_string_lossy().to_string())
-                                }
-                                _ => None,
-                            }
-                        }
-                        _ => None,
-                    };
-
-                    projects.push(ProjectDto {
-                        id,
-                        kind: kind.into(),
-                        name,
-                        path: path_s,
-                        ext: ext_out,
-                        title,
-                        last_modified_ms: mtime,
-                        size,
-                        parent_physical_folder: parent_physical,
-                    });
-                } else {
-                    // File no longer exists
-                    delete_project_paths.push(p.to_string_lossy().to_string());
-                }
+pub async fn analyze_paths(
+    app: AppHandle,
+    root: String,
+    paths: Vec<String>,
+    options: Option<ScanOptions>,
+) -> Result<AnalyzeResultDto, RosePadError> {
+    during("analyze_paths", async move {
+        paths::workspace_root(&app, &root)?;
+        let rootp = PathBuf::from(&root);
+        let rules = ScanRules::new(&rootp, &options.unwrap_or_default())?;
+        let mut projects: Vec<ProjectDto> = Vec::new();
+        let mut delete_project_paths: Vec<String> = Vec::new();
+        let mut physical_folders: Vec<PhysicalFolderScanDto> = Vec::new();
+        let mut delete_physical_folders: Vec<String> = Vec::new();
+        let mut seen_folders: HashSet<String> = HashSet::new();
+
+        for raw in paths {
+            let p = PathBuf::from(&raw);
+            if !p.starts_with(&rootp) || p == rootp {
+                continue;
             }
-        } else if p.is_dir() {
-            // If this is the workspace root, skip; if this is a direct child of root, treat as a physical folder
-            if p == rootp {
+            // Lexically below the root is not enough: `..` or a link can lead out
+            if paths::inside_root(&root, &p).is_err() {
                 continue;
             }
-            let is_top_level = matches!(p.parent(), Some(pp) if pp == rootp);
-            if is_top_level {
-                if p.exists() {
-                    let name = p
-                        .file_name()
-                        .and_then(|s| s.to_str())
-                        .unwrap_or("")
-                        .to_string();
-                    physical_folders.push(PhysicalFolderScanDto {
-                        path: p.to_string_lossy().to_string(),
-                        name,
-                    });
-                    // Shallow rescan of files inside this folder
-                    if let Ok(children) = fs::read_dir(&p) {
-                        for child in children.flatten() {
-                            let cp = child.path();
-                            if !cp.is_file() {
-                                continue;
-                            }
-                            if !allowed_ext(&cp) {
-                                continue;
-                            }
-                            if let Ok(md) = fs::metadata(&cp) {
-                                let ext = cp
-                                    .extension()
-                                    .and_then(|s| s.to_str())
-                                    .unwrap_or("")
-                                    .to_ascii_lowercase();
-                                let (kind, ext_out) = detect_kind_ext(&ext);
-                                let name = cp
-                                    .file_stem()
-                                    .and_then(|s| s.to_str())
-                                    .unwrap_or("")
-                                    .to_string();
-                                let path_s = cp.to_string_lossy().to_string();
-                                let id = stable_id(&path_s);
-                                let mtime = mtime_ms(&md);
-                                let size = md.len() as i64;
-                                let title = if kind == "rpad" {
-                                    read_rpad_title(&cp)
-                                } else {
-                                    None
-                                };
-                                projects.push(ProjectDto {
-                                    id,
-                                    kind: kind.into(),
-                                    name,
-                                    path: path_s,
-                                    ext: ext_out,
-                                    title,
-                                    last_modified_ms: mtime,
-                                    size,
-                                    parent_physical_folder: Some(p.to_string_lossy().to_string()),
-                                });
-                            }
+
+            if p.is_file() {
+                // Files outside the scanned part of the tree are left alone
+                if !rules.includes(&p, false) {
+                    continue;
+                }
+                let parent = p
+                    .parent()
+                    .filter(|pp| *pp != rootp)
+                    .map(|pp| pp.to_string_lossy().to_string());
+                match project_from_file(&p, parent, &ScanCache::new()) {
+                    Some(project) => {
+                        if let Some(dir) = p.parent() {
+                            push_ancestors(&rootp, dir, &mut seen_folders, &mut physical_folders);
                         }
+                        projects.push(project);
                     }
-                } else {
-                    // Folder removed
-                    delete_physical_folders.push(p.to_string_lossy().to_string());
+                    None => delete_project_paths.push(p.to_string_lossy().to_string()),
                 }
+            } else if p.is_dir() {
+                if !rules.includes(&p, true) {
+                    continue;
+                }
+                push_ancestors(&rootp, &p, &mut seen_folders, &mut physical_folders);
+                // Rescan the folder's whole subtree
+                let depth = scan::depth_of(&rootp, &p);
+                let cache = index::with_index(&app, |conn| index::load_scan_cache(conn, &raw))?;
+                let node = rules.scan_folder(&p, depth, &cache);
+                for f in node.flatten() {
+                    if seen_folders.insert(f.path.clone()) {
+                        physical_folders.push(f.summary());
+                    }
+                    projects.extend(f.projects.iter().cloned());
+                }
+            } else {
+                // Gone: it was either a file or a folder, so clean up both
+                let s = p.to_string_lossy().to_string();
+                delete_project_paths.push(s.clone());
+                delete_physical_folders.push(s);
             }
-        } else {
-            // Path missing; try basic cleanup
-            let s = p.to_string_lossy().to_string();
-            delete_project_paths.push(s.clone());
         }
-    }
 
-    Ok(AnalyzeResultDto {
-        projects,
-        delete_project_paths,
-        physical_folders,
-        delete_physical_folders,
+        let mut result = AnalyzeResultDto {
+            projects,
+            delete_project_paths,
+            physical_folders,
+            delete_physical_folders,
+            moved_projects: Vec::new(),
+            moved_folders: Vec::new(),
+        };
+        index::with_index(&app, |conn| index::apply_analyze(conn, &mut result))?;
+
+        // Search content is keyed by path, so moved entries are dropped and reindexed
+        let moved_from =
+            |moves: &[MovedPathDto]| moves.iter().map(|m| m.from.clone()).collect::<Vec<_>>();
+        search::schedule_update(
+            &app,
+            IndexUpdate {
+                upserts: result.projects.clone(),
+                delete_paths: [
+                    result.delete_project_paths.clone(),
+                    moved_from(&result.moved_projects),
+                ]
+                .concat(),
+                delete_prefixes: [
+                    result.delete_physical_folders.clone(),
+                    moved_from(&result.moved_folders),
+                ]
+                .concat(),
+                prune_root: None,
+            },
+        );
+        Ok(result)
     })
+    .await
 }
@@ -0,0 +1,510 @@
+// The workspace registry: named roots kept in workspaces.json next to the
+// index. Each workspace has its own slice of the index (every row is keyed by
+// a path under its root) and its own watcher, so switching between them only
+// reads what is already indexed. Roots may not contain one another, which
+// keeps the slices apart.
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::error::{during, RosePadError};
+use crate::journal::{self, WorkspaceOp};
+use crate::search::{self, IndexUpdate, SearchHitDto};
+use crate::workspace::{self, stable_id, unique_dest};
+use crate::{durable, index, paths, watcher};
+
+const REGISTRY_FILE: &str = "workspaces.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceDto {
+    pub id: String,
+    pub name: String,
+    pub root: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceRegistryDto {
+    /// Id of the workspace the UI shows.
+    pub active: Option<String>,
+    pub workspaces: Vec<WorkspaceDto>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceSearchHitDto {
+    pub workspace_id: String,
+    #[serde(flatten)]
+    pub hit: SearchHitDto,
+}
+
+lazy_static! {
+    // Loaded from disk on first use
+    static ref REGISTRY: Mutex<Option<WorkspaceRegistryDto>> = Mutex::new(None);
+}
+
+fn registry_path(app: &AppHandle) -> Result<PathBuf, RosePadError> {
+    let dir = app
+        .path()
+        .app_config_dir()
+        .map_err(|e| RosePadError::failed(format!("The settings folder is unavailable: {e}")))?;
+    Ok(dir.join(REGISTRY_FILE))
+}
+
+// A missing file is an empty registry. One that cannot be read fails every
+// workspace command instead, so the next change does not overwrite it
+fn read_registry(path: &Path) -> Result<WorkspaceRegistryDto, RosePadError> {
+    let raw = match fs::read(path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Default::default()),
+        Err(e) => return Err(RosePadError::io(e, path)),
+    };
+    serde_json::from_slice(&raw).map_err(|e| {
+        RosePadError::failed(format!(
+            "The workspace list in {} is damaged ({e}); fix or remove the file",
+            path.display()
+        ))
+    })
+}
+
+fn load(app: &AppHandle) -> Result<WorkspaceRegistryDto, RosePadError> {
+    read_registry(&registry_path(app)?)
+}
+
+/// Run `f` against the registry. When `f` succeeds the (possibly changed)
+/// registry is written back and sent to every window as `workspaces:changed`.
+fn with_registry<T>(
+    app: &AppHandle,
+    f: impl FnOnce(&mut WorkspaceRegistryDto) -> Result<T, RosePadError>,
+) -> Result<T, RosePadError> {
+    let mut guard = match REGISTRY.lock() {
+        Ok(g) => g,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut registry = match guard.as_ref() {
+        Some(r) => r.clone(),
+        None => load(app)?,
+    };
+    let before = serde_json::to_vec(&registry).map_err(|e| e.to_string())?;
+    let out = f(&mut registry)?;
+    let after = serde_json::to_vec(&registry).map_err(|e| e.to_string())?;
+    if after != before {
+        let path = registry_path(app)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| RosePadError::io(e, dir))?;
+        }
+        durable::write_atomic(&path, &after)?;
+        let _ = app.emit("workspaces:changed", &registry);
+    }
+    *guard = Some(registry);
+    Ok(out)
+}
+
+fn find<'a>(
+    registry: &'a WorkspaceRegistryDto,
+    id: &str,
+) -> Result<&'a WorkspaceDto, RosePadError> {
+    registry
+        .workspaces
+        .iter()
+        .find(|w| w.id == id)
+        .ok_or_else(|| RosePadError::unknown_workspace(id))
+}
+
+/// Roots of all registered workspaces.
+pub(crate) fn roots(app: &AppHandle) -> Vec<String> {
+    with_registry(app, |r| {
+        Ok(r.workspaces.iter().map(|w| w.root.clone()).collect())
+    })
+    .unwrap_or_default()
+}
+
+/// Root of the workspace the UI shows, if any.
+pub(crate) fn active_root(app: &AppHandle) -> Option<String> {
+    with_registry(app, |r| {
+        Ok(r.active
+            .as_deref()
+            .and_then(|id| find(r, id).ok())
+            .map(|w| w.root.clone()))
+    })
+    .ok()
+    .flatten()
+}
+
+/// The registered workspace whose root contains `path`.
+pub(crate) fn workspace_of(app: &AppHandle, path: &Path) -> Option<WorkspaceDto> {
+    let path = path.canonicalize().ok()?;
+    with_registry(app, |r| {
+        Ok(r.workspaces
+            .iter()
+            .find(|w| {
+                Path::new(&w.root)
+                    .canonicalize()
+                    .is_ok_and(|root| path.starts_with(root))
+            })
+            .cloned())
+    })
+    .ok()
+    .flatten()
+}
+
+// Roots are stored as given, since index rows use the same spelling, but
+// compared canonically so a symlink cannot sneak in an overlap
+fn check_root(registry: &WorkspaceRegistryDto, root: &str) -> Result<(), RosePadError> {
+    let canon = Path::new(root)
+        .canonicalize()
+        .map_err(|e| RosePadError::io(e, Path::new(root)))?;
+    if !canon.is_dir() {
+        return Err(RosePadError::not_a_directory(Path::new(root)));
+    }
+    for w in &registry.workspaces {
+        let Ok(other) = Path::new(&w.root).canonicalize() else {
+            continue;
+        };
+        if canon.starts_with(&other) || other.starts_with(&canon) {
+            return Err(RosePadError::invalid_path(
+                Path::new(root),
+                &format!("overlaps the workspace \"{}\"", w.name),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_name(
+    registry: &WorkspaceRegistryDto,
+    name: &str,
+    id: Option<&str>,
+) -> Result<(), RosePadError> {
+    if name.is_empty() {
+        return Err(RosePadError::invalid_name(name));
+    }
+    let taken = registry
+        .workspaces
+        .iter()
+        .any(|w| Some(w.id.as_str()) != id && w.name.to_lowercase() == name.to_lowercase());
+    if taken {
+        return Err(RosePadError::name_taken(name));
+    }
+    Ok(())
+}
+
+// Rename, or copy and delete when the target is on another file system
+pub(crate) fn move_file(src: &Path, dest: &Path) -> Result<(), RosePadError> {
+    if fs::rename(src, dest).is_ok() {
+        return Ok(());
+    }
+    fs::copy(src, dest).map_err(|e| RosePadError::io(e, src))?;
+    if let Err(e) = fs::remove_file(src) {
+        let _ = fs::remove_file(dest);
+        return Err(RosePadError::io(e, src));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn list_workspaces(app: AppHandle) -> Result<WorkspaceRegistryDto, RosePadError> {
+    during("list_workspaces", async move {
+        with_registry(&app, |r| Ok(r.clone()))
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn active_workspace(app: AppHandle) -> Result<Option<WorkspaceDto>, RosePadError> {
+    during("active_workspace", async move {
+        with_registry(&app, |r| {
+            Ok(r.active.as_deref().and_then(|id| find(r, id).ok()).cloned())
+        })
+    })
+    .await
+}
+
+// Add `root` to the registry unless it is there already. A new root has to be
+// a folder the user picked (see `paths::grant_root`), or the webview could make
+// any folder a workspace and with it everything below reachable
+fn register(
+    registry: &mut WorkspaceRegistryDto,
+    root: String,
+    name: Option<String>,
+) -> Result<WorkspaceDto, RosePadError> {
+    if let Some(w) = registry.workspaces.iter().find(|w| w.root == root) {
+        return Ok(w.clone());
+    }
+    paths::picked_root(&root)?;
+    check_root(registry, &root)?;
+    let name = match name {
+        Some(n) => n.trim().to_string(),
+        None => Path::new(&root)
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| root.clone()),
+    };
+    check_name(registry, &name, None)?;
+    let workspace = WorkspaceDto {
+        id: stable_id(&root)[..16].to_string(),
+        name,
+        root,
+    };
+    if registry.active.is_none() {
+        registry.active = Some(workspace.id.clone());
+    }
+    registry.workspaces.push(workspace.clone());
+    Ok(workspace)
+}
+
+/// Register `root`, named after its folder unless a name is given. The first
+/// workspace becomes the active one. Adding a registered root returns it as is.
+#[tauri::command]
+pub async fn add_workspace(
+    app: AppHandle,
+    root: String,
+    name: Option<String>,
+) -> Result<WorkspaceDto, RosePadError> {
+    during("add_workspace", async move {
+        let root = root.trim_end_matches(['/', '\\']).to_string();
+        with_registry(&app, |r| register(r, root, name))
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn rename_workspace(
+    app: AppHandle,
+    id: String,
+    name: String,
+) -> Result<WorkspaceDto, RosePadError> {
+    during("rename_workspace", async move {
+        let name = name.trim().to_string();
+        with_registry(&app, |r| {
+            check_name(r, &name, Some(&id))?;
+            let w = r
+                .workspaces
+                .iter_mut()
+                .find(|w| w.id == id)
+                .ok_or_else(|| RosePadError::unknown_workspace(&id))?;
+            w.name = name;
+            Ok(w.clone())
+        })
+    })
+    .await
+}
+
+/// Forget a workspace: its watcher stops and its part of the index is
+/// dropped. Nothing on disk is touched.
+#[tauri::command]
+pub async fn remove_workspace(
+    app: AppHandle,
+    id: String,
+) -> Result<WorkspaceRegistryDto, RosePadError> {
+    during("remove_workspace", async move {
+        let removed = with_registry(&app, |r| {
+            let removed = find(r, &id)?.clone();
+            r.workspaces.retain(|w| w.id != id);
+            if r.active.as_deref() == Some(id.as_str()) {
+                r.active = r.workspaces.first().map(|w| w.id.clone());
+            }
+            Ok(removed)
+        })?;
+        watcher::stop(&removed.root);
+        index::forget_root(&app, &removed.root)?;
+        search::schedule_update(
+            &app,
+            IndexUpdate {
+                delete_prefixes: vec![removed.root],
+                ..IndexUpdate::default()
+            },
+        );
+        with_registry(&app, |r| Ok(r.clone()))
+    })
+    .await
+}
+
+/// Make `id` the active workspace. Its index is kept while it is in the
+/// background, so this does not rescan.
+#[tauri::command]
+pub async fn switch_workspace(app: AppHandle, id: String) -> Result<WorkspaceDto, RosePadError> {
+    during("switch_workspace", async move {
+        with_registry(&app, |r| {
+            let w = find(r, &id)?.clone();
+            r.active = Some(w.id.clone());
+            Ok(w)
+        })
+    })
+    .await
+}
+
+/// Full-text search across workspaces (all of them unless `workspaces` lists
+/// ids), best hits first.
+#[tauri::command]
+pub async fn search_workspaces(
+    app: AppHandle,
+    query: String,
+    workspaces: Option<Vec<String>>,
+    limit: Option<u32>,
+) -> Result<Vec<WorkspaceSearchHitDto>, RosePadError> {
+    during("search_workspaces", async move {
+        let limit = limit.unwrap_or(50).clamp(1, 500);
+        let targets: Vec<WorkspaceDto> = with_registry(&app, |r| {
+            Ok(r.workspaces
+                .iter()
+                .filter(|w| workspaces.as_ref().is_none_or(|ids| ids.contains(&w.id)))
+                .cloned()
+                .collect())
+        })?;
+        tauri::async_runtime::spawn_blocking(move || {
+            let mut hits = Vec::new();
+            for w in targets {
+                let found = index::with_index(&app, |conn| {
+                    search::run_search(conn, &query, Some(&w.root), limit)
+                })?;
+                hits.extend(found.into_iter().map(|hit| WorkspaceSearchHitDto {
+                    workspace_id: w.id.clone(),
+                    hit,
+                }));
+            }
+            hits.sort_by(|a, b| b.hit.score.total_cmp(&a.hit.score));
+            hits.truncate(limit as usize);
+            Ok::<_, RosePadError>(hits)
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    })
+    .await
+}
+
+/// Move a file into another workspace, into `dest_dir` or its root. The file
+/// keeps its id, tags and history. Returns the new path.
+#[tauri::command]
+pub async fn move_to_workspace(
+    app: AppHandle,
+    path: String,
+    workspace_id: String,
+    dest_dir: Option<String>,
+) -> Result<String, RosePadError> {
+    during("move_to_workspace", async move {
+        let src = PathBuf::from(&path);
+        if !src.is_file() {
+            return Err(RosePadError::not_a_file(&src));
+        }
+        let source =
+            workspace_of(&app, &src).ok_or_else(|| RosePadError::outside_workspace(&src))?;
+        let target = with_registry(&app, |r| find(r, &workspace_id).cloned())?;
+        let dest = PathBuf::from(dest_dir.unwrap_or_else(|| target.root.clone()));
+        let dest_checked = paths::inside_root(&target.root, &dest)?;
+        if !dest.is_dir() {
+            return Err(RosePadError::not_a_directory(&dest));
+        }
+        let file_name = src
+            .file_name()
+            .ok_or_else(|| RosePadError::not_a_file(&src))?;
+        // Index rows use the root as registered, not resolved (see `check_root`)
+        let root = Path::new(&target.root);
+        let resolved_root = root.canonicalize().map_err(|e| RosePadError::io(e, root))?;
+        let rel = dest_checked
+            .strip_prefix(&resolved_root)
+            .map_err(|_| RosePadError::outside_workspace(&dest))?;
+        let new_path = unique_dest(root.join(rel).join(file_name));
+        move_file(&src, &new_path)?;
+        let new_path = new_path.to_string_lossy().to_string();
+        index::move_project(&app, &path, &new_path)?;
+        // Both trees change; index them now rather than waiting for the watchers,
+        // which may not run for a workspace in the background
+        workspace::analyze_paths(app.clone(), source.root, vec![path.clone()], None).await?;
+        workspace::analyze_paths(
+            app.clone(),
+            target.root.clone(),
+            vec![new_path.clone()],
+            None,
+        )
+        .await?;
+        // Undone from the workspace the file ended up in
+        journal::record(
+            &target.root,
+            WorkspaceOp::Move {
+                from: path,
+                to: new_path.clone(),
+            },
+        )
+        .await;
+        Ok(new_path)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch;
+
+    #[test]
+    fn a_missing_registry_is_empty() {
+        let dir = scratch::dir("workspaces-missing");
+        let registry = read_registry(&dir.join(REGISTRY_FILE)).unwrap();
+        assert!(registry.workspaces.is_empty());
+        assert_eq!(registry.active, None);
+    }
+
+    #[test]
+    fn a_damaged_registry_is_an_error() {
+        let dir = scratch::dir("workspaces-damaged");
+        let path = dir.join(REGISTRY_FILE);
+        fs::write(&path, b"{\"workspaces\": [").unwrap();
+        let err = read_registry(&path).unwrap_err();
+        assert!(err.to_string().contains("damaged"));
+        assert_eq!(fs::read(&path).unwrap(), b"{\"workspaces\": [");
+    }
+
+    #[test]
+    fn the_registry_round_trips() {
+        let dir = scratch::dir("workspaces-round-trip");
+        let path = dir.join(REGISTRY_FILE);
+        let registry = WorkspaceRegistryDto {
+            active: Some("a".into()),
+            workspaces: vec![WorkspaceDto {
+                id: "a".into(),
+                name: "Notes".into(),
+                root: "/home/me/notes".into(),
+            }],
+        };
+        fs::write(&path, serde_json::to_vec(&registry).unwrap()).unwrap();
+        let read = read_registry(&path).unwrap();
+        assert_eq!(read.active, registry.active);
+        assert_eq!(read.workspaces, registry.workspaces);
+    }
+
+    #[test]
+    fn only_picked_folders_become_workspaces() {
+        let dir = scratch::dir("workspaces-picked");
+        fs::create_dir_all(dir.join("notes/inner")).unwrap();
+        let path = |p: &str| dir.join(p).to_string_lossy().to_string();
+        let mut registry = WorkspaceRegistryDto::default();
+        for root in [
+            "/".to_string(),
+            path(""),
+            path("notes"),
+            path("notes/inner/.."),
+        ] {
+            assert!(
+                register(&mut registry, root.clone(), None).is_err(),
+                "{root}"
+            );
+        }
+        assert!(registry.workspaces.is_empty());
+
+        paths::grant_root(&dir.join("notes")).unwrap();
+        let added = register(&mut registry, path("notes"), None).unwrap();
+        assert_eq!(added.name, "notes");
+        assert_eq!(registry.active, Some(added.id.clone()));
+        // The grant does not reach the folders inside it
+        assert!(register(&mut registry, path("notes/inner"), None).is_err());
+        assert_eq!(registry.workspaces, vec![added]);
+    }
+}
@@ -0,0 +1,220 @@
+import { useEffect, useRef, useState } from "react"
+import style from "../../styles/components/editor/largeFileViewer.module.css"
+import { closeLargeFile, openLargeFile, readLargeFileLines, saveLargeFileRegion, searchLargeFile, errorMessage, type LargeFile } from "../../core/db"
+import { useToast } from "../../core/toast"
+
+// Rows have a fixed height so the visible range follows straight from the scroll offset
+const LINE_HEIGHT = 20
+const PAGE_LINES = 500
+const OVERSCAN = 50
+
+type LargeFileViewerProps = {
+  path: string
+}
+
+function formatSize(bytes: number) {
+  if (bytes >= 1 << 30) return `${(bytes / (1 << 30)).toFixed(1)} GB`
+  return `${(bytes / (1 << 20)).toFixed(1)} MB`
+}
+
+export default function LargeFileViewer({ path }: LargeFileViewerProps) {
+  const pushToast = useToast()
+  const [file, setFile] = useState<LargeFile | null>(null)
+  const [error, setError] = useState<string | null>(null)
+  const [scrollTop, setScrollTop] = useState(0)
+  const [viewHeight, setViewHeight] = useState(0)
+  const [, setPagesLoaded] = useState(0)
+  const [query, setQuery] = useState("")
+  const [caseSensitive, setCaseSensitive] = useState(false)
+  const [found, setFound] = useState<{ line: number; column: number } | null>(null)
+  const [editing, setEditing] = useState<{ line: number; text: string } | null>(null)
+  const scrollRef = useRef<HTMLDivElement>(null)
+  // Loaded pages of lines, keyed by page number; cleared whenever the file is re-indexed
+  const pagesRef = useRef(new Map<number, string[]>())
+  const pendingRef = useRef(new Set<number>())
+
+  useEffect(() => {
+    let handle: number | null = null
+    let cancelled = false
+    pagesRef.current.clear()
+    pendingRef.current.clear()
+    setFile(null)
+    setError(null)
+    setFound(null)
+    setEditing(null)
+    openLargeFile(path)
+      .then((f) => {
+        if (cancelled) {
+          void closeLargeFile(f.handle)
+          return
+        }
+        handle = f.handle
+        setFile(f)
+      })
+      .catch((err) => {
+        if (!cancelled) setError(String(err))
+      })
+    return () => {
+      cancelled = true
+      if (handle !== null) void closeLargeFile(handle)
+    }
+  }, [path])
+
+  useEffect(() => {
+    const el = scrollRef.current
+    if (!el) return
+    const observer = new ResizeObserver(() => setViewHeight(el.clientHeight))
+    observer.observe(el)
+    setViewHeight(el.clientHeight)
+    return () => observer.disconnect()
+  }, [file])
+
+  const first = Math.max(0, Math.floor(scrollTop / LINE_HEIGHT) - OVERSCAN)
+  const last = file ? Math.min(file.lineCount, Math.ceil((scrollTop + viewHeight) / LINE_HEIGHT) + OVERSCAN) : 0
+
+  useEffect(() => {
+    if (!file || last <= first) return
+    const handle = file.handle
+    for (let page = Math.floor(first / PAGE_LINES); page * PAGE_LINES < last; page++) {
+      if (pagesRef.current.has(page) || pendingRef.current.has(page)) continue
+      pendingRef.current.add(page)
+      readLargeFileLines(handle, page * PAGE_LINES, PAGE_LINES)
+        .then((range) => {
+          pendingRef.current.delete(page)
+          pagesRef.current.set(page, range.lines)
+          setPagesLoaded((n) => n + 1)
+        })
+        .catch((err) => {
+          pendingRef.current.delete(page)
+          setError(String(err))
+        })
+    }
+  }, [file, first, last])
+
+  const lineText = (line: number) => pagesRef.current.get(Math.floor(line / PAGE_LINES))?.[line % PAGE_LINES]
+
+  const jumpTo = (line: number) => {
+    const el = scrollRef.current
+    if (!el) return
+    el.scrollTop = Math.max(0, line * LINE_HEIGHT - el.clientHeight / 3)
+  }
+
+  const findNext = async () => {
+    if (!file || !query) return
+    try {
+      const from = found ? found.line + 1 : 0
+      let res = await searchLargeFile(file.handle, query, { caseSensitive, from, maxMatches: 1 })
+      if (res.matches.length === 0 && from > 0) {
+        res = await searchLargeFile(file.handle, query, { caseSensitive, from: 0, maxMatches: 1 })
+      }
+      const match = res.matches[0]
+      if (!match) {
+        setFound(null)
+        pushToast({ message: "No matches", kind: "info" })
+        return
+      }
+      setFound({ line: match.line, column: match.column })
+      jumpTo(match.line)
+    } catch (err) {
+      pushToast({ message: `Search failed: ${errorMessage(err)}`, kind: "error" })
+    }
+  }
+
+  const commitEdit = async () => {
+    if (!file || !editing) return
+    const { line, text } = editing
+    if (text === lineText(line)) {
+      setEditing(null)
+      return
+    }
+    try {
+      const updated = await saveLargeFileRegion(file.handle, line, line + 1, text.split("\n"))
+      pagesRef.current.clear()
+      pendingRef.current.clear()
+      setEditing(null)
+      setFile(updated)
+      pushToast({ message: "Saved", kind: "success" })
+    } catch (err) {
+      pushToast({ message: `Save failed: ${errorMessage(err)}`, kind: "error" })
+    }
+  }
+
+  if (error) {
+    return <div className={style.viewer}><p className={style.status}>Could not open {path}: {error}</p></div>
+  }
+  if (!file) {
+    return <div className={style.viewer}><p className={style.status}>Indexing {path}…</p></div>
+  }
+
+  const rows = []
+  for (let line = first; line < last; line++) {
+    const text = lineText(line)
+    const isEditing = editing?.line === line
+    rows.push(
+      <div
+        key={line}
+        className={`${style.row} ${found?.line === line ? style.rowFound : ""}`}
+        style={{ top: line * LINE_HEIGHT, height: LINE_HEIGHT }}
+        onDoubleClick={() => text !== undefined && setEditing({ line, text })}
+      >
+        <span className={style.gutter}>{line + 1}</span>
+        {isEditing ? (
+          <input
+            className={style.lineInput}
+            value={editing.text}
+            autoFocus
+            onChange={(e) => setEditing({ line, text: e.target.value })}
+            onKeyDown={(e) => {
+              if (e.key === "Enter") void commitEdit()
+              if (e.key === "Escape") setEditing(null)
+            }}
+            onBlur={() => setEditing(null)}
+          />
+        ) : (
+          <span className={style.text}>{text ?? ""}</span>
+        )}
+      </div>
+    )
+  }
+
+  return (
+    <div className={style.viewer}>
+      <div className={style.toolbar}>
+        <span className={style.info} title={path}>
+          {file.lineCount.toLocaleString()} lines · {formatSize(file.size)} · {file.format.encoding}
+        </span>
+        <input
+          className={style.search}
+          placeholder="Find in file"
+          value={query}
+          onChange={(e) => {
+            setQuery(e.target.value)
+            setFound(null)
+          }}
+          onKeyDown={(e) => {
+            if (e.key === "Enter") void findNext()
+          }}
+        />
+        <label className={style.toggle}>
+          <input
+            type="checkbox"
+            checked={caseSensitive}
+            onChange={(e) => {
+              setCaseSensitive(e.target.checked)
+              setFound(null)
+            }}
+          />
+          Aa
+        </label>
+        <button className={style.button} type="button" onClick={() => void findNext()} disabled={!query}>
+          Next
+        </button>
+      </div>
+      <div className={style.scroll} ref={scrollRef} onScroll={(e) => setScrollTop(e.currentTarget.scrollTop)}>
+        <div className={style.spacer} style={{ height: file.lineCount * LINE_HEIGHT }}>
+          {rows}
+        </div>
+      </div>
+    </div>
+  )
+}
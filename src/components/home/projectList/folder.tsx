@@ -1,7 +1,7 @@
 import { useMemo, useState } from 'react'
 import ProjectCard from './project'
 import style from '../../../styles/components/home/projectList/folder.module.css'
-import { Project, setPhysicalFolderColor, renamePhysicalFolder, deletePhysicalFolder } from '../../../core/db';
+import { Project, setPhysicalFolderColor, renamePhysicalFolder, deletePhysicalFolder, errorMessage } from '../../../core/db';
 import { Menu } from '@tauri-apps/api/menu';
 import MultiModal from '../../modal'
 import ColorPalette from '../../colorPalette'
@@ -11,6 +11,8 @@ import { useToast } from '../../../core/toast'
 export function Folder({
   id,
   name,
+  depth = 0,
+  hasSubfolders = false,
   projectIds,
   projectMap,
   onChanged,
@@ -23,6 +25,8 @@ export function Folder({
 }: {
   id:string;
   name:string;
+  depth?: number;
+  hasSubfolders?: boolean;
   projectIds:string[];
   projectMap:Record<string,Project>;
   onChanged:()=>void;
@@ -64,7 +68,7 @@ export function Folder({
       pushToast({ message: `Renamed folder to ${newName}`, kind: "success" })
       onChanged()
     } catch (err) {
-      pushToast({ message: `Rename failed: ${err}`, kind: "error" })
+      pushToast({ message: `Rename failed: ${errorMessage(err)}`, kind: "error" })
     } finally {
       setIsRenameOpen(false)
     }
@@ -73,10 +77,10 @@ export function Folder({
   const handleDeletion = async () => {
     try {
       await deletePhysicalFolder(id)
-      pushToast({ message: `Deleted folder ${name}`, kind: "info" })
+      pushToast({ message: `Moved folder ${name} to the trash`, kind: "info" })
       onChanged()
     } catch (err) {
-      pushToast({ message: `Delete failed: ${err}`, kind: "error" })
+      pushToast({ message: `Delete failed: ${errorMessage(err)}`, kind: "error" })
     } finally {
       setIsDeleteOpen(false)
     }
@@ -106,7 +110,7 @@ export function Folder({
           <ColorPalette value={selectedColor} onChange={setSelectedColor} renderAs="panel" />
         </div>
       </MultiModal>
-      <div className={style.container} data-type="physical">
+      <div className={style.container} data-type="physical" style={depth > 0 ? { marginLeft: depth * 16 } : undefined}>
         <div className={style.head} style={(bg || titleColor) ? ({ background: bg, ['--project-title' as any]: titleColor } as any) : undefined}>
           <h3>{name}</h3>
           <div className={style.right}>
@@ -115,7 +119,7 @@ export function Folder({
                 <path stroke="currentColor" strokeLinecap="round" strokeWidth="4" d="M12 6h.01M12 12h.01M12 18h.01" />
               </svg>
             </button>
-            <button className={style.index} style={ids.length <= 0 ? { display: 'none' } : undefined} onClick={toggleList} aria-expanded={!collapsed}>
+            <button className={style.index} style={(ids.length <= 0 && !hasSubfolders) ? { display: 'none' } : undefined} onClick={toggleList} aria-expanded={!collapsed}>
               <svg style={collapsed ? undefined : { transform: 'rotate(-90deg)' }} aria-hidden="true" xmlns="http://www.w3.org/2000/svg" width="24" height="24" fill="none" viewBox="0 0 24 24">
                 <path stroke="currentColor" strokeLinecap="round" strokeLinejoin="round" strokeWidth="2" d="m15 19-7-7 7-7"/>
               </svg>
@@ -6,6 +6,8 @@ import ProjectCard, { formatProjectDisplayName } from "./project"
 import { Folder } from "./folder"
 import { useNavigate } from "react-router-dom"
 import { rpc_project } from "../../../core/discord_rpc"
+import { errorMessage, redoWorkspaceOp, undoWorkspaceOp } from "../../../core/db"
+import { useToast } from "../../../core/toast"
 
 type ListType = 'all' | 'folders' | 'projects'
 
@@ -62,7 +64,8 @@ export const ProjectList = () => {
   const selectionModeRef = useRef(false)
   const selectedPathsRef = useRef<string[]>([])
   const navigator = useNavigate()
-  const { tree, loading, reindex } = useWorkspace()
+  const { tree, loading, scanProgress, reindex } = useWorkspace()
+  const pushToast = useToast()
   useFsAutoReload()
   const projectsMap = useMemo(() => {
     const m: Record<string, any> = {}
@@ -92,6 +95,22 @@ export const ProjectList = () => {
   }, [tree, projectsMap])
 
   const folderKey = (id: string) => `physical:${id}`
+
+  // Folders in tree order with their nesting depth; children of collapsed folders are hidden
+  const visibleFolders = useMemo(() => {
+    if (!tree) return []
+    const byId = new Map(tree.physicalFolders.map(f => [f.id, f]))
+    const out: { folder: typeof tree.physicalFolders[number]; depth: number }[] = []
+    const visit = (id: string, depth: number) => {
+      const f = byId.get(id)
+      if (!f) return
+      out.push({ folder: f, depth })
+      if (collapsedFolders[folderKey(f.id)]) return
+      for (const child of f.folderIds) visit(child, depth + 1)
+    }
+    for (const id of tree.rootFolders) visit(id, 0)
+    return out
+  }, [tree, collapsedFolders])
   const toggleFolder = (id: string) => {
     setCollapsedFolders(prev => {
       const key = folderKey(id)
@@ -111,9 +130,9 @@ export const ProjectList = () => {
 
   const itemCount = useMemo(() => {
     if (!tree) return 0
-    const countFolderItems = (folders: typeof tree.physicalFolders) => {
+    const countFolderItems = (folders: typeof visibleFolders) => {
       let count = 0
-      for (const f of folders) {
+      for (const { folder: f } of folders) {
         const key = folderKey(f.id)
         const isCollapsed = collapsedFolders[key] ?? false
         count += 1 // folder header itself
@@ -123,13 +142,13 @@ export const ProjectList = () => {
     }
 
     const totalProjects = tree.projects.length
-    const physicalCount = countFolderItems(tree.physicalFolders)
+    const physicalCount = countFolderItems(visibleFolders)
     const rootProjectsCount = tree.rootProjects.length
 
     if (listCurrentType === 'projects') return totalProjects
     if (listCurrentType === 'folders') return physicalCount
     return physicalCount + rootProjectsCount
-  }, [tree, loading, listCurrentType, collapsedFolders])
+  }, [tree, loading, listCurrentType, collapsedFolders, visibleFolders])
 
   const visibleRows = useMemo(() => {
     const maxRows = Math.max(1, rows)
@@ -252,6 +271,31 @@ export const ProjectList = () => {
     }
   }, [clearShiftTimer, openSelectedProjects, exitSelection])
 
+  // Ctrl/Cmd+Z undoes the last workspace operation, Ctrl/Cmd+Shift+Z (or Ctrl+Y) redoes it
+  useEffect(() => {
+    const handleUndoKeys = async (e: KeyboardEvent) => {
+      if (!(e.ctrlKey || e.metaKey) || e.altKey) return
+      const key = e.key.toLowerCase()
+      const redo = (key === 'z' && e.shiftKey) || key === 'y'
+      if (key !== 'z' && !redo) return
+      const el = e.target as HTMLElement | null
+      const tag = el?.tagName?.toLowerCase()
+      if (tag === 'input' || tag === 'textarea' || el?.isContentEditable) return
+      e.preventDefault()
+      try {
+        const state = await (redo ? redoWorkspaceOp() : undoWorkspaceOp())
+        await reindex()
+        // The op just undone is now on top of the redo stack and vice versa
+        const label = redo ? state.undoLabel : state.redoLabel
+        pushToast({ message: `${redo ? 'Redid' : 'Undid'}: ${label}`, kind: 'info' })
+      } catch (err) {
+        pushToast({ message: `${redo ? 'Redo' : 'Undo'} failed: ${errorMessage(err)}`, kind: 'error' })
+      }
+    }
+    window.addEventListener('keydown', handleUndoKeys)
+    return () => window.removeEventListener('keydown', handleUndoKeys)
+  }, [reindex, pushToast])
+
   useLayoutEffect(() => {
     if (typeof window === 'undefined') return
     const recalcRows = () => {
@@ -301,7 +345,7 @@ export const ProjectList = () => {
       <div className={`${style.listBody} ${isEmptyBody ? style.listBodyEmpty : ''}`}>
         {!tree && (
           <div className={style.empty}>
-            {loading ? 'Loading...' : 'No workspace selected'}
+            {loading ? (scanProgress ? `Loading... (${scanProgress.files} files)` : 'Loading...') : 'No workspace selected'}
           </div>
         )}
 
@@ -310,10 +354,10 @@ export const ProjectList = () => {
             <div className={style.empty}>There are no projects or folders!</div>
           ) : (
             <>
-              {tree.physicalFolders.map(f => {
+              {visibleFolders.map(({ folder: f, depth }) => {
                 const sortedIds = [...f.projectIds].sort((a, b) => ((projectsMap[b]?.lastModifiedMs ?? 0) - (projectsMap[a]?.lastModifiedMs ?? 0)))
                 const collapsed = collapsedFolders[folderKey(f.id)] ?? false
-                return <Folder key={f.id} id={f.id} name={f.name} projectIds={sortedIds} projectMap={projectsMap} onChanged={reindex} color={f.color ?? undefined} collapsed={collapsed} onToggle={() => toggleFolder(f.id)} selectionMode={isSelectionMode} selectedPaths={selectedPaths} onToggleSelect={toggleSelection} />
+                return <Folder key={f.id} id={f.id} name={f.name} depth={depth} hasSubfolders={f.folderIds.length > 0} projectIds={sortedIds} projectMap={projectsMap} onChanged={reindex} color={f.color ?? undefined} collapsed={collapsed} onToggle={() => toggleFolder(f.id)} selectionMode={isSelectionMode} selectedPaths={selectedPaths} onToggleSelect={toggleSelection} />
               })}
               {sortedRootProjectIds.map(pid => {
                 const p = projectsMap[pid]
@@ -330,10 +374,10 @@ export const ProjectList = () => {
             <div className={style.empty}>There are no folders!</div>
           ) : (
             <>
-              {tree.physicalFolders.map(f => {
+              {visibleFolders.map(({ folder: f, depth }) => {
                 const sortedIds = [...f.projectIds].sort((a, b) => ((projectsMap[b]?.lastModifiedMs ?? 0) - (projectsMap[a]?.lastModifiedMs ?? 0)))
                 const collapsed = collapsedFolders[folderKey(f.id)] ?? false
-                return <Folder key={f.id} id={f.id} name={f.name} projectIds={sortedIds} projectMap={projectsMap} onChanged={reindex} color={f.color ?? undefined} collapsed={collapsed} onToggle={() => toggleFolder(f.id)} selectionMode={isSelectionMode} selectedPaths={selectedPaths} onToggleSelect={toggleSelection} />
+                return <Folder key={f.id} id={f.id} name={f.name} depth={depth} hasSubfolders={f.folderIds.length > 0} projectIds={sortedIds} projectMap={projectsMap} onChanged={reindex} color={f.color ?? undefined} collapsed={collapsed} onToggle={() => toggleFolder(f.id)} selectionMode={isSelectionMode} selectedPaths={selectedPaths} onToggleSelect={toggleSelection} />
               })}
             </>
           )
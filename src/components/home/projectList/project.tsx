@@ -4,7 +4,7 @@ import { rpc_project } from '../../../core/discord_rpc'
 import { useMemo, useState } from 'react'
 import { Menu } from '@tauri-apps/api/menu'
 import MultiModal from '../../modal'
-import { deleteProjectPath, renameProjectPath, moveProjectPath } from '../../../core/db'
+import { deleteProjectPath, renameProjectPath, moveProjectPath, moveToWorkspace, getEncryptionStatus, encryptDocument, unlockDocument, decryptDocument, errorMessage } from '../../../core/db'
 import { useWorkspace } from '../../../core/workspaceContext'
 import { readableTextColor, withAlpha } from '../../../utils/color'
 import Select, { SelectOption } from '../../select'
@@ -52,11 +52,13 @@ function Project({
   onToggleSelect?: (path: string) => void;
 }) {
   const navigator = useNavigate()
-  const { tree, rootPath } = useWorkspace()
+  const { tree, rootPath, workspaces } = useWorkspace()
   const [isRenameModalOpen, setIsRenameOpen] = useState(false)
   const [isMoveModalOpen, setIsMoveOpen] = useState(false)
   const [isDeleteModalOpen, setIsDeleteOpen] = useState(false)
   const [targetDir, setTargetDir] = useState<string>('')
+  const [passphraseMode, setPassphraseMode] = useState<'encrypt' | 'decrypt' | null>(null)
+  const [passphraseError, setPassphraseError] = useState('')
   const pushToast = useToast()
 
   // Ensure unique menu and item IDs so duplicated names don't cross-wire actions
@@ -65,9 +67,12 @@ function Project({
     items: [
       { id: `project:${path}:rename`, text: "Rename", action: () => { setIsRenameOpen(true) }},
       { id: `project:${path}:move`, text: "Move", action: () => { setIsMoveOpen(true)}},
+      ...(kind === 'rpad'
+        ? [{ id: `project:${path}:encryption`, text: "Encryption", action: () => { void handleEncryption() }}]
+        : []),
       { id: `project:${path}:delete`, text: "Delete", action: () => { setIsDeleteOpen(true) }},
     ],
-  }), [path])
+  }), [path, kind])
 
   const handleOptionsMenu = async (event: { stopPropagation: () => void }) => {
     event.stopPropagation()
@@ -99,7 +104,7 @@ function Project({
       pushToast({ message: `Renamed to ${newName}`, kind: "success" })
       onRename()
     } catch (err) {
-      pushToast({ message: `Rename failed: ${err}`, kind: "error" })
+      pushToast({ message: `Rename failed: ${errorMessage(err)}`, kind: "error" })
     } finally {
       setIsRenameOpen(false)
     }
@@ -108,10 +113,10 @@ function Project({
   const handleDeletion = async () => {
     try {
       await deleteProjectPath(path)
-      pushToast({ message: `Deleted ${displayName}`, kind: "info" })
+      pushToast({ message: `Moved ${displayName} to the trash`, kind: "info" })
       onDelete()
     } catch (err) {
-      pushToast({ message: `Delete failed: ${err}`, kind: "error" })
+      pushToast({ message: `Delete failed: ${errorMessage(err)}`, kind: "error" })
     } finally {
       setIsDeleteOpen(false)
     }
@@ -123,16 +128,53 @@ function Project({
     // Otherwise, move physically if changed
     if (dest === currentDir) { setIsMoveOpen(false); return }
     try {
-      await moveProjectPath(path, dest)
-      pushToast({ message: `Moved ${displayName}`, kind: "success" })
+      // Other workspaces are offered by their root
+      const other = workspaces.find(w => w.root === dest && w.root !== rootPath)
+      if (other) await moveToWorkspace(path, other.id)
+      else await moveProjectPath(path, dest)
+      pushToast({ message: other ? `Moved ${displayName} to ${other.name}` : `Moved ${displayName}`, kind: "success" })
       onRename()
     } catch (err) {
-      pushToast({ message: `Move failed: ${err}`, kind: "error" })
+      pushToast({ message: `Move failed: ${errorMessage(err)}`, kind: "error" })
     } finally {
       setIsMoveOpen(false)
     }
   }
 
+  const handleEncryption = async () => {
+    try {
+      const status = await getEncryptionStatus(path)
+      if (!status.encrypted) { setPassphraseMode('encrypt'); return }
+      // Removing encryption needs the passphrase unless it was entered this session
+      if (!status.unlocked) { setPassphraseMode('decrypt'); return }
+      await decryptDocument(path)
+      pushToast({ message: `Removed encryption from ${displayName}`, kind: "success" })
+    } catch (err) {
+      pushToast({ message: `Encryption failed: ${errorMessage(err)}`, kind: "error" })
+    }
+  }
+
+  const closePassphrase = () => {
+    setPassphraseMode(null)
+    setPassphraseError('')
+  }
+
+  const handlePassphrase = async (passphrase: string) => {
+    try {
+      if (passphraseMode === 'encrypt') {
+        await encryptDocument(path, passphrase)
+        pushToast({ message: `Encrypted ${displayName}`, kind: "success" })
+      } else {
+        await unlockDocument(path, passphrase)
+        await decryptDocument(path)
+        pushToast({ message: `Removed encryption from ${displayName}`, kind: "success" })
+      }
+      closePassphrase()
+    } catch (err) {
+      setPassphraseError(errorMessage(err))
+    }
+  }
+
   const bg = color ? withAlpha(color, 0.3) : undefined
   const border = color ? withAlpha(color, 0.5) : undefined
   const iconColor = color ? readableTextColor(color) : undefined
@@ -142,13 +184,31 @@ function Project({
     tree?.physicalFolders.forEach((f) => {
       options.push({ kind: "option", value: f.path, label: f.name })
     })
+    const others = workspaces.filter(w => w.root !== rootPath)
+    if (others.length) options.push({ kind: "section", label: "Other workspaces" })
+    others.forEach((w) => {
+      options.push({ kind: "option", value: w.root, label: w.name })
+    })
     return options
-  }, [rootPath, tree])
+  }, [rootPath, tree, workspaces])
 
   return(
     <>
       <MultiModal type='renameProject' isOpen={isRenameModalOpen} onClose={() => setIsRenameOpen(false)} onSubmit={handleRename} title={'New project name'} buttonLabel='Rename' placeholder='New project name' initialName={name} />
       <MultiModal type='delete' isOpen={isDeleteModalOpen} onClose={() => setIsDeleteOpen(false)} onSubmit={handleDeletion} title={'Warning'} declineButtonLabel='Cancel' acceptButtonLabel='Delete' message={`Are you sure you want to delete this project!?`} name={(<>Project Name:<span>{displayName}</span></>)}/>
+      <MultiModal
+        type='passphrase'
+        isOpen={passphraseMode !== null}
+        onClose={closePassphrase}
+        onSubmit={handlePassphrase}
+        title={passphraseMode === 'encrypt' ? 'Encrypt project' : 'Remove encryption'}
+        message={passphraseMode === 'encrypt'
+          ? 'The passphrase cannot be recovered. Without it, the project cannot be opened.'
+          : `Enter the passphrase of ${displayName}.`}
+        buttonLabel={passphraseMode === 'encrypt' ? 'Encrypt' : 'Decrypt'}
+        confirm={passphraseMode === 'encrypt'}
+        error={passphraseError}
+      />
       <MultiModal type='custom' isOpen={isMoveModalOpen} onClose={() => setIsMoveOpen(false)} title={'Move project'} primaryAction={{ label: 'Move', onClick: handleMove }}>
         <label className={style.label} htmlFor="dest">Destination folder</label>
         <Select
@@ -67,6 +67,17 @@ type ChooseCreateProps = BaseProps & {
   onChoose: (choice: "project" | "folder") => void;
 };
 
+type PassphraseProps = BaseProps & {
+  type: "passphrase";
+  title?: string;
+  message?: React.ReactNode;
+  buttonLabel?: string;
+  // Ask twice, for setting a new passphrase
+  confirm?: boolean;
+  error?: string;
+  onSubmit: (passphrase: string) => void;
+};
+
 type CustomProps = BaseProps & {
   type: "custom";
   title?: string;
@@ -82,6 +93,7 @@ export type MultiModalProps =
   | CreateFolderProps
   | ChangelogProps
   | ChooseCreateProps
+  | PassphraseProps
   | CustomProps;
 
 // Allow human-friendly names; forbid only filesystem-invalid characters.
@@ -399,6 +411,70 @@ const ChangelogView: React.FC<{
   );
 };
 
+const PassphraseView: React.FC<{
+  title: string;
+  message?: React.ReactNode;
+  buttonLabel: string;
+  confirm: boolean;
+  error?: string;
+  onSubmit: (passphrase: string) => void;
+  onClose: () => void;
+}> = ({ title, message, buttonLabel, confirm, error: outerError, onSubmit, onClose }) => {
+  const [passphrase, setPassphrase] = useState("");
+  const [repeat, setRepeat] = useState("");
+  const [error, setError] = useState("");
+
+  useEffect(() => setError(outerError ?? ""), [outerError]);
+
+  const submit = () => {
+    if (!passphrase) {
+      setError("Enter a passphrase.");
+      return;
+    }
+    if (confirm && passphrase !== repeat) {
+      setError("The passphrases do not match.");
+      return;
+    }
+    setError("");
+    onSubmit(passphrase);
+  };
+
+  useModalKeydown({ onClose, onEnter: submit });
+
+  return (
+    <div className={style.modal}>
+      <h2>{title}</h2>
+      {message ? <div className={style.content}>{message}</div> : null}
+      <input
+        type="password"
+        value={passphrase}
+        onChange={(e) => setPassphrase(e.target.value)}
+        placeholder="Passphrase"
+        className={style.input}
+        autoFocus
+      />
+      {confirm ? (
+        <input
+          type="password"
+          value={repeat}
+          onChange={(e) => setRepeat(e.target.value)}
+          placeholder="Repeat passphrase"
+          className={style.input}
+        />
+      ) : null}
+      {error && <div className={style.error}>{error}</div>}
+      <div className={style.modalActions}>
+        <button className={style.button} onClick={submit}>
+          {buttonLabel}
+        </button>
+        <button className={style.button} onClick={onClose}>
+          Cancel
+        </button>
+      </div>
+    </div>
+  );
+};
+
 const CustomView: React.FC<{
   title?: string;
   children: React.ReactNode;
@@ -531,6 +607,19 @@ const MultiModal: React.FC<MultiModalProps> = (props) => {
         />
       );
       break;
+    case "passphrase":
+      resolved = (
+        <PassphraseView
+          title={props.title ?? "Enter passphrase"}
+          message={props.message}
+          buttonLabel={props.buttonLabel ?? "Unlock"}
+          confirm={!!props.confirm}
+          error={props.error}
+          onSubmit={props.onSubmit}
+          onClose={onClose}
+        />
+      );
+      break;
     case "custom":
       resolved = (
         <CustomView
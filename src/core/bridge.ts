@@ -1,14 +1,56 @@
 import { invoke } from '@tauri-apps/api/core'
 import { listen, UnlistenFn } from '@tauri-apps/api/event'
+import { scanOptions } from './db'
+import type { WorkspaceRegistry } from './db'
 
-export async function startWatching(folders:string[]) { 
-    await invoke('watch_physical_folders', { folders }) 
+// One watcher per workspace, keyed by its root (the first folder unless given).
+// Events for ignored paths are dropped by the backend, using the root's scan options
+export async function startWatching(folders:string[], root?:string) { 
+    const key = root ?? folders[0]
+    const options = key ? await scanOptions(key) : null
+    await invoke('start_watching', { folders, options, root: key ?? null }) 
 }
 
-export async function stopWatching() {
-    try { await invoke('stop_watching') } catch {}
+// Stops the watcher of `root`, or all of them
+export async function stopWatching(root?:string) {
+    try { await invoke('stop_watching', { root: root ?? null }) } catch {}
 }
 
-export function onFsChanged(cb:(paths:string[])=>void):Promise<UnlistenFn> { 
-    return listen<string[]>('fs:changed', e => cb(e.payload)) 
+export type WatcherStatus = { root: string; folders: string[] }
+
+export async function watcherStatus() {
+    return await invoke<WatcherStatus[]>('watcher_status')
+}
+
+// Net change per path; the backend debounces events and sends them in batches
+export type FsChange =
+    | { kind: 'created'; path: string }
+    | { kind: 'modified'; path: string }
+    | { kind: 'removed'; path: string }
+    | { kind: 'renamed'; from: string; to: string }
+
+export function changedPaths(changes: FsChange[]) {
+    return changes.flatMap(c => c.kind === 'renamed' ? [c.from, c.to] : [c.path])
+}
+
+// `root` is the workspace whose watcher saw the changes
+export type FsChangeBatch = { root: string; changes: FsChange[] }
+
+export function onFsChanged(cb:(batch:FsChangeBatch)=>void):Promise<UnlistenFn> { 
+    return listen<FsChangeBatch>('fs:changed', e => cb(e.payload)) 
+}
+
+export function onWorkspacesChanged(cb:(registry:WorkspaceRegistry)=>void):Promise<UnlistenFn> {
+    return listen<WorkspaceRegistry>('workspaces:changed', e => cb(e.payload))
+}
+
+export type ScanProgress = { root: string; files: number; folders: number; done: boolean }
+
+export function onScanProgress(cb:(p:ScanProgress)=>void):Promise<UnlistenFn> {
+    return listen<ScanProgress>('scan:progress', e => cb(e.payload))
+}
+
+// The background content index could not be updated; search may miss recent changes
+export function onSearchFailed(cb:(message:string)=>void):Promise<UnlistenFn> {
+    return listen<string>('search:failed', e => cb(e.payload))
 }
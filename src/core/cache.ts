@@ -1,6 +1,8 @@
+import { invoke } from '@tauri-apps/api/core';
 import { themes } from './themeManager';
 import { setTheme } from "@tauri-apps/api/app";
-import { ensureSettingsPrimed, getSettings, updateSettings } from "./settings";
+import { ensureSettingsPrimed, getSettings, updateSettings, type ScanOverrides } from "./settings";
+import type { Workspace } from './db';
 
 export async function setup(){
     await ensureSettingsPrimed()
@@ -18,21 +20,33 @@ export async function setThemeCache(theme:themes) {
     await updateSettings(s => ({ ...s, theme }))
 }
 
+// Root of the active workspace. Older builds kept a single root in
+// `projectPath`; only folders picked in the folder dialog may be registered,
+// so it is dropped and the user is asked to pick the folder again
 export async function getWorkspaceRoot(): Promise<string | null> {
+  const active = await invoke<Workspace | null>('active_workspace')
+  if (active) return active.root
   const s = await getSettings()
-  return s.projectPath ?? null
-}
-
-export async function setWorkspaceRoot(path: string | null): Promise<void> {
-  await updateSettings(s => ({ ...s, projectPath: path }))
+  if (s.projectPath) await updateSettings(curr => ({ ...curr, projectPath: null }))
+  return null
 }
 
 export async function getWatchedFolders(): Promise<string[]> {
   const s = await getSettings()
   if (s.watched && s.watched.length) return s.watched
-  return s.projectPath ? [s.projectPath] : []
+  const root = await getWorkspaceRoot()
+  return root ? [root] : []
 }
 
 export async function setWatchedFolders(folders: string[]): Promise<void> {
   await updateSettings(s => ({ ...s, watched: folders }))
 }
+
+export async function getScanOverrides(root: string): Promise<ScanOverrides> {
+  const s = await getSettings()
+  return s.scan.overrides[root] ?? { include: [], exclude: [] }
+}
+
+export async function setScanOverrides(root: string, overrides: ScanOverrides): Promise<void> {
+  await updateSettings(s => ({ ...s, scan: { ...s.scan, overrides: { ...s.scan.overrides, [root]: overrides } } }))
+}
@@ -1,9 +1,16 @@
-import Database from '@tauri-apps/plugin-sql'
 import { invoke } from '@tauri-apps/api/core'
-import { getWorkspaceRoot } from './cache'
+import { getScanOverrides, getWorkspaceRoot } from './cache'
+import { getSettings } from './settings'
 
 export type ProjectKind = 'rpad'|'doc'|'pdf'|'txt'|'unknown'
 
+export type TextFormat = {
+  // WHATWG name: 'UTF-8', 'UTF-16LE', 'windows-1252', ...
+  encoding: string
+  bom: boolean
+  lineEnding: 'lf'|'crlf'|'cr'
+}
+
 export type Project = {
   id: string
   kind: ProjectKind
@@ -14,12 +21,15 @@ export type Project = {
   lastModifiedMs: number
   size: number
   parentPhysicalFolder?: string|null
+  textFormat?: TextFormat|null
 }
 
-export type PhysicalFolder = { id:string; name:string; path:string; projectIds:string[]; collapsed:boolean; color?:string|null }
+export type PhysicalFolder = { id:string; name:string; path:string; parentPath?:string|null; projectIds:string[]; folderIds:string[]; collapsed:boolean; color?:string|null }
 
 export type WorkspaceTree = {
   rootProjects: string[]
+  rootFolders: string[]
+  // Every folder, parents before children
   physicalFolders: PhysicalFolder[]
   projects: Project[]
 }
@@ -27,150 +37,69 @@ export type WorkspaceTree = {
 export type AnalyzeResult = {
   projects: Project[]
   deleteProjectPaths: string[]
-  physicalFolders: { path: string; name: string }[]
+  physicalFolders: { path: string; name: string; parentPath?: string|null }[]
   deletePhysicalFolders: string[]
+  // Renamed or moved outside the app; they keep their id, tags and folder color
+  movedProjects: MovedPath[]
+  movedFolders: MovedPath[]
 }
 
-type ScanFolder = { path:string; name:string }
-type ScanCamel = { rootProjects: any[]; physicalFolders: [ScanFolder, any[]][] }
-type ScanSnake = { root_projects: any[]; physical_folders: [ScanFolder, any[]][] }
-
-let dbPromise: Promise<any> | null = null
-let tuned = false
-
-export async function db() {
-  if (!dbPromise) dbPromise = Database.load('sqlite:rosepad.db')
-  const conn = await dbPromise
-  if (!tuned) {
-    await ensureSchema(conn)
-    try {
-      await conn.execute('PRAGMA foreign_keys=ON')
-      await conn.execute('PRAGMA journal_mode=WAL')
-      await conn.execute('PRAGMA synchronous=NORMAL')
-    } catch {}
-    tuned = true
-  }
-  return conn
-}
-
-async function ensureSchema(conn: any) {
-  await conn.execute(`
-    CREATE TABLE IF NOT EXISTS projects (
-      rowid INTEGER PRIMARY KEY,
-      id TEXT NOT NULL UNIQUE,
-      path TEXT NOT NULL UNIQUE,
-      name TEXT NOT NULL,
-      kind TEXT NOT NULL,
-      ext TEXT,
-      title TEXT,
-      last_modified_ms INTEGER NOT NULL,
-      size INTEGER NOT NULL,
-      parent_physical_folder TEXT
-    );
-  `)
-  await conn.execute(`
-    CREATE TABLE IF NOT EXISTS physical_folders (
-      path TEXT PRIMARY KEY,
-      name TEXT NOT NULL,
-      color TEXT
-    );
-  `)
-  await conn.execute(`
-    DROP TABLE IF EXISTS virtual_folder_projects;
-  `)
-  await conn.execute(`
-    DROP TABLE IF EXISTS virtual_folders;
-  `)
-  await conn.execute(`CREATE TABLE IF NOT EXISTS tags (name TEXT PRIMARY KEY);`)
-  await conn.execute(`
-    CREATE TABLE IF NOT EXISTS project_tags (
-      project_id TEXT NOT NULL,
-      tag_name TEXT NOT NULL,
-      PRIMARY KEY (project_id, tag_name)
-    );
-  `)
-  await conn.execute(`
-    CREATE VIRTUAL TABLE IF NOT EXISTS project_fts USING fts5(
-      name, title, tags, content='projects', content_rowid='rowid'
-    );
-  `)
-  await conn.execute(`
-    CREATE TRIGGER IF NOT EXISTS projects_ai AFTER INSERT ON projects BEGIN
-      INSERT INTO project_fts(rowid,name,title,tags)
-      VALUES (new.rowid,new.name,new.title,COALESCE((SELECT group_concat(tag_name,' ') FROM project_tags WHERE project_id=new.id),''));
-    END;
-  `)
-  await conn.execute(`
-    CREATE TRIGGER IF NOT EXISTS projects_au AFTER UPDATE ON projects BEGIN
-      INSERT INTO project_fts(project_fts,rowid,name,title,tags) VALUES('delete',old.rowid,old.name,old.title,'');
-      INSERT INTO project_fts(rowid,name,title,tags)
-      VALUES (new.rowid,new.name,new.title,COALESCE((SELECT group_concat(tag_name,' ') FROM project_tags WHERE project_id=new.id),''));
-    END;
-  `)
-  await conn.execute(`
-    CREATE TRIGGER IF NOT EXISTS projects_ad AFTER DELETE ON projects BEGIN
-      INSERT INTO project_fts(project_fts,rowid,name,title,tags) VALUES('delete',old.rowid,old.name,old.title,'');
-    END;
-  `)
-  await conn.execute(`
-    CREATE TRIGGER IF NOT EXISTS project_tags_ai AFTER INSERT ON project_tags BEGIN
-      UPDATE project_fts SET tags=COALESCE((SELECT group_concat(tag_name,' ') FROM project_tags WHERE project_id=new.project_id),'')
-      WHERE rowid=(SELECT rowid FROM projects WHERE id=new.project_id);
-    END;
-  `)
-  await conn.execute(`
-    CREATE TRIGGER IF NOT EXISTS project_tags_ad AFTER DELETE ON project_tags BEGIN
-      UPDATE project_fts SET tags=COALESCE((SELECT group_concat(tag_name,' ') FROM project_tags WHERE project_id=old.project_id),'')
-      WHERE rowid=(SELECT rowid FROM projects WHERE id=old.project_id);
-    END;
-  `)
-  await conn.execute(`CREATE INDEX IF NOT EXISTS idx_projects_parent ON projects(parent_physical_folder);`)
-  await conn.execute(`CREATE INDEX IF NOT EXISTS idx_projects_mtime ON projects(last_modified_ms);`)
-}
-
-export async function scanWorkspace(root:string): Promise<ScanCamel> {
-  const raw = await invoke<any>('scan_workspace', { root })
+export type MovedPath = { from: string; to: string }
+
+export type FolderNode = { path:string; name:string; parentPath?:string|null; projects:Project[]; folders:FolderNode[] }
+export type ScanResult = { rootProjects: Project[]; physicalFolders: FolderNode[] }
+
+// .gitignore and .rosepadignore files are read by the backend; these globs come on top
+export type ScanOptions = { maxDepth?: number; include?: string[]; exclude?: string[] }
+
+export async function scanOptions(root: string): Promise<ScanOptions> {
+  const { scan } = await getSettings()
+  const { include, exclude } = await getScanOverrides(root)
+  return { maxDepth: scan.maxDepth, include, exclude }
+}
+
+export function isIgnoreFile(path: string) {
+  const name = path.split(/[/\\]/).pop()
+  return name === '.gitignore' || name === '.rosepadignore'
+}
+
+// Scans and analyze passes are written to the index by the backend before they return
+export async function scanWorkspace(root:string): Promise<ScanResult> {
+  const raw = await invoke<any>('scan_workspace', { root, options: await scanOptions(root) })
   return normalizeScan(raw)
 }
 
 export async function analyzePaths(root:string, paths:string[]): Promise<AnalyzeResult> {
-  const raw = await invoke<any>('analyze_paths', { root, paths })
+  const raw = await invoke<any>('analyze_paths', { root, paths, options: await scanOptions(root) })
   const res: AnalyzeResult = {
     projects: Array.isArray(raw?.projects) ? raw.projects.map(coerceProject) : [],
     deleteProjectPaths: Array.isArray(raw?.delete_project_paths) ? raw.delete_project_paths : Array.isArray(raw?.deleteProjectPaths) ? raw.deleteProjectPaths : [],
     physicalFolders: Array.isArray(raw?.physical_folders) ? raw.physical_folders : Array.isArray(raw?.physicalFolders) ? raw.physicalFolders : [],
-    deletePhysicalFolders: Array.isArray(raw?.delete_physical_folders) ? raw.delete_physical_folders : Array.isArray(raw?.deletePhysicalFolders) ? raw.deletePhysicalFolders : []
+    deletePhysicalFolders: Array.isArray(raw?.delete_physical_folders) ? raw.delete_physical_folders : Array.isArray(raw?.deletePhysicalFolders) ? raw.deletePhysicalFolders : [],
+    movedProjects: Array.isArray(raw?.movedProjects) ? raw.movedProjects : [],
+    movedFolders: Array.isArray(raw?.movedFolders) ? raw.movedFolders : []
   }
   return res
 }
 
-function normalizeScan(raw: any): ScanCamel {
+function normalizeScan(raw: any): ScanResult {
   if (!raw || typeof raw !== 'object') return { rootProjects: [], physicalFolders: [] }
-  if (Array.isArray(raw.rootProjects) && Array.isArray(raw.physicalFolders)) {
-    return {
-      rootProjects: raw.rootProjects.map(coerceProject),
-      physicalFolders: (raw.physicalFolders as any[]).map(pair => normalizePair(pair))
-    }
-  }
-  if (Array.isArray(raw.root_projects) && Array.isArray(raw.physical_folders)) {
-    return {
-      rootProjects: (raw.root_projects as any[]).map(coerceProject),
-      physicalFolders: (raw.physical_folders as any[]).map(pair => normalizePair(pair))
-    }
+  const projects = raw.rootProjects ?? raw.root_projects
+  const folders = raw.physicalFolders ?? raw.physical_folders
+  return {
+    rootProjects: Array.isArray(projects) ? projects.map(coerceProject) : [],
+    physicalFolders: Array.isArray(folders) ? folders.map(normalizeNode) : []
   }
-  return { rootProjects: [], physicalFolders: [] }
 }
 
-function normalizePair(pair:any): [ScanFolder, Project[]] {
-  if (Array.isArray(pair)) {
-    const [folder, items] = pair
-    return [folder as ScanFolder, (items as any[]).map(coerceProject)]
-  } else if (pair && typeof pair === 'object') {
-    const folder = pair.folder ?? pair[0] ?? { path:'', name:'' }
-    const items = pair.items ?? pair[1] ?? []
-    return [folder as ScanFolder, (items as any[]).map(coerceProject)]
+function normalizeNode(node: any): FolderNode {
+  return {
+    path: node?.path ?? '',
+    name: node?.name ?? '',
+    parentPath: node?.parentPath ?? node?.parent_path ?? null,
+    projects: Array.isArray(node?.projects) ? node.projects.map(coerceProject) : [],
+    folders: Array.isArray(node?.folders) ? node.folders.map(normalizeNode) : []
   }
-  return [{ path:'', name:'' }, []]
 }
 
 function coerceProject(p: any): Project {
@@ -183,7 +112,8 @@ function coerceProject(p: any): Project {
     title: p.title ?? null,
     lastModifiedMs: p.lastModifiedMs ?? p.last_modified_ms ?? 0,
     size: p.size ?? p.sizeBytes ?? 0,
-    parentPhysicalFolder: p.parentPhysicalFolder ?? p.parent_physical_folder ?? null
+    parentPhysicalFolder: p.parentPhysicalFolder ?? p.parent_physical_folder ?? null,
+    textFormat: p.textFormat ?? p.text_format ?? null
   }
 }
 
@@ -193,10 +123,11 @@ export async function renameProjectPath(oldPath:string, newName:string) {
   return await invoke<string>('rename_project', { workspaceRoot: root, oldPath, newName })
 }
 
+// Deletes move items into the workspace trash (.rosepad/trash) so they can be restored
 export async function deleteProjectPath(path:string) {
   const root = await getWorkspaceRoot()
   if (!root) throw new Error('workspace root not set')
-  await invoke('delete_project', { workspaceRoot: root, path })
+  return await invoke<TrashEntry>('delete_project', { workspaceRoot: root, path })
 }
 
 export async function moveProjectPath(oldPath: string, destDir: string) {
@@ -206,176 +137,400 @@ export async function moveProjectPath(oldPath: string, destDir: string) {
 }
 
 export async function renamePhysicalFolder(path: string, newName: string) {
-  // Perform the physical rename via Tauri backend and receive the new path
+  // The backend renames the folder and carries its index row (color) over to the new path
   const root = await getWorkspaceRoot()
   if (!root) throw new Error('workspace root not set')
-  const newPath = await invoke<string>('rename_physical_folder', { workspaceRoot: root, path, newName })
-  try {
-    // Preserve folder color (and any other metadata) by moving the row to the new path
-    const d = await db()
-    await d.execute(`UPDATE physical_folders SET path=? WHERE path=?`, [newPath, path])
-  } catch {}
-  return newPath
+  return await invoke<string>('rename_physical_folder', { workspaceRoot: root, path, newName })
 }
 
 export async function deletePhysicalFolder(path: string) {
   const root = await getWorkspaceRoot()
   if (!root) throw new Error('workspace root not set')
-  await invoke('delete_physical_folder', { workspaceRoot: root, path })
+  return await invoke<TrashEntry>('delete_physical_folder', { workspaceRoot: root, path })
 }
 
-export async function createPhysicalFolder(root: string, name: string) {
-  return await invoke<string>('create_physical_folder', { root, name })
+export type TrashEntry = {
+  id: string
+  name: string
+  originalPath: string
+  deletedAtMs: number
+  isDir: boolean
+  size: number
 }
 
-export async function reconcileFromScan(root:string, scanIn: ScanCamel | ScanSnake | any) {
-  const scan = normalizeScan(scanIn)
-  const d = await db()
-  await d.execute('BEGIN')
-  try {
-    const seenProj: string[] = []
-    const seenFold: string[] = []
-
-    for (const p of scan.rootProjects) {
-      await d.execute(
-        `INSERT INTO projects(id,path,name,kind,ext,title,last_modified_ms,size,parent_physical_folder)
-         VALUES (?,?,?,?,?,?,?,?,NULL)
-         ON CONFLICT(path) DO UPDATE SET name=excluded.name, kind=excluded.kind, ext=excluded.ext, title=excluded.title, last_modified_ms=excluded.last_modified_ms, size=excluded.size, parent_physical_folder=NULL`,
-        [p.id, p.path, p.name, p.kind, p.ext ?? null, p.title ?? null, p.lastModifiedMs, p.size]
-      )
-      seenProj.push(p.path)
-    }
-
-    for (const [folder, items] of scan.physicalFolders) {
-      await d.execute(
-        `INSERT INTO physical_folders(path,name) VALUES(?,?)
-         ON CONFLICT(path) DO UPDATE SET name=excluded.name`,
-        [folder.path, folder.name]
-      )
-      seenFold.push(folder.path)
-      for (const p of items) {
-        await d.execute(
-          `INSERT INTO projects(id,path,name,kind,ext,title,last_modified_ms,size,parent_physical_folder)
-           VALUES (?,?,?,?,?,?,?,?,?)
-           ON CONFLICT(path) DO UPDATE SET name=excluded.name, kind=excluded.kind, ext=excluded.ext, title=excluded.title, last_modified_ms=excluded.last_modified_ms, size=excluded.size, parent_physical_folder=excluded.parent_physical_folder`,
-          [p.id, p.path, p.name, p.kind, p.ext ?? null, p.title ?? null, p.lastModifiedMs, p.size, folder.path]
-        )
-        seenProj.push(p.path)
-      }
-    }
-
-    const normRoot = root.replace(/\\/g,'/').toLowerCase()
-    const normRootWithSlash = normRoot.endsWith('/') ? normRoot : `${normRoot}/`
-    const projPlace = seenProj.map(()=>'?').join(',')
-    await d.execute(
-      `DELETE FROM projects WHERE (REPLACE(LOWER(path),'\\','/') = ? OR REPLACE(LOWER(path),'\\','/') LIKE ?) ${seenProj.length ? `AND path NOT IN (${projPlace})` : ''}`,
-      [normRoot, normRootWithSlash + '%', ...seenProj]
-    )
-
-    const foldPlace = seenFold.map(()=>'?').join(',')
-    await d.execute(
-      `DELETE FROM physical_folders WHERE (REPLACE(LOWER(path),'\\','/') = ? OR REPLACE(LOWER(path),'\\','/') LIKE ?) ${seenFold.length ? `AND path NOT IN (${foldPlace})` : ''}`,
-      [normRoot, normRootWithSlash + '%', ...seenFold]
-    )
-
-    await d.execute('COMMIT')
-  } catch (e) {
-    await d.execute('ROLLBACK')
-    throw e
-  }
+export async function listTrash(root: string) {
+  return await invoke<TrashEntry[]>('list_trash', { workspaceRoot: root })
+}
+
+// Returns the restored path, which gets a " (n)" suffix if the original name is taken
+export async function restoreTrashItem(root: string, id: string) {
+  return await invoke<string>('restore_trash_item', { workspaceRoot: root, id })
+}
+
+export async function emptyTrash(root: string, ids?: string[]) {
+  return await invoke<number>('empty_trash', { workspaceRoot: root, ids: ids ?? null })
+}
+
+export async function purgeTrash(root: string, maxAgeDays: number) {
+  return await invoke<number>('purge_trash', { workspaceRoot: root, maxAgeDays })
+}
+
+export type DocVersion = {
+  lastModifiedMs: number
+  hash: string
+}
+
+export type RosePadErrorCode =
+  | 'notFound' | 'notAFile' | 'notADirectory' | 'alreadyExists' | 'permissionDenied'
+  | 'outsideWorkspace' | 'notAWorkspace' | 'invalidName' | 'invalidPath' | 'unsupportedType'
+  | 'conflict' | 'locked' | 'rpcDisabled' | 'rpcUnavailable' | 'io' | 'failed'
+
+// What the workspace, settings and Discord commands reject with; `message` is meant for the user
+export type RosePadError = {
+  code: RosePadErrorCode
+  message: string
+  path: string | null
+  operation: string | null
+} & (
+  | { code: 'conflict', disk: DocVersion & { content: string } }
+  | { code: 'locked', title: string | null }
+  | { code: Exclude<RosePadErrorCode, 'conflict' | 'locked'> }
+)
+
+export function isRosePadError(err: unknown): err is RosePadError {
+  return typeof err === 'object' && err !== null && typeof (err as RosePadError).code === 'string'
+}
+
+// Other commands still reject with a plain string
+export function errorMessage(err: unknown) {
+  return isRosePadError(err) ? err.message : String(err)
+}
+
+export function isSaveConflict(err: unknown): err is Extract<RosePadError, { code: 'conflict' }> {
+  return isRosePadError(err) && err.code === 'conflict'
+}
+
+export async function getDocumentVersion(path: string) {
+  return await invoke<DocVersion>('document_version', { path })
+}
+
+export async function getDocumentSize(path: string) {
+  return await invoke<number>('document_size', { path })
+}
+
+export type Snapshot = {
+  id: number
+  path: string
+  hash: string
+  savedAtMs: number
+  size: number
+}
+
+export type DiffLine = {
+  kind: 'equal' | 'insert' | 'delete'
+  text: string
+  oldLine: number | null
+  newLine: number | null
+}
+
+export type DiffHunk = {
+  oldStart: number
+  oldLen: number
+  newStart: number
+  newLen: number
+  lines: DiffLine[]
+}
+
+export type DocumentDiff = {
+  // 'blocks' when both sides are .rpad: each line is then one HTML block
+  mode: 'text' | 'blocks'
+  hunks: DiffHunk[]
+}
+
+export type MergeChunk =
+  | { kind: 'resolved', lines: string[] }
+  | { kind: 'conflict', base: string[], ours: string[], theirs: string[] }
+
+export type MergeResult = {
+  mode: 'text' | 'blocks'
+  merged: string
+  conflicts: number
+  chunks: MergeChunk[]
+}
+
+export async function diffDocuments(oldPath: string, newPath: string) {
+  return await invoke<DocumentDiff>('diff_documents', { oldPath, newPath })
+}
+
+// The base is a file or a history snapshot; without one, what both documents share is used
+export async function mergeDocuments(oursPath: string, theirsPath: string, base?: { path?: string, snapshot?: number }) {
+  return await invoke<MergeResult>('merge_documents', {
+    oursPath,
+    theirsPath,
+    basePath: base?.path ?? null,
+    baseSnapshot: base?.snapshot ?? null,
+  })
+}
+
+// Saved versions of a document, newest first
+export async function listHistory(path: string) {
+  return await invoke<Snapshot[]>('list_history', { path })
+}
+
+export async function readSnapshot(id: number) {
+  return await invoke<string>('read_snapshot', { id })
+}
+
+// Compares against another snapshot, or the file on disk when `against` is omitted
+export async function diffSnapshot(id: number, against?: number) {
+  return await invoke<DiffHunk[]>('diff_snapshot', { id, against: against ?? null })
+}
+
+export async function restoreSnapshot(id: number) {
+  return await invoke<void>('restore_snapshot', { id })
+}
+
+export type JournalState = {
+  canUndo: boolean
+  canRedo: boolean
+  undoLabel: string | null
+  redoLabel: string | null
+}
+
+// Undo/redo the last rename, move, create or delete in the workspace
+export async function undoWorkspaceOp() {
+  const root = await getWorkspaceRoot()
+  if (!root) throw new Error('workspace root not set')
+  return await invoke<JournalState>('undo_workspace_op', { workspaceRoot: root })
+}
+
+export async function redoWorkspaceOp() {
+  const root = await getWorkspaceRoot()
+  if (!root) throw new Error('workspace root not set')
+  return await invoke<JournalState>('redo_workspace_op', { workspaceRoot: root })
+}
+
+export async function getJournalState() {
+  const root = await getWorkspaceRoot()
+  if (!root) throw new Error('workspace root not set')
+  return await invoke<JournalState>('workspace_journal', { workspaceRoot: root })
+}
+
+export type TextDocument = {
+  // Always '\n' line breaks
+  content: string
+  format: TextFormat
+}
+
+export async function readTextDocument(path: string) {
+  return await invoke<TextDocument>('read_text_document', { path })
+}
+
+// Re-encode a text file; plain saves keep whatever format the file has
+export async function convertTextFormat(path: string, format: TextFormat) {
+  return await invoke<DocVersion>('convert_text_format', { path, format })
+}
+
+// Files too big for the editor are read a page of lines at a time through a handle
+export type LargeFile = {
+  handle: number
+  path: string
+  size: number
+  lineCount: number
+  format: TextFormat
+}
+
+export type LineRange = { start: number; lines: string[] }
+
+export type LineMatch = {
+  line: number
+  // Character offset of the match within the line
+  column: number
+  preview: string
+}
+
+export type LargeSearch = { matches: LineMatch[]; truncated: boolean }
+
+export async function openLargeFile(path: string) {
+  return await invoke<LargeFile>('open_large_file', { path })
+}
+
+export async function closeLargeFile(handle: number) {
+  await invoke('close_large_file', { handle })
+}
+
+export async function readLargeFileLines(handle: number, start: number, count: number) {
+  return await invoke<LineRange>('read_large_file_lines', { handle, start, count })
+}
+
+export async function searchLargeFile(handle: number, query: string, opts: { caseSensitive?: boolean; from?: number; maxMatches?: number } = {}) {
+  return await invoke<LargeSearch>('search_large_file', {
+    handle,
+    query,
+    caseSensitive: opts.caseSensitive ?? null,
+    from: opts.from ?? null,
+    maxMatches: opts.maxMatches ?? null,
+  })
+}
+
+// Replaces lines start..end (end exclusive); fails if the file changed on disk since it was opened
+export async function saveLargeFileRegion(handle: number, start: number, end: number, lines: string[]) {
+  return await invoke<LargeFile>('save_large_file_region', { handle, start, end, lines })
+}
+
+// Encrypted .rpad documents refuse to load until unlocked with their passphrase
+export function isLocked(err: unknown): err is Extract<RosePadError, { code: 'locked' }> {
+  return isRosePadError(err) && err.code === 'locked'
+}
+
+export type EncryptionStatus = {
+  encrypted: boolean
+  unlocked: boolean
+}
+
+export async function getEncryptionStatus(path: string) {
+  return await invoke<EncryptionStatus>('rpad_encryption_status', { path })
+}
+
+export async function encryptDocument(path: string, passphrase: string) {
+  return await invoke<void>('encrypt_rpad', { path, passphrase })
+}
+
+export async function unlockDocument(path: string, passphrase: string) {
+  return await invoke<void>('unlock_rpad', { path, passphrase })
+}
+
+export async function lockDocument(path: string) {
+  return await invoke<void>('lock_rpad', { path })
+}
+
+export async function decryptDocument(path: string) {
+  return await invoke<void>('decrypt_rpad', { path })
+}
+
+export type RecoverableDraft = {
+  path: string
+  updatedAtMs: number
+  base: DocVersion | null
+  disk: DocVersion | null
+  // The file changed since the edits started (or is gone)
+  diskChanged: boolean
+}
+
+// Unsaved editor state is streamed to the backend so it survives a crash; a save drops it
+export async function putDraft(path: string, content: string, base: DocVersion | null) {
+  return await invoke<void>('put_draft', { path, content, base })
+}
+
+export async function listDrafts() {
+  return await invoke<RecoverableDraft[]>('list_drafts')
+}
+
+export async function readDraft(path: string) {
+  return await invoke<string>('read_draft', { path })
+}
+
+export async function discardDraft(path: string) {
+  return await invoke<void>('discard_draft', { path })
+}
+
+// Finishes or cleans up saves a crash interrupted; returns the files it put back
+export async function recoverInterruptedWrites(root: string) {
+  return await invoke<string[]>('recover_interrupted_writes', { workspaceRoot: root })
+}
+
+export async function createPhysicalFolder(root: string, name: string) {
+  return await invoke<string>('create_physical_folder', { root, name })
 }
 
 export async function getWorkspaceTree(root:string): Promise<WorkspaceTree> {
-  const d = await db()
-  const normRoot = root.replace(/\\/g,'/').toLowerCase()
-  const normRootWithSlash = normRoot.endsWith('/') ? normRoot : `${normRoot}/`
-  const projects = await d.select(
-    `SELECT id,kind,name,path,ext,title,last_modified_ms as lastModifiedMs,parent_physical_folder as parentPhysicalFolder,size
-     FROM projects WHERE REPLACE(LOWER(path),'\\','/') = ? OR REPLACE(LOWER(path),'\\','/') LIKE ?`,
-    [normRoot, normRootWithSlash + '%']
-  )
-
-  const rootProjects = projects
-    .filter((p:any)=>!p.parentPhysicalFolder)
-    .map((p:any)=>p.id)
-
-  const pf = await d.select(`SELECT path,name,color FROM physical_folders WHERE REPLACE(LOWER(path),'\\','/') = ? OR REPLACE(LOWER(path),'\\','/') LIKE ?`, [normRoot, normRootWithSlash + '%'])
-  const physicalFolders: PhysicalFolder[] = []
-  for (const row of pf) {
-    const ids = await d.select(`SELECT id FROM projects WHERE parent_physical_folder=? ORDER BY name COLLATE NOCASE`, [row.path])
-    physicalFolders.push({
-      id: row.path,
-      name: row.name,
-      path: row.path,
-      projectIds: ids.map((x:any)=>x.id),
-      collapsed: false,
-      color: row.color ?? null
-    })
-  }
-  return { rootProjects, physicalFolders, projects }
+  const t = await invoke<WorkspaceTree>('get_workspace_tree', { root })
+  return { ...t, projects: t.projects.map(coerceProject) }
 }
 
 export async function searchProjects(q:string, limit=50) {
-  const d = await db()
-  const rows = await d.select(
-    `SELECT p.id,p.kind,p.name,p.path,p.ext,p.title,p.last_modified_ms as lastModifiedMs,p.parent_physical_folder as parentPhysicalFolder,p.size
-     FROM project_fts f JOIN projects p ON f.rowid = p.rowid
-     WHERE project_fts MATCH ? ORDER BY rank LIMIT ?`,
-    [q, limit]
-  )
-  return rows as Project[]
-}
-
-export async function reconcileFromAnalyze(_root:string, diff: AnalyzeResult) {
-  const d = await db()
-  await d.execute('BEGIN')
-  try {
-    // Upsert physical folders
-    for (const f of diff.physicalFolders) {
-      await d.execute(
-        `INSERT INTO physical_folders(path,name) VALUES(?,?)
-         ON CONFLICT(path) DO UPDATE SET name=excluded.name`,
-        [f.path, f.name]
-      )
-    }
-
-    // Delete removed physical folders and their projects
-    for (const p of diff.deletePhysicalFolders) {
-      await d.execute(`DELETE FROM projects WHERE parent_physical_folder=?`, [p])
-      await d.execute(`DELETE FROM physical_folders WHERE path=?`, [p])
-    }
-
-    // Upsert projects
-    for (const p of diff.projects) {
-      await d.execute(
-        `INSERT INTO projects(id,path,name,kind,ext,title,last_modified_ms,size,parent_physical_folder)
-         VALUES (?,?,?,?,?,?,?,?,?)
-         ON CONFLICT(path) DO UPDATE SET name=excluded.name, kind=excluded.kind, ext=excluded.ext, title=excluded.title, last_modified_ms=excluded.last_modified_ms, size=excluded.size, parent_physical_folder=excluded.parent_physical_folder`,
-        [p.id, p.path, p.name, p.kind, p.ext ?? null, p.title ?? null, p.lastModifiedMs, p.size, p.parentPhysicalFolder ?? null]
-      )
-    }
-
-    // Delete removed projects by path
-    if (diff.deleteProjectPaths.length) {
-      const placeholders = diff.deleteProjectPaths.map(()=>'?').join(',')
-      await d.execute(`DELETE FROM projects WHERE path IN (${placeholders})`, diff.deleteProjectPaths)
-    }
-
-    await d.execute('COMMIT')
-  } catch (e) {
-    await d.execute('ROLLBACK')
-    throw e
-  }
+  const rows = await invoke<any[]>('search_projects', { query: q, limit })
+  return rows.map(coerceProject)
 }
 
 export async function setPhysicalFolderColor(path:string, color?:string|null) {
-  const d = await db()
-  const name = (path.split(/[/\\]/).filter(Boolean).pop()) || path
-  // Ensure row exists and set color atomically; preserve any existing name on conflict except update color
-  await d.execute(
-    `INSERT INTO physical_folders(path,name,color) VALUES(?,?,?)
-     ON CONFLICT(path) DO UPDATE SET color=excluded.color`,
-    [path, name, color ?? null]
-  )
+  await invoke('set_physical_folder_color', { path, color: color ?? null })
+}
+
+export async function projectExists(path: string) {
+  return await invoke<boolean>('project_exists', { path })
+}
+
+export async function listTags() {
+  return await invoke<string[]>('list_tags')
+}
+
+export async function getProjectTags(projectId: string) {
+  return await invoke<string[]>('get_project_tags', { projectId })
+}
+
+export async function setProjectTags(projectId: string, tags: string[]) {
+  await invoke('set_project_tags', { projectId, tags })
+}
+
+export type TextRange = { start: number; end: number }
+
+export type SearchHit = {
+  id: string
+  path: string
+  name: string
+  title?: string|null
+  score: number
+  snippet: string
+  highlights: TextRange[]
+  matches: TextRange[]
+}
+
+// Full-text search over document contents (indexed by the backend during scans)
+export async function searchWorkspace(query: string, root?: string|null, limit = 50) {
+  return await invoke<SearchHit[]>('search_workspace', { query, root: root ?? null, limit })
 }
 
+// Named workspace roots, kept by the backend. Each has its own part of the
+// index and its own watcher, so switching reads the tree without a rescan
+export type Workspace = { id: string; name: string; root: string }
+
+export type WorkspaceRegistry = { active: string|null; workspaces: Workspace[] }
+
+export type WorkspaceSearchHit = SearchHit & { workspaceId: string }
+
+export async function listWorkspaces() {
+  return await invoke<WorkspaceRegistry>('list_workspaces')
+}
+
+export async function activeWorkspace() {
+  return await invoke<Workspace|null>('active_workspace')
+}
+
+// Adding a root that is already registered returns its workspace
+export async function addWorkspace(root: string, name?: string) {
+  return await invoke<Workspace>('add_workspace', { root, name: name ?? null })
+}
+
+export async function renameWorkspace(id: string, name: string) {
+  return await invoke<Workspace>('rename_workspace', { id, name })
+}
+
+// Only forgets the workspace; its files stay where they are
+export async function removeWorkspace(id: string) {
+  return await invoke<WorkspaceRegistry>('remove_workspace', { id })
+}
+
+export async function switchWorkspace(id: string) {
+  return await invoke<Workspace>('switch_workspace', { id })
+}
+
+// Searches every workspace unless `workspaceIds` narrows it down
+export async function searchWorkspaces(query: string, workspaceIds?: string[]|null, limit = 50) {
+  return await invoke<WorkspaceSearchHit[]>('search_workspaces', { query, workspaces: workspaceIds ?? null, limit })
+}
+
+// The file keeps its id, tags and history; returns its new path
+export async function moveToWorkspace(path: string, workspaceId: string, destDir?: string|null) {
+  return await invoke<string>('move_to_workspace', { path, workspaceId, destDir: destDir ?? null })
+}
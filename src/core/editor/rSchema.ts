@@ -50,6 +50,23 @@ export const rSchema = new Schema({
             parseDOM: [{ tag: "footnote" }],
             toDOM() { return ["footnote", 0] as any }
         },
+        image: {
+            inline: true,
+            group: "inline",
+            draggable: true,
+            attrs: { src: {}, alt: { default: null } },
+            parseDOM: [{
+                tag: "img[src]",
+                getAttrs: dom => {
+                    const el = dom as HTMLElement;
+                    return { src: el.getAttribute("src"), alt: el.getAttribute("alt") };
+                }
+            }],
+            toDOM(node) {
+                const { src, alt } = node.attrs;
+                return ["img", alt ? { src, alt } : { src }];
+            }
+        },
         list_item: {
             content: "paragraph block*",
             defining: true,
@@ -99,6 +116,21 @@ export const rSchema = new Schema({
                 return ["pre", ["code", 0]]; 
             } 
         },
+        // One side of a conflict left by merge_documents; the user keeps what
+        // they want and deletes the rest
+        merge_conflict: {
+            content: "block+",
+            group: "block",
+            defining: true,
+            attrs: { side: { default: "ours" } },
+            parseDOM: [{
+                tag: "div[data-merge-conflict]",
+                getAttrs: dom => ({ side: (dom as HTMLElement).getAttribute("data-merge-conflict") || "ours" })
+            }],
+            toDOM(node) {
+                return ["div", { "data-merge-conflict": node.attrs.side }, 0];
+            }
+        },
         text: { group: "inline" },
     },
     marks: rMarks
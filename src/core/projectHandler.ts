@@ -1,8 +1,5 @@
-import { open } from '@tauri-apps/plugin-dialog'
-import { db } from './db'
+import { projectExists as indexedProjectExists } from './db'
 import { invoke } from '@tauri-apps/api/core'
-import { join } from '@tauri-apps/api/path'
-import { exists } from '@tauri-apps/plugin-fs'
 import { getSettings, updateSettings } from './settings'
 
 export const settingsFile = 'settings.json'
@@ -37,39 +34,17 @@ export async function settings() {
   await ensureSettings()
 }
 
-async function ensureWorkspaceFolder(baseDir: string): Promise<string> {
-  const candidates = ['RosePad Workspace', 'RosePadWorkspace']
-  // If the selected folder already looks like a workspace folder, use it as-is
-  const parts = baseDir.split(/\\|\//).filter(Boolean)
-  const last = parts.length ? parts[parts.length - 1] : ''
-  if (candidates.some(c => c.toLowerCase() === last.toLowerCase())) {
-    return baseDir
-  }
-  // If there is already a known workspace-style folder inside, use it; otherwise, use the chosen folder directly
-  for (const name of candidates) {
-    const p = await join(baseDir, name)
-    if (await exists(p)) {
-      return p
-    }
-  }
-  return baseDir
-}
-
+// The backend picks the folder so it can allow registering it as a workspace;
+// it also finds the workspace folder older builds created inside the picked one
 export async function selectDir(): Promise<string | null> {
-  const base = await open({ directory: true, multiple: false, title: 'Select a directory for the RosePad workspace' })
-  if (!base || Array.isArray(base)) return null
-  const workspace = await ensureWorkspaceFolder(base)
-  const s = await ensureSettings()
-  s.projectPath = workspace
-  await saveSettings(s)
-  return workspace
+  return await invoke<string | null>('open_folder_dialog', {
+    title: 'Select a directory for the RosePad workspace'
+  })
 }
 
 export async function projectExists(filePath: string): Promise<boolean> {
   try {
-    const d = await db()
-    const rows = await d.select('SELECT 1 AS x FROM projects WHERE path = ? LIMIT 1', [filePath])
-    return Array.isArray(rows) && rows.length > 0
+    return await indexedProjectExists(filePath)
   } catch {
     return false
   }
@@ -2,13 +2,20 @@ import { Store } from '@tauri-apps/plugin-store'
 import { themes } from './themeManager'
 
 export type AppSettings = {
+  // Legacy single root; workspaces now live in the backend registry
   projectPath: string | null
   watched: string[]
   theme: themes
   autosave: { enabled: boolean; interval: number }
   recent: { name: string; path: string; ts: number }[]
+  // `overrides` is keyed by workspace root
+  scan: { maxDepth: number; overrides: Record<string, ScanOverrides> }
+  // Trashed items older than this are deleted for good
+  trash: { retentionDays: number }
 }
 
+export type ScanOverrides = { include: string[]; exclude: string[] }
+
 const SETTINGS_FILE = 'settings.json'
 const SETTINGS_BACKUP = 'settings.json.bak'
 const defaults: AppSettings = {
@@ -16,7 +23,9 @@ const defaults: AppSettings = {
   watched: [],
   theme: 'dark',
   autosave: { enabled: true, interval: 2 },
-  recent: []
+  recent: [],
+  scan: { maxDepth: 16, overrides: {} },
+  trash: { retentionDays: 30 }
 }
 
 let storePromise: Promise<Store> | null = null
@@ -37,6 +46,19 @@ async function readRaw(): Promise<Record<string, any>> {
   }
 }
 
+function normalizeGlobs(v: unknown): string[] {
+  return Array.isArray(v) ? v.filter((p): p is string => typeof p === 'string') : []
+}
+
+function normalizeOverrides(v: unknown): Record<string, ScanOverrides> {
+  if (!v || typeof v !== 'object') return {}
+  const out: Record<string, ScanOverrides> = {}
+  for (const [root, o] of Object.entries(v as Record<string, any>)) {
+    out[root] = { include: normalizeGlobs(o?.include), exclude: normalizeGlobs(o?.exclude) }
+  }
+  return out
+}
+
 function normalize(raw: Record<string, any>): AppSettings & Record<string, any> {
   return {
     ...defaults,
@@ -47,7 +69,16 @@ function normalize(raw: Record<string, any>): AppSettings & Record<string, any>
     autosave: raw.autosave && typeof raw.autosave === 'object'
       ? { enabled: !!raw.autosave.enabled, interval: Number(raw.autosave.interval) || defaults.autosave.interval }
       : { ...defaults.autosave },
-    recent: Array.isArray(raw.recent) ? raw.recent : []
+    recent: Array.isArray(raw.recent) ? raw.recent : [],
+    scan: raw.scan && typeof raw.scan === 'object'
+      ? {
+          maxDepth: Number.isFinite(Number(raw.scan.maxDepth)) ? Math.max(0, Number(raw.scan.maxDepth)) : defaults.scan.maxDepth,
+          overrides: normalizeOverrides(raw.scan.overrides)
+        }
+      : { ...defaults.scan, overrides: {} },
+    trash: {
+      retentionDays: Number.isFinite(Number(raw.trash?.retentionDays)) ? Math.max(1, Number(raw.trash.retentionDays)) : defaults.trash.retentionDays
+    }
   }
 }
 
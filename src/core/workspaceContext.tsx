@@ -1,16 +1,28 @@
 import { createContext, useCallback, useContext, useEffect, useMemo, useRef, useState } from "react"
-import { getWorkspaceRoot, setWatchedFolders, setWorkspaceRoot as persistWorkspaceRoot } from "./cache"
-import { startWatching, stopWatching } from "./bridge"
-import { getWorkspaceTree, reconcileFromScan, scanWorkspace, analyzePaths, reconcileFromAnalyze } from "./db"
-import { setWorkspaceRoot as clearPersistedRoot } from "./cache"
-import type { WorkspaceTree } from "./db"
+import { getWorkspaceRoot } from "./cache"
+import { onScanProgress, onSearchFailed, onWorkspacesChanged, startWatching, stopWatching } from "./bridge"
+import type { ScanProgress } from "./bridge"
+import { getWorkspaceTree, scanWorkspace, analyzePaths, isIgnoreFile, isRosePadError, purgeTrash, recoverInterruptedWrites } from "./db"
+import { addWorkspace as registerWorkspace, listWorkspaces, removeWorkspace as forgetWorkspace, switchWorkspace as activateWorkspace } from "./db"
+import { getSettings } from "./settings"
+import { useToast } from "./toast"
+import type { Workspace, WorkspaceTree } from "./db"
 
 type Ctx = {
   rootPath: string|null
+  workspaces: Workspace[]
+  activeWorkspace: Workspace|null
   tree: WorkspaceTree|null
   loading: boolean
+  // Live counts while a full scan is running
+  scanProgress: ScanProgress|null
   init: () => Promise<void>
+  // Registers `root` as a workspace if needed, switches to it and rescans it
   setRoot: (root: string) => Promise<void>
+  addWorkspace: (root: string, name?: string) => Promise<Workspace>
+  // Shows another workspace from its index, without a rescan
+  switchWorkspace: (id: string) => Promise<void>
+  removeWorkspace: (id: string) => Promise<void>
   reload: (rootOverride?: string) => Promise<void>
   reindex: (rootOverride?: string) => Promise<void>
   applyFsChanges: (paths: string[]) => Promise<void>
@@ -20,16 +32,35 @@ const WorkspaceCtx = createContext<Ctx | null>(null)
 
 export function WorkspaceProvider({ children }: { children: React.ReactNode }) {
   const [rootPath, setRootPath] = useState<string|null>(null)
+  const [workspaces, setWorkspaces] = useState<Workspace[]>([])
   const [tree, setTree] = useState<WorkspaceTree|null>(null)
   const [loading, setLoading] = useState(false)
+  const [scanProgress, setScanProgress] = useState<ScanProgress|null>(null)
   const busy = useRef(false)
   const pending = useRef(false)
   const pendingPaths = useRef<Set<string>>(new Set())
   const didAutoInit = useRef(false)
   const rootRef = useRef<string|null>(null)
+  const watchedRoots = useRef<Set<string>>(new Set())
+  const pushToast = useToast()
 
   useEffect(() => { rootRef.current = rootPath }, [rootPath])
 
+  useEffect(() => {
+    const un = onScanProgress(p => {
+      if (p.root !== rootRef.current) return
+      setScanProgress(p.done ? null : p)
+    })
+    return () => { un.then(f => f()).catch(() => {}) }
+  }, [])
+
+  useEffect(() => {
+    const un = onSearchFailed(message => {
+      pushToast({ message: `Search index update failed: ${message}`, kind: 'error' })
+    })
+    return () => { un.then(f => f()).catch(() => {}) }
+  }, [pushToast])
+
   const resolveRoot = useCallback(async (rootOverride?: string) => {
     let effectiveRoot = rootOverride ?? rootRef.current
     if (!effectiveRoot) {
@@ -57,24 +88,20 @@ export function WorkspaceProvider({ children }: { children: React.ReactNode }) {
     try {
       do {
         pending.current = false
-        const scan = await scanWorkspace(effectiveRoot)
-        await reconcileFromScan(effectiveRoot, scan)
+        await scanWorkspace(effectiveRoot)
         const t = await getWorkspaceTree(effectiveRoot)
         setTree(t)
       } while (pending.current)
     } catch (e) {
       console.error('reindex failed', e)
-      const msg = String(e || '')
-      // If the persisted root is invalid, clear it to recover the UI
-      if (msg.includes('not a directory') || msg.includes('cannot read workspace root')) {
-        try {
-          await clearPersistedRoot(null)
-        } catch {}
+      // The root may be on a drive that is gone; keep the workspace registered but recover the UI
+      if (isRosePadError(e) && ['notADirectory', 'notFound', 'permissionDenied', 'io'].includes(e.code)) {
         setRootPath(null)
         setTree(null)
       }
     } finally {
       setLoading(false)
+      setScanProgress(null)
       busy.current = false
     }
   }, [resolveRoot])
@@ -82,21 +109,68 @@ export function WorkspaceProvider({ children }: { children: React.ReactNode }) {
   const init = useCallback(async () => {
     const root = await getWorkspaceRoot()
     if (root) {
-      // Only set state and reindex; watcher is managed by a separate effect on rootPath
+      // Only set state and reindex; watchers are managed by a separate effect on workspaces
       setRootPath(root)
+      // Before the scan, so restored files are indexed and temp files are not
+      await recoverInterruptedWrites(root)
+        .then(restored => { if (restored.length) console.warn('recovered interrupted saves', restored) })
+        .catch(err => console.error('write recovery failed', err))
       await reindex(root)
+      const { trash } = await getSettings()
+      purgeTrash(root, trash.retentionDays).catch(err => console.error('trash purge failed', err))
+    }
+    const registry = await listWorkspaces()
+    setWorkspaces(registry.workspaces)
+    // Catch up on what changed in the other workspaces while the app was closed
+    for (const w of registry.workspaces) {
+      if (w.root === root) continue
+      await scanWorkspace(w.root).catch(err => console.error(`scan of ${w.name} failed`, err))
     }
   }, [reindex])
 
   const setRoot = useCallback(async (root: string) => {
-    setRootPath(root)
-    // Persist selection for future sessions and watcher config
-    await persistWorkspaceRoot(root)
-    await setWatchedFolders([root])
-    // Watcher will be (re)started by the rootPath effect
-    await reindex(root)
+    const w = await registerWorkspace(root)
+    await activateWorkspace(w.id)
+    setRootPath(w.root)
+    await reindex(w.root)
   }, [reindex])
 
+  const addWorkspace = useCallback(async (root: string, name?: string) => {
+    const w = await registerWorkspace(root, name)
+    // Index it now so switching to it later does not have to
+    scanWorkspace(w.root).catch(err => console.error(`scan of ${w.name} failed`, err))
+    return w
+  }, [])
+
+  const switchWorkspace = useCallback(async (id: string) => {
+    const w = await activateWorkspace(id)
+    setRootPath(w.root)
+    await reload(w.root)
+  }, [reload])
+
+  const removeWorkspace = useCallback(async (id: string) => {
+    const registry = await forgetWorkspace(id)
+    setWorkspaces(registry.workspaces)
+    const next = registry.workspaces.find(w => w.id === registry.active) ?? null
+    if (next?.root === rootRef.current) return
+    setRootPath(next?.root ?? null)
+    if (next) await reload(next.root)
+    else setTree(null)
+  }, [reload])
+
+  useEffect(() => {
+    // Also picks up workspaces added or switched from the settings window
+    const un = onWorkspacesChanged(registry => {
+      setWorkspaces(registry.workspaces)
+      const active = registry.workspaces.find(w => w.id === registry.active) ?? null
+      if (active?.root === rootRef.current) return
+      setRootPath(active?.root ?? null)
+      if (active) reload(active.root).catch(err => console.error('reload failed', err))
+      else setTree(null)
+    })
+    return () => { un.then(f => f()).catch(() => {}) }
+  }, [reload])
+
   useEffect(() => {
     if (didAutoInit.current) return
     didAutoInit.current = true
@@ -104,14 +178,27 @@ export function WorkspaceProvider({ children }: { children: React.ReactNode }) {
   }, [init])
 
   useEffect(() => {
-    if (!rootPath) return
-    stopWatching().catch(()=>{})
-    startWatching([rootPath]).catch(err => console.error('watch start failed', err))
-  }, [rootPath])
+    // Every workspace keeps its own watcher, so background ones stay indexed
+    const roots = new Set(workspaces.map(w => w.root))
+    for (const root of watchedRoots.current) {
+      if (!roots.has(root)) stopWatching(root).catch(()=>{})
+    }
+    for (const root of roots) {
+      if (watchedRoots.current.has(root)) continue
+      startWatching([root], root).catch(err => {
+        // Not watched after all; try again on the next workspace change
+        watchedRoots.current.delete(root)
+        console.error('watch start failed', err)
+      })
+    }
+    watchedRoots.current = roots
+  }, [workspaces])
 
   const applyFsChanges = useCallback(async (paths: string[]) => {
     const effectiveRoot = await resolveRoot()
     if (!effectiveRoot) return
+    // Edited ignore rules can change what is listed anywhere in the tree
+    if (paths.some(isIgnoreFile)) return reindex(effectiveRoot)
     paths.forEach(p => pendingPaths.current.add(p))
     if (busy.current) { pending.current = true; return }
     busy.current = true
@@ -122,8 +209,7 @@ export function WorkspaceProvider({ children }: { children: React.ReactNode }) {
         const batch = Array.from(pendingPaths.current)
         pendingPaths.current.clear()
         if (batch.length === 0) break
-        const diff = await analyzePaths(effectiveRoot, batch)
-        await reconcileFromAnalyze(effectiveRoot, diff)
+        await analyzePaths(effectiveRoot, batch)
         const t = await getWorkspaceTree(effectiveRoot)
         setTree(t)
       } while (pending.current || pendingPaths.current.size > 0)
@@ -139,7 +225,9 @@ export function WorkspaceProvider({ children }: { children: React.ReactNode }) {
     }
   }, [resolveRoot, reindex])
 
-  const value = useMemo(() => ({ rootPath, tree, loading, init, setRoot, reload, reindex, applyFsChanges }), [rootPath, tree, loading, init, setRoot, reload, reindex, applyFsChanges])
+  const activeWorkspace = useMemo(() => workspaces.find(w => w.root === rootPath) ?? null, [workspaces, rootPath])
+
+  const value = useMemo(() => ({ rootPath, workspaces, activeWorkspace, tree, loading, scanProgress, init, setRoot, addWorkspace, switchWorkspace, removeWorkspace, reload, reindex, applyFsChanges }), [rootPath, workspaces, activeWorkspace, tree, loading, scanProgress, init, setRoot, addWorkspace, switchWorkspace, removeWorkspace, reload, reindex, applyFsChanges])
 
   return <WorkspaceCtx.Provider value={value}>{children}</WorkspaceCtx.Provider>
 }
@@ -4,8 +4,7 @@ import style from './styles/Editor.module.css'
 
 import { useNavigate } from "react-router-dom"
 import { useEffect, useMemo, useRef, useState } from "react"
-import { save } from "@tauri-apps/plugin-dialog"
-import { readTextFile } from "@tauri-apps/plugin-fs"
+import { ask } from "@tauri-apps/plugin-dialog"
 import { invoke } from "@tauri-apps/api/core"
 import { getCurrentWindow } from "@tauri-apps/api/window"
 import { rpc_project } from "./core/discord_rpc"
@@ -18,10 +17,16 @@ import { getView, onDocChange } from "./core/editor/editorBridge"
 import { DOMSerializer, DOMParser as PMDOMParser, Node as PMNode } from "prosemirror-model"
 import { rSchema } from "./core/editor/rSchema"
 import ProjectPickerModal from "./components/editor/projectPickerModal"
+import LargeFileViewer from "./components/editor/largeFileViewer"
 import { useWorkspace } from "./core/workspaceContext"
 import { useToast } from "./core/toast"
+import { discardDraft, errorMessage, getDocumentSize, getDocumentVersion, getEncryptionStatus, isLocked, isSaveConflict, putDraft, readTextDocument, unlockDocument, type DocVersion } from "./core/db"
+import MultiModal from "./components/modal"
 
 const DOC_CACHE_TTL_MS = 5 * 60 * 1000
+const DRAFT_STREAM_MS = 2000
+// Text files above this are shown in the paged viewer instead of the editor
+const LARGE_FILE_BYTES = 8 * 1024 * 1024
 
 function escapeHtml(s: string) {
   return s.replace(/&/g, "&amp;").replace(/</g, "&lt;").replace(/>/g, "&gt;")
@@ -64,14 +69,13 @@ async function loadCurrentFile(path: string): Promise<string> {
     try {
       const html = await invoke<string>("read_rpad_data", { path })
       return html || "<p></p>"
-    } catch {
+    } catch (err) {
+      if (isLocked(err)) throw err
       return "<p></p>"
     }
   }
-  if (ext === "txt") {
-    try { return await readTextFile(path) } catch { return "" }
-  }
-  try { return await readTextFile(path) } catch { return "" }
+  // Decoded by the backend, which keeps the file's encoding and line endings on save
+  try { return (await readTextDocument(path)).content } catch { return "" }
 }
 
 export default function Editor() {
@@ -149,6 +153,20 @@ export default function Editor() {
   const hasSyncedOpenProjects = useRef(false)
   const isRestoring = useRef(false)
   const docCacheRef = useRef<Map<string, { doc: PMNode; fromDraft: boolean; updatedAt: number }>>(new Map())
+  // Last known on-disk version per path; saves are rejected if the file changed since
+  const versionsRef = useRef<Map<string, DocVersion>>(new Map())
+  const draftStreamTimer = useRef<number | undefined>(undefined)
+  // Encrypted documents never get plaintext drafts in localStorage
+  const encryptedPathsRef = useRef<Set<string>>(new Set())
+  // Large text files are never loaded into (or saved from) the editor
+  const largePathsRef = useRef<Set<string>>(new Set())
+  const [largeFilePath, setLargeFilePath] = useState<string | null>(null)
+  const [unlockPrompt, setUnlockPrompt] = useState<{
+    path: string
+    title: string
+    error?: string
+    resolve: (unlocked: boolean) => void
+  } | null>(null)
 
   const readDrafts = () => {
     try {
@@ -239,10 +257,11 @@ export default function Editor() {
     if (isSaved && !unsavedPaths.has(path)) return
     const v = getView()
     if (!v) return
-    const html = serializeHTML()
-    const drafts = readDrafts()
-    drafts[path] = html
-    localStorage.setItem("drafts", JSON.stringify(drafts))
+    if (!encryptedPathsRef.current.has(path)) {
+      const drafts = readDrafts()
+      drafts[path] = serializeHTML()
+      localStorage.setItem("drafts", JSON.stringify(drafts))
+    }
     setUnsavedPaths(prev => {
       const next = new Set(prev)
       next.add(path)
@@ -250,7 +269,23 @@ export default function Editor() {
     })
   }
 
+  // Send the active document's state to the backend draft journal once typing pauses
+  const scheduleDraftStream = (path: string) => {
+    window.clearTimeout(draftStreamTimer.current)
+    draftStreamTimer.current = window.setTimeout(() => {
+      draftStreamTimer.current = undefined
+      const activePath = currentPathRef.current || sessionStorage.getItem("path") || currentPath
+      const v = getView()
+      if (!v || path !== activePath || !unsavedPathsRef.current.has(path)) return
+      putDraft(path, serializeHTML(v.state.doc), versionsRef.current.get(path) ?? null)
+        .catch(err => console.error("Failed to store draft", err))
+    }, DRAFT_STREAM_MS)
+  }
+
   const clearDraft = (path: string) => {
+    window.clearTimeout(draftStreamTimer.current)
+    draftStreamTimer.current = undefined
+    discardDraft(path).catch(() => {})
     const drafts = readDrafts()
     if (drafts[path]) {
       delete drafts[path]
@@ -400,14 +435,14 @@ export default function Editor() {
     return div.innerHTML
   }
 
-  const saveNow = async (target?: { path?: string; name?: string; doc?: PMNode }) => {
+  const saveNow = async (target?: { path?: string; name?: string; doc?: PMNode; force?: boolean }) => {
     const v = getView()
     const path =
       target?.path ||
       currentPathRef.current ||
       sessionStorage.getItem("path") ||
       ""
-    if (!path) return
+    if (!path || largePathsRef.current.has(path)) return
     const title =
       target?.name ||
       openProjectsRef.current.find(p => p.path === path)?.name ||
@@ -419,17 +454,20 @@ export default function Editor() {
     if (!doc) return
 
     const ext = extOf(path)
+    const expected = target?.force ? null : versionsRef.current.get(path) ?? null
+    let version: DocVersion
     if (ext === "txt") {
       const payload = doc.textContent
-      await invoke("write_text_atomic", { path, contents: payload })
+      version = await invoke<DocVersion>("write_text_atomic", { path, contents: payload, expected })
     } else if (ext === "rpad") {
       const html = serializeHTML(doc)
-      await invoke("write_rpad_html", { path, html, title })
+      version = await invoke<DocVersion>("write_rpad_html", { path, html, title, expected })
     } else {
       // fallback: write plain text
       const payload = doc.textContent
-      await invoke("write_text_atomic", { path, contents: payload })
+      version = await invoke<DocVersion>("write_text_atomic", { path, contents: payload, expected })
     }
+    versionsRef.current.set(path, version)
 
     const activePath = currentPathRef.current || sessionStorage.getItem("path") || currentPath
     if (path === activePath) {
@@ -495,17 +533,42 @@ export default function Editor() {
       const text = extractDocText(v.state.doc)
       setCharacters(text.replace(/\n/g, "").length)
       setWords(countWords(text))
+      if (path) scheduleDraftStream(path)
       scheduleAutoSave()
     })
     return off
   }, [])
 
+  // Another program changed the file since we loaded or saved it
+  const resolveSaveConflict = async () => {
+    const overwrite = await ask(
+      "This file was changed outside RosePad since it was opened. Overwrite those changes with your version, or save your version as a new file?",
+      { title: "File changed on disk", kind: "warning", okLabel: "Overwrite", cancelLabel: "Save as..." }
+    )
+    if (!overwrite) {
+      // handleSavingAs reports its own errors
+      await handleSavingAs().catch(() => {})
+      return
+    }
+    try {
+      await saveNow({ force: true })
+      pushToast({ message: "Saved", kind: "success" })
+    } catch (err) {
+      pushToast({ message: `Save failed: ${errorMessage(err)}`, kind: "error" })
+    }
+  }
+
+  const handleSaveError = async (err: unknown) => {
+    if (isSaveConflict(err)) return resolveSaveConflict()
+    pushToast({ message: `Save failed: ${errorMessage(err)}`, kind: "error" })
+  }
+
   const handleSaving = async () => {
     try {
       await saveNow()
       pushToast({ message: "Saved", kind: "success" })
     } catch (err) {
-      pushToast({ message: `Save failed: ${err}`, kind: "error" })
+      await handleSaveError(err)
     }
   }
 
@@ -516,7 +579,7 @@ export default function Editor() {
       if (!mod) return
       if (e.key.toLowerCase() !== "s") return
       e.preventDefault()
-      void saveNow()
+      void saveNow().catch(handleSaveError)
     }
     window.addEventListener("keydown", onSaveShortcut)
     return () => window.removeEventListener("keydown", onSaveShortcut)
@@ -525,7 +588,9 @@ export default function Editor() {
   const handleSavingAs = async () => {
     const oldPath = sessionStorage.getItem("path") || ""
     const suggested = oldPath || sessionStorage.getItem("projectName") || "Untitled.rpad"
-    const newPath = await save({
+    // The dialog runs in the backend, which lets the commands below write to
+    // the picked path even when it is outside the open workspaces
+    const newPath = await invoke<string | null>("save_file_dialog", {
       defaultPath: suggested,
       filters: [
         { name: "RosePad Files", extensions: ["rpad", "txt"] },
@@ -551,15 +616,18 @@ export default function Editor() {
     window.dispatchEvent(new Event("storage"))
 
     try {
+      // Save as replaces whatever is at the chosen path, so no version check here
+      let version: DocVersion
       if (isRpad) {
         const title = sessionStorage.getItem("projectName") || "Untitled"
-        await invoke("write_rpad_html", { path: newPath, html: payload, title })
+        version = await invoke<DocVersion>("write_rpad_html", { path: newPath, html: payload, title })
       } else {
-        await invoke("write_text_atomic", { path: newPath, contents: payload })
+        version = await invoke<DocVersion>("write_text_atomic", { path: newPath, contents: payload })
       }
+      versionsRef.current.set(newPath, version)
       pushToast({ message: `Saved to ${newPath}`, kind: "success" })
     } catch (err) {
-      pushToast({ message: `Save as failed: ${err}`, kind: "error" })
+      pushToast({ message: `Save as failed: ${errorMessage(err)}`, kind: "error" })
       throw err
     }
 
@@ -569,6 +637,41 @@ export default function Editor() {
     cacheDoc(newPath, v.state.doc, false)
   }
 
+  const requestUnlock = (path: string, title: string) =>
+    new Promise<boolean>(resolve => setUnlockPrompt({ path, title, resolve }))
+
+  const submitUnlock = async (passphrase: string) => {
+    const prompt = unlockPrompt
+    if (!prompt) return
+    try {
+      await unlockDocument(prompt.path, passphrase)
+      setUnlockPrompt(null)
+      prompt.resolve(true)
+    } catch (err) {
+      setUnlockPrompt({ ...prompt, error: errorMessage(err) })
+    }
+  }
+
+  const cancelUnlock = () => {
+    unlockPrompt?.resolve(false)
+    setUnlockPrompt(null)
+  }
+
+  // Ask for the passphrase while the document is locked; rethrows the lock if the user gives up
+  const loadUnlocked = async (path: string): Promise<string> => {
+    for (;;) {
+      try {
+        return await loadCurrentFile(path)
+      } catch (err) {
+        if (!isLocked(err)) throw err
+        if (!await requestUnlock(path, err.title || "this document")) throw err
+        await getDocumentVersion(path)
+          .then(v => versionsRef.current.set(path, v))
+          .catch(() => versionsRef.current.delete(path))
+      }
+    }
+  }
+
   const loadProject = async (pathOverride?: string) => {
     const requestId = ++loadRequestIdRef.current
     const path = pathOverride || sessionStorage.getItem("path")
@@ -576,6 +679,22 @@ export default function Editor() {
     cancelAutoSaveTimer()
     currentPathRef.current = path
     setCurrentPath(path)
+    setLargeFilePath(null)
+    if (!/\.rpad$/i.test(path)) {
+      const size = await getDocumentSize(path).catch(() => 0)
+      if (requestId !== loadRequestIdRef.current) return
+      if (size > LARGE_FILE_BYTES) {
+        largePathsRef.current.add(path)
+        setLargeFilePath(path)
+        const name =
+          sessionStorage.getItem("projectName") ||
+          sessionStorage.getItem("name") ||
+          "Untitled"
+        rememberProject(path, name)
+        return
+      }
+    }
+    largePathsRef.current.delete(path)
     const cached = docCacheRef.current.get(path)
     if (cached?.doc && Date.now() - cached.updatedAt <= DOC_CACHE_TTL_MS) {
       const activePath = currentPathRef.current || sessionStorage.getItem("path") || currentPath
@@ -591,7 +710,26 @@ export default function Editor() {
     try {
       const drafts = readDrafts()
       const draft = drafts[path]
-      const content = draft ?? await loadCurrentFile(path)
+      if (draft === undefined) {
+        // Read the version before the content, so a change in between is caught as a conflict on save
+        await getDocumentVersion(path)
+          .then(v => versionsRef.current.set(path, v))
+          .catch(() => versionsRef.current.delete(path))
+      }
+      if (/\.rpad$/i.test(path)) {
+        const status = await getEncryptionStatus(path).catch(() => null)
+        if (status?.encrypted) encryptedPathsRef.current.add(path)
+        else encryptedPathsRef.current.delete(path)
+      }
+      let content: string
+      try {
+        content = draft ?? await loadUnlocked(path)
+      } catch (err) {
+        if (!isLocked(err)) throw err
+        pushToast({ message: "The document stays locked", kind: "info" })
+        navigator("/")
+        return
+      }
 
       const looksHtml = !!draft || /\.rpad$/i.test(path) || /<\/?[a-z][\s\S]*>/i.test(content.trim())
       const html = looksHtml ? content : `<p>${escapeHtml(content)}</p>`
@@ -893,8 +1031,19 @@ export default function Editor() {
           ) : null}
           <EditorPanel/>
           <StyleMenu/>
+          {largeFilePath ? <LargeFileViewer path={largeFilePath}/> : null}
         </div>
       </div>
+      <MultiModal
+        type="passphrase"
+        isOpen={!!unlockPrompt}
+        onClose={cancelUnlock}
+        onSubmit={submitUnlock}
+        title="Unlock document"
+        message={`"${unlockPrompt?.title ?? ""}" is encrypted. Enter its passphrase to open it.`}
+        buttonLabel="Unlock"
+        error={unlockPrompt?.error}
+      />
       <ProjectPickerModal
         isOpen={isPickerOpen}
         projects={projectOptions}
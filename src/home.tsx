@@ -5,7 +5,7 @@ import SettingsButton from "./components/settings/buttonSettings"
 import MultiModal from './components/modal'
 import { ProjectList } from './components/home/projectList/list'
 
-import { open } from '@tauri-apps/plugin-dialog'
+import { ask } from '@tauri-apps/plugin-dialog'
 import { getCurrentWindow } from '@tauri-apps/api/window'
 import { useEffect, useState } from 'react'
 import type { ReactNode } from 'react'
@@ -15,7 +15,7 @@ import { listen, type UnlistenFn } from '@tauri-apps/api/event'
 import { rpc_main_menu, rpc_project } from './core/discord_rpc'
 import { applyTheme, setup } from './core/cache'
 import { addProject, settings } from './core/projectHandler'
-import { setPhysicalFolderColor, createPhysicalFolder } from './core/db'
+import { setPhysicalFolderColor, createPhysicalFolder, listDrafts, readDraft, discardDraft, errorMessage } from './core/db'
 
 import { useWorkspace } from './core/workspaceContext'
 import { invoke } from '@tauri-apps/api/core'
@@ -27,8 +27,8 @@ setup()
 applyTheme()
 settings()
 
-async function createRpadFile(dir: string, name: string) {
-  const filePath = await invoke<string>('create_rpad_project', { destDir: dir, name })
+async function createRpadFile(dir: string, name: string, workspaceRoot: string) {
+  const filePath = await invoke<string>('create_rpad_project', { destDir: dir, name, workspaceRoot })
   return filePath
 }
 
@@ -44,9 +44,7 @@ function HomeShell() {
 
   const { handleFileOpen, ensureWorkspace } = useHandleFileOpen()
   const importProject = async () => {
-    const p = await open({
-      multiple: false,
-      directory: false,
+    const p = await invoke<string | null>('open_file_dialog', {
       title: 'Select a project to import',
       filters: [
         { name: 'RosePad Files', extensions: ['rpad','txt','pdf','doc','docx'] },
@@ -57,10 +55,10 @@ function HomeShell() {
     })
     if (!p) return
     try {
-      await handleFileOpen(p as string)
+      await handleFileOpen(p)
       pushToast({ message: 'Imported project', kind: 'success' })
     } catch (err) {
-      pushToast({ message: `Import failed: ${err}`, kind: 'error' })
+      pushToast({ message: `Import failed: ${errorMessage(err)}`, kind: 'error' })
     }
   }
 
@@ -134,6 +132,44 @@ function HomeShell() {
     return () => { cancelled = true }
   }, [isChangeLogOpen])
 
+  useEffect(() => {
+    // Offer drafts a crashed session left behind, once per launch
+    if (sessionStorage.getItem('draftsChecked') === 'true') return
+    sessionStorage.setItem('draftsChecked', 'true')
+
+    const recoverDrafts = async () => {
+      const found = await listDrafts()
+      for (const draft of found) {
+        const name = draft.path.split(/[\\/]/).pop() || draft.path
+        const when = new Date(draft.updatedAtMs).toLocaleString()
+        const warning = draft.diskChanged
+          ? (draft.disk ? '\n\nThe file was changed since then; restoring will overwrite those changes when you save.' : '\n\nThe file no longer exists.')
+          : ''
+        const restore = await ask(`Unsaved changes to "${name}" from ${when} were recovered.${warning}`, {
+          title: 'Recover unsaved changes',
+          kind: 'warning',
+          okLabel: 'Restore',
+          cancelLabel: 'Discard'
+        })
+        let drafts: Record<string, string> = {}
+        try {
+          drafts = JSON.parse(localStorage.getItem('drafts') || '{}') || {}
+        } catch { }
+        if (restore) {
+          // The editor picks the draft up the next time the file is opened
+          drafts[draft.path] = await readDraft(draft.path)
+          pushToast({ message: `Restored unsaved changes to ${name}`, kind: 'success' })
+        } else {
+          delete drafts[draft.path]
+          await discardDraft(draft.path)
+        }
+        localStorage.setItem('drafts', JSON.stringify(drafts))
+      }
+    }
+
+    recoverDrafts().catch(err => console.error('Failed to recover drafts', err))
+  }, [])
+
   useEffect(() => {
     if (sessionStorage.getItem('openChangelogRequested') === 'true') {
       sessionStorage.removeItem('openChangelogRequested')
@@ -170,7 +206,7 @@ function HomeShell() {
       // If user picked a folder, use it; otherwise use workspace root
       const dir = dest || root
 
-      const filePath = await createRpadFile(dir, name)
+      const filePath = await createRpadFile(dir, name, root)
       await rpc_project(name, filePath)
       sessionStorage.setItem("name", name)
       sessionStorage.setItem("projectName", name)
@@ -182,7 +218,7 @@ function HomeShell() {
       setIsCreateProjectOpen(false)
       navigator(`/editor/${name}`)
     } catch (err) {
-      pushToast({ message: `Create failed: ${err}`, kind: 'error' })
+      pushToast({ message: `Create failed: ${errorMessage(err)}`, kind: 'error' })
     }
   }
 
@@ -227,7 +263,7 @@ function HomeShell() {
           await reindex()
           pushToast({ message: 'Folder created', kind: 'success' })
         } catch (err) {
-          pushToast({ message: `Folder creation failed: ${err}`, kind: 'error' })
+          pushToast({ message: `Folder creation failed: ${errorMessage(err)}`, kind: 'error' })
         } finally {
           setIsCreateFolderOpen(false)
         }
@@ -18,7 +18,7 @@ function Settings() {
     const [autoSaveInterval, setAutoSaveInterval] = useState(2);
     const [theme, setThemeButton] = useState<themes>(null);
     const [richPresenceEnabled, setRichPresenceEnabled] = useState<boolean>(isRpcEnabled());
-    const { setRoot, rootPath } = useWorkspace();
+    const { setRoot, rootPath, workspaces, switchWorkspace, removeWorkspace } = useWorkspace();
 
     const handleAutoSaveChange  = (event: React.ChangeEvent<HTMLInputElement>) => {
         const checked = event.target.checked;
@@ -124,10 +124,19 @@ function Settings() {
             <NavSettings/>
             <div className={style.container}>
                 <div>
-                    <h3 className={style.heads}>Workspace Directory</h3>
-                    <div className={style.pathInput} title={rootPath as string}>
-                        <p>{rootPath}</p>
-                        <button className={style.button} onClick={ () => handleDirChange() }>Select</button>
+                    <h3 className={style.heads}>Workspaces</h3>
+                    {workspaces.map(w => (
+                        <div key={w.id} className={style.pathInput} title={w.root}>
+                            <p>{w.name} <span>({w.root})</span></p>
+                            <div className={style.workspaceActions}>
+                                {w.root !== rootPath && <button className={style.button} onClick={ () => switchWorkspace(w.id) }>Open</button>}
+                                <button className={style.button} onClick={ () => removeWorkspace(w.id) }>Remove</button>
+                            </div>
+                        </div>
+                    ))}
+                    <div className={style.pathInput}>
+                        <p>Add a folder as a workspace</p>
+                        <button className={style.button} onClick={ () => handleDirChange() }>Add</button>
                     </div>
                 </div>
                 <div>